@@ -0,0 +1,83 @@
+use crate::recording::Recording;
+use crate::session::WindowGeometry;
+use crate::state::{ClipboardData, DocumentId, EditorState};
+
+/// Top-level application state: every open document plus data shared
+/// between them.
+pub struct App {
+    pub documents: Vec<EditorState>,
+    pub active: usize,
+    // App-wide so a copy in one tab can be pasted into another
+    pub clipboard: Option<ClipboardData>,
+    pub reopen_last_session: bool,
+    // Last known window size and position, saved with the session
+    pub window: Option<WindowGeometry>,
+    // Device pixels per logical pixel of the window
+    pub scale_factor: f32,
+    // Messages captured since "Record session" was pressed
+    pub recording: Option<Recording>,
+}
+
+impl App {
+    pub fn new(document: EditorState) -> Self {
+        Self {
+            documents: vec![document],
+            active: 0,
+            clipboard: None,
+            reopen_last_session: true,
+            window: None,
+            scale_factor: 1.0,
+            recording: None,
+        }
+    }
+
+    pub fn active_document(&self) -> &EditorState {
+        &self.documents[self.active]
+    }
+
+    pub fn active_document_mut(&mut self) -> &mut EditorState {
+        &mut self.documents[self.active]
+    }
+
+    /// Open a blank document next to the active one and switch to it.
+    pub fn add_document(&mut self) {
+        let document = self.active_document().new_document(32, 32);
+        self.insert_document(document);
+    }
+
+    /// Insert `document` after the active one and switch to it.
+    pub fn insert_document(&mut self, document: EditorState) {
+        self.active += 1;
+        self.documents.insert(self.active, document);
+    }
+
+    /// Where the document `id` currently sits among the tabs, if it's
+    /// still open.
+    pub fn document_index(&self, id: DocumentId) -> Option<usize> {
+        self.documents.iter().position(|document| document.id == id)
+    }
+
+    pub fn select_document(&mut self, index: usize) {
+        if index < self.documents.len() {
+            self.active_document_mut().finish_continuous_edit();
+            self.active = index;
+        }
+    }
+
+    /// Close the document at `index`. Closing the last document leaves a
+    /// fresh blank one so there is always something to draw on.
+    pub fn close_document(&mut self, index: usize) {
+        if index >= self.documents.len() {
+            return;
+        }
+        if self.documents.len() == 1 {
+            self.documents[0] = self.documents[0].new_document(32, 32);
+            return;
+        }
+
+        self.documents.remove(index);
+        if self.active > index || self.active >= self.documents.len() {
+            self.active -= 1;
+        }
+    }
+}
@@ -1,43 +1,245 @@
 use crate::message::Message;
-use crate::state::EditorState;
+use crate::state::{EditorState, SliceEdge};
+use crate::transform::{BufferTransform, ShiftAxis};
+use iced::keyboard;
 use iced::mouse;
 use iced::widget::canvas;
 use iced::{Color, Point, Rectangle, Size};
 
-pub struct CanvasProgram {
-    state: EditorState,
+pub struct CanvasProgram<'a> {
+    state: &'a EditorState,
+    // Device pixels per logical pixel, so art pixels can be snapped to
+    // whole device pixels
+    scale_factor: f32,
 }
 
-impl CanvasProgram {
-    pub fn new(state: EditorState) -> Self {
-        Self { state }
+impl<'a> CanvasProgram<'a> {
+    pub fn new(state: &'a EditorState, scale_factor: f32) -> Self {
+        Self {
+            state,
+            scale_factor,
+        }
     }
 
-    pub fn update_state(&mut self, state: EditorState) {
-        self.state = state;
+    /// Canvas size as displayed; quarter turns of the view swap width and
+    /// height.
+    fn view_size(&self) -> (f32, f32) {
+        let (width, height) = (
+            self.state.canvas_width as f32,
+            self.state.canvas_height as f32,
+        );
+        if self.state.view_rotation % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        }
     }
 
-    fn canvas_to_pixel(&self, point: Point, bounds: Rectangle, zoom: f32) -> Option<(u32, u32)> {
-        // Calculate pixel coordinates from canvas coordinates
-        let pixel_size = zoom;
-        let canvas_pixel_width = self.state.canvas_width as f32 * pixel_size;
-        let canvas_pixel_height = self.state.canvas_height as f32 * pixel_size;
+    /// Map a canvas point into view space: flip first, then rotate clockwise
+    /// in quarter turns. Only the display is transformed, never the pixels.
+    fn canvas_to_view(&self, x: f32, y: f32) -> (f32, f32) {
+        let (width, height) = (
+            self.state.canvas_width as f32,
+            self.state.canvas_height as f32,
+        );
+        let x = if self.state.view_flipped {
+            width - x
+        } else {
+            x
+        };
+        match self.state.view_rotation % 4 {
+            1 => (height - y, x),
+            2 => (width - x, height - y),
+            3 => (y, width - x),
+            _ => (x, y),
+        }
+    }
 
-        // Calculate center offsets to center the canvas in the bounds
-        let offset_x = (bounds.width - canvas_pixel_width) / 2.0;
-        let offset_y = (bounds.height - canvas_pixel_height) / 2.0;
+    /// Inverse of `canvas_to_view`.
+    fn view_to_canvas(&self, x: f32, y: f32) -> (f32, f32) {
+        let (width, height) = (
+            self.state.canvas_width as f32,
+            self.state.canvas_height as f32,
+        );
+        let (x, y) = match self.state.view_rotation % 4 {
+            1 => (y, height - x),
+            2 => (width - x, height - y),
+            3 => (width - y, x),
+            _ => (x, y),
+        };
+        (
+            if self.state.view_flipped {
+                width - x
+            } else {
+                x
+            },
+            y,
+        )
+    }
+
+    /// Displayed size of one art pixel: the zoom level rounded to a whole
+    /// number of device pixels, so pixels stay crisp and evenly sized on
+    /// scaled displays.
+    fn pixel_size(&self) -> f32 {
+        crate::utils::snap_zoom(self.state.zoom_level, self.scale_factor)
+    }
+
+    /// Top-left of the displayed canvas: centered in the bounds, then moved
+    /// by the pan offset.
+    fn view_offset(&self, bounds: Rectangle) -> (f32, f32) {
+        self.view_offset_at(bounds, self.pixel_size())
+    }
+
+    /// Top-left of the displayed canvas at a pixel size of `zoom`, snapped
+    /// to a device pixel boundary of the window.
+    fn view_offset_at(&self, bounds: Rectangle, zoom: f32) -> (f32, f32) {
+        let (width, height) = self.view_size();
+        let (pan_x, pan_y) = self.state.pan;
+        let snap = |origin: f32, offset: f32| {
+            crate::utils::snap_to_device(origin + offset, self.scale_factor) - origin
+        };
+        (
+            snap(bounds.x, (bounds.width - width * zoom) / 2.0 + pan_x),
+            snap(bounds.y, (bounds.height - height * zoom) / 2.0 + pan_y),
+        )
+    }
 
-        // Convert mouse position relative to canvas bounds
-        // Note: point is already relative to bounds (from cursor.position_in(bounds))
-        let x = point.x - offset_x;
-        let y = point.y - offset_y;
+    /// Screen position of a canvas point.
+    fn canvas_to_screen(&self, bounds: Rectangle, x: f32, y: f32) -> Point {
+        let zoom = self.pixel_size();
+        let (offset_x, offset_y) = self.view_offset(bounds);
+        let (x, y) = self.canvas_to_view(x, y);
+        Point::new(offset_x + x * zoom, offset_y + y * zoom)
+    }
+
+    /// Canvas point (in fractional pixels) under a screen position. `point`
+    /// is relative to the bounds, as from `cursor.position_in(bounds)`.
+    fn screen_to_canvas(&self, bounds: Rectangle, point: Point) -> (f32, f32) {
+        let zoom = self.pixel_size();
+        let (offset_x, offset_y) = self.view_offset(bounds);
+        self.view_to_canvas((point.x - offset_x) / zoom, (point.y - offset_y) / zoom)
+    }
+
+    /// Screen rectangle covering a canvas rectangle.
+    fn screen_rect(
+        &self,
+        bounds: Rectangle,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> (Point, Size) {
+        let a = self.canvas_to_screen(bounds, x, y);
+        let b = self.canvas_to_screen(bounds, x + width, y + height);
+        (
+            Point::new(a.x.min(b.x), a.y.min(b.y)),
+            Size::new((a.x - b.x).abs(), (a.y - b.y).abs()),
+        )
+    }
+
+    /// Nearest pixel boundary to a guide drag position, clamped to the canvas.
+    fn canvas_to_boundary(&self, point: Point, bounds: Rectangle, vertical: bool) -> u32 {
+        let (x, y) = self.screen_to_canvas(bounds, point);
+        if vertical {
+            x.round().clamp(0.0, self.state.canvas_width as f32) as u32
+        } else {
+            y.round().clamp(0.0, self.state.canvas_height as f32) as u32
+        }
+    }
+
+    /// The 9-slice guide within a few screen pixels of `point`, if any.
+    fn slice_guide_at(&self, point: Point, bounds: Rectangle) -> Option<SliceEdge> {
+        const GRAB_DISTANCE: f32 = 4.0;
+        let slice = self.state.nine_slice?;
+        let zoom = self.pixel_size();
+        let (width, height) = (self.state.canvas_width, self.state.canvas_height);
+        let (x, y) = self.screen_to_canvas(bounds, point);
+
+        SliceEdge::ALL
+            .into_iter()
+            .map(|edge| {
+                let guide = slice.guide_position(edge, width, height) as f32;
+                let distance = if edge.is_vertical() {
+                    (x - guide).abs() * zoom
+                } else {
+                    (y - guide).abs() * zoom
+                };
+                (edge, distance)
+            })
+            .filter(|(_, distance)| *distance <= GRAB_DISTANCE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(edge, _)| edge)
+    }
 
+    /// What a wheel movement over `position` does: Ctrl zooms toward the
+    /// cursor, the configured brush modifier changes the brush size, Shift
+    /// pans sideways and a plain wheel pans vertically.
+    fn wheel_message(
+        &self,
+        delta: mouse::ScrollDelta,
+        position: Point,
+        bounds: Rectangle,
+    ) -> Option<Message> {
+        // Screen pixels panned per wheel notch
+        const LINE_HEIGHT: f32 = 40.0;
+        let (dx, dy) = match delta {
+            mouse::ScrollDelta::Lines { x, y } => (x * LINE_HEIGHT, y * LINE_HEIGHT),
+            mouse::ScrollDelta::Pixels { x, y } => (x, y),
+        };
+        let modifiers = self.state.modifiers;
+
+        if modifiers.command() {
+            if dy == 0.0 {
+                return None;
+            }
+            let old_zoom = self.pixel_size();
+            let zoom = (self.state.zoom_level + dy.signum()).clamp(1.0, 32.0);
+            let new_size = crate::utils::snap_zoom(zoom, self.scale_factor);
+            // Solve for the pan that maps the same view point back under
+            // the cursor at the new zoom
+            let (offset_x, offset_y) = self.view_offset(bounds);
+            let view_x = (position.x - offset_x) / old_zoom;
+            let view_y = (position.y - offset_y) / old_zoom;
+            let (center_x, center_y) = {
+                let (pan_x, pan_y) = self.state.pan;
+                let (x, y) = self.view_offset_at(bounds, new_size);
+                (x - pan_x, y - pan_y)
+            };
+            let pan = (
+                position.x - view_x * new_size - center_x,
+                position.y - view_y * new_size - center_y,
+            );
+            return Some(Message::ZoomedAt { zoom, pan });
+        }
+        if self.state.wheel_brush_modifier.is_held(modifiers) {
+            if dy == 0.0 {
+                return None;
+            }
+            let size = self.state.brush_size();
+            let size = if dy > 0.0 {
+                size + 1
+            } else {
+                size.saturating_sub(1)
+            };
+            return Some(Message::BrushSizeChanged(size));
+        }
+        if modifiers.shift() {
+            return Some(Message::ViewPanned {
+                dx: dx + dy,
+                dy: 0.0,
+            });
+        }
+        Some(Message::ViewPanned { dx, dy })
+    }
+
+    fn canvas_to_pixel(&self, point: Point, bounds: Rectangle) -> Option<(u32, u32)> {
+        let (x, y) = self.screen_to_canvas(bounds, point);
         if x < 0.0 || y < 0.0 {
             return None;
         }
 
-        let pixel_x = (x / pixel_size) as u32;
-        let pixel_y = (y / pixel_size) as u32;
+        let pixel_x = x as u32;
+        let pixel_y = y as u32;
 
         if pixel_x < self.state.canvas_width && pixel_y < self.state.canvas_height {
             Some((pixel_x, pixel_y))
@@ -47,7 +249,7 @@ impl CanvasProgram {
     }
 }
 
-impl canvas::Program<Message> for CanvasProgram {
+impl canvas::Program<Message> for CanvasProgram<'_> {
     type State = ();
 
     fn draw(
@@ -56,79 +258,285 @@ impl canvas::Program<Message> for CanvasProgram {
         renderer: &iced::Renderer,
         _theme: &iced::Theme,
         bounds: Rectangle,
-        _cursor: mouse::Cursor,
+        cursor: mouse::Cursor,
     ) -> Vec<canvas::Geometry> {
         let mut frame = canvas::Frame::new(renderer, bounds.size());
-        let zoom = self.state.zoom_level;
-        let pixel_size = zoom;
-        let canvas_pixel_width = self.state.canvas_width as f32 * pixel_size;
-        let canvas_pixel_height = self.state.canvas_height as f32 * pixel_size;
-
-        // Calculate center offsets to center the canvas in the bounds
-        let offset_x = (bounds.width - canvas_pixel_width) / 2.0;
-        let offset_y = (bounds.height - canvas_pixel_height) / 2.0;
+        let zoom = self.pixel_size();
+        let (width, height) = (
+            self.state.canvas_width as f32,
+            self.state.canvas_height as f32,
+        );
+        // Everything below is positioned in canvas coordinates and mapped
+        // through the view flip/rotation
+        let line = |x1: f32, y1: f32, x2: f32, y2: f32| {
+            canvas::Path::line(
+                self.canvas_to_screen(bounds, x1, y1),
+                self.canvas_to_screen(bounds, x2, y2),
+            )
+        };
 
-        // Draw background checkerboard pattern
+        // Shade everything outside the canvas, and show the transparency
+        // checkerboard only inside it, anchored to the canvas corner
+        frame.fill_rectangle(
+            Point::ORIGIN,
+            bounds.size(),
+            crate::utils::rgba8_to_color(self.state.outside_canvas_color),
+        );
+        let (canvas_point, canvas_size) = self.screen_rect(bounds, 0.0, 0.0, width, height);
         let checker_size = 8.0;
-        for y in 0..(bounds.height as u32 / checker_size as u32 + 1) {
-            for x in 0..(bounds.width as u32 / checker_size as u32 + 1) {
-                let is_light = (x + y) % 2 == 0;
-                let color = if is_light {
+        let visible_left = canvas_point.x.max(0.0);
+        let visible_top = canvas_point.y.max(0.0);
+        let visible_right = (canvas_point.x + canvas_size.width).min(bounds.width);
+        let visible_bottom = (canvas_point.y + canvas_size.height).min(bounds.height);
+        let first_column = ((visible_left - canvas_point.x) / checker_size).floor() as i32;
+        let first_row = ((visible_top - canvas_point.y) / checker_size).floor() as i32;
+        let mut row = first_row;
+        while canvas_point.y + row as f32 * checker_size < visible_bottom {
+            let top = (canvas_point.y + row as f32 * checker_size).max(visible_top);
+            let bottom = (canvas_point.y + (row + 1) as f32 * checker_size).min(visible_bottom);
+            let mut column = first_column;
+            while canvas_point.x + column as f32 * checker_size < visible_right {
+                let left = (canvas_point.x + column as f32 * checker_size).max(visible_left);
+                let right =
+                    (canvas_point.x + (column + 1) as f32 * checker_size).min(visible_right);
+                let color = if (row + column) % 2 == 0 {
                     Color::from_rgb(0.9, 0.9, 0.9)
                 } else {
                     Color::from_rgb(0.8, 0.8, 0.8)
                 };
-                let point = Point::new(x as f32 * checker_size, y as f32 * checker_size);
-                let size = Size::new(checker_size, checker_size);
-                frame.fill_rectangle(point, size, canvas::Fill::from(color));
+                frame.fill_rectangle(
+                    Point::new(left, top),
+                    Size::new(right - left, bottom - top),
+                    canvas::Fill::from(color),
+                );
+                column += 1;
             }
+            row += 1;
         }
 
-        // Draw all visible layers
-        for layer in &self.state.layers {
-            if !layer.visible {
-                continue;
+        // Swap in the preview buffer while a filter or rotation is open, or
+        // the active layer with the floating paste written over it
+        let preview_layer = match &self.state.preview_overlay {
+            Some(preview) => self
+                .state
+                .layers
+                .get(preview.layer_index)
+                .map(|layer| (preview.layer_index, preview.pixels.clone(), layer)),
+            None => crate::tools::floating_paste_pixels(self.state).and_then(|pixels| {
+                let index = self.state.active_layer_index;
+                Some((index, pixels, self.state.layers.get(index)?))
+            }),
+        }
+        .map(|(index, pixels, layer)| {
+            let mut layer = layer.clone();
+            layer.pixels = pixels;
+            // The paste shows at full opacity whatever the layer's setting
+            if self.state.floating_paste.is_some() && self.state.preview_overlay.is_none() {
+                layer.opacity = 1.0;
             }
+            (index, layer)
+        });
+
+        let display_layer = |index: usize| match &preview_layer {
+            Some((preview_index, preview)) if index == *preview_index => Some(preview),
+            _ => self.state.layers.get(index),
+        };
+        // Each visible layer paired with the layer it is clipped to
+        let display_layers: Vec<(&crate::state::Layer, Option<&crate::state::Layer>)> = self
+            .state
+            .visible_layers()
+            .filter_map(|(index, clip)| Some((display_layer(index)?, clip.and_then(display_layer))))
+            .collect();
 
+        let comparison = self.state.comparison.as_ref();
+        if let Some(theirs) =
+            comparison.filter(|comparison| comparison.view == crate::state::CompareView::Theirs)
+        {
+            // The reference image replaces the layers
+            for (index, pixel) in theirs.pixels.chunks_exact(4).enumerate() {
+                if pixel[3] == 0 {
+                    continue;
+                }
+                let (x, y) = (index as u32 % theirs.width, index as u32 / theirs.width);
+                let (point, size) = self.screen_rect(bounds, x as f32, y as f32, 1.0, 1.0);
+                let color =
+                    Color::from_rgba8(pixel[0], pixel[1], pixel[2], pixel[3] as f32 / 255.0);
+                frame.fill_rectangle(point, size, canvas::Fill::from(color));
+            }
+        } else if self.state.outline_view {
+            // Only each layer's silhouette edge, in that layer's color
+            for (index, _) in self.state.visible_layers() {
+                let Some(layer) = display_layer(index) else {
+                    continue;
+                };
+                let fill = canvas::Fill::from(crate::utils::rgba8_to_color(
+                    crate::filters::outline_color(index),
+                ));
+                let edges =
+                    crate::filters::silhouette_outline(&layer.pixels, layer.width, layer.height);
+                for (i, _) in edges.iter().enumerate().filter(|(_, edge)| **edge) {
+                    let (x, y) = (i as u32 % layer.width, i as u32 / layer.width);
+                    let (point, size) = self.screen_rect(bounds, x as f32, y as f32, 1.0, 1.0);
+                    frame.fill_rectangle(point, size, fill);
+                }
+            }
+        } else if self.state.alpha_edit {
+            // The active layer's alpha as greyscale, white where opaque
+            if let Some(layer) = display_layer(self.state.active_layer_index) {
+                for y in 0..self.state.canvas_height {
+                    for x in 0..self.state.canvas_width {
+                        let alpha = layer.get_rgba8(x, y)[3];
+                        let (point, size) = self.screen_rect(bounds, x as f32, y as f32, 1.0, 1.0);
+                        frame.fill_rectangle(
+                            point,
+                            size,
+                            canvas::Fill::from(Color::from_rgb8(alpha, alpha, alpha)),
+                        );
+                    }
+                }
+            }
+        } else if let Some(matrix) = self.state.color_vision.matrix() {
+            // Simulations operate on the final composited color, so blend the
+            // layers here instead of letting the renderer stack them
             for y in 0..self.state.canvas_height {
                 for x in 0..self.state.canvas_width {
-                    let mut color = layer.get_pixel(x, y);
-                    // Apply layer opacity to the color's alpha channel
-                    color = Color::from_rgba(color.r, color.g, color.b, color.a * layer.opacity);
-                    if color.a > 0.0 {
-                        let point = Point::new(
-                            offset_x + x as f32 * pixel_size,
-                            offset_y + y as f32 * pixel_size,
+                    let mut color = Color::TRANSPARENT;
+                    for &(layer, base) in &display_layers {
+                        let pixel = crate::state::clip_rgba8(layer, base, x, y);
+                        color = crate::state::blend_color(
+                            color,
+                            crate::utils::rgba8_to_color(pixel),
+                            layer.opacity,
                         );
-                        let size = Size::new(pixel_size, pixel_size);
+                    }
+                    if color.a > 0.0 {
+                        let color = crate::utils::simulate_color_vision(color, &matrix);
+                        let (point, size) = self.screen_rect(bounds, x as f32, y as f32, 1.0, 1.0);
                         frame.fill_rectangle(point, size, canvas::Fill::from(color));
                     }
                 }
             }
+        } else {
+            // Draw all visible layers
+            for &(layer, base) in &display_layers {
+                for y in 0..self.state.canvas_height {
+                    for x in 0..self.state.canvas_width {
+                        let [r, g, b, a] = crate::state::clip_rgba8(layer, base, x, y);
+                        if a == 0 {
+                            continue;
+                        }
+                        // Apply layer opacity to the color's alpha channel
+                        let color = Color::from_rgba8(r, g, b, a as f32 / 255.0 * layer.opacity);
+                        if color.a > 0.0 {
+                            let (point, size) =
+                                self.screen_rect(bounds, x as f32, y as f32, 1.0, 1.0);
+                            frame.fill_rectangle(point, size, canvas::Fill::from(color));
+                        }
+                    }
+                }
+            }
         }
 
+        // Differing pixels in red over dimmed matching ones
+        if let Some(diff) =
+            comparison.filter(|comparison| comparison.view == crate::state::CompareView::Diff)
+        {
+            let differing = canvas::Fill::from(Color::from_rgba(1.0, 0.0, 0.0, 0.8));
+            let matching = canvas::Fill::from(Color::from_rgba(0.0, 0.0, 0.0, 0.5));
+            for (index, &differs) in diff.differs.iter().enumerate() {
+                let (x, y) = (index as u32 % diff.width, index as u32 / diff.width);
+                let (point, size) = self.screen_rect(bounds, x as f32, y as f32, 1.0, 1.0);
+                frame.fill_rectangle(point, size, if differs { differing } else { matching });
+            }
+        }
+
+        // Highlight the region a click with the fill tool would cover
+        if let Some(preview) = &self.state.fill_preview {
+            let highlight = canvas::Fill::from(Color::from_rgba(0.0, 0.5, 1.0, 0.3));
+            for &(x, y) in &preview.pixels {
+                let (point, size) = self.screen_rect(bounds, x as f32, y as f32, 1.0, 1.0);
+                frame.fill_rectangle(point, size, highlight);
+            }
+        }
+
+        // Shape being dragged out, in the pixels it will paint on release
+        if let Some(draft) = &self.state.shape_draft {
+            let color = self.state.primary_color;
+            let fill = canvas::Fill::from(Color {
+                a: color.a * 0.5,
+                ..color
+            });
+            for (x, y) in crate::tools::shape_pixels(self.state, draft) {
+                let (point, size) = self.screen_rect(bounds, x as f32, y as f32, 1.0, 1.0);
+                frame.fill_rectangle(point, size, fill);
+            }
+        }
+
+        // Outline the part of the row or column being shifted
+        if let Some(shift) = &self.state.line_shift {
+            let (start, end) = shift.span;
+            let (x, y, width, height) = match shift.axis {
+                ShiftAxis::Row => (start, shift.index, end - start, 1),
+                ShiftAxis::Column => (shift.index, start, 1, end - start),
+            };
+            let (point, size) =
+                self.screen_rect(bounds, x as f32, y as f32, width as f32, height as f32);
+            frame.stroke(
+                &canvas::Path::rectangle(point, size),
+                canvas::Stroke::default()
+                    .with_width(2.0)
+                    .with_color(Color::from_rgb(1.0, 0.5, 0.0)),
+            );
+        }
+
+        // Ring each stray pixel so single pixels stand out at any zoom
+        if let Some(strays) = &self.state.stray_pixels {
+            let ring = canvas::Stroke::default()
+                .with_width(2.0)
+                .with_color(Color::from_rgb(1.0, 0.0, 1.0));
+            for &(x, y) in strays {
+                let (point, size) =
+                    self.screen_rect(bounds, x as f32 - 1.0, y as f32 - 1.0, 3.0, 3.0);
+                frame.stroke(&canvas::Path::rectangle(point, size), ring);
+            }
+        }
+
+        // Outline the pixels found by a color usage scan
+        if let Some(usage) = &self.state.color_usage {
+            let outline = canvas::Stroke::default()
+                .with_width(2.0)
+                .with_color(Color::from_rgb(0.0, 1.0, 1.0));
+            for &(x, y) in &usage.pixels {
+                let (point, size) = self.screen_rect(bounds, x as f32, y as f32, 1.0, 1.0);
+                frame.stroke(&canvas::Path::rectangle(point, size), outline);
+            }
+        }
+
+        // One-pixel border just outside the canvas edge
+        frame.stroke(
+            &canvas::Path::rectangle(
+                Point::new(canvas_point.x - 0.5, canvas_point.y - 0.5),
+                Size::new(canvas_size.width + 1.0, canvas_size.height + 1.0),
+            ),
+            canvas::Stroke::default()
+                .with_width(1.0)
+                .with_color(crate::utils::rgba8_to_color(self.state.canvas_border_color)),
+        );
+
         // Draw grid if enabled
         if self.state.grid_visible && zoom >= 4.0 {
             let grid_color = Color::from_rgba(0.5, 0.5, 0.5, 0.3);
             for x in 0..=self.state.canvas_width {
-                let line_x = offset_x + x as f32 * pixel_size;
                 frame.stroke(
-                    &canvas::Path::line(
-                        Point::new(line_x, offset_y),
-                        Point::new(line_x, offset_y + canvas_pixel_height),
-                    ),
+                    &line(x as f32, 0.0, x as f32, height),
                     canvas::Stroke::default()
                         .with_width(1.0)
                         .with_color(grid_color),
                 );
             }
             for y in 0..=self.state.canvas_height {
-                let line_y = offset_y + y as f32 * pixel_size;
                 frame.stroke(
-                    &canvas::Path::line(
-                        Point::new(offset_x, line_y),
-                        Point::new(offset_x + canvas_pixel_width, line_y),
-                    ),
+                    &line(0.0, y as f32, width, y as f32),
                     canvas::Stroke::default()
                         .with_width(1.0)
                         .with_color(grid_color),
@@ -136,25 +544,368 @@ impl canvas::Program<Message> for CanvasProgram {
             }
         }
 
+        // Lines every `step_x`/`step_y` pixels, heavier than the pixel grid
+        let block_lines =
+            |frame: &mut canvas::Frame, step_x: u32, step_y: u32, stroke: canvas::Stroke| {
+                for x in (0..=self.state.canvas_width).step_by(step_x as usize) {
+                    frame.stroke(&line(x as f32, 0.0, x as f32, height), stroke);
+                }
+                for y in (0..=self.state.canvas_height).step_by(step_y as usize) {
+                    frame.stroke(&line(0.0, y as f32, width, y as f32), stroke);
+                }
+            };
+
+        // Outline pixel snap blocks so block-aligned strokes are predictable
+        let snap = self.state.tool_options.pixel_snap;
+        if self.state.grid_visible && snap > 1 {
+            block_lines(
+                &mut frame,
+                snap,
+                snap,
+                canvas::Stroke::default()
+                    .with_width(1.0)
+                    .with_color(Color::from_rgba(0.2, 0.4, 0.9, 0.5)),
+            );
+        }
+
+        // Tile boundaries, and the tile being Ctrl-dragged
+        if self.state.tile_mode {
+            block_lines(
+                &mut frame,
+                self.state.tile_width.max(1),
+                self.state.tile_height.max(1),
+                canvas::Stroke::default()
+                    .with_width(2.0)
+                    .with_color(Color::from_rgba(0.1, 0.1, 0.1, 0.8)),
+            );
+            if let Some((x, y)) = self.state.dragging_tile {
+                let (start_x, start_y, end_x, end_y) = self.state.tile_bounds(x, y);
+                let (point, size) = self.screen_rect(
+                    bounds,
+                    start_x as f32,
+                    start_y as f32,
+                    (end_x - start_x) as f32,
+                    (end_y - start_y) as f32,
+                );
+                frame.fill_rectangle(
+                    point,
+                    size,
+                    canvas::Fill::from(Color::from_rgba(1.0, 0.5, 0.0, 0.25)),
+                );
+            }
+        }
+
+        // Dashed mirror axes so mirrored drawing is never a surprise
+        if self.state.mirror_horizontal || self.state.mirror_vertical {
+            let stroke = canvas::Stroke {
+                line_dash: canvas::LineDash {
+                    segments: &[6.0, 4.0],
+                    offset: 0,
+                },
+                ..canvas::Stroke::default()
+                    .with_width(2.0)
+                    .with_color(crate::utils::rgba8_to_color(self.state.guide_color))
+            };
+            if self.state.mirror_horizontal {
+                frame.stroke(&line(width / 2.0, 0.0, width / 2.0, height), stroke);
+            }
+            if self.state.mirror_vertical {
+                frame.stroke(&line(0.0, height / 2.0, width, height / 2.0), stroke);
+            }
+        }
+
+        // Seam cross left in the middle by the make-tileable offset
+        if self.state.seams_exposed {
+            let stroke = canvas::Stroke {
+                line_dash: canvas::LineDash {
+                    segments: &[4.0, 4.0],
+                    offset: 0,
+                },
+                ..canvas::Stroke::default()
+                    .with_width(2.0)
+                    .with_color(Color::from_rgb(1.0, 0.5, 0.0))
+            };
+            let seam_x = (self.state.canvas_width / 2) as f32;
+            let seam_y = (self.state.canvas_height / 2) as f32;
+            frame.stroke(&line(seam_x, 0.0, seam_x, height), stroke);
+            frame.stroke(&line(0.0, seam_y, width, seam_y), stroke);
+        }
+
+        // Draw 9-slice guides across the whole canvas
+        if let Some(slice) = self.state.nine_slice {
+            let guide_color = Color::from_rgb(1.0, 0.0, 1.0);
+            for edge in SliceEdge::ALL {
+                let position =
+                    slice.guide_position(edge, self.state.canvas_width, self.state.canvas_height)
+                        as f32;
+                let path = if edge.is_vertical() {
+                    line(position, 0.0, position, height)
+                } else {
+                    line(0.0, position, width, position)
+                };
+                let stroke_width = if self.state.dragging_slice_edge == Some(edge) {
+                    3.0
+                } else {
+                    2.0
+                };
+                frame.stroke(
+                    &path,
+                    canvas::Stroke::default()
+                        .with_width(stroke_width)
+                        .with_color(guide_color),
+                );
+            }
+        }
+
+        // Safe-area overlay: the target box solid, the safe area dashed
+        if let Some(preset) = self.state.safe_area.as_ref().and_then(|name| {
+            self.state
+                .safe_area_presets()
+                .into_iter()
+                .find(|preset| &preset.name == name)
+        }) {
+            let (outer, inner) = preset.rects(self.state.canvas_width, self.state.canvas_height);
+            let color = crate::utils::rgba8_to_color(self.state.guide_color);
+            let rectangle = |[x, y, w, h]: [f32; 4]| {
+                let (point, size) = self.screen_rect(bounds, x, y, w, h);
+                canvas::Path::rectangle(point, size)
+            };
+            if preset.aspect.is_some() {
+                frame.stroke(
+                    &rectangle(outer),
+                    canvas::Stroke::default().with_width(2.0).with_color(color),
+                );
+            }
+            if inner != outer {
+                frame.stroke(
+                    &rectangle(inner),
+                    canvas::Stroke {
+                        line_dash: canvas::LineDash {
+                            segments: &[6.0, 4.0],
+                            offset: 0,
+                        },
+                        ..canvas::Stroke::default().with_width(2.0).with_color(color)
+                    },
+                );
+            }
+        }
+
+        // Outline the floating paste
+        if let Some(paste) = &self.state.floating_paste {
+            let (point, size) = self.screen_rect(
+                bounds,
+                paste.x as f32,
+                paste.y as f32,
+                paste.width as f32,
+                paste.height as f32,
+            );
+            frame.stroke(
+                &canvas::Path::rectangle(point, size),
+                canvas::Stroke::default()
+                    .with_width(1.0)
+                    .with_color(Color::from_rgb(1.0, 0.5, 0.0)),
+            );
+        }
+
+        // Stamp preview of a pending paste at half opacity under the cursor
+        if let Some(paste) = &self.state.pending_paste
+            && let Some((x, y)) = cursor
+                .position_in(bounds)
+                .and_then(|position| self.canvas_to_pixel(position, bounds))
+            && let Some((origin_x, origin_y)) = crate::tools::pending_paste_origin(self.state, x, y)
+        {
+            for py in 0..paste.height {
+                for px in 0..paste.width {
+                    let index = ((py * paste.width + px) * 4) as usize;
+                    let Some(rgba) = paste.pixels.get(index..index + 4) else {
+                        continue;
+                    };
+                    let Some((canvas_x, canvas_y)) = crate::tools::resolve_position(
+                        origin_x + px as i32,
+                        origin_y + py as i32,
+                        self.state.canvas_width,
+                        self.state.canvas_height,
+                        self.state.wrap_edges,
+                    ) else {
+                        continue;
+                    };
+                    if rgba[3] == 0 {
+                        continue;
+                    }
+                    let mut color =
+                        crate::utils::rgba8_to_color([rgba[0], rgba[1], rgba[2], rgba[3]]);
+                    color.a *= 0.5;
+                    let (point, size) =
+                        self.screen_rect(bounds, canvas_x as f32, canvas_y as f32, 1.0, 1.0);
+                    frame.fill_rectangle(point, size, canvas::Fill::from(color));
+                }
+            }
+            let (point, size) = self.screen_rect(
+                bounds,
+                origin_x as f32,
+                origin_y as f32,
+                paste.width as f32,
+                paste.height as f32,
+            );
+            frame.stroke(
+                &canvas::Path::rectangle(point, size),
+                canvas::Stroke::default()
+                    .with_width(1.0)
+                    .with_color(Color::from_rgba(1.0, 0.5, 0.0, 0.5)),
+            );
+        }
+
+        // Chip of the color just cycled to, beside the cursor
+        if self.state.recent_color_chip
+            && let Some(position) = cursor.position_in(bounds)
+        {
+            let point = Point::new(position.x + 12.0, position.y - 28.0);
+            let size = Size::new(16.0, 16.0);
+            frame.fill_rectangle(point, size, canvas::Fill::from(self.state.primary_color));
+            frame.stroke(
+                &canvas::Path::rectangle(point, size),
+                canvas::Stroke::default()
+                    .with_width(1.0)
+                    .with_color(Color::BLACK),
+            );
+        }
+
+        // While the wheel resizes the brush, outline its footprint and size
+        if self
+            .state
+            .wheel_brush_modifier
+            .is_held(self.state.modifiers)
+            && matches!(
+                self.state.current_tool,
+                crate::state::Tool::Pencil | crate::state::Tool::Eraser
+            )
+            && let Some(position) = cursor.position_in(bounds)
+            && let Some((x, y)) = self.canvas_to_pixel(position, bounds)
+        {
+            let size = self.state.brush_size();
+            let snap = self.state.tool_options.pixel_snap.max(1);
+            let radius = size / 2;
+            let (point, rect_size) = self.screen_rect(
+                bounds,
+                ((x / snap) as f32 - radius as f32) * snap as f32,
+                ((y / snap) as f32 - radius as f32) * snap as f32,
+                ((radius * 2 + 1) * snap) as f32,
+                ((radius * 2 + 1) * snap) as f32,
+            );
+            frame.stroke(
+                &canvas::Path::rectangle(point, rect_size),
+                canvas::Stroke::default()
+                    .with_width(1.0)
+                    .with_color(Color::from_rgb(1.0, 0.5, 0.0)),
+            );
+            frame.fill_text(canvas::Text {
+                content: format!("{}px", size),
+                position: Point::new(position.x + 12.0, position.y + 12.0),
+                color: Color::from_rgb(1.0, 0.5, 0.0),
+                size: iced::Pixels(14.0),
+                ..canvas::Text::default()
+            });
+        }
+
+        // Live preview of the text being typed, with a caret after it
+        if let Some(pending) = &self.state.pending_text {
+            let color = crate::utils::color_to_rgba8(self.state.primary_color);
+            let (text_width, text_height, pixels) = crate::font::rasterize_text(
+                &pending.text,
+                self.state.tool_options.text.font,
+                color,
+            );
+            for py in 0..text_height {
+                for px in 0..text_width {
+                    let index = ((py * text_width + px) * 4) as usize;
+                    if pixels[index + 3] == 0 {
+                        continue;
+                    }
+                    let (point, size) = self.screen_rect(
+                        bounds,
+                        (pending.x + px) as f32,
+                        (pending.y + py) as f32,
+                        1.0,
+                        1.0,
+                    );
+                    frame.fill_rectangle(point, size, canvas::Fill::from(self.state.primary_color));
+                }
+            }
+            let (_, glyph_height) = self.state.tool_options.text.font.glyph_size();
+            let caret_x = if text_width > 0 {
+                pending.x + text_width + 1
+            } else {
+                pending.x
+            };
+            let (point, size) = self.screen_rect(
+                bounds,
+                caret_x as f32,
+                pending.y as f32,
+                1.0,
+                glyph_height as f32,
+            );
+            frame.stroke(
+                &canvas::Path::rectangle(point, size),
+                canvas::Stroke::default()
+                    .with_width(1.0)
+                    .with_color(Color::from_rgb(1.0, 0.5, 0.0)),
+            );
+        }
+
+        // Draw a combined selection (or the one a Shift/Ctrl drag started
+        // from) pixel by pixel, outlined along its edges
+        let selection_color = Color::from_rgba(0.0, 0.5, 1.0, 1.0);
+        let overlay_color = Color::from_rgba(0.0, 0.5, 1.0, 0.2);
+        let mask = match &self.state.selection_base {
+            Some((_, base)) => Some(base),
+            None => self.state.selection_mask.as_ref(),
+        };
+        if let Some(mask) = mask {
+            for y in 0..mask.height() {
+                for x in 0..mask.width() {
+                    if mask.contains(x, y) {
+                        let (point, size) = self.screen_rect(bounds, x as f32, y as f32, 1.0, 1.0);
+                        frame.fill_rectangle(point, size, canvas::Fill::from(overlay_color));
+                    }
+                }
+            }
+            let outline = canvas::Path::new(|builder| {
+                for ((x1, y1), (x2, y2)) in mask.outline() {
+                    builder.move_to(self.canvas_to_screen(bounds, x1 as f32, y1 as f32));
+                    builder.line_to(self.canvas_to_screen(bounds, x2 as f32, y2 as f32));
+                }
+            });
+            frame.stroke(
+                &outline,
+                canvas::Stroke::default()
+                    .with_width(2.0)
+                    .with_color(selection_color),
+            );
+        }
+
         // Draw selection rectangle if active
-        if let Some(selection) = self.state.selection {
-            let sel_x = offset_x + selection.x * pixel_size;
-            let sel_y = offset_y + selection.y * pixel_size;
-            let sel_width = selection.width * pixel_size;
-            let sel_height = selection.height * pixel_size;
+        if let Some(selection) = self
+            .state
+            .selection
+            .filter(|_| self.state.selection_mask.is_none())
+        {
+            let (sel_point, sel_size) = self.screen_rect(
+                bounds,
+                selection.x,
+                selection.y,
+                selection.width,
+                selection.height,
+            );
 
             // Draw selection border
-            let sel_point = Point::new(sel_x, sel_y);
-            let sel_size = Size::new(sel_width, sel_height);
             frame.stroke(
                 &canvas::Path::rectangle(sel_point, sel_size),
                 canvas::Stroke::default()
                     .with_width(2.0)
-                    .with_color(Color::from_rgba(0.0, 0.5, 1.0, 1.0)),
+                    .with_color(selection_color),
             );
 
             // Draw selection overlay
-            let overlay_color = Color::from_rgba(0.0, 0.5, 1.0, 0.2);
             frame.fill_rectangle(sel_point, sel_size, canvas::Fill::from(overlay_color));
         }
 
@@ -171,24 +922,195 @@ impl canvas::Program<Message> for CanvasProgram {
         use canvas::Event;
         use mouse::Button;
 
+        // Shift/Ctrl change what selection and tile drags do
+        if let Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) = event {
+            let message =
+                (modifiers != self.state.modifiers).then_some(Message::ModifiersChanged(modifiers));
+            return (canvas::event::Status::Ignored, message);
+        }
+
+        // Typing goes to the pending text rather than to the shortcuts
+        if self.state.pending_text.is_some()
+            && let Event::Keyboard(keyboard::Event::KeyPressed {
+                key,
+                modifiers,
+                text,
+                ..
+            }) = &event
+        {
+            use keyboard::key::Named;
+            let message = match key.as_ref() {
+                keyboard::Key::Named(Named::Enter) => Some(Message::TextCommitted),
+                keyboard::Key::Named(Named::Escape) => Some(Message::TextCancelled),
+                keyboard::Key::Named(Named::Backspace) => Some(Message::TextBackspaced),
+                _ if modifiers.command() => None,
+                _ => text
+                    .as_ref()
+                    .filter(|text| !text.chars().any(char::is_control))
+                    .map(|text| Message::TextTyped(text.to_string())),
+            };
+            if message.is_some() {
+                return (canvas::event::Status::Captured, message);
+            }
+        }
+
+        // H, V and R flip or rotate the clipboard while a paste preview
+        // follows the cursor
+        if self.state.pending_paste.is_some()
+            && let Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) = &event
+            && modifiers.is_empty()
+            && let keyboard::Key::Character(c) = key.as_ref()
+        {
+            let transform = match c.to_ascii_lowercase().as_str() {
+                "h" => Some(BufferTransform::FlipHorizontal),
+                "v" => Some(BufferTransform::FlipVertical),
+                "r" => Some(BufferTransform::RotateClockwise),
+                _ => None,
+            };
+            if let Some(transform) = transform {
+                return (
+                    canvas::event::Status::Captured,
+                    Some(Message::ClipboardTransformed(transform)),
+                );
+            }
+        }
+
         let position = match cursor.position_in(bounds) {
             Some(pos) => pos,
-            None => return (canvas::event::Status::Ignored, None),
+            None => {
+                // Drop the fill highlight once the cursor leaves the canvas
+                let message = self
+                    .state
+                    .fill_preview
+                    .is_some()
+                    .then_some(Message::FillHoverEnded);
+                return (canvas::event::Status::Ignored, message);
+            }
         };
 
         match event {
             Event::Mouse(mouse_event) => match mouse_event {
                 mouse::Event::ButtonPressed(Button::Left) => {
-                    if let Some((x, y)) =
-                        self.canvas_to_pixel(position, bounds, self.state.zoom_level)
+                    // Guides take priority over drawing when grabbed
+                    if let Some(edge) = self.slice_guide_at(position, bounds) {
+                        return (
+                            canvas::event::Status::Captured,
+                            Some(Message::SliceGuideDragStarted(edge)),
+                        );
+                    }
+                    let pixel = self.canvas_to_pixel(position, bounds);
+                    // Alt+dragging the selection with the selection tool
+                    // clones its pixels
+                    if self.state.modifiers.alt()
+                        && !self.state.modifiers.shift()
+                        && self.state.current_tool == crate::state::Tool::Selection
+                        && self.state.floating_paste.is_none()
+                        && let Some((x, y)) = pixel
+                        && self.state.selection_bounds().is_some()
+                        && self.state.is_selected(x, y)
                     {
                         return (
                             canvas::event::Status::Captured,
-                            Some(Message::DrawingStarted { x, y }),
+                            Some(Message::SelectionCloneStarted { x, y }),
+                        );
+                    }
+                    // Alt+click picks a row to shift, Alt+Shift+click a column
+                    if self.state.modifiers.alt()
+                        && let Some((x, y)) = pixel
+                    {
+                        let (axis, index) = if self.state.modifiers.shift() {
+                            (ShiftAxis::Column, x)
+                        } else {
+                            (ShiftAxis::Row, y)
+                        };
+                        return (
+                            canvas::event::Status::Captured,
+                            Some(Message::LineShiftChanged {
+                                axis,
+                                index,
+                                offset: 0,
+                            }),
+                        );
+                    }
+                    if self.state.tile_mode
+                        && let Some((x, y)) = pixel
+                    {
+                        if self.state.picking_tile {
+                            return (
+                                canvas::event::Status::Captured,
+                                Some(Message::TileSelected { x, y }),
+                            );
+                        }
+                        // Ctrl+drag copies a tile, except where Ctrl already
+                        // subtracts from the selection or duplicates a layer
+                        if self.state.modifiers.command()
+                            && !matches!(
+                                self.state.current_tool,
+                                crate::state::Tool::Selection | crate::state::Tool::Move
+                            )
+                        {
+                            return (
+                                canvas::event::Status::Captured,
+                                Some(Message::TileDragStarted { x, y }),
+                            );
+                        }
+                    }
+                    // A pending paste is stamped wherever it is clicked down
+                    if self.state.pending_paste.is_some() {
+                        return (
+                            canvas::event::Status::Captured,
+                            pixel.map(|(x, y)| Message::PastePreviewPlaced { x, y }),
+                        );
+                    }
+                    // Clicking the floating paste drags it; clicking anywhere
+                    // else commits it
+                    if let Some(paste) = &self.state.floating_paste {
+                        let message = match pixel {
+                            Some((x, y)) if paste.contains(x, y) => {
+                                Message::FloatingPasteDragStarted { x, y }
+                            }
+                            _ => Message::FloatingPasteCommitted,
+                        };
+                        return (canvas::event::Status::Captured, Some(message));
+                    }
+                    if let Some((x, y)) = pixel {
+                        return (
+                            canvas::event::Status::Captured,
+                            // iced does not report pen force yet, so mouse
+                            // and pen input both arrive without pressure
+                            Some(Message::DrawingStarted {
+                                x,
+                                y,
+                                pressure: None,
+                            }),
                         );
                     }
                 }
                 mouse::Event::ButtonReleased(Button::Left) => {
+                    if self.state.dragging_tile.is_some() {
+                        return (
+                            canvas::event::Status::Captured,
+                            Some(Message::TileDropped(self.canvas_to_pixel(position, bounds))),
+                        );
+                    }
+                    if self.state.dragging_paste.is_some() {
+                        return (
+                            canvas::event::Status::Captured,
+                            Some(Message::FloatingPasteDragEnded),
+                        );
+                    }
+                    if self.state.clone_drag.is_some() {
+                        return (
+                            canvas::event::Status::Captured,
+                            Some(Message::SelectionCloneEnded),
+                        );
+                    }
+                    if self.state.dragging_slice_edge.is_some() {
+                        return (
+                            canvas::event::Status::Captured,
+                            Some(Message::SliceGuideDragEnded),
+                        );
+                    }
                     if self.state.current_tool == crate::state::Tool::Selection
                         && self.state.selection.is_some()
                         && self.state.is_selecting
@@ -201,13 +1123,33 @@ impl canvas::Program<Message> for CanvasProgram {
                     return (canvas::event::Status::Captured, Some(Message::DrawingEnded));
                 }
                 mouse::Event::CursorMoved { .. } => {
-                    if let Some((x, y)) =
-                        self.canvas_to_pixel(position, bounds, self.state.zoom_level)
-                    {
-                        if self.state.is_drawing {
+                    if let Some(edge) = self.state.dragging_slice_edge {
+                        let position =
+                            self.canvas_to_boundary(position, bounds, edge.is_vertical());
+                        return (
+                            canvas::event::Status::Captured,
+                            Some(Message::SliceGuideMoved { edge, position }),
+                        );
+                    }
+                    if let Some((x, y)) = self.canvas_to_pixel(position, bounds) {
+                        if self.state.dragging_paste.is_some() {
+                            return (
+                                canvas::event::Status::Captured,
+                                Some(Message::FloatingPasteMoved { x, y }),
+                            );
+                        } else if self.state.clone_drag.is_some() {
+                            return (
+                                canvas::event::Status::Captured,
+                                Some(Message::SelectionCloneMoved { x, y }),
+                            );
+                        } else if self.state.is_drawing {
                             return (
                                 canvas::event::Status::Captured,
-                                Some(Message::PixelDrawn { x, y }),
+                                Some(Message::PixelDrawn {
+                                    x,
+                                    y,
+                                    pressure: None,
+                                }),
                             );
                         } else if self.state.current_tool == crate::state::Tool::Selection
                             && self.state.selection.is_some()
@@ -221,26 +1163,24 @@ impl canvas::Program<Message> for CanvasProgram {
                                     y: y as f32,
                                 }),
                             );
+                        } else if self.state.current_tool == crate::state::Tool::Fill
+                            && self.state.tool_options.fill.preview
+                        {
+                            return (
+                                canvas::event::Status::Ignored,
+                                Some(Message::FillHovered { x, y }),
+                            );
                         }
                     }
                 }
                 mouse::Event::WheelScrolled { delta } => {
-                    use mouse::ScrollDelta;
-                    match delta {
-                        ScrollDelta::Lines { y, .. } | ScrollDelta::Pixels { y, .. } => {
-                            if y > 0.0 {
-                                return (canvas::event::Status::Captured, Some(Message::ZoomIn));
-                            } else if y < 0.0 {
-                                return (canvas::event::Status::Captured, Some(Message::ZoomOut));
-                            }
-                        }
+                    if let Some(message) = self.wheel_message(delta, position, bounds) {
+                        return (canvas::event::Status::Captured, Some(message));
                     }
                 }
                 mouse::Event::ButtonPressed(Button::Middle) => {
                     // Start panning with middle mouse button
-                    if let Some((x, y)) =
-                        self.canvas_to_pixel(position, bounds, self.state.zoom_level)
-                    {
+                    if let Some((x, y)) = self.canvas_to_pixel(position, bounds) {
                         return (
                             canvas::event::Status::Captured,
                             Some(Message::PanChanged {
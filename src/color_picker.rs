@@ -0,0 +1,175 @@
+use crate::message::Message;
+use crate::utils;
+use iced::mouse;
+use iced::widget::canvas;
+use iced::{Color, Point, Rectangle, Size};
+
+/// Cells per side of the saturation/value square; enough for a smooth
+/// looking gradient at popup size.
+const SQUARE_STEPS: u32 = 48;
+/// Cells along the hue strip.
+const HUE_STEPS: u32 = 90;
+
+/// Position of the cursor within `bounds` as fractions of its size, clamped
+/// to 0.0..=1.0, so drags that leave the gradient keep tracking its edge.
+fn fraction_in(bounds: Rectangle, cursor: mouse::Cursor) -> Option<(f32, f32)> {
+    let position = cursor.position()?;
+    Some((
+        ((position.x - bounds.x) / bounds.width).clamp(0.0, 1.0),
+        ((position.y - bounds.y) / bounds.height).clamp(0.0, 1.0),
+    ))
+}
+
+/// Shared press/drag/release handling. The state is whether a drag that
+/// started on this widget is in progress.
+fn drag_update(
+    dragging: &mut bool,
+    event: &canvas::Event,
+    bounds: Rectangle,
+    cursor: mouse::Cursor,
+) -> Option<(f32, f32)> {
+    match event {
+        canvas::Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left))
+            if cursor.is_over(bounds) =>
+        {
+            *dragging = true;
+            fraction_in(bounds, cursor)
+        }
+        canvas::Event::Mouse(mouse::Event::CursorMoved { .. }) if *dragging => {
+            fraction_in(bounds, cursor)
+        }
+        canvas::Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+            *dragging = false;
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Saturation (left to right) against value (top to bottom) for one hue.
+pub struct SaturationValueSquare {
+    pub hue: f32,
+    pub saturation: f32,
+    pub value: f32,
+}
+
+impl canvas::Program<Message> for SaturationValueSquare {
+    type State = bool;
+
+    fn update(
+        &self,
+        dragging: &mut bool,
+        event: canvas::Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> (canvas::event::Status, Option<Message>) {
+        match drag_update(dragging, &event, bounds, cursor) {
+            Some((x, y)) => (
+                canvas::event::Status::Captured,
+                Some(Message::ColorPickerSaturationValueChanged {
+                    saturation: x,
+                    value: 1.0 - y,
+                }),
+            ),
+            None => (canvas::event::Status::Ignored, None),
+        }
+    }
+
+    fn draw(
+        &self,
+        _dragging: &bool,
+        renderer: &iced::Renderer,
+        _theme: &iced::Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<canvas::Geometry> {
+        let mut frame = canvas::Frame::new(renderer, bounds.size());
+        let cell = Size::new(
+            bounds.width / SQUARE_STEPS as f32,
+            bounds.height / SQUARE_STEPS as f32,
+        );
+        for row in 0..SQUARE_STEPS {
+            for column in 0..SQUARE_STEPS {
+                let saturation = column as f32 / (SQUARE_STEPS - 1) as f32;
+                let value = 1.0 - row as f32 / (SQUARE_STEPS - 1) as f32;
+                let rgb = utils::hsv_to_rgb(self.hue, saturation, value);
+                frame.fill_rectangle(
+                    Point::new(column as f32 * cell.width, row as f32 * cell.height),
+                    // Overlap neighbors slightly so no seams show between cells
+                    Size::new(cell.width + 0.5, cell.height + 0.5),
+                    Color::from_rgb8(rgb[0], rgb[1], rgb[2]),
+                );
+            }
+        }
+
+        // Ring around the current color, dark on light areas and vice versa
+        let center = Point::new(
+            self.saturation * bounds.width,
+            (1.0 - self.value) * bounds.height,
+        );
+        let ring = if self.value > 0.5 && self.saturation < 0.5 {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        };
+        frame.stroke(
+            &canvas::Path::circle(center, 5.0),
+            canvas::Stroke::default().with_width(2.0).with_color(ring),
+        );
+        vec![frame.into_geometry()]
+    }
+}
+
+/// Horizontal strip of fully saturated hues, 0° to 360°.
+pub struct HueStrip {
+    pub hue: f32,
+}
+
+impl canvas::Program<Message> for HueStrip {
+    type State = bool;
+
+    fn update(
+        &self,
+        dragging: &mut bool,
+        event: canvas::Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> (canvas::event::Status, Option<Message>) {
+        match drag_update(dragging, &event, bounds, cursor) {
+            Some((x, _)) => (
+                canvas::event::Status::Captured,
+                Some(Message::ColorPickerHueChanged(x * 360.0)),
+            ),
+            None => (canvas::event::Status::Ignored, None),
+        }
+    }
+
+    fn draw(
+        &self,
+        _dragging: &bool,
+        renderer: &iced::Renderer,
+        _theme: &iced::Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<canvas::Geometry> {
+        let mut frame = canvas::Frame::new(renderer, bounds.size());
+        let width = bounds.width / HUE_STEPS as f32;
+        for step in 0..HUE_STEPS {
+            let rgb = utils::hsv_to_rgb(step as f32 / HUE_STEPS as f32 * 360.0, 1.0, 1.0);
+            frame.fill_rectangle(
+                Point::new(step as f32 * width, 0.0),
+                Size::new(width + 0.5, bounds.height),
+                Color::from_rgb8(rgb[0], rgb[1], rgb[2]),
+            );
+        }
+
+        let x = self.hue / 360.0 * bounds.width;
+        frame.stroke(
+            &canvas::Path::rectangle(Point::new(x - 2.0, 0.0), Size::new(4.0, bounds.height)),
+            canvas::Stroke::default()
+                .with_width(2.0)
+                .with_color(Color::WHITE),
+        );
+        vec![frame.into_geometry()]
+    }
+}
@@ -1,57 +1,69 @@
-use crate::message::ExportFormat;
-use crate::state::EditorState;
+use crate::message::{ExportFormat, ExportUpscale};
+use crate::project::Project;
+use crate::state::{
+    EditorState, ExportProfile, ExportScope, ExportSettings, Layer, NineSlice, PrintOptions,
+    blend_rgba8,
+};
 use std::path::Path;
 
-pub fn save_image(state: &EditorState, path: &Path, format: ExportFormat) -> Result<(), String> {
-    // Composite all visible layers into a single image
-    let width = state.canvas_width;
-    let height = state.canvas_height;
-    let mut rgba_data = vec![0u8; (width * height * 4) as usize];
+/// Private ancillary PNG chunk holding the layered project. The lowercase
+/// first letter marks it ancillary and the lowercase second letter private,
+/// so other viewers skip it.
+const PROJECT_CHUNK: [u8; 4] = *b"pxRs";
 
-    // Start with transparent background
-    for pixel in rgba_data.chunks_exact_mut(4) {
-        pixel[0] = 0;
-        pixel[1] = 0;
-        pixel[2] = 0;
-        pixel[3] = 0;
-    }
+/// Largest project chunk accepted when writing or reading a PNG.
+const MAX_PROJECT_CHUNK_SIZE: usize = 64 * 1024 * 1024;
 
-    // Composite layers from bottom to top
-    for layer in &state.layers {
-        if !layer.visible {
-            continue;
+/// Largest width/height a single ICO image can have.
+const ICO_MAX_SIZE: u32 = 256;
+
+/// Standard icon sizes embedded when multi-size ICO export is enabled.
+const ICO_STANDARD_SIZES: [u32; 3] = [16, 32, 48];
+
+/// Export `state` to `path` as `settings` describe, writing the whole canvas
+/// or only the selection. Returns a warning when the image was written but
+/// without its embedded layers.
+pub fn save_export(
+    state: &EditorState,
+    path: &Path,
+    settings: &ExportSettings,
+) -> Result<Option<String>, String> {
+    match settings.scope {
+        ExportScope::Canvas if settings.ignore_solo && state.solo_layer.is_some() => {
+            let mut unsoloed = state.clone();
+            unsoloed.solo_layer = None;
+            save_image(&unsoloed, path, settings)
         }
+        ExportScope::Canvas => save_image(state, path, settings),
+        ExportScope::Selection => save_selection(state, path, settings),
+    }
+}
 
-        // Use get_pixel_buffer for efficient pixel access
-        let layer_pixels = layer.get_pixel_buffer();
-        for y in 0..height {
-            for x in 0..width {
-                let index = ((y * width + x) * 4) as usize;
-                if index + 3 >= layer_pixels.len() {
-                    continue;
-                }
+pub fn save_image(
+    state: &EditorState,
+    path: &Path,
+    settings: &ExportSettings,
+) -> Result<Option<String>, String> {
+    let format = settings.format;
+    let mut width = state.canvas_width;
+    let mut height = state.canvas_height;
+    let mut rgba_data = composite_layers(state);
+    if settings.matte_enabled {
+        apply_matte(&mut rgba_data, settings.matte_color);
+    }
 
-                let r = layer_pixels[index];
-                let g = layer_pixels[index + 1];
-                let b = layer_pixels[index + 2];
-                let a = layer_pixels[index + 3];
-
-                let out_index = ((y * width + x) * 4) as usize;
-                if out_index + 3 < rgba_data.len() {
-                    // Alpha blend
-                    let alpha = (a as f32 / 255.0) * layer.opacity;
-                    let inv_alpha = 1.0 - alpha;
-
-                    rgba_data[out_index] =
-                        (r as f32 * alpha + rgba_data[out_index] as f32 * inv_alpha) as u8;
-                    rgba_data[out_index + 1] =
-                        (g as f32 * alpha + rgba_data[out_index + 1] as f32 * inv_alpha) as u8;
-                    rgba_data[out_index + 2] =
-                        (b as f32 * alpha + rgba_data[out_index + 2] as f32 * inv_alpha) as u8;
-                    rgba_data[out_index + 3] = (rgba_data[out_index + 3] as f32
-                        + a as f32 * layer.opacity)
-                        .min(255.0) as u8;
-                }
+    // Icons have fixed sizes and SVG scales freely, so only raster formats
+    // are upscaled
+    if !matches!(format, ExportFormat::Ico | ExportFormat::Svg) {
+        match settings.upscale {
+            ExportUpscale::None => {}
+            ExportUpscale::Scale2x => {
+                rgba_data = crate::scaling::scale2x(&rgba_data, width, height);
+                (width, height) = (width * 2, height * 2);
+            }
+            ExportUpscale::Scale4x => {
+                rgba_data = crate::scaling::scale4x(&rgba_data, width, height);
+                (width, height) = (width * 4, height * 4);
             }
         }
     }
@@ -60,10 +72,43 @@ pub fn save_image(state: &EditorState, path: &Path, format: ExportFormat) -> Res
     let img = image::RgbaImage::from_raw(width, height, rgba_data)
         .ok_or("Failed to create image from pixel data")?;
 
+    let mut warning = None;
     match format {
         ExportFormat::Png => {
-            img.save(path)
-                .map_err(|e| format!("Failed to save PNG: {}", e))?;
+            // Embed the layered document so pxrs can restore it on open. One
+            // too big to embed still leaves a usable flat image
+            let project = Project::from_state(state)
+                .encode()
+                .and_then(|project| {
+                    if project.len() > MAX_PROJECT_CHUNK_SIZE {
+                        Err("Project data is too large to embed in a PNG".to_string())
+                    } else {
+                        Ok(project)
+                    }
+                })
+                .inspect_err(|e| {
+                    eprintln!("Saving {} without layers: {}", path.display(), e);
+                    warning = Some(format!("layers weren't embedded: {}", e));
+                })
+                .ok();
+            let project = project.as_deref();
+            let print = settings.print;
+            if print.scale > 1 || print.grid || print.index_numbers {
+                let palette: Vec<[u8; 4]> = state
+                    .used_colors
+                    .iter()
+                    .map(|used| crate::utils::color_to_rgba8(used.color))
+                    .collect();
+                let (out_width, out_height, pixels) =
+                    render_printable(img.as_raw(), width, height, &print, &palette);
+                save_png_with_project(path, out_width, out_height, &pixels, None, project)?;
+            } else if !settings.matte_enabled
+                && let Some((palette, indices)) = indexed_image(state, settings.upscale)
+            {
+                save_png_with_project(path, width, height, indices, Some(palette), project)?;
+            } else {
+                save_png_with_project(path, width, height, img.as_raw(), None, project)?;
+            }
         }
         ExportFormat::Gif => {
             // GIF doesn't support RGBA directly, convert to RGB
@@ -76,9 +121,742 @@ pub fn save_image(state: &EditorState, path: &Path, format: ExportFormat) -> Res
             img.save(path)
                 .map_err(|e| format!("Failed to save BMP: {}", e))?;
         }
+        ExportFormat::Ico => {
+            save_ico(&img, path, settings.ico_multi_size)?;
+        }
+        ExportFormat::WebP => {
+            // Lossless so every pixel color survives exactly
+            let file =
+                std::fs::File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+            image::codecs::webp::WebPEncoder::new_lossless(std::io::BufWriter::new(file))
+                .encode(img.as_raw(), width, height, image::ExtendedColorType::Rgba8)
+                .map_err(|e| format!("Failed to save WebP: {}", e))?;
+        }
+        ExportFormat::Svg => {
+            let svg = rgba_to_svg(img.as_raw(), width, height, settings.svg_unit_size);
+            std::fs::write(path, svg).map_err(|e| format!("Failed to save SVG: {}", e))?;
+        }
+        ExportFormat::Tga => {
+            // 32-bit TGA with alpha
+            img.save_with_format(path, image::ImageFormat::Tga)
+                .map_err(|e| format!("Failed to save TGA: {}", e))?;
+        }
+        ExportFormat::Rgb565 | ExportFormat::Mono => {
+            let options = settings.raw;
+            let (data, description) = if format == ExportFormat::Rgb565 {
+                (
+                    rgba_to_rgb565(img.as_raw(), options.big_endian),
+                    if options.big_endian {
+                        "RGB565 big-endian"
+                    } else {
+                        "RGB565 little-endian"
+                    },
+                )
+            } else {
+                (
+                    rgba_to_mono(img.as_raw(), width, height, options.threshold),
+                    "1 bit per pixel, MSB first, rows padded to whole bytes",
+                )
+            };
+            let contents = if options.c_header {
+                let name = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                c_header(&name, width, height, description, &data).into_bytes()
+            } else {
+                data
+            };
+            std::fs::write(path, contents)
+                .map_err(|e| format!("Failed to save {}: {}", format, e))?;
+        }
+    }
+
+    if let Some(slice) = state.nine_slice
+        && state.nine_slice_sidecar
+    {
+        // Insets follow the exported pixels when the image was upscaled
+        let scale = width / state.canvas_width.max(1);
+        save_nine_slice_sidecar(path, width, height, slice, scale)?;
+    }
+
+    Ok(warning)
+}
+
+/// Pack RGBA pixels as 16-bit RGB565 (5 bits red, 6 green, 5 blue), two
+/// bytes per pixel in the chosen byte order. Alpha is dropped, so transparent
+/// pixels export as whatever color they hold; use a matte to choose one.
+pub fn rgba_to_rgb565(rgba: &[u8], big_endian: bool) -> Vec<u8> {
+    let mut result = Vec::with_capacity(rgba.len() / 2);
+    for pixel in rgba.chunks_exact(4) {
+        let value =
+            ((pixel[0] as u16 >> 3) << 11) | ((pixel[1] as u16 >> 2) << 5) | (pixel[2] as u16 >> 3);
+        if big_endian {
+            result.extend_from_slice(&value.to_be_bytes());
+        } else {
+            result.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    result
+}
+
+/// Pack RGBA pixels one bit each, most significant bit first, with every row
+/// padded to a whole byte. A bit is set where the pixel is at least half
+/// opaque and its luminance reaches `threshold`.
+pub fn rgba_to_mono(rgba: &[u8], width: u32, height: u32, threshold: u8) -> Vec<u8> {
+    let row_bytes = width.div_ceil(8) as usize;
+    let mut result = vec![0u8; row_bytes * height as usize];
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let index = (y * width as usize + x) * 4;
+            let Some(&[r, g, b, a]) = rgba.get(index..index + 4) else {
+                continue;
+            };
+            // Rec. 601 luma, in integer math so results are exact
+            let luma = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+            if a >= 128 && luma >= threshold as u32 {
+                result[y * row_bytes + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+    result
+}
+
+/// Wrap exported bytes in a C header declaring a `uint8_t` array named after
+/// the file, with width and height defines.
+fn c_header(name: &str, width: u32, height: u32, description: &str, data: &[u8]) -> String {
+    // Keep the name a valid C identifier
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !identifier.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        identifier.insert(0, '_');
+    }
+    let upper = identifier.to_ascii_uppercase();
+
+    let mut header = format!(
+        "// {}x{}, {}\n#include <stdint.h>\n\n#define {}_WIDTH {}\n#define {}_HEIGHT {}\n\nconst uint8_t {}[{}] = {{\n",
+        width,
+        height,
+        description,
+        upper,
+        width,
+        upper,
+        height,
+        identifier,
+        data.len()
+    );
+    for line in data.chunks(12) {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+        header.push_str(&format!("    {},\n", bytes.join(", ")));
+    }
+    header.push_str("};\n");
+    header
+}
+
+/// Write 9-slice metadata next to an exported image, replacing its extension
+/// with `.json` (`sprite.png` -> `sprite.json`). The schema is:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "image": "sprite.png",
+///   "width": 32,
+///   "height": 32,
+///   "left": 4,
+///   "right": 4,
+///   "top": 6,
+///   "bottom": 6
+/// }
+/// ```
+///
+/// `width`/`height` are the exported image size and the insets are pixel
+/// distances from each edge, matching Godot's `patch_margin_*` and Unity's
+/// sprite border (left, bottom, right, top).
+fn save_nine_slice_sidecar(
+    path: &Path,
+    width: u32,
+    height: u32,
+    slice: NineSlice,
+    scale: u32,
+) -> Result<(), String> {
+    let image = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let metadata = serde_json::json!({
+        "version": 1,
+        "image": image,
+        "width": width,
+        "height": height,
+        "left": slice.left * scale,
+        "right": slice.right * scale,
+        "top": slice.top * scale,
+        "bottom": slice.bottom * scale,
+    });
+    let contents = serde_json::to_string_pretty(&metadata)
+        .map_err(|e| format!("Failed to serialize 9-slice metadata: {}", e))?;
+    std::fs::write(path.with_extension("json"), contents)
+        .map_err(|e| format!("Failed to write 9-slice metadata: {}", e))
+}
+
+/// Write a Windows icon. With `multi_size`, the 16, 32 and 48 pixel standard
+/// sizes are generated by nearest-neighbor scaling and embedded alongside the
+/// canvas' own size.
+fn save_ico(img: &image::RgbaImage, path: &Path, multi_size: bool) -> Result<(), String> {
+    use image::codecs::ico::{IcoEncoder, IcoFrame};
+
+    let (width, height) = img.dimensions();
+    if width > ICO_MAX_SIZE || height > ICO_MAX_SIZE {
+        return Err(format!(
+            "ICO images can be at most {}x{} pixels (canvas is {}x{})",
+            ICO_MAX_SIZE, ICO_MAX_SIZE, width, height
+        ));
+    }
+
+    let mut sizes = vec![(width, height)];
+    if multi_size {
+        for size in ICO_STANDARD_SIZES {
+            if !sizes.contains(&(size, size)) {
+                sizes.push((size, size));
+            }
+        }
+        sizes.sort();
+    }
+
+    let mut frames = Vec::new();
+    for (frame_width, frame_height) in sizes {
+        let pixels = if (frame_width, frame_height) == (width, height) {
+            img.as_raw().clone()
+        } else {
+            crate::transform::scale_nearest(img.as_raw(), width, height, frame_width, frame_height)
+        };
+        let frame = IcoFrame::as_png(
+            &pixels,
+            frame_width,
+            frame_height,
+            image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| format!("Failed to encode ICO frame: {}", e))?;
+        frames.push(frame);
+    }
+
+    let file = std::fs::File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+    IcoEncoder::new(std::io::BufWriter::new(file))
+        .encode_images(&frames)
+        .map_err(|e| format!("Failed to save ICO: {}", e))
+}
+
+/// Build an SVG document with one `<rect>` per horizontal run of identical,
+/// non-transparent pixels. Each pixel is `unit` user units wide; colors are
+/// written as hex with `fill-opacity` for partial alpha.
+pub fn rgba_to_svg(pixels: &[u8], width: u32, height: u32, unit: u32) -> String {
+    use std::fmt::Write;
+
+    let unit = unit.max(1);
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}" shape-rendering="crispEdges">"#,
+        width * unit,
+        height * unit,
+        width * unit,
+        height * unit
+    );
+
+    for y in 0..height {
+        let mut x = 0;
+        while x < width {
+            let index = ((y * width + x) * 4) as usize;
+            let rgba = &pixels[index..index + 4];
+            // Extend the run while the next pixel has the exact same color
+            let mut run = 1;
+            while x + run < width {
+                let next = ((y * width + x + run) * 4) as usize;
+                if &pixels[next..next + 4] != rgba {
+                    break;
+                }
+                run += 1;
+            }
+
+            if rgba[3] > 0 {
+                let _ = write!(
+                    svg,
+                    r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#{:02x}{:02x}{:02x}""##,
+                    x * unit,
+                    y * unit,
+                    run * unit,
+                    unit,
+                    rgba[0],
+                    rgba[1],
+                    rgba[2]
+                );
+                if rgba[3] < 255 {
+                    let _ = write!(svg, r#" fill-opacity="{:.3}""#, rgba[3] as f32 / 255.0);
+                }
+                svg.push_str("/>\n");
+            }
+            x += run;
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// 3x5 bitmap glyphs for the digits 0-9, one row per entry, using the low
+/// three bits (most significant bit is the leftmost column).
+const DIGITS_3X5: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+const MINOR_GRID_COLOR: [u8; 4] = [160, 160, 160, 255];
+const MAJOR_GRID_COLOR: [u8; 4] = [0, 0, 0, 255];
+
+/// Upscale an image for printing/cross-stitch charts. Every source pixel
+/// becomes a `scale` x `scale` cell; with `grid` a 1px line is burned in at
+/// each cell edge (darker every 10 cells) and the image grows by one pixel
+/// to close the right/bottom border. With `index_numbers`, cells whose color
+/// is in `palette` get their 1-based palette index drawn in tiny digits when
+/// they fit. Returns `(width, height, pixels)`.
+pub fn render_printable(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    options: &PrintOptions,
+    palette: &[[u8; 4]],
+) -> (u32, u32, Vec<u8>) {
+    let scale = options.scale.max(1);
+    let border = u32::from(options.grid);
+    let out_width = width * scale + border;
+    let out_height = height * scale + border;
+    let scaled =
+        crate::transform::scale_nearest(pixels, width, height, width * scale, height * scale);
+
+    // Copy the upscaled rows into the output, which is one pixel wider and
+    // taller when the grid needs a closing border
+    let mut out = vec![0u8; (out_width * out_height * 4) as usize];
+    let row_bytes = (width * scale * 4) as usize;
+    for y in 0..(height * scale) as usize {
+        let src = y * row_bytes;
+        let dst = y * out_width as usize * 4;
+        out[dst..dst + row_bytes].copy_from_slice(&scaled[src..src + row_bytes]);
+    }
+
+    let put = |out: &mut [u8], x: u32, y: u32, rgba: [u8; 4]| {
+        if x < out_width && y < out_height {
+            let index = ((y * out_width + x) * 4) as usize;
+            out[index..index + 4].copy_from_slice(&rgba);
+        }
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = ((y * width + x) * 4) as usize;
+            let rgba = [
+                pixels[index],
+                pixels[index + 1],
+                pixels[index + 2],
+                pixels[index + 3],
+            ];
+
+            if options.index_numbers
+                && rgba[3] > 0
+                && let Some(palette_index) = palette.iter().position(|c| *c == rgba)
+            {
+                let label = (palette_index + 1).to_string();
+                let text_width = label.len() as u32 * 4 - 1;
+                // Leave room for the grid line and a 1px margin
+                if text_width + 2 + border <= scale && 5 + 2 + border <= scale {
+                    let ink = if crate::filters::luminance(rgba) > 128 {
+                        [0, 0, 0, 255]
+                    } else {
+                        [255, 255, 255, 255]
+                    };
+                    let origin_x = x * scale + border + (scale - border - text_width) / 2;
+                    let origin_y = y * scale + border + (scale - border - 5) / 2;
+                    for (i, digit) in label.bytes().enumerate() {
+                        let glyph = DIGITS_3X5[(digit - b'0') as usize];
+                        for (row, bits) in glyph.iter().enumerate() {
+                            for col in 0..3 {
+                                if bits & (0b100 >> col) != 0 {
+                                    put(
+                                        &mut out,
+                                        origin_x + i as u32 * 4 + col,
+                                        origin_y + row as u32,
+                                        ink,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if options.grid {
+        // Minor lines first so major lines win where they cross
+        for major in [false, true] {
+            let color = if major {
+                MAJOR_GRID_COLOR
+            } else {
+                MINOR_GRID_COLOR
+            };
+            for cell in (0..=width).filter(|cell| (cell % 10 == 0) == major) {
+                for y in 0..out_height {
+                    put(&mut out, cell * scale, y, color);
+                }
+            }
+            for cell in (0..=height).filter(|cell| (cell % 10 == 0) == major) {
+                for x in 0..out_width {
+                    put(&mut out, x, cell * scale, color);
+                }
+            }
+        }
+    }
+
+    (out_width, out_height, out)
+}
+
+/// Side of one canvas pixel's cell in a pattern sheet.
+const PATTERN_CELL_SIZE: u32 = 16;
+
+/// White space around the chart and between legend entries.
+const PATTERN_MARGIN: u32 = 6;
+
+/// Write a bead or cross-stitch pattern sheet of the visible layers to a
+/// PNG: every pixel a large cell in its color with its legend number,
+/// separated by a grid (darker every 10 cells), row and column numbers
+/// along the edges, and a legend of the colors with their counts, most used
+/// first. Transparent pixels are left as empty cells. Returns the number of
+/// colors in the legend.
+pub fn export_pattern_sheet(state: &EditorState, path: &Path) -> Result<usize, String> {
+    let (width, height) = (state.canvas_width, state.canvas_height);
+    let pixels = composite_layers(state);
+    let colors = crate::palette::count_colors([pixels.as_slice()]);
+    let palette: Vec<[u8; 4]> = colors.iter().map(|&(rgba, _)| rgba).collect();
+    let options = PrintOptions {
+        scale: PATTERN_CELL_SIZE,
+        grid: true,
+        index_numbers: true,
+    };
+    let (chart_width, chart_height, chart) =
+        render_printable(&pixels, width, height, &options, &palette);
+
+    let ink = [0, 0, 0, 255];
+    let font = crate::font::BitmapFont::Medium;
+    let (_, glyph_height) = font.glyph_size();
+    let label = |text: &str| crate::font::rasterize_text(text, font, ink);
+    let row_label_width = label(&height.to_string()).0;
+    let chart_x = PATTERN_MARGIN * 2 + row_label_width;
+    let chart_y = PATTERN_MARGIN * 2 + glyph_height;
+
+    // Legend entries: a swatch with the cell number, then hex and count
+    let entries: Vec<(u32, u32, Vec<u8>)> = colors
+        .iter()
+        .enumerate()
+        .map(|(index, &(rgba, count))| {
+            label(&format!(
+                "{}: {} ({})",
+                index + 1,
+                &crate::utils::hex_color(rgba)[..7],
+                count
+            ))
+        })
+        .collect();
+    let entry_width = PATTERN_CELL_SIZE
+        + PATTERN_MARGIN
+        + entries.iter().map(|entry| entry.0).max().unwrap_or(0)
+        + PATTERN_MARGIN * 2;
+    let entry_height = PATTERN_CELL_SIZE + PATTERN_MARGIN;
+    let sheet_width = (chart_x + chart_width + PATTERN_MARGIN).max(entry_width + PATTERN_MARGIN);
+    let columns = ((sheet_width - PATTERN_MARGIN) / entry_width).max(1);
+    let legend_rows = (entries.len() as u32).div_ceil(columns);
+    let legend_y = chart_y + chart_height + PATTERN_MARGIN * 2;
+    let sheet_height = legend_y + legend_rows * entry_height + PATTERN_MARGIN;
+
+    let mut sheet = vec![255u8; (sheet_width * sheet_height * 4) as usize];
+    // Draws an RGBA image at (x, y), skipping its transparent pixels
+    let mut blit = |x: u32, y: u32, image_width: u32, image: &[u8]| {
+        for (index, pixel) in image.chunks_exact(4).enumerate() {
+            let (px, py) = (
+                x + index as u32 % image_width,
+                y + index as u32 / image_width,
+            );
+            if pixel[3] == 0 || px >= sheet_width || py >= sheet_height {
+                continue;
+            }
+            let target = ((py * sheet_width + px) * 4) as usize;
+            sheet[target..target + 4].copy_from_slice(pixel);
+        }
+    };
+
+    blit(chart_x, chart_y, chart_width, &chart);
+    // Row and column numbers, 1-based, centered on their cells; column
+    // numbers too wide for a cell only label the first and every tenth
+    let column_step = if label(&width.to_string()).0 + 2 <= PATTERN_CELL_SIZE {
+        1
+    } else {
+        10
+    };
+    for column in (0..width).filter(|&column| column == 0 || (column + 1) % column_step == 0) {
+        let (text_width, _, text) = label(&(column + 1).to_string());
+        let center = chart_x + column * PATTERN_CELL_SIZE + PATTERN_CELL_SIZE / 2 + 1;
+        blit(
+            center.saturating_sub(text_width / 2),
+            PATTERN_MARGIN,
+            text_width,
+            &text,
+        );
+    }
+    for row in 0..height {
+        let (text_width, _, text) = label(&(row + 1).to_string());
+        let center = chart_y + row * PATTERN_CELL_SIZE + PATTERN_CELL_SIZE / 2 + 1;
+        blit(
+            chart_x - PATTERN_MARGIN - text_width,
+            center.saturating_sub(glyph_height / 2),
+            text_width,
+            &text,
+        );
+    }
+
+    for (index, ((rgba, _), (text_width, _, text))) in colors.iter().zip(&entries).enumerate() {
+        let x = PATTERN_MARGIN + (index as u32 % columns) * entry_width;
+        let y = legend_y + (index as u32 / columns) * entry_height;
+        // The swatch is a one-cell chart, so it carries the same number
+        let (swatch_width, _, swatch) = render_printable(
+            rgba,
+            1,
+            1,
+            &PrintOptions {
+                scale: PATTERN_CELL_SIZE,
+                grid: true,
+                index_numbers: false,
+            },
+            &[],
+        );
+        blit(x, y, swatch_width, &swatch);
+        let (number_width, _, number) = crate::font::rasterize_text(
+            &(index + 1).to_string(),
+            crate::font::BitmapFont::Small,
+            if crate::filters::luminance(*rgba) > 128 {
+                ink
+            } else {
+                [255, 255, 255, 255]
+            },
+        );
+        blit(
+            x + (PATTERN_CELL_SIZE + 1).saturating_sub(number_width) / 2,
+            y + (PATTERN_CELL_SIZE + 1 - 5) / 2,
+            number_width,
+            &number,
+        );
+        blit(
+            x + PATTERN_CELL_SIZE + 1 + PATTERN_MARGIN,
+            y + (PATTERN_CELL_SIZE + 1).saturating_sub(glyph_height) / 2,
+            *text_width,
+            text,
+        );
+    }
+
+    let image = image::RgbaImage::from_raw(sheet_width, sheet_height, sheet)
+        .ok_or("Failed to create image from pixel data")?;
+    image
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))?;
+    Ok(colors.len())
+}
+
+/// Composite RGBA pixels over an opaque `matte` color, leaving no
+/// transparency behind.
+fn apply_matte(rgba_data: &mut [u8], matte: [u8; 4]) {
+    for pixel in rgba_data.chunks_exact_mut(4) {
+        let alpha = pixel[3] as f32 / 255.0;
+        for channel in 0..3 {
+            pixel[channel] = (pixel[channel] as f32 * alpha + matte[channel] as f32 * (1.0 - alpha))
+                .round() as u8;
+        }
+        pixel[3] = 255;
+    }
+}
+
+/// The visible layers flattened to RGBA bytes, blended with `blend_color`
+/// exactly as the canvas and the eyedropper see them. Walks each layer's
+/// bytes in one pass rather than compositing pixel by pixel.
+pub fn composite_layers(state: &EditorState) -> Vec<u8> {
+    let pixel_count = state.canvas_width as usize * state.canvas_height as usize;
+    let mut composite = vec![iced::Color::TRANSPARENT; pixel_count];
+    for (index, clip) in state.visible_layers() {
+        let Some(layer) = state.layers.get(index) else {
+            continue;
+        };
+        let base = clip.and_then(|base| state.layers.get(base));
+        for (i, (pixel, result)) in layer
+            .pixels
+            .chunks_exact(4)
+            .zip(composite.iter_mut())
+            .enumerate()
+        {
+            let mut rgba = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            if let Some(base) = base
+                && rgba[3] > 0
+            {
+                let base_alpha = base.pixels.get(i * 4 + 3).copied().unwrap_or(0);
+                rgba[3] = (rgba[3] as u16 * base_alpha as u16 / 255) as u8;
+            }
+            *result = blend_rgba8(*result, rgba, layer.opacity);
+        }
+    }
+    composite
+        .into_iter()
+        .flat_map(|color| color.into_rgba8())
+        .collect()
+}
+
+/// Save only the selected pixels, cropped to the selection, through
+/// `save_image`.
+fn save_selection(
+    state: &EditorState,
+    path: &Path,
+    settings: &ExportSettings,
+) -> Result<Option<String>, String> {
+    let (start_x, start_y, end_x, end_y) = state.selection_bounds().ok_or("Nothing is selected")?;
+    let bounds = crate::state::RegionBounds {
+        x: start_x,
+        y: start_y,
+        width: end_x - start_x,
+        height: end_y - start_y,
+    };
+
+    let mut cropped = state.new_document(bounds.width, bounds.height);
+    cropped.layers = state
+        .layers
+        .iter()
+        .map(|layer| {
+            let mut copy = Layer::new(layer.name.clone(), bounds.width, bounds.height);
+            copy.id = layer.id;
+            copy.pixels = layer.read_region(bounds).into_vec();
+            copy.visible = layer.visible;
+            copy.opacity = layer.opacity;
+            copy.clipped = layer.clipped;
+            copy
+        })
+        .collect();
+    cropped.used_colors = state.used_colors.clone();
+    cropped.solo_layer = state.solo_layer.filter(|_| !settings.ignore_solo);
+    cropped.ramps = state.ramps.clone();
+    cropped.export = state.export;
+    save_image(&cropped, path, settings)
+}
+
+/// Write one export profile from `state`, using the profile's upscale
+/// setting. The destination directory must already exist.
+pub fn save_profile(
+    state: &EditorState,
+    profile: &ExportProfile,
+) -> Result<Option<String>, String> {
+    let path = Path::new(&profile.path);
+    if let Some(dir) = path.parent()
+        && !dir.as_os_str().is_empty()
+        && !dir.is_dir()
+    {
+        return Err(format!("Directory {} does not exist", dir.display()));
+    }
+
+    save_export(state, path, &profile.settings(&state.export))
+}
+
+/// Slice the composited canvas into one PNG per tile, named
+/// `tile_<row>_<column>.png`, in `dir`. Tiles at the right and bottom edges
+/// are cut short when the canvas isn't a whole number of tiles. Returns how
+/// many tiles were written.
+pub fn save_tiles(state: &EditorState, dir: &Path) -> Result<usize, String> {
+    let composite = composite_layers(state);
+    let image = image::RgbaImage::from_raw(state.canvas_width, state.canvas_height, composite)
+        .ok_or("Failed to create image from pixel data")?;
+
+    let mut count = 0;
+    for (row, y) in (0..state.canvas_height)
+        .step_by(state.tile_height.max(1) as usize)
+        .enumerate()
+    {
+        for (column, x) in (0..state.canvas_width)
+            .step_by(state.tile_width.max(1) as usize)
+            .enumerate()
+        {
+            let (start_x, start_y, end_x, end_y) = state.tile_bounds(x, y);
+            let tile = image::imageops::crop_imm(
+                &image,
+                start_x,
+                start_y,
+                end_x - start_x,
+                end_y - start_y,
+            )
+            .to_image();
+            let path = dir.join(format!("tile_{}_{}.png", row, column));
+            tile.save(&path)
+                .map_err(|e| format!("Failed to save {}: {}", path.display(), e))?;
+            count += 1;
+        }
     }
+    Ok(count)
+}
 
-    Ok(())
+/// Write the outline view of the visible layers to a PNG at canvas size.
+/// Layer data is left untouched.
+pub fn save_outlines(state: &EditorState, path: &Path) -> Result<(), String> {
+    let pixels = crate::filters::outline_image(state);
+    let image = image::RgbaImage::from_raw(state.canvas_width, state.canvas_height, pixels)
+        .ok_or("Failed to create image from pixel data")?;
+    image
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+/// Extensions `list_images` picks up when importing a folder.
+const IMPORT_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "gif", "bmp", "webp", "tga"];
+
+/// Image files directly inside `dir` with their dimensions, natural-sorted by
+/// file name so `frame2.png` comes before `frame10.png`. Files whose header
+/// can't be read are skipped.
+pub fn list_images(dir: &Path) -> Result<Vec<(std::path::PathBuf, u32, u32)>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut images = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let supported = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                IMPORT_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+            });
+        if !supported || !path.is_file() {
+            continue;
+        }
+        match image::image_dimensions(&path) {
+            Ok((width, height)) => images.push((path, width, height)),
+            Err(e) => eprintln!("Skipping {}: {}", path.display(), e),
+        }
+    }
+    images.sort_by(|(a, ..), (b, ..)| {
+        crate::utils::natural_cmp(
+            &a.file_name().unwrap_or_default().to_string_lossy(),
+            &b.file_name().unwrap_or_default().to_string_lossy(),
+        )
+    });
+    Ok(images)
 }
 
 pub fn load_image(path: &Path) -> Result<(u32, u32, Vec<u8>), String> {
@@ -86,8 +864,404 @@ pub fn load_image(path: &Path) -> Result<(u32, u32, Vec<u8>), String> {
 
     let rgba_img = img.to_rgba8();
     let (width, height) = rgba_img.dimensions();
-    // Use get_pixel_buffer equivalent - just get raw pixels
+    // The layer format is the same raw RGBA bytes
     let pixels = rgba_img.into_raw();
 
     Ok((width, height, pixels))
 }
+
+/// The palette and index buffer to write as an indexed PNG, when the export
+/// is exactly one unscaled indexed layer. Anything that blends layers or
+/// resamples falls back to RGBA.
+fn indexed_image(state: &EditorState, upscale: ExportUpscale) -> Option<(&[[u8; 4]], &[u8])> {
+    let palette = state.indexed_palette.as_deref()?;
+    if upscale != ExportUpscale::None {
+        return None;
+    }
+    let mut visible = state.visible_layers();
+    let (index, clip) = visible.next()?;
+    let layer = state.layers.get(index)?;
+    if visible.next().is_some() || clip.is_some() || layer.opacity < 1.0 {
+        return None;
+    }
+    Some((palette, layer.indices.as_deref()?))
+}
+
+/// Write a PNG with `project`, if any, stored in a `pxRs` chunk ahead of the
+/// image data. With a `palette`, `pixels` holds one palette index per pixel
+/// and an indexed PNG is written; otherwise `pixels` is RGBA.
+fn save_png_with_project(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    palette: Option<&[[u8; 4]]>,
+    project: Option<&[u8]>,
+) -> Result<(), String> {
+    let file = std::fs::File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+    encoder.set_depth(png::BitDepth::Eight);
+    match palette {
+        Some(palette) => {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_palette(
+                palette
+                    .iter()
+                    .flat_map(|rgba| [rgba[0], rgba[1], rgba[2]])
+                    .collect::<Vec<u8>>(),
+            );
+            encoder.set_trns(palette.iter().map(|rgba| rgba[3]).collect::<Vec<u8>>());
+        }
+        None => encoder.set_color(png::ColorType::Rgba),
+    }
+
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| format!("Failed to save PNG: {}", e))?;
+    if let Some(project) = project {
+        writer
+            .write_chunk(png::chunk::ChunkType(PROJECT_CHUNK), project)
+            .map_err(|e| format!("Failed to save PNG: {}", e))?;
+    }
+    writer
+        .write_image_data(pixels)
+        .and_then(|_| writer.finish())
+        .map_err(|e| format!("Failed to save PNG: {}", e))
+}
+
+/// Read the layered project embedded in a PNG by `save_image`. Returns
+/// `Ok(None)` for non-PNG files and PNGs without a project chunk.
+pub fn load_project(path: &Path) -> Result<Option<Project>, String> {
+    const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    if !data.starts_with(&SIGNATURE) {
+        return Ok(None);
+    }
+
+    // Walk the chunk list: 4-byte length, 4-byte type, data, 4-byte CRC
+    let mut offset = SIGNATURE.len();
+    while offset + 8 <= data.len() {
+        let length = u32::from_be_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ]) as usize;
+        let chunk_type = &data[offset + 4..offset + 8];
+        let start = offset + 8;
+        let end = start
+            .checked_add(length)
+            .filter(|end| end + 4 <= data.len())
+            .ok_or("Truncated PNG chunk")?;
+
+        match chunk_type {
+            b"pxRs" => {
+                if length > MAX_PROJECT_CHUNK_SIZE {
+                    return Err("Embedded project data is too large".to_string());
+                }
+                return Project::decode(&data[start..end]).map(Some);
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+        offset = end + 4;
+    }
+
+    Ok(None)
+}
+
+/// Load a palette from an image file.
+///
+/// A 1-pixel-tall image is treated as a palette strip and keeps its
+/// left-to-right order; anything else has its unique colors sorted by hue.
+/// Fully transparent pixels are skipped. Fails if the image holds more than
+/// `max_colors` distinct colors.
+pub fn load_palette(path: &Path, max_colors: usize) -> Result<Vec<[u8; 4]>, String> {
+    let (_width, height, pixels) = load_image(path)?;
+
+    let colors: Vec<[u8; 4]> = if height == 1 {
+        let mut colors = Vec::new();
+        for pixel in pixels.chunks_exact(4) {
+            let rgba = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            if rgba[3] > 0 && !colors.contains(&rgba) {
+                colors.push(rgba);
+            }
+        }
+        colors
+    } else {
+        let mut counted = crate::palette::count_colors([pixels.as_slice()]);
+        crate::palette::sort_colors(&mut counted, crate::palette::PaletteSort::Hue);
+        counted.into_iter().map(|(rgba, _)| rgba).collect()
+    };
+
+    if colors.is_empty() {
+        return Err("Image contains no opaque colors".to_string());
+    }
+    if colors.len() > max_colors {
+        return Err(format!(
+            "Image has {} colors, more than the limit of {}",
+            colors.len(),
+            max_colors
+        ));
+    }
+
+    Ok(colors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iced::Color;
+
+    /// Opaque white under a half-transparent red layer and a blue layer at
+    /// 50% opacity, with a green layer clipped to the red one.
+    fn translucent_stack() -> EditorState {
+        let mut state = EditorState::new(2, 1);
+        state.layers[0].set_rgba8(0, 0, [255, 255, 255, 255]);
+        state.layers[0].set_rgba8(1, 0, [255, 255, 255, 255]);
+        state.add_layer("Red".to_string());
+        state.layers[1].set_rgba8(0, 0, [255, 0, 0, 128]);
+        state.add_layer("Blue".to_string());
+        state.layers[2].set_rgba8(0, 0, [0, 0, 255, 255]);
+        state.layers[2].opacity = 0.5;
+        state.add_layer("Clipped".to_string());
+        state.layers[3].clipped = true;
+        state.layers[3].set_rgba8(1, 0, [0, 255, 0, 255]);
+        state
+    }
+
+    fn assert_close(actual: &[u8], expected: [u8; 4]) {
+        let close = actual
+            .iter()
+            .zip(expected)
+            .all(|(&a, e)| a.abs_diff(e) <= 1);
+        assert!(close, "{:?} is not within 1 of {:?}", actual, expected);
+    }
+
+    #[test]
+    fn composite_matches_hand_computed_over() {
+        let composite = composite_layers(&translucent_stack());
+
+        // Red at 128/255 over white, then blue at half opacity
+        assert_close(&composite[0..4], [128, 64, 191, 255]);
+        // The clipped green is hidden where the red layer it clips to is
+        // empty
+        assert_close(&composite[4..8], [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn composite_matches_the_on_screen_colors() {
+        let mut state = translucent_stack();
+        state.layers[1].set_rgba8(1, 0, [10, 20, 30, 77]);
+        let composite = composite_layers(&state);
+
+        for x in 0..2 {
+            let index = x as usize * 4;
+            assert_eq!(composite[index..index + 4], state.get_rgba8(x, 0));
+            let mut color = Color::TRANSPARENT;
+            for (index, clip) in state.visible_layers() {
+                let pixel = crate::utils::rgba8_to_color(state.clipped_rgba8(index, clip, x, 0));
+                color = crate::state::blend_color(color, pixel, state.layers[index].opacity);
+            }
+            assert_eq!(composite[index..index + 4], color.into_rgba8());
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored composite_timing
+    /// --nocapture` to compare the byte pass against per-pixel compositing.
+    #[test]
+    #[ignore]
+    fn composite_timing() {
+        let mut state = EditorState::new(256, 256);
+        for index in 1..5 {
+            state.add_layer(format!("Layer {}", index));
+        }
+        for (index, layer) in state.layers.iter_mut().enumerate() {
+            layer.opacity = if index % 2 == 0 { 1.0 } else { 0.6 };
+            for (i, pixel) in layer.pixels.chunks_exact_mut(4).enumerate() {
+                pixel.copy_from_slice(&[(i * 7) as u8, index as u8 * 50, 90, (i * 13) as u8]);
+            }
+        }
+        let runs = 10;
+
+        let start = std::time::Instant::now();
+        let mut composite = Vec::new();
+        for _ in 0..runs {
+            composite = composite_layers(&state);
+        }
+        let bytes = start.elapsed() / runs;
+
+        let start = std::time::Instant::now();
+        let mut per_pixel = Vec::new();
+        for _ in 0..runs {
+            per_pixel = (0..256)
+                .flat_map(|y| (0..256).map(move |x| (x, y)))
+                .flat_map(|(x, y)| state.get_pixel(x, y).into_rgba8())
+                .collect();
+        }
+        let pixels = start.elapsed() / runs;
+
+        assert_eq!(composite, per_pixel);
+        println!(
+            "256x256, five layers: bytes {:?}, per pixel {:?}",
+            bytes, pixels
+        );
+    }
+
+    /// Save `state` as `format` to a temporary file and read it back.
+    fn round_trip(state: &EditorState, format: ExportFormat) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "pxrs-round-trip-{}.{}",
+            std::process::id(),
+            format.extension()
+        ));
+        let settings = ExportSettings {
+            format,
+            ..ExportSettings::default()
+        };
+        save_image(state, &path, &settings).unwrap();
+        let loaded = load_image(&path);
+        let _ = std::fs::remove_file(&path);
+        let (width, height, pixels) = loaded.unwrap();
+        assert_eq!((width, height), (state.canvas_width, state.canvas_height));
+        pixels
+    }
+
+    #[test]
+    fn exports_can_ignore_solo() {
+        let mut state = translucent_stack();
+        let everything = composite_layers(&state);
+        state.solo_layer = Some(state.layers[2].id);
+        let soloed = composite_layers(&state);
+        assert_ne!(soloed, everything);
+
+        let path = std::env::temp_dir().join(format!("pxrs-solo-{}.png", std::process::id()));
+        let mut exported = Vec::new();
+        for ignore_solo in [false, true] {
+            let settings = ExportSettings {
+                ignore_solo,
+                ..ExportSettings::default()
+            };
+            save_export(&state, &path, &settings).unwrap();
+            exported.push(load_image(&path).map(|(_, _, pixels)| pixels));
+        }
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(exported, [Ok(soloed), Ok(everything)]);
+    }
+
+    #[test]
+    fn webp_and_tga_keep_every_pixel() {
+        let mut state = EditorState::new(3, 2);
+        state.layers[0].set_rgba8(0, 0, [255, 0, 0, 255]);
+        state.layers[0].set_rgba8(1, 0, [12, 34, 56, 255]);
+        state.layers[0].set_rgba8(2, 0, [200, 100, 50, 128]);
+        state.layers[0].set_rgba8(0, 1, [1, 2, 3, 1]);
+        state.layers[0].set_rgba8(1, 1, [0, 255, 0, 255]);
+        let expected = composite_layers(&state);
+
+        for format in [ExportFormat::WebP, ExportFormat::Tga] {
+            assert_eq!(round_trip(&state, format), expected, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn svg_merges_runs_of_the_same_color() {
+        let red = [255, 0, 0, 255];
+        let faint = [0, 0, 255, 64];
+        #[rustfmt::skip]
+        let pixels = [
+            red, red, red, [0; 4],
+            faint, faint, red, red,
+        ]
+        .concat();
+
+        assert_eq!(
+            rgba_to_svg(&pixels, 4, 2, 2),
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="4" viewBox="0 0 8 4" shape-rendering="crispEdges">"#,
+                "\n",
+                r##"<rect x="0" y="0" width="6" height="2" fill="#ff0000"/>"##,
+                "\n",
+                r##"<rect x="0" y="2" width="4" height="2" fill="#0000ff" fill-opacity="0.251"/>"##,
+                "\n",
+                r##"<rect x="4" y="2" width="4" height="2" fill="#ff0000"/>"##,
+                "\n",
+                "</svg>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn palette_strips_keep_their_order() {
+        // Greys, then hues running backwards, which a hue sort would reverse
+        let strip: Vec<[u8; 4]> = (0..16u8)
+            .map(|i| match i {
+                0..8 => [i * 32, i * 32, i * 32, 255],
+                _ => [255 - (i - 8) * 32, (i - 8) * 32, 128, 255],
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("pxrs-strip-{}.png", std::process::id()));
+        image::RgbaImage::from_raw(16, 1, strip.concat())
+            .unwrap()
+            .save(&path)
+            .unwrap();
+
+        let loaded = load_palette(&path, 64);
+        let capped = load_palette(&path, 15);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.unwrap(), strip);
+        assert!(capped.is_err());
+    }
+
+    #[test]
+    fn rgb565_packs_exact_values_in_either_byte_order() {
+        let pixels = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255, 255, 255, 0],
+            [0x84, 0x82, 0x84, 255],
+        ]
+        .concat();
+        let values: [u16; 5] = [0xF800, 0x07E0, 0x001F, 0xFFFF, 0x8410];
+        let little: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let big: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        assert_eq!(rgba_to_rgb565(&pixels, false), little);
+        assert_eq!(rgba_to_rgb565(&pixels, true), big);
+    }
+
+    #[test]
+    fn mono_rows_are_padded_to_whole_bytes() {
+        // 10x2: the first row alternates white and black, the second is
+        // white except for a half-transparent pixel
+        let white = [255, 255, 255, 255];
+        let black = [0, 0, 0, 255];
+        let mut pixels = Vec::new();
+        for x in 0..10 {
+            pixels.extend_from_slice(if x % 2 == 0 { &white } else { &black });
+        }
+        for x in 0..10 {
+            pixels.extend_from_slice(if x == 9 {
+                &[255, 255, 255, 127]
+            } else {
+                &white
+            });
+        }
+        assert_eq!(
+            rgba_to_mono(&pixels, 10, 2, 128),
+            [0b1010_1010, 0b1000_0000, 0b1111_1111, 0b1000_0000]
+        );
+
+        // The threshold compares against luminance
+        let grey = [100, 100, 100, 255];
+        assert_eq!(rgba_to_mono(&grey, 1, 1, 100), [0b1000_0000]);
+        assert_eq!(rgba_to_mono(&grey, 1, 1, 101), [0]);
+    }
+}
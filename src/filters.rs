@@ -0,0 +1,419 @@
+use crate::state::{EditorState, Layer};
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Image adjustments that can be previewed and applied to the active layer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Filter {
+    /// Reduce each RGB channel to `levels` evenly spaced values.
+    Posterize { levels: u32 },
+    /// Luminance grayscale, optionally posterized to `levels` shades.
+    Grayscale { levels: Option<u32> },
+    /// Replace each pixel by a ramp color chosen by its luminance, darkest
+    /// pixels mapping to the first entry. `stepped` picks the nearest ramp
+    /// entry instead of blending between neighbours.
+    GradientMap { ramp: Vec<[u8; 4]>, stepped: bool },
+    /// Random per-pixel texture, the same for the same `seed`. `amount` is
+    /// the brightness jitter range or the scatter density in percent.
+    /// Transparent pixels are only scattered over with `fill_transparent`.
+    Noise {
+        mode: NoiseMode,
+        amount: u32,
+        seed: u32,
+        fill_transparent: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NoiseMode {
+    /// Shift each pixel's brightness by up to ±amount.
+    Jitter,
+    /// Replace pixels with one of two colors at the given density.
+    Scatter { colors: [[u8; 4]; 2] },
+}
+
+impl Filter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Posterize { .. } => "Posterize",
+            Filter::Grayscale { .. } => "Grayscale",
+            Filter::GradientMap { .. } => "Gradient map",
+            Filter::Noise { .. } => "Noise",
+        }
+    }
+
+    /// Apply the filter to the RGBA pixel at (x, y). Only noise depends on
+    /// the position, and only scattering changes alpha.
+    pub fn apply_pixel(&self, x: u32, y: u32, rgba: [u8; 4]) -> [u8; 4] {
+        match self {
+            Filter::Posterize { levels } => [
+                posterize_channel(rgba[0], *levels),
+                posterize_channel(rgba[1], *levels),
+                posterize_channel(rgba[2], *levels),
+                rgba[3],
+            ],
+            Filter::Grayscale { levels } => {
+                let mut gray = luminance(rgba);
+                if let Some(levels) = levels {
+                    gray = posterize_channel(gray, *levels);
+                }
+                [gray, gray, gray, rgba[3]]
+            }
+            Filter::GradientMap { ramp, stepped } => {
+                let mapped = gradient_map(luminance(rgba), ramp, *stepped).unwrap_or(rgba);
+                [mapped[0], mapped[1], mapped[2], rgba[3]]
+            }
+            &Filter::Noise {
+                mode,
+                amount,
+                seed,
+                fill_transparent,
+            } => {
+                if rgba[3] == 0 && !(fill_transparent && matches!(mode, NoiseMode::Scatter { .. }))
+                {
+                    return rgba;
+                }
+                let hash = noise_hash(seed, x, y);
+                match mode {
+                    NoiseMode::Jitter => {
+                        let delta = (hash % (2 * amount + 1)) as i32 - amount as i32;
+                        let jitter = |c: u8| (c as i32 + delta).clamp(0, 255) as u8;
+                        [jitter(rgba[0]), jitter(rgba[1]), jitter(rgba[2]), rgba[3]]
+                    }
+                    NoiseMode::Scatter { colors } => {
+                        if hash % 100 < amount {
+                            colors[(hash >> 16) as usize & 1]
+                        } else {
+                            rgba
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Well-mixed 32-bit hash of a seed and pixel position, so noise is
+/// reproducible and doesn't depend on the region being filtered.
+pub fn noise_hash(seed: u32, x: u32, y: u32) -> u32 {
+    let mut hash = seed ^ x.wrapping_mul(0x9E37_79B1) ^ y.wrapping_mul(0x85EB_CA77);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2C1B_3C6D);
+    hash ^= hash >> 12;
+    hash = hash.wrapping_mul(0x297A_2D39);
+    hash ^ (hash >> 15)
+}
+
+/// Perceptual luminance using the Rec. 601 weights (0.299, 0.587, 0.114).
+pub fn luminance(rgba: [u8; 4]) -> u8 {
+    let l = 0.299 * rgba[0] as f32 + 0.587 * rgba[1] as f32 + 0.114 * rgba[2] as f32;
+    l.round().clamp(0.0, 255.0) as u8
+}
+
+/// Look up the ramp color for a luminance value. Returns `None` for an empty
+/// ramp so callers can leave the pixel untouched.
+pub fn gradient_map(luminance: u8, ramp: &[[u8; 4]], stepped: bool) -> Option<[u8; 4]> {
+    let last = ramp.len().checked_sub(1)?;
+    let t = luminance as f32 / 255.0;
+
+    if stepped {
+        let index = ((t * ramp.len() as f32) as usize).min(last);
+        return Some(ramp[index]);
+    }
+
+    let position = t * last as f32;
+    let index = (position.floor() as usize).min(last);
+    let next = (index + 1).min(last);
+    let frac = position - index as f32;
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * frac).round() as u8;
+    let (a, b) = (ramp[index], ramp[next]);
+    Some([
+        lerp(a[0], b[0]),
+        lerp(a[1], b[1]),
+        lerp(a[2], b[2]),
+        lerp(a[3], b[3]),
+    ])
+}
+
+/// Snap a channel value to the nearest of `levels` evenly spaced values
+/// between 0 and 255. `levels` is clamped to 2..=256.
+pub fn posterize_channel(value: u8, levels: u32) -> u8 {
+    let levels = levels.clamp(2, 256);
+    let step = 255.0 / (levels - 1) as f32;
+    ((value as f32 / step).round() * step)
+        .round()
+        .clamp(0.0, 255.0) as u8
+}
+
+/// Return a copy of the layer's pixels with `filter` applied inside `bounds`
+/// (`start_x, start_y, end_x, end_y`, end exclusive), or the whole layer when
+/// no bounds are given.
+pub fn filtered_pixels(
+    layer: &Layer,
+    filter: &Filter,
+    bounds: Option<(u32, u32, u32, u32)>,
+) -> Vec<u8> {
+    let (start_x, start_y, end_x, end_y) = bounds.unwrap_or((0, 0, layer.width, layer.height));
+    let mut pixels = layer.pixels.clone();
+
+    for y in start_y..end_y.min(layer.height) {
+        for x in start_x..end_x.min(layer.width) {
+            let index = ((y * layer.width + x) * 4) as usize;
+            if index + 3 >= pixels.len() {
+                continue;
+            }
+            let rgba = [
+                pixels[index],
+                pixels[index + 1],
+                pixels[index + 2],
+                pixels[index + 3],
+            ];
+            pixels[index..index + 4].copy_from_slice(&filter.apply_pixel(x, y, rgba));
+        }
+    }
+
+    pixels
+}
+
+/// Preview `filter` on the active layer, limited to the selection if any.
+pub fn preview(state: &mut EditorState, filter: Filter) {
+    let bounds = state.selection_bounds();
+    let Some(layer) = state.active_layer() else {
+        return;
+    };
+    let pixels = filtered_pixels(layer, &filter, bounds);
+    state.rotation = None;
+    state.line_shift = None;
+    state.active_filter = Some(filter);
+    state.set_preview(pixels);
+}
+
+/// Clear the alpha of every active-layer pixel within `tolerance` of `key`
+/// (fully transparent pixels are skipped) as one undo entry. Returns the
+/// number of pixels cleared.
+pub fn make_color_transparent(state: &mut EditorState, key: [u8; 4], tolerance: u8) -> usize {
+    let Some(layer) = state.active_layer() else {
+        return 0;
+    };
+
+    let mut pixels = layer.pixels.clone();
+    let mut cleared = 0;
+    for pixel in pixels.chunks_exact_mut(4) {
+        if pixel[3] > 0
+            && utils::colors_match([pixel[0], pixel[1], pixel[2], pixel[3]], key, tolerance)
+        {
+            pixel[3] = 0;
+            cleared += 1;
+        }
+    }
+
+    let layer_index = state.active_layer_index;
+    state.replace_layer_pixels(layer_index, pixels);
+    cleared
+}
+
+/// Set the alpha of every active-layer pixel to its luminance, keeping its
+/// color, as one undo entry. Pixels that were transparent are treated as
+/// black, so dark areas become see-through.
+pub fn luminance_to_alpha(state: &mut EditorState) {
+    let Some(layer) = state.active_layer() else {
+        return;
+    };
+    let mut pixels = layer.pixels.clone();
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel[3] = luminance([pixel[0], pixel[1], pixel[2], 255]);
+    }
+    let layer_index = state.active_layer_index;
+    state.replace_layer_pixels(layer_index, pixels);
+}
+
+/// Replace every active-layer pixel by an opaque grey of its alpha (white =
+/// opaque), as one undo entry.
+pub fn alpha_to_luminance(state: &mut EditorState) {
+    let Some(layer) = state.active_layer() else {
+        return;
+    };
+    let mut pixels = layer.pixels.clone();
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        pixel.copy_from_slice(&[alpha, alpha, alpha, 255]);
+    }
+    let layer_index = state.active_layer_index;
+    state.replace_layer_pixels(layer_index, pixels);
+}
+
+/// Non-transparent pixels of `layer` with no non-transparent pixel among
+/// their eight neighbors.
+pub fn find_stray_pixels(layer: &Layer) -> Vec<(u32, u32)> {
+    let opaque = |x: i64, y: i64| x >= 0 && y >= 0 && layer.get_rgba8(x as u32, y as u32)[3] > 0;
+    let mut strays = Vec::new();
+    for y in 0..layer.height {
+        for x in 0..layer.width {
+            let (ix, iy) = (x as i64, y as i64);
+            if !opaque(ix, iy) {
+                continue;
+            }
+            let isolated = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                .filter(|&offset| offset != (0, 0))
+                .all(|(dx, dy)| !opaque(ix + dx, iy + dy));
+            if isolated {
+                strays.push((x, y));
+            }
+        }
+    }
+    strays
+}
+
+/// Clear every stray pixel on the active layer as one undo entry. Returns
+/// the number of pixels cleared.
+pub fn delete_stray_pixels(state: &mut EditorState) -> usize {
+    let Some(layer) = state.active_layer() else {
+        return 0;
+    };
+
+    let strays = find_stray_pixels(layer);
+    let mut pixels = layer.pixels.clone();
+    for &(x, y) in &strays {
+        let index = ((y * layer.width + x) * 4) as usize;
+        pixels[index..index + 4].fill(0);
+    }
+
+    let layer_index = state.active_layer_index;
+    state.replace_layer_pixels(layer_index, pixels);
+    strays.len()
+}
+
+/// Flag each opaque pixel of an RGBA buffer that touches a transparent pixel
+/// or the buffer edge on any side, tracing the one-pixel silhouette outline.
+pub fn silhouette_outline(pixels: &[u8], width: u32, height: u32) -> Vec<bool> {
+    let opaque = |x: i64, y: i64| {
+        x >= 0
+            && y >= 0
+            && x < width as i64
+            && y < height as i64
+            && pixels
+                .get(((y * width as i64 + x) * 4 + 3) as usize)
+                .is_some_and(|&alpha| alpha > 0)
+    };
+    let mut edges = vec![false; (width * height) as usize];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            edges[(y * width as i64 + x) as usize] = opaque(x, y)
+                && [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                    .iter()
+                    .any(|&(dx, dy)| !opaque(x + dx, y + dy));
+        }
+    }
+    edges
+}
+
+/// Color of layer `index` in the outline view, stepping around the hue wheel
+/// by the golden angle so neighboring layers never look alike.
+pub fn outline_color(index: usize) -> [u8; 4] {
+    let [r, g, b] = utils::hsv_to_rgb((index as f32 * 137.5) % 360.0, 0.9, 0.9);
+    [r, g, b, 255]
+}
+
+/// The outline view as an RGBA image: each visible layer's silhouette
+/// outline in its own color, upper layers drawn over lower ones.
+pub fn outline_image(state: &EditorState) -> Vec<u8> {
+    let mut result = vec![0u8; (state.canvas_width * state.canvas_height * 4) as usize];
+    for (index, _) in state.visible_layers() {
+        let Some(layer) = state.layers.get(index) else {
+            continue;
+        };
+        let color = outline_color(index);
+        let edges = silhouette_outline(&layer.pixels, layer.width, layer.height);
+        for (pixel, _) in result
+            .chunks_exact_mut(4)
+            .zip(edges)
+            .filter(|(_, edge)| *edge)
+        {
+            pixel.copy_from_slice(&color);
+        }
+    }
+    result
+}
+
+/// Thresholds for 4x4 ordered dithering, 0-15.
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Channel spread of the dither pattern, wide enough to mix neighbouring
+/// palette entries in typical pixel art palettes.
+const DITHER_SPREAD: f32 = 48.0;
+
+/// Snap an RGBA buffer to its nearest `palette` colors, optionally with
+/// ordered dithering. Transparent pixels and pixels already on the palette
+/// are left alone. Returns the distinct colors that had to be remapped.
+pub fn snap_to_palette(
+    pixels: &mut [u8],
+    width: u32,
+    palette: &[[u8; 4]],
+    dither: bool,
+) -> HashSet<[u8; 4]> {
+    let entries: Vec<[u8; 4]> = palette
+        .iter()
+        .copied()
+        .filter(|entry| entry[3] > 0)
+        .collect();
+    let mut remapped = HashSet::new();
+    if entries.is_empty() || width == 0 {
+        return remapped;
+    }
+    let nearest = |rgba: [f32; 4]| {
+        entries
+            .iter()
+            .copied()
+            .min_by(|a, b| {
+                let distance = |entry: &[u8; 4]| {
+                    entry
+                        .iter()
+                        .zip(rgba)
+                        .map(|(&channel, value)| (channel as f32 - value).powi(2))
+                        .sum::<f32>()
+                };
+                distance(a).total_cmp(&distance(b))
+            })
+            .unwrap_or(entries[0])
+    };
+    let mut cache: HashMap<[u8; 4], [u8; 4]> = HashMap::new();
+    for (index, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+        let rgba = [pixel[0], pixel[1], pixel[2], pixel[3]];
+        if rgba[3] == 0 || entries.contains(&rgba) {
+            continue;
+        }
+        remapped.insert(rgba);
+        let snapped = if dither {
+            let (x, y) = (index as u32 % width, index as u32 / width);
+            let threshold = BAYER_4X4[(y % 4) as usize][(x % 4) as usize];
+            let offset = ((threshold as f32 + 0.5) / 16.0 - 0.5) * DITHER_SPREAD;
+            nearest([
+                rgba[0] as f32 + offset,
+                rgba[1] as f32 + offset,
+                rgba[2] as f32 + offset,
+                rgba[3] as f32,
+            ])
+        } else {
+            *cache
+                .entry(rgba)
+                .or_insert_with(|| nearest(rgba.map(|channel| channel as f32)))
+        };
+        pixel.copy_from_slice(&snapped);
+    }
+    remapped
+}
+
+/// Palette imports snap to: the indexed palette in indexed mode, otherwise
+/// the used color swatches.
+pub fn import_palette(state: &EditorState) -> Vec<[u8; 4]> {
+    match &state.indexed_palette {
+        Some(palette) => palette.clone(),
+        None => state
+            .used_colors
+            .iter()
+            .map(|used| utils::color_to_rgba8(used.color))
+            .collect(),
+    }
+}
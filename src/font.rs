@@ -0,0 +1,280 @@
+use serde::{Deserialize, Serialize};
+
+/// Monospace bitmap fonts built into the text tool. Each glyph row is stored
+/// in the low `width` bits of a byte, leftmost pixel in the highest bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BitmapFont {
+    Small,
+    Medium,
+}
+
+impl BitmapFont {
+    pub const ALL: [BitmapFont; 2] = [BitmapFont::Small, BitmapFont::Medium];
+
+    /// Glyph cell size as `(width, height)`.
+    pub fn glyph_size(self) -> (u32, u32) {
+        match self {
+            BitmapFont::Small => (3, 5),
+            BitmapFont::Medium => (5, 7),
+        }
+    }
+
+    /// Rows of the glyph for `c`. Lowercase letters use the uppercase
+    /// glyphs and characters without a glyph show as `?`.
+    fn glyph(self, c: char) -> &'static [u8] {
+        let c = c.to_ascii_uppercase();
+        let find = |c: char| -> Option<&'static [u8]> {
+            match self {
+                BitmapFont::Small => SMALL
+                    .iter()
+                    .find(|(glyph, _)| *glyph == c)
+                    .map(|(_, rows)| rows.as_slice()),
+                BitmapFont::Medium => MEDIUM
+                    .iter()
+                    .find(|(glyph, _)| *glyph == c)
+                    .map(|(_, rows)| rows.as_slice()),
+            }
+        };
+        find(c).or_else(|| find('?')).unwrap_or(&[])
+    }
+}
+
+impl std::fmt::Display for BitmapFont {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitmapFont::Small => write!(f, "3x5"),
+            BitmapFont::Medium => write!(f, "5x7"),
+        }
+    }
+}
+
+/// Rasterize `text` in `font` as RGBA pixels of `color` on transparency,
+/// returning `(width, height, pixels)`. Glyphs and lines are one pixel apart
+/// and `\n` starts a new line. Empty text gives a 0x0 image.
+pub fn rasterize_text(text: &str, font: BitmapFont, color: [u8; 4]) -> (u32, u32, Vec<u8>) {
+    let (glyph_width, glyph_height) = font.glyph_size();
+    let lines: Vec<&str> = text.split('\n').collect();
+    let columns = lines
+        .iter()
+        .map(|line| line.chars().count() as u32)
+        .max()
+        .unwrap_or(0);
+    if columns == 0 {
+        return (0, 0, Vec::new());
+    }
+
+    let width = columns * (glyph_width + 1) - 1;
+    let height = lines.len() as u32 * (glyph_height + 1) - 1;
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+
+    for (line_index, line) in lines.iter().enumerate() {
+        let top = line_index as u32 * (glyph_height + 1);
+        for (column, c) in line.chars().enumerate() {
+            let left = column as u32 * (glyph_width + 1);
+            for (row, bits) in font.glyph(c).iter().enumerate() {
+                for x in 0..glyph_width {
+                    if bits & (1 << (glyph_width - 1 - x)) == 0 {
+                        continue;
+                    }
+                    let index = (((top + row as u32) * width + left + x) * 4) as usize;
+                    pixels[index..index + 4].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    (width, height, pixels)
+}
+
+const SMALL: &[(char, [u8; 5])] = &[
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b110, 0b001, 0b010, 0b100, 0b111]),
+    ('3', [0b110, 0b001, 0b010, 0b001, 0b110]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b110, 0b001, 0b110]),
+    ('6', [0b011, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b110]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b110, 0b001, 0b010, 0b000, 0b010]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    (';', [0b000, 0b010, 0b000, 0b010, 0b100]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('*', [0b000, 0b101, 0b010, 0b101, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('\'', [0b010, 0b010, 0b000, 0b000, 0b000]),
+    ('"', [0b101, 0b101, 0b000, 0b000, 0b000]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+];
+
+#[rustfmt::skip]
+const MEDIUM: &[(char, [u8; 7])] = &[
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    ('!', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    (';', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('*', [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+    ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('\'', [0b01100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('"', [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INK: [u8; 4] = [10, 20, 30, 255];
+
+    fn rows(text: &str, font: BitmapFont) -> Vec<String> {
+        let (width, _, pixels) = rasterize_text(text, font, INK);
+        pixels
+            .chunks_exact(width as usize * 4)
+            .map(|row| {
+                row.chunks_exact(4)
+                    .map(|pixel| match pixel {
+                        [0, 0, 0, 0] => '.',
+                        pixel if pixel == INK => '#',
+                        _ => '?',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn glyphs_are_spaced_one_pixel_apart() {
+        assert_eq!(
+            rows("Hi", BitmapFont::Small),
+            ["#.#.###", "#.#..#.", "###..#.", "#.#..#.", "#.#.###"]
+        );
+        assert_eq!(rows("hi", BitmapFont::Small), rows("HI", BitmapFont::Small));
+    }
+
+    #[test]
+    fn lines_stack_and_size_to_the_longest() {
+        let (width, height, pixels) = rasterize_text("AB\nC", BitmapFont::Medium, INK);
+        assert_eq!((width, height), (11, 15));
+        assert_eq!(pixels.len(), 11 * 15 * 4);
+        assert_eq!(
+            rasterize_text("", BitmapFont::Medium, INK),
+            (0, 0, Vec::new())
+        );
+    }
+
+    #[test]
+    fn unknown_characters_fall_back_to_the_question_mark() {
+        for font in BitmapFont::ALL {
+            assert_eq!(rows("\u{e9}", font), rows("?", font));
+        }
+    }
+
+    #[test]
+    fn glyphs_fit_their_cells() {
+        for (font, glyphs) in [
+            (
+                BitmapFont::Small,
+                SMALL
+                    .iter()
+                    .map(|(c, rows)| (*c, &rows[..]))
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                BitmapFont::Medium,
+                MEDIUM.iter().map(|(c, rows)| (*c, &rows[..])).collect(),
+            ),
+        ] {
+            let (width, height) = font.glyph_size();
+            for (c, glyph_rows) in glyphs {
+                assert_eq!(glyph_rows.len() as u32, height, "{:?} in {}", c, font);
+                assert!(
+                    glyph_rows.iter().all(|row| (*row as u32) < 1 << width),
+                    "{:?} in {}",
+                    c,
+                    font
+                );
+            }
+        }
+    }
+}
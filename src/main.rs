@@ -1,26 +1,99 @@
+mod app;
 mod canvas;
+mod color_picker;
 mod file_io;
+mod filters;
+mod font;
 mod message;
+mod palette;
+mod project;
+mod recording;
+mod scaling;
+mod selection;
+mod session;
+mod settings;
 mod state;
 mod tools;
+mod transform;
 mod ui;
+mod update;
 mod utils;
 
+use app::App;
 use iced::Task;
 use message::Message;
 use state::EditorState;
 
 fn main() -> iced::Result {
-    iced::application("Pixel Art Editor", update, view)
+    let settings = settings::load();
+    let session = session::load();
+
+    let mut application = iced::application("Pixel Art Editor", update, view)
         .subscription(subscription)
-        .run()
+        .exit_on_close_request(false);
+    if let Some(window) = session.window {
+        application = application.window_size(iced::Size::new(window.width, window.height));
+        if let Some((x, y)) = window.position {
+            application =
+                application.position(iced::window::Position::Specific(iced::Point::new(x, y)));
+        }
+    }
+
+    application.run_with(move || {
+        let mut state = EditorState::default();
+        settings.apply_to(&mut state);
+        let mut app = App::new(state);
+        app.reopen_last_session = settings.reopen_last_session;
+        app.window = session.window;
+
+        if settings.reopen_last_session {
+            let (documents, active, missing) = session.restore(app.active_document());
+            if !documents.is_empty() {
+                app.documents = documents;
+                app.active = active;
+            }
+            if !missing.is_empty() {
+                app.active_document_mut().notification =
+                    Some(format!("Couldn't reopen: {}", missing.join(", ")));
+            }
+        }
+        (app, Task::none())
+    })
 }
 
-fn subscription(_state: &EditorState) -> iced::Subscription<Message> {
+fn subscription(app: &App) -> iced::Subscription<Message> {
     use iced::keyboard;
     use iced::keyboard::key;
 
-    keyboard::on_key_press(|key, modifiers| {
+    let close_requests = iced::window::close_requests().map(Message::WindowCloseRequested);
+
+    let window_events = iced::event::listen_with(|event, _status, id| match event {
+        iced::Event::Window(
+            iced::window::Event::Opened { size, .. } | iced::window::Event::Resized(size),
+        ) => Some(Message::WindowResized(id, size)),
+        iced::Event::Window(iced::window::Event::Moved(position)) => {
+            Some(Message::WindowMoved(position))
+        }
+        _ => None,
+    });
+
+    // Drawing mode keeps only undo and redo
+    let kiosk_key_presses =
+        keyboard::on_key_press(|key, modifiers| match (key.as_ref(), modifiers) {
+            (key::Key::Character(c), _) if modifiers.command() && c.eq_ignore_ascii_case("z") => {
+                if modifiers.shift() {
+                    Some(Message::Redo)
+                } else {
+                    Some(Message::Undo)
+                }
+            }
+            (key::Key::Character(c), keyboard::Modifiers::CTRL) if c.eq_ignore_ascii_case("y") => {
+                Some(Message::Redo)
+            }
+            _ => None,
+        });
+
+    let key_presses = keyboard::on_key_press(|key, modifiers| {
         match (key.as_ref(), modifiers) {
             (key::Key::Character(c), keyboard::Modifiers::CTRL) if c.eq_ignore_ascii_case("z") => {
                 if modifiers.contains(keyboard::Modifiers::SHIFT) {
@@ -36,8 +109,16 @@ fn subscription(_state: &EditorState) -> iced::Subscription<Message> {
                 Some(Message::CopySelection)
             }
             (key::Key::Character(c), keyboard::Modifiers::CTRL) if c.eq_ignore_ascii_case("v") => {
-                // Paste at current mouse position - for now paste at center
-                Some(Message::PasteSelection { x: 16, y: 16 })
+                Some(Message::PasteSelection)
+            }
+            (key::Key::Character(c), _)
+                if modifiers == keyboard::Modifiers::CTRL | keyboard::Modifiers::SHIFT
+                    && c.eq_ignore_ascii_case("v") =>
+            {
+                Some(Message::PastePreviewStarted)
+            }
+            (key::Key::Character(c), keyboard::Modifiers::CTRL) if c.eq_ignore_ascii_case("e") => {
+                Some(Message::ExportAllRequested)
             }
             (key::Key::Character(c), keyboard::Modifiers::CTRL) if c.eq_ignore_ascii_case("x") => {
                 Some(Message::CutSelection)
@@ -46,489 +127,398 @@ fn subscription(_state: &EditorState) -> iced::Subscription<Message> {
                 // Select all - create selection covering entire canvas
                 Some(Message::SelectionStarted { x: 0.0, y: 0.0 })
             }
-            (key::Key::Named(key::Named::Delete), _)
-            | (key::Key::Named(key::Named::Backspace), _) => {
-                // Clear selection or delete key
-                Some(Message::SelectionCleared)
+            (key::Key::Character(c), keyboard::Modifiers::SHIFT) if c.eq_ignore_ascii_case("h") => {
+                Some(Message::MirrorHorizontalToggled)
             }
-            _ => None,
-        }
-    })
-}
-
-fn update(state: &mut EditorState, message: Message) -> Task<Message> {
-    match message {
-        Message::ToolSelected(tool) => {
-            state.current_tool = tool;
-        }
-        Message::PrimaryColorChanged(color) => {
-            state.primary_color = color;
-        }
-        Message::SecondaryColorChanged(color) => {
-            state.secondary_color = color;
-        }
-        Message::ColorPicked(color) => {
-            // Color picker clicked - swap primary and secondary or set primary
-            state.primary_color = color;
-        }
-        Message::UsedColorPicked(color) => {
-            state.primary_color = color;
-        }
-        Message::BrushSizeChanged(size) => {
-            state.brush_size = size.clamp(1, 20);
-        }
-        Message::CanvasResized { width, height } => {
-            state.canvas_width = width;
-            state.canvas_height = height;
-            // Resize all layers
-            for layer in &mut state.layers {
-                let new_pixels = vec![0u8; (width * height * 4) as usize];
-                layer.pixels = new_pixels;
-                layer.width = width;
-                layer.height = height;
+            (key::Key::Character(c), keyboard::Modifiers::SHIFT) if c.eq_ignore_ascii_case("v") => {
+                Some(Message::MirrorVerticalToggled)
             }
-        }
-        Message::CanvasCleared => {
-            for layer in &mut state.layers {
-                layer.pixels.fill(0);
+            (key::Key::Character(","), _) => Some(Message::RampStepped(-1)),
+            (key::Key::Character("."), _) => Some(Message::RampStepped(1)),
+            (key::Key::Character("/"), _) => Some(Message::PreserveTransparencyToggled),
+            (key::Key::Named(key::Named::ArrowLeft), _) => {
+                Some(Message::FloatingPasteNudged { dx: -1, dy: 0 })
             }
-        }
-        Message::LayerAdded(name) => {
-            state.add_layer(name);
-        }
-        Message::LayerDeleted(index) => {
-            state.delete_layer(index);
-        }
-        Message::LayerMoved { from, to } => {
-            if from < state.layers.len() && to < state.layers.len() {
-                let layer = state.layers.remove(from);
-                state.layers.insert(to, layer);
-                if state.active_layer_index == from {
-                    state.active_layer_index = to;
-                } else if state.active_layer_index == to && from < to {
-                    state.active_layer_index -= 1;
-                } else if state.active_layer_index == to && from > to {
-                    state.active_layer_index += 1;
-                }
+            (key::Key::Named(key::Named::ArrowRight), _) => {
+                Some(Message::FloatingPasteNudged { dx: 1, dy: 0 })
             }
-        }
-        Message::LayerVisibilityToggled(index) => {
-            if let Some(layer) = state.layers.get_mut(index) {
-                layer.visible = !layer.visible;
+            (key::Key::Named(key::Named::ArrowUp), _) => {
+                Some(Message::FloatingPasteNudged { dx: 0, dy: -1 })
             }
-        }
-        Message::LayerSelected(index) => {
-            if index < state.layers.len() {
-                state.active_layer_index = index;
+            (key::Key::Named(key::Named::ArrowDown), _) => {
+                Some(Message::FloatingPasteNudged { dx: 0, dy: 1 })
             }
-        }
-        Message::LayerOpacityChanged { index, opacity } => {
-            if let Some(layer) = state.layers.get_mut(index) {
-                layer.opacity = utils::clamp_f32(opacity, 0.0, 1.0);
+            (key::Key::Named(key::Named::Enter), _) => Some(Message::FloatingPasteCommitted),
+            (key::Key::Named(key::Named::Escape), _) => Some(Message::EscapePressed),
+            (key::Key::Named(key::Named::Delete), _)
+            | (key::Key::Named(key::Named::Backspace), _) => {
+                // Clear selection or delete key
+                Some(Message::SelectionCleared)
             }
-        }
-        Message::LayerRenamed { index, name } => {
-            if let Some(layer) = state.layers.get_mut(index)
-                && !name.is_empty()
+            // The color history key is a setting, so `update` checks it. Keys
+            // typed into a focused text input never get here
+            (key::Key::Character(c), _)
+                if modifiers.is_empty() || modifiers == keyboard::Modifiers::SHIFT =>
             {
-                layer.name = name;
+                let mut chars = c.chars().flat_map(char::to_lowercase);
+                match (chars.next(), chars.next()) {
+                    (Some(key), None) => Some(Message::RecentColorKeyPressed {
+                        key,
+                        shift: modifiers.shift(),
+                    }),
+                    _ => None,
+                }
             }
+            _ => None,
         }
-        Message::DrawingStarted { x, y } => {
-            let is_selection_tool = matches!(state.current_tool, state::Tool::Selection);
-            let is_eyedropper = matches!(state.current_tool, state::Tool::Eyedropper);
-
-            state.is_drawing = !is_selection_tool && !is_eyedropper;
-            state.is_selecting = is_selection_tool;
-            state.last_pixel = Some((x, y));
+    });
 
-            match state.current_tool {
-                state::Tool::Pencil => {
-                    tools::apply_pencil(state, x, y);
-                }
-                state::Tool::Eraser => {
-                    tools::apply_eraser(state, x, y);
-                }
-                state::Tool::Fill => {
-                    tools::apply_fill(state, x, y);
-                }
-                state::Tool::Selection => {
-                    state.selection = Some(iced::Rectangle {
-                        x: x as f32,
-                        y: y as f32,
-                        width: 0.0,
-                        height: 0.0,
-                    });
-                }
-                state::Tool::Eyedropper => {
-                    tools::apply_eyedropper(state, x, y);
-                }
+    // A layer drag ends wherever the button is released, even off the panel
+    let layer_drop = if app.active_document().layer_drag.is_some() {
+        iced::event::listen_with(|event, _status, _id| match event {
+            iced::Event::Mouse(iced::mouse::Event::ButtonReleased(iced::mouse::Button::Left)) => {
+                Some(Message::LayerDragEnded)
             }
+            _ => None,
+        })
+    } else {
+        iced::Subscription::none()
+    };
+
+    let key_presses = if app.active_document().kiosk_mode {
+        kiosk_key_presses
+    } else {
+        key_presses
+    };
+
+    iced::Subscription::batch([key_presses, close_requests, window_events, layer_drop])
+}
+
+fn update(app: &mut App, message: Message) -> Task<Message> {
+    if let Some(recording) = &mut app.recording
+        && let Some(recorded) = recording::RecordedMessage::from_message(&message)
+    {
+        recording.messages.push(recorded);
+    }
+
+    match message {
+        Message::DocumentAdded => {
+            app.add_document();
         }
-        Message::PixelDrawn { x, y } => {
-            if state.is_drawing {
-                // Prevent drawing the same pixel twice in a row
-                if state.last_pixel != Some((x, y)) {
-                    state.last_pixel = Some((x, y));
-                    match state.current_tool {
-                        state::Tool::Pencil => {
-                            tools::apply_pencil(state, x, y);
-                        }
-                        state::Tool::Eraser => {
-                            tools::apply_eraser(state, x, y);
-                        }
-                        state::Tool::Fill | state::Tool::Selection | state::Tool::Eyedropper => {
-                            // Fill only happens on click, not drag
-                            // Selection is handled by SelectionUpdated messages
-                            // Eyedropper only works on click
-                        }
-                    }
-                }
-            }
+        Message::DocumentSelected(index) => {
+            app.select_document(index);
         }
-        Message::DrawingEnded => {
-            state.is_drawing = false;
-            state.last_pixel = None;
-            if !matches!(state.current_tool, state::Tool::Selection) {
-                state.is_selecting = false;
+        Message::DocumentCloseRequested(index) => {
+            let Some(document) = app.documents.get(index) else {
+                return Task::none();
+            };
+            if !document.is_dirty() {
+                app.close_document(index);
+                return Task::none();
             }
-        }
-        Message::FileNew => {
-            *state = EditorState::new(32, 32);
-        }
-        Message::FileOpen => {
+            let description = format!(
+                "\"{}\" has unsaved changes. Close it anyway?",
+                document.display_name()
+            );
+            // Other tabs may close while the dialog is up, moving this one
+            let id = document.id;
             return Task::perform(
-                async {
-                    let file = rfd::AsyncFileDialog::new()
-                        .add_filter("Image files", &["png", "jpg", "jpeg", "gif", "bmp"])
-                        .add_filter("PNG", &["png"])
-                        .add_filter("JPEG", &["jpg", "jpeg"])
-                        .add_filter("GIF", &["gif"])
-                        .add_filter("BMP", &["bmp"])
-                        .pick_file()
-                        .await;
-
-                    if let Some(file) = file {
-                        let path = file.path().to_string_lossy().to_string();
-                        let path_clone = path.clone();
-                        match file_io::load_image(file.path()) {
-                            Ok((_width, _height, pixels)) => Message::FileLoaded {
-                                path: path_clone,
-                                data: pixels,
-                            },
-                            Err(e) => {
-                                eprintln!("Failed to load image: {}", e);
-                                Message::None
-                            }
-                        }
+                async move {
+                    rfd::AsyncMessageDialog::new()
+                        .set_title("Close document")
+                        .set_description(description)
+                        .set_buttons(rfd::MessageButtons::YesNo)
+                        .show()
+                        .await
+                },
+                move |result| {
+                    if result == rfd::MessageDialogResult::Yes {
+                        Message::DocumentClosed(id)
                     } else {
                         Message::None
                     }
                 },
-                |msg| msg,
             );
         }
-        Message::FileSave => {
-            let format = state.selected_export_format;
-            let extension = match format {
-                message::ExportFormat::Png => "png",
-                message::ExportFormat::Gif => "gif",
-                message::ExportFormat::Bmp => "bmp",
+        Message::DocumentClosed(id) => {
+            if let Some(index) = app.document_index(id) {
+                app.close_document(index);
+            }
+        }
+        Message::RecordingStarted => match recording::Recording::start(app.active_document()) {
+            Ok(recording) => app.recording = Some(recording),
+            Err(e) => {
+                app.active_document_mut().notification =
+                    Some(format!("Failed to start recording: {}", e));
+            }
+        },
+        Message::RecordingStopped => {
+            let Some(recording) = app.recording.take() else {
+                return Task::none();
             };
-
             return Task::perform(
                 async move {
                     let file = rfd::AsyncFileDialog::new()
-                        .add_filter(format!("{} files", extension.to_uppercase()), &[extension])
-                        .add_filter("All files", &["*"])
-                        .set_file_name(format!("output.{}", extension))
+                        .add_filter("Session recordings", &["json"])
+                        .set_file_name("session.json")
                         .save_file()
                         .await;
 
-                    if let Some(file) = file {
-                        let path = file.path().to_string_lossy().to_string();
-                        Message::FileSaveDialogResult { path, format }
-                    } else {
-                        Message::None
+                    match file {
+                        Some(file) => match recording::save(file.path(), &recording) {
+                            Ok(()) => {
+                                Message::RecordingSaved(file.path().to_string_lossy().to_string())
+                            }
+                            Err(e) => Message::RecordingFailed(e),
+                        },
+                        None => Message::None,
                     }
                 },
                 |msg| msg,
             );
         }
-        Message::FileSaveDialogResult { path, format } => {
-            use std::path::Path;
-            if let Err(e) = file_io::save_image(state, Path::new(&path), format) {
-                eprintln!("Failed to save: {}", e);
-            } else {
-                // Emit FileSaved message
-                return Task::perform(
-                    async move { Message::FileSaved { path: path.clone() } },
-                    |msg| msg,
-                );
-            }
+        Message::RecordingSaved(path) => {
+            app.active_document_mut().notification = Some(format!("Saved recording {}", path));
         }
-        Message::ExportFormatSelected(format) => {
-            state.selected_export_format = format;
+        Message::RecordingFailed(e) => {
+            eprintln!("{}", e);
+            app.active_document_mut().notification = Some(e);
         }
-        Message::FileLoaded { path, data } => {
-            // Use the data directly if provided, otherwise load from path
-            let (width, height, pixels) = if !data.is_empty() {
-                // Calculate dimensions from data (assuming square for now)
-                let pixel_count = data.len() / 4;
-                let size = (pixel_count as f32).sqrt() as u32;
-                (size, size, data)
-            } else {
-                use std::path::Path;
-                match file_io::load_image(Path::new(&path)) {
-                    Ok(result) => result,
-                    Err(e) => {
-                        eprintln!("Failed to load image: {}", e);
-                        return Task::none();
+        Message::ReplayRequested => {
+            return Task::perform(
+                async {
+                    let file = rfd::AsyncFileDialog::new()
+                        .add_filter("Session recordings", &["json"])
+                        .pick_file()
+                        .await;
+
+                    match file {
+                        Some(file) => match recording::load(file.path()) {
+                            Ok(recording) => Message::ReplayLoaded(Box::new(recording)),
+                            Err(e) => Message::RecordingFailed(e),
+                        },
+                        None => Message::None,
                     }
+                },
+                |msg| msg,
+            );
+        }
+        Message::ReplayLoaded(recording) => {
+            // Replay into a fresh tab so the current document is untouched
+            let document = match recording.new_document(app.active_document()) {
+                Ok(document) => document,
+                Err(e) => {
+                    app.active_document_mut().notification =
+                        Some(format!("Failed to replay recording: {}", e));
+                    return Task::none();
                 }
             };
-            // Create a new layer with the loaded image
-            let mut new_layer = state::Layer::new("Imported".to_string(), width, height);
-            new_layer.pixels = pixels;
-            state.layers.push(new_layer);
-            state.active_layer_index = state.layers.len() - 1;
-            // Resize canvas if needed
-            if width > state.canvas_width || height > state.canvas_height {
-                state.canvas_width = width.max(state.canvas_width);
-                state.canvas_height = height.max(state.canvas_height);
-                // Resize all existing layers to match new canvas size
-                for layer in &mut state.layers {
-                    if layer.width != state.canvas_width || layer.height != state.canvas_height {
-                        let new_pixels =
-                            vec![0u8; (state.canvas_width * state.canvas_height * 4) as usize];
-                        layer.pixels = new_pixels;
-                        layer.width = state.canvas_width;
-                        layer.height = state.canvas_height;
-                    }
-                }
-            }
+            let id = document.id;
+            app.insert_document(document);
+            let count = recording.messages.len();
+            let tasks: Vec<Task<Message>> = recording
+                .messages
+                .into_iter()
+                .map(|recorded| update(app, recorded.into_message(id)))
+                .collect();
+            app.active_document_mut().notification =
+                Some(format!("Replayed {} recorded messages", count));
+            return Task::batch(tasks);
+        }
+        Message::ReopenSessionToggled(enabled) => {
+            app.reopen_last_session = enabled;
+        }
+        Message::WindowResized(id, size) => {
+            let position = app.window.and_then(|window| window.position);
+            app.window = Some(session::WindowGeometry {
+                width: size.width,
+                height: size.height,
+                position,
+            });
+            // Moving to a display with another scale factor also resizes
+            // the window, so this keeps the canvas snapping current
+            return iced::window::get_scale_factor(id).map(Message::ScaleFactorChanged);
         }
-        Message::FileSaved { path } => {
-            // File saved successfully - log the path
-            eprintln!("File saved successfully: {}", path);
+        Message::ScaleFactorChanged(scale_factor) => {
+            app.scale_factor = scale_factor;
         }
-        Message::Undo => {
-            if let Some(command) = state.history.undo() {
-                apply_undo_command(state, command);
+        Message::WindowMoved(position) => {
+            if let Some(window) = &mut app.window {
+                window.position = Some((position.x, position.y));
             }
         }
-        Message::Redo => {
-            if let Some(command) = state.history.redo() {
-                apply_redo_command(state, command);
+        Message::WindowCloseRequested(id) => {
+            let mut settings = settings::Settings::from_state(app.active_document());
+            settings.reopen_last_session = app.reopen_last_session;
+            if let Err(e) = settings::save(&settings) {
+                eprintln!("Failed to save settings: {}", e);
             }
-        }
-        Message::ZoomChanged(zoom) => {
-            state.zoom_level = utils::clamp_f32(zoom, 1.0, 32.0);
-        }
-        Message::ZoomIn => {
-            state.zoom_level = (state.zoom_level + 1.0).min(32.0);
-        }
-        Message::ZoomOut => {
-            state.zoom_level = (state.zoom_level - 1.0).max(1.0);
-        }
-        Message::GridToggled => {
-            state.grid_visible = !state.grid_visible;
-        }
-        Message::PanChanged { x, y } => {
-            // Store pan offset for future use
-            // Pan can be used for canvas offset when implementing panning
-            // For now, pan is handled by canvas scrolling, but we store the values
-            let _pan_x = x;
-            let _pan_y = y;
-        }
-        Message::SelectionStarted { x, y } => {
-            state.is_selecting = true;
-            state.selection = Some(iced::Rectangle {
-                x,
-                y,
-                width: 0.0,
-                height: 0.0,
-            });
-        }
-        Message::SelectionUpdated { x, y } => {
-            if state.is_selecting {
-                if let Some(sel) = &mut state.selection {
-                    sel.width = x - sel.x;
-                    sel.height = y - sel.y;
-                } else if state.current_tool == state::Tool::Selection {
-                    // Start selection if not already started
-                    state.selection = Some(iced::Rectangle {
-                        x,
-                        y,
-                        width: 0.0,
-                        height: 0.0,
-                    });
-                }
+            if let Err(e) = session::save(&session::Session::from_app(app)) {
+                eprintln!("Failed to save session: {}", e);
             }
-        }
-        Message::SelectionEnded => {
-            state.is_selecting = false;
-            if let Some(sel) = &mut state.selection {
-                if sel.width < 0.0 {
-                    sel.x += sel.width;
-                    sel.width = sel.width.abs();
-                }
-                if sel.height < 0.0 {
-                    sel.y += sel.height;
-                    sel.height = sel.height.abs();
-                }
+            return iced::window::close(id);
+        }
+        message => {
+            // Saving gives a document a path, so keep the session file current
+            // in case pxrs doesn't get to exit cleanly
+            let saved = matches!(message, Message::FileSaved { .. });
+            // Async results go to the document that started the work, and
+            // are dropped if it has been closed since
+            let index = match message.document() {
+                Some(id) => match app.document_index(id) {
+                    Some(index) => index,
+                    None => return Task::none(),
+                },
+                None => app.active,
+            };
+            let document = &mut app.documents[index];
+            let task = update::update_document(document, &mut app.clipboard, message);
+            // Whatever wrote RGBA pixels, indexed documents snap them back to
+            // palette entries
+            document.sync_indexed();
+            // A combined selection only makes sense alongside its bounding
+            // rectangle and at the current canvas size
+            if document.selection.is_none()
+                || document.selection_mask.as_ref().is_some_and(|mask| {
+                    mask.width() != document.canvas_width || mask.height() != document.canvas_height
+                })
+            {
+                document.selection_mask = None;
             }
-        }
-        Message::SelectionCleared => {
-            state.selection = None;
-            state.is_selecting = false;
-        }
-        Message::CopySelection => {
-            if let Some(selection) = state.selection
-                && let Some(pixels) = tools::get_selection_pixels(state, selection)
+            // A usage highlight is stale after any edit
+            if document
+                .color_usage
+                .as_ref()
+                .is_some_and(|usage| usage.revision != document.history.revision)
             {
-                // Calculate dimensions the same way as get_selection_pixels does
-                let start_x = crate::utils::clamp_u32(selection.x as i32, 0, state.canvas_width);
-                let start_y = crate::utils::clamp_u32(selection.y as i32, 0, state.canvas_height);
-                let end_x = crate::utils::clamp_u32(
-                    (selection.x + selection.width) as i32,
-                    0,
-                    state.canvas_width,
-                );
-                let end_y = crate::utils::clamp_u32(
-                    (selection.y + selection.height) as i32,
-                    0,
-                    state.canvas_height,
-                );
-                let width = end_x.saturating_sub(start_x);
-                let height = end_y.saturating_sub(start_y);
-                state.clipboard = Some(state::ClipboardData {
-                    pixels,
-                    width,
-                    height,
-                });
+                document.color_usage = None;
             }
-        }
-        Message::PasteSelection { x, y } => {
-            if let Some(clipboard) = state.clipboard.clone() {
-                tools::paste_pixels(
-                    state,
-                    &clipboard.pixels,
-                    x,
-                    y,
-                    clipboard.width,
-                    clipboard.height,
-                );
+            // Keep the comparison diff in step with edits
+            if document
+                .comparison
+                .as_ref()
+                .is_some_and(|comparison| comparison.revision != document.history.revision)
+            {
+                document.refresh_comparison();
             }
-        }
-        Message::CutSelection => {
-            if let Some(selection) = state.selection {
-                let canvas_width = state.canvas_width;
-                let canvas_height = state.canvas_height;
-                if let Some(pixels) = tools::get_selection_pixels(state, selection) {
-                    // Calculate dimensions the same way as get_selection_pixels does
-                    let start_x = crate::utils::clamp_u32(selection.x as i32, 0, canvas_width);
-                    let start_y = crate::utils::clamp_u32(selection.y as i32, 0, canvas_height);
-                    let end_x = crate::utils::clamp_u32(
-                        (selection.x + selection.width) as i32,
-                        0,
-                        canvas_width,
-                    );
-                    let end_y = crate::utils::clamp_u32(
-                        (selection.y + selection.height) as i32,
-                        0,
-                        canvas_height,
-                    );
-                    let width = end_x.saturating_sub(start_x);
-                    let height = end_y.saturating_sub(start_y);
-                    state.clipboard = Some(state::ClipboardData {
-                        pixels,
-                        width,
-                        height,
-                    });
-                    // Clear the selected area
-                    if let Some(layer) = state.active_layer_mut() {
-                        for y in start_y..end_y {
-                            for x in start_x..end_x {
-                                layer.set_pixel(x, y, iced::Color::TRANSPARENT);
-                            }
-                        }
-                    }
-                }
+            // Keep 9-slice guides on the canvas whatever changed its size
+            if let Some(slice) = document.nine_slice {
+                document.nine_slice =
+                    Some(slice.clamped(document.canvas_width, document.canvas_height));
             }
-        }
-        Message::CanvasEvent(event) => {
-            // Forward canvas events if needed
-            // Most are handled directly by canvas program
-            // Handle any additional canvas events here if needed
-            let _ = event;
-        }
-        Message::MirrorHorizontalToggled => {
-            state.mirror_horizontal = !state.mirror_horizontal;
-        }
-        Message::MirrorVerticalToggled => {
-            state.mirror_vertical = !state.mirror_vertical;
-        }
-        Message::None => {
-            // No-op message
+            if saved && let Err(e) = session::save(&session::Session::from_app(app)) {
+                eprintln!("Failed to save session: {}", e);
+            }
+            return task;
         }
     }
-
     Task::none()
 }
 
-fn apply_undo_command(state: &mut EditorState, command: state::EditCommand) {
-    match command {
-        state::EditCommand::PixelChange {
-            layer_index,
-            x,
-            y,
-            old_color,
-            ..
-        } => {
-            if let Some(layer) = state.layers.get_mut(layer_index) {
-                layer.set_pixel(x, y, old_color);
-            }
-        }
-        state::EditCommand::MultiPixelChange {
-            layer_index,
-            changes,
-        } => {
-            if let Some(layer) = state.layers.get_mut(layer_index) {
-                for (x, y, old_color, _) in changes {
-                    layer.set_pixel(x, y, old_color);
-                }
-            }
-        }
-    }
+fn view(app: &App) -> iced::Element<'_, Message> {
+    ui::view(app)
 }
 
-fn apply_redo_command(state: &mut EditorState, command: state::EditCommand) {
-    match command {
-        state::EditCommand::PixelChange {
-            layer_index,
-            x,
-            y,
-            new_color,
-            ..
-        } => {
-            if let Some(layer) = state.layers.get_mut(layer_index) {
-                layer.set_pixel(x, y, new_color);
-            }
-        }
-        state::EditCommand::MultiPixelChange {
-            layer_index,
-            changes,
-        } => {
-            if let Some(layer) = state.layers.get_mut(layer_index) {
-                for (x, y, _, new_color) in changes {
-                    layer.set_pixel(x, y, new_color);
-                }
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn async_results_reach_their_document_after_tabs_move() {
+        let mut app = App::new(EditorState::new(4, 4));
+        app.add_document();
+        app.add_document();
+        let (first, exporting) = (app.documents[0].id, app.documents[1].id);
+        app.documents[1].exporting = true;
+
+        // Closing the first tab moves the exporting one to index 0
+        let _ = update(&mut app, Message::DocumentClosed(first));
+        let _ = update(
+            &mut app,
+            Message::ExportFailed(exporting, "disk full".into()),
+        );
+
+        assert_eq!(app.document_index(exporting), Some(0));
+        assert!(!app.documents[0].exporting);
+        assert_eq!(app.active, 1);
+        assert_eq!(app.documents[1].notification, None);
     }
-}
 
-fn view(state: &EditorState) -> iced::Element<'_, Message> {
-    ui::view(state)
+    #[test]
+    fn async_results_for_closed_documents_are_dropped() {
+        let mut app = App::new(EditorState::new(4, 4));
+        app.add_document();
+        let closed = app.documents[0].id;
+        let _ = update(&mut app, Message::DocumentClosed(closed));
+        let _ = update(&mut app, Message::ExportFailed(closed, "disk full".into()));
+
+        assert_eq!(app.documents.len(), 1);
+        assert_eq!(app.documents[0].notification, None);
+    }
+
+    #[test]
+    fn exports_finish_on_the_tab_that_started_them() {
+        let mut app = App::new(EditorState::new(4, 4));
+        let first = app.documents[0].id;
+        app.add_document();
+        let settings = app.documents[0].export;
+
+        // The save dialog was opened on the first tab, then tabs switched
+        let _ = update(
+            &mut app,
+            Message::FileSaveDialogResult {
+                document: first,
+                path: "out.png".into(),
+                settings,
+            },
+        );
+        assert!(app.documents[0].exporting);
+        assert!(!app.documents[1].exporting);
+
+        let _ = update(&mut app, Message::DocumentSelected(0));
+        let _ = update(&mut app, Message::DocumentSelected(1));
+        let _ = update(&mut app, Message::ExportFailed(first, "disk full".into()));
+        assert!(!app.documents[0].exporting);
+        assert_eq!(
+            app.documents[0].notification.as_deref(),
+            Some("Failed to save: disk full")
+        );
+        assert_eq!(app.documents[1].notification, None);
+    }
+
+    #[test]
+    fn scans_and_imports_finish_on_the_tab_that_started_them() {
+        let mut app = App::new(EditorState::new(4, 4));
+        let first = app.documents[0].id;
+        app.add_document();
+        let _ = update(&mut app, Message::DocumentSelected(0));
+        let _ = update(&mut app, Message::ColorScanRequested { rebuild: None });
+        let _ = update(&mut app, Message::DocumentScanRequested);
+        let _ = update(&mut app, Message::DocumentSelected(1));
+        let second_colors = app.documents[1].used_colors.clone();
+
+        let red = [255, 0, 0, 255];
+        let _ = update(
+            &mut app,
+            Message::ColorScanCompleted {
+                document: first,
+                colors: vec![(red, 3)],
+                rebuild: Some(palette::PaletteSort::Frequency),
+            },
+        );
+        let _ = update(
+            &mut app,
+            Message::DocumentScanned(first, state::DocumentScan::scan(0, &[])),
+        );
+        let _ = update(&mut app, Message::PaletteImported(first, vec![red]));
+
+        assert!(!app.documents[0].color_scan_pending);
+        assert!(!app.documents[0].document_scan_pending);
+        assert!(app.documents[0].document_scan.is_some());
+        assert_eq!(app.documents[0].color_count, Some(1));
+        assert_eq!(app.documents[0].used_colors.len(), 1);
+        assert_eq!(app.documents[1].used_colors, second_colors);
+        assert_eq!(app.documents[1].color_count, None);
+        assert!(app.documents[1].document_scan.is_none());
+    }
 }
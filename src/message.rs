@@ -1,5 +1,16 @@
-use crate::state::Tool;
+use crate::filters::Filter;
+use crate::font::BitmapFont;
+use crate::palette::PaletteSort;
+use crate::project::Project;
+use crate::recording::Recording;
+use crate::state::{
+    ColorTarget, ColorVision, CompareView, DocumentId, DocumentScan, ExportProfile, ExportSettings,
+    JitterOptions, RampGenerator, RampShift, SafeAreaPreset, ScaleFactor, SliceEdge, Tool,
+    UsedColorSort, WheelBrushModifier,
+};
+use crate::transform::{BufferTransform, RotationAlgorithm, ShiftAxis};
 use iced::Color;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub enum Message {
@@ -10,57 +21,412 @@ pub enum Message {
     PrimaryColorChanged(Color),
     SecondaryColorChanged(Color),
     ColorPicked(Color),
+    // A plain or Shift+ letter key, which steps the recent colors when it is
+    // the color history key
+    RecentColorKeyPressed {
+        key: char,
+        shift: bool,
+    },
     UsedColorPicked(Color),
+    // Indices into `EditorState::used_colors`
+    UsedColorPinned(usize),
+    UsedColorRemoved(usize),
+    // Removes every unpinned color
+    UsedColorsCleared,
+    UsedColorSortChanged(UsedColorSort),
+    NearDuplicatesOpened,
+    NearDuplicatesToleranceChanged(u8),
+    NearDuplicatesClosed,
+    NearDuplicateMerged {
+        from: [u8; 4],
+        into: [u8; 4],
+    },
+
+    // Comparison with a reference image
+    CompareRequested,
+    CompareLoaded {
+        document: DocumentId,
+        path: String,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    CompareFailed(String),
+    CompareViewChanged(CompareView),
+    CompareClosed,
+
+    // Color picker popup
+    ColorPickerOpened(ColorTarget),
+    ColorPickerSaturationValueChanged {
+        saturation: f32,
+        value: f32,
+    },
+    ColorPickerHueChanged(f32),
+    ColorPickerAlphaChanged(f32),
+    ColorPickerHexChanged(String),
+    ColorPickerConfirmed,
+    ColorPickerCancelled,
+    // Alt+click on a swatch: highlight every pixel using the color
+    ColorUsageHighlighted(Color),
+
+    // Indexed color mode
+    IndexedModeToggled(bool),
+    PaletteEntrySelected(usize),
+    PaletteEntryRecolored {
+        index: usize,
+        color: Color,
+    },
+
+    // Shading ramps
+    RampDraftStarted,
+    RampDraftColorToggled(Color),
+    RampDraftFinished,
+    RampDraftCancelled,
+    RampDeleted(usize),
+    RampGeneratorOpened,
+    RampGeneratorChanged(RampGenerator),
+    RampGeneratorAccepted,
+    RampGeneratorCancelled,
+    RampStepped(i32),
+    RampShiftOpened(usize),
+    RampShiftChanged(RampShift),
+    RampShiftApplied,
+    RampShiftCancelled,
+
+    // Document color analysis
+    ColorScanRequested {
+        rebuild: Option<PaletteSort>,
+    },
+    ColorScanCompleted {
+        document: DocumentId,
+        colors: Vec<([u8; 4], usize)>,
+        rebuild: Option<PaletteSort>,
+    },
+    PaletteImportRequested,
+    PaletteImported(DocumentId, Vec<[u8; 4]>),
+    PaletteImportLimitChanged(u32),
 
     // Brush settings
     BrushSizeChanged(u32),
+    BrushOpacityChanged(f32),
+    FillPatternToggled(bool),
+    PixelSnapChanged(u32),
+    FillPreviewToggled(bool),
 
     // Canvas operations
-    CanvasResized { width: u32, height: u32 },
+    CanvasResized {
+        width: u32,
+        height: u32,
+    },
+    CanvasWidthInputChanged(String),
+    CanvasHeightInputChanged(String),
+    CanvasAspectLockToggled(bool),
+    CanvasSizeSubmitted,
     CanvasCleared,
+    ImageScaled(ScaleFactor),
+    ScaleFactorInputChanged(String),
+
+    // Filters
+    FilterOpened(Filter),
+    FilterChanged(Filter),
+    FilterApplied,
+    GradientMapColorAdded(Color),
+    GradientMapColorRemoved(usize),
+    FilterCancelled,
+    // `None` keys on the active layer's top-left pixel
+    ColorMadeTransparent(Option<Color>),
+    StrayPixelsHighlighted,
+    StrayPixelsHidden,
+    StrayPixelsDeleted,
+    AlphaEditToggled(bool),
+    LuminanceToAlpha,
+    AlphaToLuminance,
+    TransparencyToleranceChanged(u8),
+
+    // Rotation of the selection or active layer
+    RotationChanged {
+        degrees: f32,
+        algorithm: RotationAlgorithm,
+    },
+    RotationApplied,
+    RotationCancelled,
+
+    // Shifting one row or column of the active layer with wrap
+    LineShiftChanged {
+        axis: ShiftAxis,
+        index: u32,
+        offset: i32,
+    },
+    LineShiftApplied,
+    LineShiftCancelled,
 
     // Layer operations
     LayerAdded(String),
     LayerDeleted(usize),
-    LayerMoved { from: usize, to: usize },
+    LayerMoved {
+        from: usize,
+        to: usize,
+    },
+    LayerDragStarted(usize),
+    LayerDragHovered(Option<usize>),
+    LayerDragEnded,
     LayerVisibilityToggled(usize),
+    LayerSoloToggled(usize),
+    HiddenLayerShown(usize),
+    HiddenLayerWarningToggled(bool),
+    LayerClippingToggled(usize),
     LayerSelected(usize),
-    LayerOpacityChanged { index: usize, opacity: f32 },
-    LayerRenamed { index: usize, name: String },
+    LayerOpacityChanged {
+        index: usize,
+        opacity: f32,
+    },
+    LayerRenamed {
+        index: usize,
+        name: String,
+    },
+    LayerNameClicked(usize),
+    LayerHovered(Option<usize>),
+    LayerRenameStarted(usize),
+    LayerRenameEdited(String),
+    LayerRenameCommitted,
+    LayerRenameCancelled,
+
+    NotificationDismissed,
+
+    // Sent when a continuous control (slider drag) is released
+    ContinuousEditFinished,
 
     // Drawing operations
-    PixelDrawn { x: u32, y: u32 },
-    DrawingStarted { x: u32, y: u32 },
+    // `pressure` is the pen force in 0.0..=1.0 where the platform reports it
+    PixelDrawn {
+        x: u32,
+        y: u32,
+        pressure: Option<f32>,
+    },
+    DrawingStarted {
+        x: u32,
+        y: u32,
+        pressure: Option<f32>,
+    },
+    PressureSensitivityToggled(bool),
+    StabilizerChanged(u32),
+    PencilJitterChanged(JitterOptions),
+    WheelBrushModifierChanged(WheelBrushModifier),
+    IsometricAssistToggled(bool),
+    TileModeToggled(bool),
+    TileSizeChanged {
+        width: u32,
+        height: u32,
+    },
+    TilePickRequested,
+    TileSelected {
+        x: u32,
+        y: u32,
+    },
+    TileDragStarted {
+        x: u32,
+        y: u32,
+    },
+    // Where the tile was dropped, or `None` if it left the canvas
+    SeamsExposed,
+    SeamsRestored,
+    TileDropped(Option<(u32, u32)>),
+    TilesExportRequested,
+    TilesExportFolderSelected(DocumentId, String),
+    OutlinesExportRequested,
+    OutlinesExportPathSelected(DocumentId, String),
+    PatternSheetExportRequested,
+    PatternSheetExportPathSelected(DocumentId, String),
+    TextFontSelected(BitmapFont),
+    TextTyped(String),
+    TextBackspaced,
+    TextCommitted,
+    TextCancelled,
     DrawingEnded,
+    FillHovered {
+        x: u32,
+        y: u32,
+    },
+    FillHoverEnded,
+
+    // Documents (tabs)
+    DocumentAdded,
+    DocumentSelected(usize),
+    DocumentCloseRequested(usize),
+    DocumentClosed(DocumentId),
+
+    // 9-slice guides
+    NineSliceToggled(bool),
+    NineSliceSidecarToggled(bool),
+    SliceGuideDragStarted(SliceEdge),
+    SliceGuideMoved {
+        edge: SliceEdge,
+        position: u32,
+    },
+    SliceGuideDragEnded,
 
     // File operations
     FileNew,
     FileOpen,
     FileSave,
-    FileSaveDialogResult { path: String, format: ExportFormat },
-    ExportFormatSelected(ExportFormat),
-    FileLoaded { path: String, data: Vec<u8> },
-    FileSaved { path: String },
+    FileSaveDialogResult {
+        document: DocumentId,
+        path: String,
+        settings: ExportSettings,
+    },
+    ExportSettingsChanged(ExportSettings),
+    FlattenAgainstMatte,
+    ExportProfileAddRequested,
+    ExportProfileAdded {
+        path: String,
+        settings: ExportSettings,
+    },
+    ExportProfileChanged(usize, ExportProfile),
+    ExportProfileRemoved(usize),
+    ExportAllRequested,
+    ExportAllFinished(DocumentId, Vec<String>),
+    ProjectLoaded {
+        document: DocumentId,
+        path: String,
+        project: Box<Project>,
+    },
+    FileLoaded {
+        document: DocumentId,
+        path: String,
+        // Width, height and RGBA pixels; `None` reads the image from `path`
+        image: Option<(u32, u32, Vec<u8>)>,
+    },
+    FileSaved {
+        document: DocumentId,
+        path: String,
+        // Set when the image was written without something, like its layers
+        warning: Option<String>,
+    },
+    FolderImportRequested,
+    FolderImportStarted(Vec<std::path::PathBuf>),
+    FolderImageLoaded {
+        document: DocumentId,
+        path: std::path::PathBuf,
+        result: Result<(u32, u32, Vec<u8>), String>,
+    },
+    FolderImportFailed(String),
+    ImportSnapToggled(bool),
+    ImportDitherToggled(bool),
+    ImportColorsToggled(bool),
+    ExportFailed(DocumentId, String),
+    DocumentScanRequested,
+    DocumentScanned(DocumentId, DocumentScan),
 
     // Undo/Redo
     Undo,
     Redo,
+    UndoActiveLayerOnlyToggled(bool),
+    RestorePointNameChanged(String),
+    RestorePointCreated,
+    RestorePointRestored(usize),
+    RestorePointDeleted(usize),
+    RestorePointsSavedToggled(bool),
 
     // View operations
     ZoomChanged(f32),
     ZoomIn,
     ZoomOut,
+    // Zoom with the canvas moved by a new pan offset, to keep the point
+    // under the cursor in place
+    ZoomedAt {
+        zoom: f32,
+        pan: (f32, f32),
+    },
+    ViewPanned {
+        dx: f32,
+        dy: f32,
+    },
+    ViewFlipped,
+    ViewRotated,
+    ViewReset,
+    SafeAreaSelected(String),
+    SafeAreaHidden,
+    SafeAreaDraftStarted,
+    SafeAreaDraftChanged(SafeAreaPreset),
+    SafeAreaDraftSaved,
+    SafeAreaDraftCancelled,
+    SafeAreaDeleted(String),
+    KioskModeEntered,
+    KioskUnlockPressed,
+    KioskUnlockReleased,
     GridToggled,
-    PanChanged { x: f32, y: f32 },
+    ColorVisionChanged(ColorVision),
+    OutlineViewToggled(bool),
+    PanChanged {
+        x: f32,
+        y: f32,
+    },
 
     // Selection
-    SelectionStarted { x: f32, y: f32 },
-    SelectionUpdated { x: f32, y: f32 },
+    SelectionStarted {
+        x: f32,
+        y: f32,
+    },
+    SelectionUpdated {
+        x: f32,
+        y: f32,
+    },
     SelectionEnded,
+    SelectionCloneStarted {
+        x: u32,
+        y: u32,
+    },
+    SelectionCloneMoved {
+        x: u32,
+        y: u32,
+    },
+    SelectionCloneEnded,
     SelectionCleared,
     CopySelection,
-    PasteSelection { x: u32, y: u32 },
+    PasteSelection,
+    PastePreviewStarted,
+    ClipboardTransformed(BufferTransform),
+    TrimOnCopyToggled(bool),
+    SampleMergedToggled(Tool, bool),
+    PixelPerfectCurvesToggled(bool),
+    PastePreviewPlaced {
+        x: u32,
+        y: u32,
+    },
+    FloatingPasteNudged {
+        dx: i32,
+        dy: i32,
+    },
+    FloatingPasteDragStarted {
+        x: u32,
+        y: u32,
+    },
+    FloatingPasteMoved {
+        x: u32,
+        y: u32,
+    },
+    FloatingPasteDragEnded,
+    FloatingPasteCommitted,
     CutSelection,
+    ModifiersChanged(iced::keyboard::Modifiers),
+    SelectionExportRequested,
+    SelectionExportDialogResult {
+        document: DocumentId,
+        path: String,
+        settings: ExportSettings,
+    },
+    SelectionImportRequested,
+    SelectionImageLoaded {
+        document: DocumentId,
+        path: String,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+
+    // Escape: drops a paste, the color usage highlight, an open color
+    // picker or an unfinished shape
+    EscapePressed,
 
     // Canvas events
     CanvasEvent(iced::widget::canvas::Event),
@@ -69,15 +435,96 @@ pub enum Message {
     MirrorHorizontalToggled,
     MirrorVerticalToggled,
 
+    // Wrap-around drawing
+    WrapEdgesToggled,
+
+    // Keep tools off fully transparent pixels
+    PreserveTransparencyToggled,
+
+    // Window events
+    WindowCloseRequested(iced::window::Id),
+    WindowResized(iced::window::Id, iced::Size),
+    WindowMoved(iced::Point),
+    ScaleFactorChanged(f32),
+    ReopenSessionToggled(bool),
+
+    // Session recording and replay
+    RecordingStarted,
+    RecordingStopped,
+    RecordingSaved(String),
+    RecordingFailed(String),
+    ReplayRequested,
+    ReplayLoaded(Box<Recording>),
+
     // No-op
     None,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Message {
+    /// The document that started the async work this message reports on.
+    /// These go to that document even if another tab is active by then.
+    pub fn document(&self) -> Option<DocumentId> {
+        match self {
+            Self::CompareLoaded { document, .. }
+            | Self::ColorScanCompleted { document, .. }
+            | Self::PaletteImported(document, _)
+            | Self::TilesExportFolderSelected(document, _)
+            | Self::OutlinesExportPathSelected(document, _)
+            | Self::PatternSheetExportPathSelected(document, _)
+            | Self::FileSaveDialogResult { document, .. }
+            | Self::ExportAllFinished(document, _)
+            | Self::ProjectLoaded { document, .. }
+            | Self::FileLoaded { document, .. }
+            | Self::FileSaved { document, .. }
+            | Self::FolderImageLoaded { document, .. }
+            | Self::ExportFailed(document, _)
+            | Self::DocumentScanned(document, _)
+            | Self::SelectionExportDialogResult { document, .. }
+            | Self::SelectionImageLoaded { document, .. } => Some(*document),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ExportFormat {
     Png,
     Gif,
     Bmp,
+    Ico,
+    WebP,
+    Tga,
+    Svg,
+    Rgb565,
+    Mono,
+}
+
+impl ExportFormat {
+    /// Every format, in the order the Export panel lists them.
+    pub const ALL: [ExportFormat; 9] = [
+        ExportFormat::Png,
+        ExportFormat::Gif,
+        ExportFormat::Bmp,
+        ExportFormat::Ico,
+        ExportFormat::WebP,
+        ExportFormat::Tga,
+        ExportFormat::Svg,
+        ExportFormat::Rgb565,
+        ExportFormat::Mono,
+    ];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Gif => "gif",
+            ExportFormat::Bmp => "bmp",
+            ExportFormat::Ico => "ico",
+            ExportFormat::WebP => "webp",
+            ExportFormat::Tga => "tga",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Rgb565 | ExportFormat::Mono => "bin",
+        }
+    }
 }
 
 impl std::fmt::Display for ExportFormat {
@@ -86,6 +533,38 @@ impl std::fmt::Display for ExportFormat {
             ExportFormat::Png => write!(f, "PNG"),
             ExportFormat::Gif => write!(f, "GIF"),
             ExportFormat::Bmp => write!(f, "BMP"),
+            ExportFormat::Ico => write!(f, "ICO"),
+            ExportFormat::WebP => write!(f, "WebP (lossless)"),
+            ExportFormat::Tga => write!(f, "TGA"),
+            ExportFormat::Svg => write!(f, "SVG"),
+            ExportFormat::Rgb565 => write!(f, "RGB565 raw"),
+            ExportFormat::Mono => write!(f, "1-bit raw"),
+        }
+    }
+}
+
+/// Upscaling applied to raster exports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportUpscale {
+    None,
+    Scale2x,
+    Scale4x,
+}
+
+impl ExportUpscale {
+    pub const ALL: [ExportUpscale; 3] = [
+        ExportUpscale::None,
+        ExportUpscale::Scale2x,
+        ExportUpscale::Scale4x,
+    ];
+}
+
+impl std::fmt::Display for ExportUpscale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportUpscale::None => write!(f, "1x"),
+            ExportUpscale::Scale2x => write!(f, "2x (Scale2x)"),
+            ExportUpscale::Scale4x => write!(f, "4x (Scale4x)"),
         }
     }
 }
@@ -0,0 +1,285 @@
+use crate::utils;
+use std::collections::{BTreeSet, HashMap};
+
+/// Ordering used when rebuilding the palette from the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteSort {
+    Frequency,
+    Hue,
+    Luminance,
+}
+
+/// Count every distinct RGBA value across the given layer buffers, skipping
+/// fully transparent pixels. The result is sorted by descending frequency.
+pub fn count_colors<'a>(buffers: impl IntoIterator<Item = &'a [u8]>) -> Vec<([u8; 4], usize)> {
+    let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+    for buffer in buffers {
+        for pixel in buffer.chunks_exact(4) {
+            if pixel[3] == 0 {
+                continue;
+            }
+            *counts
+                .entry([pixel[0], pixel[1], pixel[2], pixel[3]])
+                .or_insert(0) += 1;
+        }
+    }
+
+    let mut colors: Vec<([u8; 4], usize)> = counts.into_iter().collect();
+    // Break frequency ties by value so the order is deterministic
+    colors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    colors
+}
+
+/// Canvas positions where any of the given layer buffers (each `width`
+/// pixels wide) holds exactly `color`, each listed once.
+pub fn find_color<'a>(
+    buffers: impl IntoIterator<Item = &'a [u8]>,
+    width: u32,
+    color: [u8; 4],
+) -> Vec<(u32, u32)> {
+    let mut found = BTreeSet::new();
+    for buffer in buffers {
+        for (index, pixel) in buffer.chunks_exact(4).enumerate() {
+            if pixel == color {
+                let index = index as u32;
+                found.insert((index % width, index / width));
+            }
+        }
+    }
+    found.into_iter().collect()
+}
+
+/// Sort colors in place according to `sort`. Colors are expected to arrive in
+/// descending frequency order, as returned by `count_colors`.
+pub fn sort_colors(colors: &mut [([u8; 4], usize)], sort: PaletteSort) {
+    match sort {
+        PaletteSort::Frequency => colors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0))),
+        PaletteSort::Hue => colors.sort_by(|a, b| {
+            let (ha, sa, va) = utils::rgb_to_hsv(a.0);
+            let (hb, sb, vb) = utils::rgb_to_hsv(b.0);
+            // Keep grays together at the start, ordered by brightness
+            let gray_a = sa < 0.01;
+            let gray_b = sb < 0.01;
+            gray_b
+                .cmp(&gray_a)
+                .then(ha.total_cmp(&hb))
+                .then(va.total_cmp(&vb))
+        }),
+        PaletteSort::Luminance => {
+            colors.sort_by_key(|(rgba, _)| crate::filters::luminance(*rgba));
+        }
+    }
+}
+
+/// Pairs of distinct colors whose channels all lie within `tolerance` of
+/// each other, closest first, with the largest channel difference.
+pub fn near_duplicates(colors: &[[u8; 4]], tolerance: u8) -> Vec<([u8; 4], [u8; 4], u8)> {
+    let mut pairs = Vec::new();
+    for (index, &a) in colors.iter().enumerate() {
+        for &b in &colors[index + 1..] {
+            if a != b && utils::colors_match(a, b, tolerance) {
+                let distance = a.iter().zip(b).map(|(x, y)| x.abs_diff(y)).max();
+                pairs.push((a, b, distance.unwrap_or(0)));
+            }
+        }
+    }
+    pairs.sort_by_key(|pair| pair.2);
+    pairs
+}
+
+/// Locate `color` in `ramps` as `(ramp index, entry index)`. An exact match
+/// wins; otherwise the closest entry by squared RGBA distance is used.
+pub fn ramp_position(ramps: &[Vec<[u8; 4]>], color: [u8; 4]) -> Option<(usize, usize)> {
+    let distance = |entry: &[u8; 4]| -> u32 {
+        entry
+            .iter()
+            .zip(color)
+            .map(|(a, b)| (*a as i32 - b as i32).pow(2) as u32)
+            .sum()
+    };
+
+    ramps
+        .iter()
+        .enumerate()
+        .flat_map(|(ramp, entries)| {
+            entries
+                .iter()
+                .enumerate()
+                .map(move |(index, entry)| (ramp, index, entry))
+        })
+        .min_by_key(|(_, _, entry)| distance(entry))
+        .map(|(ramp, index, _)| (ramp, index))
+}
+
+/// The ramp entry `step` places before/after `color` in its ramp, clamped to
+/// the ends of the ramp.
+pub fn step_ramp(ramps: &[Vec<[u8; 4]>], color: [u8; 4], step: i32) -> Option<[u8; 4]> {
+    let (ramp, index) = ramp_position(ramps, color)?;
+    let entries = &ramps[ramp];
+    let target = (index as i32 + step).clamp(0, entries.len() as i32 - 1) as usize;
+    Some(entries[target])
+}
+
+/// `colors` with hue rotated `hue` degrees and `saturation` and `value`
+/// added, clamped to 0.0-1.0. Alpha is kept.
+pub fn shift_colors(colors: &[[u8; 4]], hue: f32, saturation: f32, value: f32) -> Vec<[u8; 4]> {
+    colors
+        .iter()
+        .map(|&rgba| {
+            let (h, s, v) = utils::rgb_to_hsv(rgba);
+            let [r, g, b] = utils::hsv_to_rgb(
+                (h + hue).rem_euclid(360.0),
+                (s + saturation).clamp(0.0, 1.0),
+                (v + value).clamp(0.0, 1.0),
+            );
+            [r, g, b, rgba[3]]
+        })
+        .collect()
+}
+
+/// A ramp of `steps` colors from `from` to `to`, interpolated in HSV with hue
+/// taking the shorter way around. Intermediate hues bow a further
+/// `hue_shift` degrees per step away from the nearer end (the hue-shifted
+/// ramp look) while both ends stay exactly as given.
+pub fn generate_ramp(from: [u8; 4], to: [u8; 4], steps: usize, hue_shift: f32) -> Vec<[u8; 4]> {
+    let (mut from_hue, from_saturation, from_value) = utils::rgb_to_hsv(from);
+    let (mut to_hue, to_saturation, to_value) = utils::rgb_to_hsv(to);
+    // Grays have no hue of their own, so they take the other end's
+    if from_saturation < 0.01 {
+        from_hue = to_hue;
+    } else if to_saturation < 0.01 {
+        to_hue = from_hue;
+    }
+    let mut hue_delta = to_hue - from_hue;
+    if hue_delta > 180.0 {
+        hue_delta -= 360.0;
+    } else if hue_delta < -180.0 {
+        hue_delta += 360.0;
+    }
+
+    let last = steps.saturating_sub(1).max(1);
+    (0..steps)
+        .map(|step| {
+            if step == 0 {
+                return from;
+            }
+            if step == last {
+                return to;
+            }
+            let t = step as f32 / last as f32;
+            let bow = hue_shift * step.min(last - step) as f32;
+            let hue = (from_hue + hue_delta * t + bow).rem_euclid(360.0);
+            let saturation = from_saturation + (to_saturation - from_saturation) * t;
+            let value = from_value + (to_value - from_value) * t;
+            let [r, g, b] = utils::hsv_to_rgb(hue, saturation, value);
+            let alpha = from[3] as f32 + (to[3] as f32 - from[3] as f32) * t;
+            [r, g, b, alpha.round() as u8]
+        })
+        .collect()
+}
+
+/// Build an indexed palette for the given buffers: a transparent entry
+/// followed by the document's opaque colors, keeping the `max_entries - 1`
+/// most frequent when there are too many.
+pub fn quantize_palette<'a>(
+    buffers: impl IntoIterator<Item = &'a [u8]>,
+    max_entries: usize,
+) -> Vec<[u8; 4]> {
+    let mut palette = vec![[0, 0, 0, 0]];
+    palette.extend(
+        count_colors(buffers)
+            .into_iter()
+            .take(max_entries.saturating_sub(1))
+            .map(|(rgba, _)| rgba),
+    );
+    palette
+}
+
+/// Index of the palette entry closest to `rgba`. Fully transparent colors
+/// always map to the transparent entry 0.
+pub fn nearest_index(palette: &[[u8; 4]], rgba: [u8; 4]) -> u8 {
+    if rgba[3] == 0 {
+        return 0;
+    }
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| {
+            entry
+                .iter()
+                .zip(rgba)
+                .map(|(a, b)| (*a as i32 - b as i32).pow(2) as u32)
+                .sum::<u32>()
+        })
+        .map(|(index, _)| index as u8)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check the hue, saturation and value of each color, allowing for
+    /// rounding to bytes.
+    fn assert_hsv(ramp: &[[u8; 4]], expected: &[(f32, f32, f32)]) {
+        assert_eq!(ramp.len(), expected.len());
+        for (&rgba, &(hue, saturation, value)) in ramp.iter().zip(expected) {
+            let (h, s, v) = utils::rgb_to_hsv(rgba);
+            let hue_error = (h - hue).rem_euclid(360.0).min((hue - h).rem_euclid(360.0));
+            assert!(hue_error < 1.0, "{:?} has hue {}, not {}", rgba, h, hue);
+            assert!(
+                (s - saturation).abs() < 0.01,
+                "{:?} has saturation {}",
+                rgba,
+                s
+            );
+            assert!((v - value).abs() < 0.01, "{:?} has value {}", rgba, v);
+        }
+    }
+
+    #[test]
+    fn ramps_take_the_shorter_way_around_the_hue_wheel() {
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let ramp = generate_ramp(red, blue, 5, 0.0);
+        assert_eq!((ramp[0], ramp[4]), (red, blue));
+        assert_hsv(
+            &ramp,
+            &[
+                (0.0, 1.0, 1.0),
+                (330.0, 1.0, 1.0),
+                (300.0, 1.0, 1.0),
+                (270.0, 1.0, 1.0),
+                (240.0, 1.0, 1.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn grays_borrow_the_other_ends_hue() {
+        let (black, green) = ([0, 0, 0, 255], [0, 255, 0, 255]);
+        let ramp = generate_ramp(black, green, 3, 0.0);
+        assert_eq!((ramp[0], ramp[2]), (black, green));
+        assert_hsv(&ramp[1..2], &[(120.0, 0.5, 0.5)]);
+    }
+
+    #[test]
+    fn hue_shift_bows_the_middle_and_keeps_the_ends() {
+        let from = [255, 0, 0, 255];
+        let to = [255, 0, 0, 55];
+        let ramp = generate_ramp(from, to, 5, 10.0);
+        assert_eq!((ramp[0], ramp[4]), (from, to));
+        assert_hsv(
+            &ramp,
+            &[
+                (0.0, 1.0, 1.0),
+                (10.0, 1.0, 1.0),
+                (20.0, 1.0, 1.0),
+                (10.0, 1.0, 1.0),
+                (0.0, 1.0, 1.0),
+            ],
+        );
+        let alphas: Vec<u8> = ramp.iter().map(|rgba| rgba[3]).collect();
+        assert_eq!(alphas, [255, 205, 155, 105, 55]);
+    }
+}
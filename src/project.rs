@@ -0,0 +1,455 @@
+use crate::state::{
+    CompressedPixels, DEFAULT_TILE_SIZE, DocSnapshot, EditorState, ExportProfile, ExportSettings,
+    History, Layer, LayerId, NineSlice, RestoreLayer, RestorePoint, UsedColor,
+};
+use crate::utils;
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Format version written as the first byte of embedded project data. Bump
+/// this when the serialized layout changes incompatibly. Version 1 kept the
+/// pixels inside the JSON and is still read.
+pub const PROJECT_VERSION: u8 = 2;
+
+/// Upper bound for decompressed project data, so a corrupt or hostile chunk
+/// can't exhaust memory.
+const MAX_PROJECT_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Layered document data that survives a round trip through a PNG.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub width: u32,
+    pub height: u32,
+    pub layers: Vec<ProjectLayer>,
+    pub active_layer_index: usize,
+    pub palette: Vec<[u8; 4]>,
+    // Pin flags parallel to `palette`
+    #[serde(default)]
+    pub pinned: Vec<bool>,
+    // Added after version 1 shipped; older files simply have no guides
+    #[serde(default)]
+    pub nine_slice: Option<NineSlice>,
+    #[serde(default)]
+    pub ramps: Vec<Vec<[u8; 4]>>,
+    #[serde(default)]
+    pub indexed_palette: Option<Vec<[u8; 4]>>,
+    #[serde(default)]
+    pub preserve_transparency: bool,
+    #[serde(default)]
+    pub tile_mode: bool,
+    #[serde(default = "default_tile_size")]
+    pub tile_size: (u32, u32),
+    #[serde(default)]
+    pub export_profiles: Vec<ExportProfile>,
+    #[serde(default)]
+    pub export: ExportSettings,
+    // Only written when the document opts in
+    #[serde(default)]
+    pub restore_points: Vec<ProjectRestorePoint>,
+}
+
+fn default_tile_size() -> (u32, u32) {
+    (DEFAULT_TILE_SIZE, DEFAULT_TILE_SIZE)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectLayer {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    // Written as raw bytes after the JSON rather than as a number array
+    #[serde(default, skip_serializing)]
+    pub pixels: Vec<u8>,
+    #[serde(default)]
+    pub clipped: bool,
+}
+
+/// A restore point as stored in a project, with its layers uncompressed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectRestorePoint {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub layers: Vec<ProjectLayer>,
+    pub active_layer_index: usize,
+}
+
+impl ProjectRestorePoint {
+    fn from_point(point: &RestorePoint) -> Self {
+        Self {
+            name: point.name.clone(),
+            width: point.snapshot.width,
+            height: point.snapshot.height,
+            layers: point
+                .layers
+                .iter()
+                .zip(point.snapshot.layers.iter().flatten())
+                .map(|(layer, pixels)| ProjectLayer {
+                    name: layer.name.clone(),
+                    visible: layer.visible,
+                    opacity: layer.opacity,
+                    pixels: pixels.decompress(),
+                    clipped: layer.clipped,
+                })
+                .collect(),
+            active_layer_index: point.active_layer_index,
+        }
+    }
+
+    /// The restore point, or `None` when its layers don't match its size.
+    fn into_point(self) -> Option<RestorePoint> {
+        let expected = (self.width as usize) * (self.height as usize) * 4;
+        if self.layers.is_empty()
+            || self
+                .layers
+                .iter()
+                .any(|layer| layer.pixels.len() != expected)
+        {
+            return None;
+        }
+        Some(RestorePoint {
+            name: self.name,
+            snapshot: DocSnapshot {
+                width: self.width,
+                height: self.height,
+                layers: self
+                    .layers
+                    .iter()
+                    .map(|layer| Some(CompressedPixels::compress(&layer.pixels)))
+                    .collect(),
+                seams_exposed: false,
+            },
+            layers: self
+                .layers
+                .into_iter()
+                .map(|layer| RestoreLayer {
+                    id: LayerId::next(),
+                    name: layer.name,
+                    visible: layer.visible,
+                    opacity: layer.opacity,
+                    clipped: layer.clipped,
+                })
+                .collect(),
+            active_layer_index: self.active_layer_index,
+        })
+    }
+}
+
+impl Project {
+    pub fn from_state(state: &EditorState) -> Self {
+        Self {
+            width: state.canvas_width,
+            height: state.canvas_height,
+            layers: state
+                .layers
+                .iter()
+                .map(|layer| ProjectLayer {
+                    name: layer.name.clone(),
+                    visible: layer.visible,
+                    opacity: layer.opacity,
+                    pixels: layer.pixels.clone(),
+                    clipped: layer.clipped,
+                })
+                .collect(),
+            active_layer_index: state.active_layer_index,
+            palette: state
+                .used_colors
+                .iter()
+                .map(|used| utils::color_to_rgba8(used.color))
+                .collect(),
+            pinned: state.used_colors.iter().map(|used| used.pinned).collect(),
+            nine_slice: state.nine_slice,
+            ramps: state.ramps.clone(),
+            indexed_palette: state.indexed_palette.clone(),
+            preserve_transparency: state.preserve_transparency,
+            tile_mode: state.tile_mode,
+            tile_size: (state.tile_width, state.tile_height),
+            export_profiles: state.export_profiles.clone(),
+            export: state.export,
+            restore_points: if state.save_restore_points {
+                state
+                    .restore_points
+                    .iter()
+                    .map(ProjectRestorePoint::from_point)
+                    .collect()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    /// Replace the document in `state` with this project. History is cleared
+    /// since it refers to the previous document.
+    pub fn apply_to(self, state: &mut EditorState) {
+        state.canvas_width = self.width;
+        state.canvas_height = self.height;
+        state.layers = self
+            .layers
+            .into_iter()
+            .map(|data| {
+                let mut layer = Layer::new(data.name, self.width, self.height);
+                layer.visible = data.visible;
+                layer.opacity = data.opacity;
+                layer.pixels = data.pixels;
+                layer.clipped = data.clipped;
+                layer
+            })
+            .collect();
+        state.active_layer_index = self.active_layer_index;
+        state.solo_layer = None;
+        state.used_colors = self
+            .palette
+            .into_iter()
+            .enumerate()
+            .map(|(index, rgba)| UsedColor {
+                color: utils::rgba8_to_color(rgba),
+                pinned: self.pinned.get(index).copied().unwrap_or(false),
+            })
+            .collect();
+        state.nine_slice = self
+            .nine_slice
+            .map(|slice| slice.clamped(self.width, self.height));
+        state.ramps = self.ramps;
+        state.indexed_palette = self.indexed_palette;
+        state.preserve_transparency = self.preserve_transparency;
+        state.tile_mode = self.tile_mode;
+        (state.tile_width, state.tile_height) = (self.tile_size.0.max(1), self.tile_size.1.max(1));
+        state.export_profiles = self.export_profiles;
+        state.export = self.export.clamped();
+        state.save_restore_points = !self.restore_points.is_empty();
+        state.restore_points = self
+            .restore_points
+            .into_iter()
+            .filter_map(ProjectRestorePoint::into_point)
+            .collect();
+        state.selected_palette_index = None;
+        state.sync_indexed();
+        state.selection = None;
+        state.history = History::new();
+        state.unsaved_changes = false;
+    }
+
+    /// Serialize to a version byte followed by zlib-compressed data: the
+    /// length of a JSON header as a big-endian u32, the header, then the
+    /// pixels of every layer, the canvas layers first and then each restore
+    /// point's. Fails rather than write more than `decode` accepts.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let header =
+            serde_json::to_vec(self).map_err(|e| format!("Failed to serialize project: {}", e))?;
+        let pixels: usize = self.pixel_layers().map(|layer| layer.pixels.len()).sum();
+        if (4 + header.len() + pixels) as u64 > MAX_PROJECT_SIZE {
+            return Err("Project data is too large".to_string());
+        }
+
+        let mut encoder = ZlibEncoder::new(vec![PROJECT_VERSION], Compression::default());
+        encoder
+            .write_all(&(header.len() as u32).to_be_bytes())
+            .and_then(|_| encoder.write_all(&header))
+            .and_then(|_| {
+                self.pixel_layers()
+                    .try_for_each(|layer| encoder.write_all(&layer.pixels))
+            })
+            .and_then(|_| encoder.finish())
+            .map_err(|e| format!("Failed to compress project: {}", e))
+    }
+
+    /// Layers in the order `encode` writes their pixels.
+    fn pixel_layers(&self) -> impl Iterator<Item = &ProjectLayer> {
+        self.layers.iter().chain(
+            self.restore_points
+                .iter()
+                .flat_map(|point| point.layers.iter()),
+        )
+    }
+
+    /// Decode data produced by `encode`, rejecting unknown versions and
+    /// projects whose layers don't match the canvas size.
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let (&version, compressed) = data.split_first().ok_or("Project data is empty")?;
+        if version == 0 || version > PROJECT_VERSION {
+            return Err(format!(
+                "Unsupported project version {} (expected {})",
+                version, PROJECT_VERSION
+            ));
+        }
+
+        let mut body = Vec::new();
+        ZlibDecoder::new(compressed)
+            .take(MAX_PROJECT_SIZE + 1)
+            .read_to_end(&mut body)
+            .map_err(|e| format!("Failed to decompress project: {}", e))?;
+        if body.len() as u64 > MAX_PROJECT_SIZE {
+            return Err("Project data is too large".to_string());
+        }
+
+        let project = if version == 1 {
+            parse_header(&body)?
+        } else {
+            let (length, rest) = body
+                .split_first_chunk::<4>()
+                .ok_or("Project data is truncated")?;
+            let length = u32::from_be_bytes(*length) as usize;
+            if length > rest.len() {
+                return Err("Project data is truncated".to_string());
+            }
+            let (header, mut pixels) = rest.split_at(length);
+            let mut project = parse_header(header)?;
+            let canvas = pixel_len(project.width, project.height);
+            for layer in &mut project.layers {
+                layer.pixels = take_pixels(&mut pixels, canvas)?;
+            }
+            for point in &mut project.restore_points {
+                let size = pixel_len(point.width, point.height);
+                for layer in &mut point.layers {
+                    layer.pixels = take_pixels(&mut pixels, size)?;
+                }
+            }
+            if !pixels.is_empty() {
+                return Err("Project layers don't match the canvas size".to_string());
+            }
+            project
+        };
+
+        let expected = pixel_len(project.width, project.height);
+        if project.layers.is_empty()
+            || project
+                .layers
+                .iter()
+                .any(|layer| Some(layer.pixels.len()) != expected)
+        {
+            return Err("Project layers don't match the canvas size".to_string());
+        }
+        if project.active_layer_index >= project.layers.len() {
+            return Err("Project active layer is out of range".to_string());
+        }
+
+        Ok(project)
+    }
+}
+
+fn parse_header(json: &[u8]) -> Result<Project, String> {
+    serde_json::from_slice(json).map_err(|e| format!("Failed to parse project: {}", e))
+}
+
+/// Bytes of RGBA pixels in a `width` x `height` layer, or `None` if that
+/// doesn't fit in memory.
+fn pixel_len(width: u32, height: u32) -> Option<usize> {
+    (width as usize)
+        .checked_mul(height as usize)?
+        .checked_mul(4)
+}
+
+/// Split the next `len` bytes of pixels off the front of `data`.
+fn take_pixels(data: &mut &[u8], len: Option<usize>) -> Result<Vec<u8>, String> {
+    let len = len
+        .filter(|&len| len <= data.len())
+        .ok_or("Project layers don't match the canvas size")?;
+    let (pixels, rest) = data.split_at(len);
+    *data = rest;
+    Ok(pixels.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_layer_document() -> EditorState {
+        let mut state = EditorState::new(3, 2);
+        state.layers[0].set_rgba8(0, 0, [255, 0, 0, 255]);
+        state.add_layer("Top".to_string());
+        state.layers[1].set_rgba8(2, 1, [0, 0, 255, 128]);
+        state.layers[1].opacity = 0.5;
+        state.save_restore_points = true;
+        state.create_restore_point("Before".to_string());
+        state.layers[1].set_rgba8(1, 1, [0, 255, 0, 255]);
+        state
+    }
+
+    #[test]
+    fn encoding_round_trips_layers_and_restore_points() {
+        let state = two_layer_document();
+        let project = Project::from_state(&state);
+        let decoded = Project::decode(&project.encode().expect("encodes")).expect("decodes");
+
+        assert_eq!((decoded.width, decoded.height), (3, 2));
+        assert_eq!(decoded.layers.len(), 2);
+        for (decoded, layer) in decoded.layers.iter().zip(&state.layers) {
+            assert_eq!(decoded.name, layer.name);
+            assert_eq!(decoded.opacity, layer.opacity);
+            assert_eq!(decoded.pixels, layer.pixels);
+        }
+        assert_eq!(decoded.restore_points.len(), 1);
+        let point = &decoded.restore_points[0];
+        assert_eq!(
+            point.layers[1].pixels,
+            project.restore_points[0].layers[1].pixels
+        );
+        assert_ne!(point.layers[1].pixels, state.layers[1].pixels);
+    }
+
+    #[test]
+    fn pixels_are_not_written_as_json() {
+        let mut project = Project::from_state(&EditorState::new(2, 2));
+        project.layers[0].pixels = vec![7; 16];
+        let header = serde_json::to_string(&project).expect("serializes");
+        assert!(!header.contains("pixels"));
+    }
+
+    #[test]
+    fn version_1_projects_still_decode() {
+        let project = Project::from_state(&two_layer_document());
+        let mut json: serde_json::Value = serde_json::to_value(&project).expect("serializes");
+        for (layer, data) in json["layers"]
+            .as_array_mut()
+            .expect("layers")
+            .iter_mut()
+            .zip(&project.layers)
+        {
+            layer["pixels"] = serde_json::json!(data.pixels);
+        }
+        json["restore_points"] = serde_json::json!([]);
+        let mut encoder = ZlibEncoder::new(vec![1], Compression::default());
+        encoder
+            .write_all(&serde_json::to_vec(&json).expect("serializes"))
+            .expect("compresses");
+        let data = encoder.finish().expect("compresses");
+
+        let decoded = Project::decode(&data).expect("decodes");
+        assert_eq!(decoded.layers[1].pixels, project.layers[1].pixels);
+    }
+
+    #[test]
+    fn truncated_pixels_are_rejected() {
+        let data = Project::from_state(&two_layer_document())
+            .encode()
+            .expect("encodes");
+        let mut body = Vec::new();
+        ZlibDecoder::new(&data[1..])
+            .read_to_end(&mut body)
+            .expect("decompresses");
+        body.truncate(body.len() - 1);
+        let mut encoder = ZlibEncoder::new(vec![PROJECT_VERSION], Compression::default());
+        encoder.write_all(&body).expect("compresses");
+
+        let result = Project::decode(&encoder.finish().expect("compresses"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn projects_past_the_size_limit_are_not_encoded() {
+        let mut project = Project::from_state(&EditorState::new(1, 1));
+        // Zeroed allocations are only committed when touched, and the size
+        // is checked before any pixel is read
+        let side = 8192;
+        project.width = side;
+        project.height = side;
+        project.layers[0].pixels = vec![0; side as usize * side as usize * 4];
+        assert_eq!(
+            project.encode(),
+            Err("Project data is too large".to_string())
+        );
+    }
+}
@@ -0,0 +1,915 @@
+use crate::file_io;
+use crate::filters::Filter;
+use crate::font::BitmapFont;
+use crate::message::Message;
+use crate::project::Project;
+use crate::state::{
+    DocumentId, EditorState, ExportSettings, JitterOptions, RampGenerator, RampShift, ScaleFactor,
+    Tool,
+};
+use crate::transform::{BufferTransform, RotationAlgorithm, ShiftAxis};
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A recorded editing session: the document it started on and the messages
+/// sent since, in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub width: u32,
+    pub height: u32,
+    // The document when recording started, as embedded project data.
+    // Recordings without one start on a blank canvas
+    #[serde(default)]
+    pub document: Option<Vec<u8>>,
+    pub messages: Vec<RecordedMessage>,
+}
+
+impl Recording {
+    /// Start recording `state` as it is now. Its layers, palette and other
+    /// saved settings are kept as project data, and the tool, colors and
+    /// drawing options as the messages that set them.
+    pub fn start(state: &EditorState) -> Result<Self, String> {
+        let options = &state.tool_options;
+        let mut messages = Vec::new();
+        for (&tool, brush) in &options.brushes {
+            messages.extend([
+                RecordedMessage::ToolSelected(tool),
+                RecordedMessage::BrushSizeChanged(brush.brush_size),
+                RecordedMessage::BrushOpacityChanged(brush.opacity),
+            ]);
+        }
+        messages.extend([
+            RecordedMessage::ToolSelected(state.current_tool),
+            RecordedMessage::PrimaryColorChanged(utils::color_to_rgba8(state.primary_color)),
+            RecordedMessage::SecondaryColorChanged(utils::color_to_rgba8(state.secondary_color)),
+            RecordedMessage::StabilizerChanged(options.freehand.stabilizer),
+            RecordedMessage::IsometricAssistToggled(options.freehand.isometric_assist),
+            RecordedMessage::PencilJitterChanged(options.jitter),
+            RecordedMessage::PixelSnapChanged(options.pixel_snap),
+            RecordedMessage::FillPatternToggled(options.fill.pattern),
+            RecordedMessage::SampleMergedToggled(Tool::Fill, options.fill.sample_merged),
+            RecordedMessage::SampleMergedToggled(
+                Tool::Eyedropper,
+                options.eyedropper.sample_merged,
+            ),
+            RecordedMessage::TextFontSelected(options.text.font),
+            RecordedMessage::PixelPerfectCurvesToggled(options.curve.pixel_perfect),
+            RecordedMessage::TrimOnCopyToggled(state.trim_on_copy),
+            RecordedMessage::AlphaEditToggled(state.alpha_edit),
+            RecordedMessage::UndoActiveLayerOnlyToggled(state.undo_active_layer_only),
+        ]);
+        for (on, toggle) in [
+            (
+                state.mirror_horizontal,
+                RecordedMessage::MirrorHorizontalToggled,
+            ),
+            (
+                state.mirror_vertical,
+                RecordedMessage::MirrorVerticalToggled,
+            ),
+            (state.wrap_edges, RecordedMessage::WrapEdgesToggled),
+        ] {
+            if on {
+                messages.push(toggle);
+            }
+        }
+
+        Ok(Self {
+            width: state.canvas_width,
+            height: state.canvas_height,
+            document: Some(Project::from_state(state).encode()?),
+            messages,
+        })
+    }
+
+    /// A fresh document like `template` to replay into, holding the
+    /// recorded starting document if there is one.
+    pub fn new_document(&self, template: &EditorState) -> Result<EditorState, String> {
+        let mut state = template.new_document(self.width, self.height);
+        // The recorded messages set the tool options the recording started
+        // with, on top of the defaults rather than the template's
+        state.tool_options = Default::default();
+        if let Some(data) = &self.document {
+            Project::decode(data)?.apply_to(&mut state);
+        }
+        Ok(state)
+    }
+}
+
+/// Serializable mirror of the `Message`s that change a drawing. Dialogs,
+/// view and window events aren't recorded, and loaded files are referenced
+/// by path and read again on replay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RecordedMessage {
+    ToolSelected(Tool),
+    PrimaryColorChanged([u8; 4]),
+    SecondaryColorChanged([u8; 4]),
+    ColorPicked([u8; 4]),
+    UsedColorPicked([u8; 4]),
+    RecentColorKeyPressed {
+        key: char,
+        shift: bool,
+    },
+    BrushSizeChanged(u32),
+    BrushOpacityChanged(f32),
+    FillPatternToggled(bool),
+    PixelSnapChanged(u32),
+    StabilizerChanged(u32),
+    PencilJitterChanged(JitterOptions),
+    IsometricAssistToggled(bool),
+    CanvasResized {
+        width: u32,
+        height: u32,
+    },
+    CanvasCleared,
+    LayerAdded(String),
+    LayerDeleted(usize),
+    LayerMoved {
+        from: usize,
+        to: usize,
+    },
+    LayerVisibilityToggled(usize),
+    LayerSoloToggled(usize),
+    HiddenLayerShown(usize),
+    LayerClippingToggled(usize),
+    LayerSelected(usize),
+    LayerOpacityChanged {
+        index: usize,
+        opacity: f32,
+    },
+    LayerRenamed {
+        index: usize,
+        name: String,
+    },
+    ContinuousEditFinished,
+    DrawingStarted {
+        x: u32,
+        y: u32,
+        pressure: Option<f32>,
+    },
+    PixelDrawn {
+        x: u32,
+        y: u32,
+        pressure: Option<f32>,
+    },
+    DrawingEnded,
+    TextFontSelected(BitmapFont),
+    TextTyped(String),
+    TextBackspaced,
+    TextCommitted,
+    TextCancelled,
+    Undo,
+    Redo,
+    UndoActiveLayerOnlyToggled(bool),
+    // Keyboard modifier bits, which decide how selections combine
+    ModifiersChanged(u32),
+    SelectionStarted {
+        x: f32,
+        y: f32,
+    },
+    SelectionUpdated {
+        x: f32,
+        y: f32,
+    },
+    SelectionEnded,
+    SelectionCloneStarted {
+        x: u32,
+        y: u32,
+    },
+    SelectionCloneMoved {
+        x: u32,
+        y: u32,
+    },
+    SelectionCloneEnded,
+    SelectionCleared,
+    CopySelection,
+    CutSelection,
+    PasteSelection,
+    PastePreviewStarted,
+    PastePreviewPlaced {
+        x: u32,
+        y: u32,
+    },
+    ClipboardTransformed(BufferTransform),
+    TrimOnCopyToggled(bool),
+    SampleMergedToggled(Tool, bool),
+    PixelPerfectCurvesToggled(bool),
+    FloatingPasteNudged {
+        dx: i32,
+        dy: i32,
+    },
+    FloatingPasteDragStarted {
+        x: u32,
+        y: u32,
+    },
+    FloatingPasteMoved {
+        x: u32,
+        y: u32,
+    },
+    FloatingPasteDragEnded,
+    FloatingPasteCommitted,
+    // Recorded before Escape had its own message
+    #[serde(alias = "FloatingPasteDiscarded")]
+    EscapePressed,
+    MirrorHorizontalToggled,
+    MirrorVerticalToggled,
+    WrapEdgesToggled,
+    PreserveTransparencyToggled,
+    ImageScaled(ScaleFactor),
+    FilterOpened(Filter),
+    FilterChanged(Filter),
+    GradientMapColorAdded([u8; 4]),
+    GradientMapColorRemoved(usize),
+    FilterApplied,
+    FilterCancelled,
+    TransparencyToleranceChanged(u8),
+    ColorMadeTransparent(Option<[u8; 4]>),
+    StrayPixelsDeleted,
+    AlphaEditToggled(bool),
+    LuminanceToAlpha,
+    AlphaToLuminance,
+    RotationChanged {
+        degrees: f32,
+        algorithm: RotationAlgorithm,
+    },
+    RotationApplied,
+    RotationCancelled,
+    LineShiftChanged {
+        axis: ShiftAxis,
+        index: u32,
+        offset: i32,
+    },
+    LineShiftApplied,
+    LineShiftCancelled,
+    // The matte color is part of the export settings
+    ExportSettingsChanged(ExportSettings),
+    FlattenAgainstMatte,
+    RestorePointNameChanged(String),
+    RestorePointCreated,
+    RestorePointRestored(usize),
+    RestorePointDeleted(usize),
+    IndexedModeToggled(bool),
+    PaletteEntrySelected(usize),
+    PaletteEntryRecolored {
+        index: usize,
+        color: [u8; 4],
+    },
+    NearDuplicateMerged {
+        from: [u8; 4],
+        into: [u8; 4],
+    },
+    RampDraftStarted,
+    RampDraftColorToggled([u8; 4]),
+    RampDraftFinished,
+    RampDraftCancelled,
+    RampDeleted(usize),
+    RampGeneratorOpened,
+    RampGeneratorChanged(RampGenerator),
+    RampGeneratorAccepted,
+    RampGeneratorCancelled,
+    RampStepped(i32),
+    RampShiftOpened(usize),
+    RampShiftChanged(RampShift),
+    RampShiftApplied,
+    RampShiftCancelled,
+    TileModeToggled(bool),
+    TileSizeChanged {
+        width: u32,
+        height: u32,
+    },
+    TileSelected {
+        x: u32,
+        y: u32,
+    },
+    TileDragStarted {
+        x: u32,
+        y: u32,
+    },
+    TileDropped(Option<(u32, u32)>),
+    SeamsExposed,
+    SeamsRestored,
+    FileLoaded {
+        path: String,
+    },
+    ProjectLoaded {
+        path: String,
+    },
+    SelectionImageLoaded {
+        path: String,
+    },
+    FolderImportStarted(Vec<PathBuf>),
+    FolderImageLoaded {
+        path: PathBuf,
+    },
+}
+
+impl RecordedMessage {
+    /// The recordable form of `message`, or `None` if it isn't recorded.
+    pub fn from_message(message: &Message) -> Option<Self> {
+        Some(match message {
+            Message::ToolSelected(tool) => Self::ToolSelected(*tool),
+            Message::PrimaryColorChanged(color) => {
+                Self::PrimaryColorChanged(utils::color_to_rgba8(*color))
+            }
+            Message::SecondaryColorChanged(color) => {
+                Self::SecondaryColorChanged(utils::color_to_rgba8(*color))
+            }
+            Message::ColorPicked(color) => Self::ColorPicked(utils::color_to_rgba8(*color)),
+            Message::UsedColorPicked(color) => Self::UsedColorPicked(utils::color_to_rgba8(*color)),
+            Message::RecentColorKeyPressed { key, shift } => Self::RecentColorKeyPressed {
+                key: *key,
+                shift: *shift,
+            },
+            Message::BrushSizeChanged(size) => Self::BrushSizeChanged(*size),
+            Message::BrushOpacityChanged(opacity) => Self::BrushOpacityChanged(*opacity),
+            Message::FillPatternToggled(enabled) => Self::FillPatternToggled(*enabled),
+            Message::PixelSnapChanged(snap) => Self::PixelSnapChanged(*snap),
+            Message::StabilizerChanged(amount) => Self::StabilizerChanged(*amount),
+            Message::PencilJitterChanged(jitter) => Self::PencilJitterChanged(*jitter),
+            Message::IsometricAssistToggled(enabled) => Self::IsometricAssistToggled(*enabled),
+            Message::CanvasResized { width, height } => Self::CanvasResized {
+                width: *width,
+                height: *height,
+            },
+            Message::CanvasCleared => Self::CanvasCleared,
+            Message::LayerAdded(name) => Self::LayerAdded(name.clone()),
+            Message::LayerDeleted(index) => Self::LayerDeleted(*index),
+            Message::LayerMoved { from, to } => Self::LayerMoved {
+                from: *from,
+                to: *to,
+            },
+            Message::LayerVisibilityToggled(index) => Self::LayerVisibilityToggled(*index),
+            Message::LayerSoloToggled(index) => Self::LayerSoloToggled(*index),
+            Message::HiddenLayerShown(index) => Self::HiddenLayerShown(*index),
+            Message::LayerClippingToggled(index) => Self::LayerClippingToggled(*index),
+            Message::LayerSelected(index) => Self::LayerSelected(*index),
+            Message::LayerOpacityChanged { index, opacity } => Self::LayerOpacityChanged {
+                index: *index,
+                opacity: *opacity,
+            },
+            Message::LayerRenamed { index, name } => Self::LayerRenamed {
+                index: *index,
+                name: name.clone(),
+            },
+            Message::ContinuousEditFinished => Self::ContinuousEditFinished,
+            Message::DrawingStarted { x, y, pressure } => Self::DrawingStarted {
+                x: *x,
+                y: *y,
+                pressure: *pressure,
+            },
+            Message::PixelDrawn { x, y, pressure } => Self::PixelDrawn {
+                x: *x,
+                y: *y,
+                pressure: *pressure,
+            },
+            Message::DrawingEnded => Self::DrawingEnded,
+            Message::TextFontSelected(font) => Self::TextFontSelected(*font),
+            Message::TextTyped(text) => Self::TextTyped(text.clone()),
+            Message::TextBackspaced => Self::TextBackspaced,
+            Message::TextCommitted => Self::TextCommitted,
+            Message::TextCancelled => Self::TextCancelled,
+            Message::Undo => Self::Undo,
+            Message::Redo => Self::Redo,
+            Message::UndoActiveLayerOnlyToggled(enabled) => {
+                Self::UndoActiveLayerOnlyToggled(*enabled)
+            }
+            Message::ModifiersChanged(modifiers) => Self::ModifiersChanged(modifiers.bits()),
+            Message::SelectionStarted { x, y } => Self::SelectionStarted { x: *x, y: *y },
+            Message::SelectionUpdated { x, y } => Self::SelectionUpdated { x: *x, y: *y },
+            Message::SelectionEnded => Self::SelectionEnded,
+            Message::SelectionCloneStarted { x, y } => Self::SelectionCloneStarted { x: *x, y: *y },
+            Message::SelectionCloneMoved { x, y } => Self::SelectionCloneMoved { x: *x, y: *y },
+            Message::SelectionCloneEnded => Self::SelectionCloneEnded,
+            Message::SelectionCleared => Self::SelectionCleared,
+            Message::CopySelection => Self::CopySelection,
+            Message::CutSelection => Self::CutSelection,
+            Message::PasteSelection => Self::PasteSelection,
+            Message::PastePreviewStarted => Self::PastePreviewStarted,
+            Message::PastePreviewPlaced { x, y } => Self::PastePreviewPlaced { x: *x, y: *y },
+            Message::ClipboardTransformed(transform) => Self::ClipboardTransformed(*transform),
+            Message::TrimOnCopyToggled(enabled) => Self::TrimOnCopyToggled(*enabled),
+            Message::SampleMergedToggled(tool, enabled) => {
+                Self::SampleMergedToggled(*tool, *enabled)
+            }
+            Message::PixelPerfectCurvesToggled(enabled) => {
+                Self::PixelPerfectCurvesToggled(*enabled)
+            }
+            Message::FloatingPasteNudged { dx, dy } => {
+                Self::FloatingPasteNudged { dx: *dx, dy: *dy }
+            }
+            Message::FloatingPasteDragStarted { x, y } => {
+                Self::FloatingPasteDragStarted { x: *x, y: *y }
+            }
+            Message::FloatingPasteMoved { x, y } => Self::FloatingPasteMoved { x: *x, y: *y },
+            Message::FloatingPasteDragEnded => Self::FloatingPasteDragEnded,
+            Message::FloatingPasteCommitted => Self::FloatingPasteCommitted,
+            Message::EscapePressed => Self::EscapePressed,
+            Message::MirrorHorizontalToggled => Self::MirrorHorizontalToggled,
+            Message::MirrorVerticalToggled => Self::MirrorVerticalToggled,
+            Message::WrapEdgesToggled => Self::WrapEdgesToggled,
+            Message::PreserveTransparencyToggled => Self::PreserveTransparencyToggled,
+            Message::ImageScaled(factor) => Self::ImageScaled(*factor),
+            Message::FilterOpened(filter) => Self::FilterOpened(filter.clone()),
+            Message::FilterChanged(filter) => Self::FilterChanged(filter.clone()),
+            Message::GradientMapColorAdded(color) => {
+                Self::GradientMapColorAdded(utils::color_to_rgba8(*color))
+            }
+            Message::GradientMapColorRemoved(index) => Self::GradientMapColorRemoved(*index),
+            Message::FilterApplied => Self::FilterApplied,
+            Message::FilterCancelled => Self::FilterCancelled,
+            Message::TransparencyToleranceChanged(tolerance) => {
+                Self::TransparencyToleranceChanged(*tolerance)
+            }
+            Message::ColorMadeTransparent(color) => {
+                Self::ColorMadeTransparent(color.map(utils::color_to_rgba8))
+            }
+            Message::StrayPixelsDeleted => Self::StrayPixelsDeleted,
+            Message::AlphaEditToggled(enabled) => Self::AlphaEditToggled(*enabled),
+            Message::LuminanceToAlpha => Self::LuminanceToAlpha,
+            Message::AlphaToLuminance => Self::AlphaToLuminance,
+            Message::RotationChanged { degrees, algorithm } => Self::RotationChanged {
+                degrees: *degrees,
+                algorithm: *algorithm,
+            },
+            Message::RotationApplied => Self::RotationApplied,
+            Message::RotationCancelled => Self::RotationCancelled,
+            Message::LineShiftChanged {
+                axis,
+                index,
+                offset,
+            } => Self::LineShiftChanged {
+                axis: *axis,
+                index: *index,
+                offset: *offset,
+            },
+            Message::LineShiftApplied => Self::LineShiftApplied,
+            Message::LineShiftCancelled => Self::LineShiftCancelled,
+            Message::ExportSettingsChanged(settings) => Self::ExportSettingsChanged(*settings),
+            Message::FlattenAgainstMatte => Self::FlattenAgainstMatte,
+            Message::RestorePointNameChanged(name) => Self::RestorePointNameChanged(name.clone()),
+            Message::RestorePointCreated => Self::RestorePointCreated,
+            Message::RestorePointRestored(index) => Self::RestorePointRestored(*index),
+            Message::RestorePointDeleted(index) => Self::RestorePointDeleted(*index),
+            Message::IndexedModeToggled(enabled) => Self::IndexedModeToggled(*enabled),
+            Message::PaletteEntrySelected(index) => Self::PaletteEntrySelected(*index),
+            Message::PaletteEntryRecolored { index, color } => Self::PaletteEntryRecolored {
+                index: *index,
+                color: utils::color_to_rgba8(*color),
+            },
+            Message::NearDuplicateMerged { from, into } => Self::NearDuplicateMerged {
+                from: *from,
+                into: *into,
+            },
+            Message::RampDraftStarted => Self::RampDraftStarted,
+            Message::RampDraftColorToggled(color) => {
+                Self::RampDraftColorToggled(utils::color_to_rgba8(*color))
+            }
+            Message::RampDraftFinished => Self::RampDraftFinished,
+            Message::RampDraftCancelled => Self::RampDraftCancelled,
+            Message::RampDeleted(index) => Self::RampDeleted(*index),
+            Message::RampGeneratorOpened => Self::RampGeneratorOpened,
+            Message::RampGeneratorChanged(generator) => Self::RampGeneratorChanged(*generator),
+            Message::RampGeneratorAccepted => Self::RampGeneratorAccepted,
+            Message::RampGeneratorCancelled => Self::RampGeneratorCancelled,
+            Message::RampStepped(step) => Self::RampStepped(*step),
+            Message::RampShiftOpened(ramp) => Self::RampShiftOpened(*ramp),
+            Message::RampShiftChanged(shift) => Self::RampShiftChanged(*shift),
+            Message::RampShiftApplied => Self::RampShiftApplied,
+            Message::RampShiftCancelled => Self::RampShiftCancelled,
+            Message::TileModeToggled(enabled) => Self::TileModeToggled(*enabled),
+            Message::TileSizeChanged { width, height } => Self::TileSizeChanged {
+                width: *width,
+                height: *height,
+            },
+            Message::TileSelected { x, y } => Self::TileSelected { x: *x, y: *y },
+            Message::TileDragStarted { x, y } => Self::TileDragStarted { x: *x, y: *y },
+            Message::TileDropped(target) => Self::TileDropped(*target),
+            Message::SeamsExposed => Self::SeamsExposed,
+            Message::SeamsRestored => Self::SeamsRestored,
+            Message::FileLoaded { path, .. } => Self::FileLoaded { path: path.clone() },
+            Message::ProjectLoaded { path, .. } => Self::ProjectLoaded { path: path.clone() },
+            Message::SelectionImageLoaded { path, .. } => {
+                Self::SelectionImageLoaded { path: path.clone() }
+            }
+            Message::FolderImportStarted(paths) => Self::FolderImportStarted(paths.clone()),
+            Message::FolderImageLoaded { path, .. } => {
+                Self::FolderImageLoaded { path: path.clone() }
+            }
+            _ => return None,
+        })
+    }
+
+    /// The message to replay into `document`. Files are read again from
+    /// their recorded path; one that can no longer be read replays as
+    /// `Message::None`.
+    pub fn into_message(self, document: DocumentId) -> Message {
+        match self {
+            Self::ToolSelected(tool) => Message::ToolSelected(tool),
+            Self::PrimaryColorChanged(rgba) => {
+                Message::PrimaryColorChanged(utils::rgba8_to_color(rgba))
+            }
+            Self::SecondaryColorChanged(rgba) => {
+                Message::SecondaryColorChanged(utils::rgba8_to_color(rgba))
+            }
+            Self::ColorPicked(rgba) => Message::ColorPicked(utils::rgba8_to_color(rgba)),
+            Self::UsedColorPicked(rgba) => Message::UsedColorPicked(utils::rgba8_to_color(rgba)),
+            Self::RecentColorKeyPressed { key, shift } => {
+                Message::RecentColorKeyPressed { key, shift }
+            }
+            Self::BrushSizeChanged(size) => Message::BrushSizeChanged(size),
+            Self::BrushOpacityChanged(opacity) => Message::BrushOpacityChanged(opacity),
+            Self::FillPatternToggled(enabled) => Message::FillPatternToggled(enabled),
+            Self::PixelSnapChanged(snap) => Message::PixelSnapChanged(snap),
+            Self::StabilizerChanged(amount) => Message::StabilizerChanged(amount),
+            Self::PencilJitterChanged(jitter) => Message::PencilJitterChanged(jitter),
+            Self::IsometricAssistToggled(enabled) => Message::IsometricAssistToggled(enabled),
+            Self::CanvasResized { width, height } => Message::CanvasResized { width, height },
+            Self::CanvasCleared => Message::CanvasCleared,
+            Self::LayerAdded(name) => Message::LayerAdded(name),
+            Self::LayerDeleted(index) => Message::LayerDeleted(index),
+            Self::LayerMoved { from, to } => Message::LayerMoved { from, to },
+            Self::LayerVisibilityToggled(index) => Message::LayerVisibilityToggled(index),
+            Self::LayerSoloToggled(index) => Message::LayerSoloToggled(index),
+            Self::HiddenLayerShown(index) => Message::HiddenLayerShown(index),
+            Self::LayerClippingToggled(index) => Message::LayerClippingToggled(index),
+            Self::LayerSelected(index) => Message::LayerSelected(index),
+            Self::LayerOpacityChanged { index, opacity } => {
+                Message::LayerOpacityChanged { index, opacity }
+            }
+            Self::LayerRenamed { index, name } => Message::LayerRenamed { index, name },
+            Self::ContinuousEditFinished => Message::ContinuousEditFinished,
+            Self::DrawingStarted { x, y, pressure } => Message::DrawingStarted { x, y, pressure },
+            Self::PixelDrawn { x, y, pressure } => Message::PixelDrawn { x, y, pressure },
+            Self::DrawingEnded => Message::DrawingEnded,
+            Self::TextFontSelected(font) => Message::TextFontSelected(font),
+            Self::TextTyped(text) => Message::TextTyped(text),
+            Self::TextBackspaced => Message::TextBackspaced,
+            Self::TextCommitted => Message::TextCommitted,
+            Self::TextCancelled => Message::TextCancelled,
+            Self::Undo => Message::Undo,
+            Self::Redo => Message::Redo,
+            Self::UndoActiveLayerOnlyToggled(enabled) => {
+                Message::UndoActiveLayerOnlyToggled(enabled)
+            }
+            Self::ModifiersChanged(bits) => {
+                Message::ModifiersChanged(iced::keyboard::Modifiers::from_bits_truncate(bits))
+            }
+            Self::SelectionStarted { x, y } => Message::SelectionStarted { x, y },
+            Self::SelectionUpdated { x, y } => Message::SelectionUpdated { x, y },
+            Self::SelectionEnded => Message::SelectionEnded,
+            Self::SelectionCloneStarted { x, y } => Message::SelectionCloneStarted { x, y },
+            Self::SelectionCloneMoved { x, y } => Message::SelectionCloneMoved { x, y },
+            Self::SelectionCloneEnded => Message::SelectionCloneEnded,
+            Self::SelectionCleared => Message::SelectionCleared,
+            Self::CopySelection => Message::CopySelection,
+            Self::CutSelection => Message::CutSelection,
+            Self::PasteSelection => Message::PasteSelection,
+            Self::PastePreviewStarted => Message::PastePreviewStarted,
+            Self::PastePreviewPlaced { x, y } => Message::PastePreviewPlaced { x, y },
+            Self::ClipboardTransformed(transform) => Message::ClipboardTransformed(transform),
+            Self::TrimOnCopyToggled(enabled) => Message::TrimOnCopyToggled(enabled),
+            Self::SampleMergedToggled(tool, enabled) => Message::SampleMergedToggled(tool, enabled),
+            Self::PixelPerfectCurvesToggled(enabled) => Message::PixelPerfectCurvesToggled(enabled),
+            Self::FloatingPasteNudged { dx, dy } => Message::FloatingPasteNudged { dx, dy },
+            Self::FloatingPasteDragStarted { x, y } => Message::FloatingPasteDragStarted { x, y },
+            Self::FloatingPasteMoved { x, y } => Message::FloatingPasteMoved { x, y },
+            Self::FloatingPasteDragEnded => Message::FloatingPasteDragEnded,
+            Self::FloatingPasteCommitted => Message::FloatingPasteCommitted,
+            Self::EscapePressed => Message::EscapePressed,
+            Self::MirrorHorizontalToggled => Message::MirrorHorizontalToggled,
+            Self::MirrorVerticalToggled => Message::MirrorVerticalToggled,
+            Self::WrapEdgesToggled => Message::WrapEdgesToggled,
+            Self::PreserveTransparencyToggled => Message::PreserveTransparencyToggled,
+            Self::ImageScaled(factor) => Message::ImageScaled(factor),
+            Self::FilterOpened(filter) => Message::FilterOpened(filter),
+            Self::FilterChanged(filter) => Message::FilterChanged(filter),
+            Self::GradientMapColorAdded(rgba) => {
+                Message::GradientMapColorAdded(utils::rgba8_to_color(rgba))
+            }
+            Self::GradientMapColorRemoved(index) => Message::GradientMapColorRemoved(index),
+            Self::FilterApplied => Message::FilterApplied,
+            Self::FilterCancelled => Message::FilterCancelled,
+            Self::TransparencyToleranceChanged(tolerance) => {
+                Message::TransparencyToleranceChanged(tolerance)
+            }
+            Self::ColorMadeTransparent(rgba) => {
+                Message::ColorMadeTransparent(rgba.map(utils::rgba8_to_color))
+            }
+            Self::StrayPixelsDeleted => Message::StrayPixelsDeleted,
+            Self::AlphaEditToggled(enabled) => Message::AlphaEditToggled(enabled),
+            Self::LuminanceToAlpha => Message::LuminanceToAlpha,
+            Self::AlphaToLuminance => Message::AlphaToLuminance,
+            Self::RotationChanged { degrees, algorithm } => {
+                Message::RotationChanged { degrees, algorithm }
+            }
+            Self::RotationApplied => Message::RotationApplied,
+            Self::RotationCancelled => Message::RotationCancelled,
+            Self::LineShiftChanged {
+                axis,
+                index,
+                offset,
+            } => Message::LineShiftChanged {
+                axis,
+                index,
+                offset,
+            },
+            Self::LineShiftApplied => Message::LineShiftApplied,
+            Self::LineShiftCancelled => Message::LineShiftCancelled,
+            Self::ExportSettingsChanged(settings) => Message::ExportSettingsChanged(settings),
+            Self::FlattenAgainstMatte => Message::FlattenAgainstMatte,
+            Self::RestorePointNameChanged(name) => Message::RestorePointNameChanged(name),
+            Self::RestorePointCreated => Message::RestorePointCreated,
+            Self::RestorePointRestored(index) => Message::RestorePointRestored(index),
+            Self::RestorePointDeleted(index) => Message::RestorePointDeleted(index),
+            Self::IndexedModeToggled(enabled) => Message::IndexedModeToggled(enabled),
+            Self::PaletteEntrySelected(index) => Message::PaletteEntrySelected(index),
+            Self::PaletteEntryRecolored { index, color } => Message::PaletteEntryRecolored {
+                index,
+                color: utils::rgba8_to_color(color),
+            },
+            Self::NearDuplicateMerged { from, into } => Message::NearDuplicateMerged { from, into },
+            Self::RampDraftStarted => Message::RampDraftStarted,
+            Self::RampDraftColorToggled(rgba) => {
+                Message::RampDraftColorToggled(utils::rgba8_to_color(rgba))
+            }
+            Self::RampDraftFinished => Message::RampDraftFinished,
+            Self::RampDraftCancelled => Message::RampDraftCancelled,
+            Self::RampDeleted(index) => Message::RampDeleted(index),
+            Self::RampGeneratorOpened => Message::RampGeneratorOpened,
+            Self::RampGeneratorChanged(generator) => Message::RampGeneratorChanged(generator),
+            Self::RampGeneratorAccepted => Message::RampGeneratorAccepted,
+            Self::RampGeneratorCancelled => Message::RampGeneratorCancelled,
+            Self::RampStepped(step) => Message::RampStepped(step),
+            Self::RampShiftOpened(ramp) => Message::RampShiftOpened(ramp),
+            Self::RampShiftChanged(shift) => Message::RampShiftChanged(shift),
+            Self::RampShiftApplied => Message::RampShiftApplied,
+            Self::RampShiftCancelled => Message::RampShiftCancelled,
+            Self::TileModeToggled(enabled) => Message::TileModeToggled(enabled),
+            Self::TileSizeChanged { width, height } => Message::TileSizeChanged { width, height },
+            Self::TileSelected { x, y } => Message::TileSelected { x, y },
+            Self::TileDragStarted { x, y } => Message::TileDragStarted { x, y },
+            Self::TileDropped(target) => Message::TileDropped(target),
+            Self::SeamsExposed => Message::SeamsExposed,
+            Self::SeamsRestored => Message::SeamsRestored,
+            Self::FileLoaded { path } => Message::FileLoaded {
+                document,
+                path,
+                image: None,
+            },
+            Self::ProjectLoaded { path } => match file_io::load_project(Path::new(&path)) {
+                Ok(Some(project)) => Message::ProjectLoaded {
+                    document,
+                    path,
+                    project: Box::new(project),
+                },
+                Ok(None) => {
+                    eprintln!("No layer data in {}", path);
+                    Message::None
+                }
+                Err(e) => {
+                    eprintln!("Failed to reload {}: {}", path, e);
+                    Message::None
+                }
+            },
+            Self::SelectionImageLoaded { path } => match file_io::load_image(Path::new(&path)) {
+                Ok((width, height, pixels)) => Message::SelectionImageLoaded {
+                    document,
+                    path,
+                    width,
+                    height,
+                    pixels,
+                },
+                Err(e) => {
+                    eprintln!("Failed to reload {}: {}", path, e);
+                    Message::None
+                }
+            },
+            Self::FolderImportStarted(paths) => Message::FolderImportStarted(paths),
+            // A folder image that fails to load again is skipped, as it
+            // would have been during the import
+            Self::FolderImageLoaded { path } => Message::FolderImageLoaded {
+                document,
+                result: file_io::load_image(&path),
+                path,
+            },
+        }
+    }
+}
+
+pub fn save(path: &Path, recording: &Recording) -> Result<(), String> {
+    let contents = serde_json::to_string(recording)
+        .map_err(|e| format!("Failed to serialize recording: {}", e))?;
+    std::fs::write(path, contents).map_err(|e| format!("Failed to write recording: {}", e))
+}
+
+pub fn load(path: &Path) -> Result<Recording, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read recording: {}", e))?;
+    serde_json::from_str(&contents).map_err(|e| format!("Failed to parse recording: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::EditorState;
+    use iced::Color;
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    /// Apply `messages` the way the app does and hash the resulting
+    /// composite.
+    fn composite_hash(state: &mut EditorState, messages: Vec<Message>) -> u64 {
+        let mut clipboard = None;
+        for message in messages {
+            let _ = crate::update::update_document(state, &mut clipboard, message);
+            state.sync_indexed();
+        }
+        let mut hasher = DefaultHasher::new();
+        file_io::composite_layers(state).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn stroke(color: Color, from: (u32, u32), to: (u32, u32)) -> Vec<Message> {
+        vec![
+            Message::PrimaryColorChanged(color),
+            Message::DrawingStarted {
+                x: from.0,
+                y: from.1,
+                pressure: None,
+            },
+            Message::PixelDrawn {
+                x: to.0,
+                y: to.1,
+                pressure: None,
+            },
+            Message::DrawingEnded,
+        ]
+    }
+
+    /// Every kind of edit that changes pixels, in one session.
+    fn session() -> Vec<Message> {
+        let matte = ExportSettings {
+            matte_color: [20, 40, 60, 255],
+            ..ExportSettings::default()
+        };
+        let mut messages = stroke(Color::from_rgb8(200, 30, 30), (0, 1), (6, 1));
+        messages.push(Message::LayerAdded("Top".into()));
+        messages.extend(stroke(Color::from_rgba8(30, 30, 200, 0.5), (3, 0), (3, 7)));
+        messages.extend([
+            Message::RecentColorKeyPressed {
+                key: 'c',
+                shift: false,
+            },
+            Message::IsometricAssistToggled(true),
+        ]);
+        messages.extend(stroke(Color::from_rgb8(30, 200, 30), (0, 6), (5, 4)));
+        messages.extend([
+            Message::IsometricAssistToggled(false),
+            Message::SelectionStarted { x: 0.0, y: 0.0 },
+            Message::SelectionUpdated { x: 3.0, y: 3.0 },
+            Message::SelectionEnded,
+            Message::CopySelection,
+            Message::PastePreviewStarted,
+            Message::PastePreviewPlaced { x: 4, y: 4 },
+            Message::LayerVisibilityToggled(0),
+            Message::HiddenLayerShown(0),
+            Message::TextFontSelected(BitmapFont::Small),
+            Message::FilterOpened(Filter::Posterize { levels: 3 }),
+            Message::FilterApplied,
+            Message::RotationChanged {
+                degrees: 90.0,
+                algorithm: RotationAlgorithm::NearestNeighbor,
+            },
+            Message::RotationApplied,
+            Message::LineShiftChanged {
+                axis: ShiftAxis::Row,
+                index: 3,
+                offset: 2,
+            },
+            Message::LineShiftApplied,
+            Message::RestorePointCreated,
+            Message::ColorMadeTransparent(None),
+            Message::AlphaToLuminance,
+            Message::RestorePointRestored(0),
+            Message::LayerSelected(0),
+            Message::StrayPixelsDeleted,
+            Message::TileModeToggled(true),
+            Message::TileSizeChanged {
+                width: 4,
+                height: 4,
+            },
+            Message::TileDragStarted { x: 0, y: 0 },
+            Message::TileDropped(Some((4, 4))),
+            Message::SeamsExposed,
+            Message::RampDraftStarted,
+            Message::RampDraftColorToggled(Color::from_rgb8(200, 30, 30)),
+            Message::RampDraftColorToggled(Color::WHITE),
+            Message::RampDraftFinished,
+            Message::RampShiftOpened(0),
+            Message::RampShiftChanged(RampShift {
+                hue: 120.0,
+                ..RampShift::new(0)
+            }),
+            Message::RampShiftApplied,
+            Message::ExportSettingsChanged(matte),
+            Message::FlattenAgainstMatte,
+            Message::ImageScaled(ScaleFactor::Times(2)),
+            Message::IndexedModeToggled(true),
+            Message::PaletteEntryRecolored {
+                index: 0,
+                color: Color::from_rgb8(90, 200, 90),
+            },
+            Message::Undo,
+            Message::Redo,
+        ]);
+        messages
+    }
+
+    #[test]
+    fn replaying_a_recorded_session_reproduces_the_composite() {
+        let messages = session();
+        let recorded: Vec<RecordedMessage> = messages
+            .iter()
+            .map(|message| {
+                RecordedMessage::from_message(message)
+                    .unwrap_or_else(|| panic!("{:?} is not recorded", message))
+            })
+            .collect();
+        let recording = Recording {
+            width: 8,
+            height: 8,
+            document: None,
+            messages: recorded,
+        };
+        let json = serde_json::to_string(&recording).expect("recording serializes");
+        let recording: Recording = serde_json::from_str(&json).expect("recording parses");
+
+        let mut original = EditorState::new(8, 8);
+        let expected = composite_hash(&mut original, messages);
+        let mut replayed = EditorState::new(recording.width, recording.height);
+        let id = replayed.id;
+        let replay = recording
+            .messages
+            .into_iter()
+            .map(|recorded| recorded.into_message(id))
+            .collect();
+
+        assert_eq!(composite_hash(&mut replayed, replay), expected);
+        assert_eq!(replayed.canvas_width, 16);
+        assert!(replayed.indexed_palette.is_some());
+    }
+
+    #[test]
+    fn recordings_start_from_the_current_document() {
+        let mut original = EditorState::new(8, 8);
+        let _ = composite_hash(
+            &mut original,
+            [
+                stroke(Color::from_rgb8(200, 30, 30), (0, 1), (6, 1)),
+                vec![
+                    Message::LayerAdded("Top".into()),
+                    Message::BrushSizeChanged(3),
+                    Message::PrimaryColorChanged(Color::from_rgb8(30, 30, 200)),
+                    Message::MirrorHorizontalToggled,
+                ],
+            ]
+            .concat(),
+        );
+        let recording = Recording::start(&original).expect("recording starts");
+        let json = serde_json::to_string(&recording).expect("recording serializes");
+        let recording: Recording = serde_json::from_str(&json).expect("recording parses");
+
+        // Paints with the brush size, color and mirroring set before the
+        // recording started
+        let mut messages = vec![
+            Message::DrawingStarted {
+                x: 1,
+                y: 5,
+                pressure: None,
+            },
+            Message::DrawingEnded,
+        ];
+        let expected = composite_hash(&mut original, messages.clone());
+
+        let mut replayed = recording
+            .new_document(&EditorState::new(4, 4))
+            .expect("recorded document decodes");
+        let id = replayed.id;
+        messages.splice(
+            0..0,
+            recording
+                .messages
+                .into_iter()
+                .map(|recorded| recorded.into_message(id)),
+        );
+        assert_eq!(composite_hash(&mut replayed, messages), expected);
+        assert_eq!(replayed.layers.len(), 2);
+    }
+
+    #[test]
+    fn escape_recorded_under_its_old_name_still_replays() {
+        let recorded: RecordedMessage =
+            serde_json::from_str("\"FloatingPasteDiscarded\"").expect("old name parses");
+        assert!(matches!(recorded, RecordedMessage::EscapePressed));
+    }
+}
@@ -0,0 +1,111 @@
+/// One Scale2x (EPX) pass: every pixel becomes a 2x2 block whose corners take
+/// a neighbor's color where two adjacent neighbors agree. Neighbors outside
+/// the image are clamped to the edge.
+pub fn scale2x(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let pixel = |x: i64, y: i64| -> [u8; 4] {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        let index = ((y * width + x) * 4) as usize;
+        [
+            pixels[index],
+            pixels[index + 1],
+            pixels[index + 2],
+            pixels[index + 3],
+        ]
+    };
+
+    let out_width = width * 2;
+    let mut result = vec![0u8; (out_width * height * 2 * 4) as usize];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let e = pixel(x, y);
+            let b = pixel(x, y - 1);
+            let d = pixel(x - 1, y);
+            let f = pixel(x + 1, y);
+            let h = pixel(x, y + 1);
+
+            let block = if b != h && d != f {
+                [
+                    if d == b { d } else { e },
+                    if b == f { f } else { e },
+                    if d == h { d } else { e },
+                    if h == f { f } else { e },
+                ]
+            } else {
+                [e; 4]
+            };
+
+            for (i, color) in block.iter().enumerate() {
+                let out_x = x as u32 * 2 + (i as u32 % 2);
+                let out_y = y as u32 * 2 + (i as u32 / 2);
+                let index = ((out_y * out_width + out_x) * 4) as usize;
+                result[index..index + 4].copy_from_slice(color);
+            }
+        }
+    }
+    result
+}
+
+/// Two chained Scale2x passes.
+pub fn scale4x(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+    scale2x(&scale2x(pixels, width, height), width * 2, height * 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: [u8; 4] = [255, 0, 0, 255];
+    const B: [u8; 4] = [0, 0, 255, 255];
+    // Filler for the 3x3 corners, which Scale2x never looks at
+    const CORNER: [u8; 4] = [0, 255, 0, 128];
+
+    #[test]
+    fn every_neighborhood_follows_the_published_rules() {
+        // Each of E, B, D, F and H (center, up, left, right, down) is A or B
+        for bits in 0..32u32 {
+            let color = |bit: u32| if bits >> bit & 1 == 0 { A } else { B };
+            let (e, b, d, f, h) = (color(0), color(1), color(2), color(3), color(4));
+            #[rustfmt::skip]
+            let pixels = [
+                CORNER, b, CORNER,
+                d, e, f,
+                CORNER, h, CORNER,
+            ]
+            .concat();
+
+            // The rules as written in the Scale2x description
+            let e0 = if d == b && b != f && d != h { d } else { e };
+            let e1 = if b == f && b != d && f != h { f } else { e };
+            let e2 = if d == h && d != b && h != f { d } else { e };
+            let e3 = if h == f && d != h && b != f { f } else { e };
+
+            let scaled = scale2x(&pixels, 3, 3);
+            let at = |x: usize, y: usize| &scaled[(y * 6 + x) * 4..(y * 6 + x) * 4 + 4];
+            let block = [at(2, 2), at(3, 2), at(2, 3), at(3, 3)];
+            assert_eq!(block, [e0, e1, e2, e3], "neighborhood {:05b}", bits);
+        }
+    }
+
+    #[test]
+    fn edges_clamp_to_the_nearest_pixel() {
+        assert_eq!(scale2x(&A, 1, 1), [A; 4].concat());
+
+        #[rustfmt::skip]
+        let diagonal = [
+            A, B,
+            B, A,
+        ]
+        .concat();
+        #[rustfmt::skip]
+        let expected = [
+            A, A, B, B,
+            A, B, A, B,
+            B, A, B, A,
+            B, B, A, A,
+        ]
+        .concat();
+        assert_eq!(scale2x(&diagonal, 2, 2), expected);
+        assert_eq!(scale4x(&diagonal, 2, 2), scale2x(&expected, 4, 4));
+    }
+}
@@ -0,0 +1,190 @@
+/// How a newly dragged selection rectangle combines with the existing
+/// selection. Shift adds, Ctrl subtracts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    Replace,
+    Add,
+    Subtract,
+}
+
+impl SelectionMode {
+    pub fn from_modifiers(modifiers: iced::keyboard::Modifiers) -> Self {
+        if modifiers.shift() {
+            SelectionMode::Add
+        } else if modifiers.command() {
+            SelectionMode::Subtract
+        } else {
+            SelectionMode::Replace
+        }
+    }
+}
+
+/// Per-pixel selection covering the whole canvas, for selections that are
+/// not a single rectangle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectionMask {
+    width: u32,
+    height: u32,
+    bits: Vec<bool>,
+}
+
+impl SelectionMask {
+    /// A mask selecting `bounds` (`start_x, start_y, end_x, end_y`, end
+    /// exclusive), clipped to the canvas.
+    pub fn from_rect(width: u32, height: u32, bounds: (u32, u32, u32, u32)) -> Self {
+        let mut bits = vec![false; (width * height) as usize];
+        let (start_x, start_y, end_x, end_y) = bounds;
+        for y in start_y..end_y.min(height) {
+            let row = (y * width) as usize;
+            bits[row + start_x.min(width) as usize..row + end_x.min(width) as usize].fill(true);
+        }
+        Self {
+            width,
+            height,
+            bits,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.bits[(y * self.width + x) as usize]
+    }
+
+    /// Select everything selected in `other` too.
+    pub fn union(&mut self, other: &SelectionMask) {
+        for (bit, &other) in self.bits.iter_mut().zip(&other.bits) {
+            *bit |= other;
+        }
+    }
+
+    /// Deselect everything selected in `other`.
+    pub fn difference(&mut self, other: &SelectionMask) {
+        for (bit, &other) in self.bits.iter_mut().zip(&other.bits) {
+            *bit &= !other;
+        }
+    }
+
+    /// Combine `other` into this mask according to `mode`.
+    pub fn combine(&mut self, other: &SelectionMask, mode: SelectionMode) {
+        match mode {
+            SelectionMode::Replace => self.bits.clone_from(&other.bits),
+            SelectionMode::Add => self.union(other),
+            SelectionMode::Subtract => self.difference(other),
+        }
+    }
+
+    /// Bounding box of the selected pixels as `(start_x, start_y, end_x,
+    /// end_y)` with exclusive ends, or `None` if nothing is selected.
+    pub fn bounds(&self) -> Option<(u32, u32, u32, u32)> {
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.contains(x, y) {
+                    continue;
+                }
+                bounds = Some(match bounds {
+                    Some((sx, sy, ex, ey)) => (sx.min(x), sy.min(y), ex.max(x + 1), ey.max(y + 1)),
+                    None => (x, y, x + 1, y + 1),
+                });
+            }
+        }
+        bounds
+    }
+
+    /// Unit-length edges between selected and unselected pixels, in canvas
+    /// coordinates, as `((x1, y1), (x2, y2))`. Together they trace the
+    /// outline of the selection.
+    pub fn outline(&self) -> Vec<((u32, u32), (u32, u32))> {
+        let mut edges = Vec::new();
+        let selected = |x: i64, y: i64| x >= 0 && y >= 0 && self.contains(x as u32, y as u32);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.contains(x, y) {
+                    continue;
+                }
+                let (ix, iy) = (x as i64, y as i64);
+                if !selected(ix, iy - 1) {
+                    edges.push(((x, y), (x + 1, y)));
+                }
+                if !selected(ix, iy + 1) {
+                    edges.push(((x, y + 1), (x + 1, y + 1)));
+                }
+                if !selected(ix - 1, iy) {
+                    edges.push(((x, y), (x, y + 1)));
+                }
+                if !selected(ix + 1, iy) {
+                    edges.push(((x + 1, y), (x + 1, y + 1)));
+                }
+            }
+        }
+        edges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(mask: &SelectionMask) -> Vec<String> {
+        (0..mask.height())
+            .map(|y| {
+                (0..mask.width())
+                    .map(|x| if mask.contains(x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn masks_combine_by_union_and_difference() {
+        let first = SelectionMask::from_rect(5, 4, (0, 0, 3, 2));
+        let second = SelectionMask::from_rect(5, 4, (2, 1, 5, 4));
+
+        let mut added = first.clone();
+        added.combine(&second, SelectionMode::Add);
+        assert_eq!(rows(&added), ["###..", "#####", "..###", "..###"]);
+        assert_eq!(added.bounds(), Some((0, 0, 5, 4)));
+
+        let mut subtracted = first.clone();
+        subtracted.combine(&second, SelectionMode::Subtract);
+        assert_eq!(rows(&subtracted), ["###..", "##...", ".....", "....."]);
+        assert_eq!(subtracted.bounds(), Some((0, 0, 3, 2)));
+
+        let mut replaced = first.clone();
+        replaced.combine(&second, SelectionMode::Replace);
+        assert_eq!(replaced, second);
+
+        let mut emptied = second.clone();
+        emptied.difference(&SelectionMask::from_rect(5, 4, (0, 0, 9, 9)));
+        assert_eq!(emptied.bounds(), None);
+    }
+
+    #[test]
+    fn outlines_trace_the_combined_shape() {
+        let mut mask = SelectionMask::from_rect(3, 3, (0, 0, 2, 1));
+        mask.union(&SelectionMask::from_rect(3, 3, (0, 1, 1, 2)));
+        // An L of three pixels has eight unit edges around it
+        let mut edges = mask.outline();
+        edges.sort();
+        assert_eq!(
+            edges,
+            [
+                ((0, 0), (0, 1)),
+                ((0, 0), (1, 0)),
+                ((0, 1), (0, 2)),
+                ((0, 2), (1, 2)),
+                ((1, 0), (2, 0)),
+                ((1, 1), (1, 2)),
+                ((1, 1), (2, 1)),
+                ((2, 0), (2, 1)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use crate::app::App;
+use crate::file_io;
+use crate::state::{EditorState, Layer, Tool};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Window placement remembered between runs.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub width: f32,
+    pub height: f32,
+    // Unknown until the window has been moved at least once
+    pub position: Option<(f32, f32)>,
+}
+
+/// A saved document that was open at exit, with its view.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDocument {
+    pub path: String,
+    pub zoom_level: f32,
+    pub tool: Tool,
+}
+
+/// What was open when pxrs last exited. Documents that were never saved
+/// have no path to reopen and are left out.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub documents: Vec<SessionDocument>,
+    pub active: usize,
+    pub window: Option<WindowGeometry>,
+}
+
+impl Session {
+    pub fn from_app(app: &App) -> Self {
+        let mut documents = Vec::new();
+        let mut active = 0;
+        for (index, document) in app.documents.iter().enumerate() {
+            let Some(path) = &document.file_path else {
+                continue;
+            };
+            if index == app.active {
+                active = documents.len();
+            }
+            documents.push(SessionDocument {
+                path: path.clone(),
+                zoom_level: document.zoom_level,
+                tool: document.current_tool,
+            });
+        }
+
+        Self {
+            documents,
+            active,
+            window: app.window,
+        }
+    }
+
+    /// Reopen the session's documents on top of `base` (which carries the
+    /// user's settings). Returns the documents, the index to activate, and
+    /// the paths that could not be reopened.
+    pub fn restore(&self, base: &EditorState) -> (Vec<EditorState>, usize, Vec<String>) {
+        let mut documents = Vec::new();
+        let mut missing = Vec::new();
+        let mut active = 0;
+        for (index, entry) in self.documents.iter().enumerate() {
+            match open_document(base, Path::new(&entry.path)) {
+                Ok(mut document) => {
+                    if index == self.active {
+                        active = documents.len();
+                    }
+                    document.zoom_level = entry.zoom_level.clamp(1.0, 32.0);
+                    document.current_tool = entry.tool;
+                    document.file_path = Some(entry.path.clone());
+                    documents.push(document);
+                }
+                Err(e) => {
+                    eprintln!("Failed to reopen {}: {}", entry.path, e);
+                    missing.push(entry.path.clone());
+                }
+            }
+        }
+        (documents, active, missing)
+    }
+}
+
+/// Open `path` as a new document: the layered project if the file embeds
+/// one, otherwise the flat image on a single layer.
+fn open_document(base: &EditorState, path: &Path) -> Result<EditorState, String> {
+    if let Some(project) = file_io::load_project(path)? {
+        let mut document = base.new_document(project.width, project.height);
+        project.apply_to(&mut document);
+        return Ok(document);
+    }
+
+    let (width, height, pixels) = file_io::load_image(path)?;
+    let mut document = base.new_document(width, height);
+    let mut layer = Layer::new("Layer 1".to_string(), width, height);
+    layer.pixels = pixels;
+    document.layers = vec![layer];
+    Ok(document)
+}
+
+fn session_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("pxrs").join("session.json"))
+}
+
+/// Load the last session, or an empty one when there is none.
+pub fn load() -> Session {
+    let Some(path) = session_path() else {
+        return Session::default();
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Failed to parse session: {}", e);
+            Session::default()
+        }),
+        Err(_) => Session::default(),
+    }
+}
+
+pub fn save(session: &Session) -> Result<(), String> {
+    let path = session_path().ok_or("No config directory available")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create session directory: {}", e))?;
+    }
+    let contents = serde_json::to_string_pretty(session)
+        .map_err(|e| format!("Failed to serialize session: {}", e))?;
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to write session: {}", e))
+}
@@ -0,0 +1,149 @@
+use crate::state::{
+    BrushOptions, DEFAULT_CANVAS_BORDER_COLOR, DEFAULT_GUIDE_COLOR, DEFAULT_MAX_CANVAS_SIZE,
+    DEFAULT_OUTSIDE_CANVAS_COLOR, EditorState, SafeAreaPreset, Tool, WheelBrushModifier,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// User preferences that outlive a single document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Brush size and opacity of each tool.
+    pub tool_options: HashMap<Tool, BrushOptions>,
+    pub max_canvas_size: u32,
+    /// RGBA color of the mirror axis guides.
+    pub guide_color: [u8; 4],
+    /// RGBA color of the line around the canvas edge.
+    pub canvas_border_color: [u8; 4],
+    /// RGBA color of the editor area outside the canvas.
+    pub outside_canvas_color: [u8; 4],
+    pub pressure_sensitive: bool,
+    /// Stroke stabilizer strength, 0 (off) to 10.
+    pub stabilizer: u32,
+    /// Modifier that makes the mouse wheel change the brush size.
+    pub wheel_brush_modifier: WheelBrushModifier,
+    /// Trim the transparent border off copied selections.
+    pub trim_on_copy: bool,
+    /// Fill finds its region in the composited image.
+    pub fill_sample_merged: bool,
+    /// Eyedropper picks from the composited image.
+    pub eyedropper_sample_merged: bool,
+    /// Curves drop the middle pixel of L-shaped corners.
+    pub pixel_perfect_curves: bool,
+    /// Undo skips past changes to layers other than the active one.
+    pub undo_active_layer_only: bool,
+    /// Start in the drawing-only mode.
+    pub kiosk_mode: bool,
+    /// Warn when drawing on a hidden or fully transparent layer.
+    pub warn_hidden_layer: bool,
+    /// Safe-area overlay presets defined by the user.
+    pub custom_safe_areas: Vec<SafeAreaPreset>,
+    /// Key that steps back through recently painted colors; Shift steps
+    /// forward.
+    pub color_cycle_key: char,
+    /// Reopen the documents from the previous session on startup.
+    pub reopen_last_session: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            tool_options: HashMap::new(),
+            max_canvas_size: DEFAULT_MAX_CANVAS_SIZE,
+            guide_color: DEFAULT_GUIDE_COLOR,
+            canvas_border_color: DEFAULT_CANVAS_BORDER_COLOR,
+            outside_canvas_color: DEFAULT_OUTSIDE_CANVAS_COLOR,
+            pressure_sensitive: false,
+            stabilizer: 0,
+            wheel_brush_modifier: WheelBrushModifier::default(),
+            trim_on_copy: true,
+            fill_sample_merged: false,
+            eyedropper_sample_merged: true,
+            pixel_perfect_curves: true,
+            undo_active_layer_only: false,
+            kiosk_mode: false,
+            warn_hidden_layer: true,
+            custom_safe_areas: Vec::new(),
+            color_cycle_key: 'c',
+            reopen_last_session: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_state(state: &EditorState) -> Self {
+        Self {
+            tool_options: state.tool_options.brushes.clone(),
+            max_canvas_size: state.max_canvas_size,
+            guide_color: state.guide_color,
+            canvas_border_color: state.canvas_border_color,
+            outside_canvas_color: state.outside_canvas_color,
+            pressure_sensitive: state.tool_options.freehand.pressure_sensitive,
+            stabilizer: state.tool_options.freehand.stabilizer,
+            wheel_brush_modifier: state.wheel_brush_modifier,
+            trim_on_copy: state.trim_on_copy,
+            fill_sample_merged: state.tool_options.fill.sample_merged,
+            eyedropper_sample_merged: state.tool_options.eyedropper.sample_merged,
+            pixel_perfect_curves: state.tool_options.curve.pixel_perfect,
+            undo_active_layer_only: state.undo_active_layer_only,
+            kiosk_mode: state.kiosk_mode,
+            warn_hidden_layer: state.warn_hidden_layer,
+            custom_safe_areas: state.custom_safe_areas.clone(),
+            color_cycle_key: state.color_cycle_key,
+            reopen_last_session: true,
+        }
+    }
+
+    pub fn apply_to(&self, state: &mut EditorState) {
+        state.tool_options.brushes = self.tool_options.clone();
+        state.max_canvas_size = self.max_canvas_size.max(1);
+        state.guide_color = self.guide_color;
+        state.canvas_border_color = self.canvas_border_color;
+        state.outside_canvas_color = self.outside_canvas_color;
+        state.tool_options.freehand.pressure_sensitive = self.pressure_sensitive;
+        state.tool_options.freehand.stabilizer = self.stabilizer.min(10);
+        state.wheel_brush_modifier = self.wheel_brush_modifier;
+        state.trim_on_copy = self.trim_on_copy;
+        state.tool_options.fill.sample_merged = self.fill_sample_merged;
+        state.tool_options.eyedropper.sample_merged = self.eyedropper_sample_merged;
+        state.tool_options.curve.pixel_perfect = self.pixel_perfect_curves;
+        state.undo_active_layer_only = self.undo_active_layer_only;
+        state.kiosk_mode = self.kiosk_mode;
+        state.warn_hidden_layer = self.warn_hidden_layer;
+        state.custom_safe_areas = self.custom_safe_areas.clone();
+        state.color_cycle_key = self.color_cycle_key.to_ascii_lowercase();
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("pxrs").join("settings.json"))
+}
+
+/// Load settings from the user's config directory, falling back to defaults
+/// when the file is missing or unreadable.
+pub fn load() -> Settings {
+    let Some(path) = settings_path() else {
+        return Settings::default();
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Failed to parse settings: {}", e);
+            Settings::default()
+        }),
+        Err(_) => Settings::default(),
+    }
+}
+
+pub fn save(settings: &Settings) -> Result<(), String> {
+    let path = settings_path().ok_or("No config directory available")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create settings directory: {}", e))?;
+    }
+    let contents = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to write settings: {}", e))
+}
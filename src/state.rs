@@ -1,29 +1,224 @@
-use crate::message::ExportFormat;
+use crate::filters::Filter;
+use crate::font::BitmapFont;
+use crate::message::{ExportFormat, ExportUpscale};
+use crate::selection::{SelectionMask, SelectionMode};
+use crate::transform::{RotationAlgorithm, ShiftAxis};
 use iced::Color;
 use iced::Rectangle;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Names an open document for as long as it's open. Tab indices shift as
+/// tabs open and close, so async results are tagged with this instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocumentId(u64);
+
+impl DocumentId {
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Names a layer for as long as it exists. Layer indices shift as layers
+/// are added, deleted and moved, so state that follows a layer stores this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerId(u64);
+
+impl LayerId {
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct EditorState {
+    // Every new document gets a fresh one, including via `new_document`
+    pub id: DocumentId,
     pub canvas_width: u32,
     pub canvas_height: u32,
     pub current_tool: Tool,
     pub primary_color: Color,
     pub secondary_color: Color,
-    pub brush_size: u32,
+    pub tool_options: ToolOptions,
     pub zoom_level: f32,
+    // View-only orientation: mirrored, then rotated clockwise in quarter turns
+    pub view_flipped: bool,
+    pub view_rotation: u8,
+    // Screen-space offset of the canvas from the centered position
+    pub pan: (f32, f32),
     pub grid_visible: bool,
     pub layers: Vec<Layer>,
     pub active_layer_index: usize,
     pub history: History,
     pub selection: Option<Rectangle>,
-    pub clipboard: Option<ClipboardData>,
+    // Refines `selection` (then its bounding box) when it isn't one rectangle
+    pub selection_mask: Option<SelectionMask>,
+    // Keyboard modifiers currently held, as last seen by the canvas
+    pub modifiers: iced::keyboard::Modifiers,
+    // The selection a combining drag started from, and how to combine
+    pub selection_base: Option<(SelectionMode, SelectionMask)>,
+    pub file_path: Option<String>,
+    // Project data outside the undo history changed since the last save
+    pub unsaved_changes: bool,
     pub is_drawing: bool,
     pub last_pixel: Option<(u32, u32)>,
-    pub selected_export_format: ExportFormat,
+    pub last_pressure: Option<f32>,
+    pub wheel_brush_modifier: WheelBrushModifier,
+    pub isometric_stroke: Option<IsometricStroke>,
+    // Copies drop the transparent border around the selected pixels
+    pub trim_on_copy: bool,
+    // Undo skips past changes to other layers
+    pub undo_active_layer_only: bool,
+    // Drawing-only mode: just tools, brush size, a fixed palette, undo/redo
+    // and save
+    pub kiosk_mode: bool,
+    // Warn when a stroke starts on a layer that won't show it
+    pub warn_hidden_layer: bool,
+    // The layer and text of the hidden-layer warning while it is shown
+    pub hidden_layer_warning: Option<(usize, String)>,
+    // When the drawing mode unlock button was pressed, if it is held
+    pub kiosk_unlock_pressed: Option<Instant>,
+    pub shape_draft: Option<ShapeDraft>,
+    pub layer_drag: Option<LayerDrag>,
+    // Name of the safe-area preset drawn over the canvas
+    pub safe_area: Option<String>,
+    // User-defined safe-area presets, kept in the settings
+    pub custom_safe_areas: Vec<SafeAreaPreset>,
+    // Custom safe-area preset being defined in the sidebar
+    pub safe_area_draft: Option<SafeAreaPreset>,
+    // Layer whose name is being edited in its card, with the text so far
+    pub renaming_layer: Option<(usize, String)>,
+    // Layer name last clicked and when, to detect double-clicks
+    pub last_layer_click: Option<(usize, Instant)>,
+    // Layer card under the cursor, which shows its details tooltip
+    pub hovered_layer: Option<usize>,
+    // Layer shown alone, whatever the visibility flags, until unsoloed
+    pub solo_layer: Option<LayerId>,
+    // Recent cursor positions of the current stroke, newest last
+    pub stroke_positions: VecDeque<(u32, u32)>,
+    pub stroke_pixels: HashSet<(u32, u32)>,
+    // Format and options used by Save, saved with the project
+    pub export: ExportSettings,
+    // Outputs written by "Export all", saved with the project
+    pub export_profiles: Vec<ExportProfile>,
+    pub palette_import_limit: u32,
+    // Imported images are snapped to the palette before becoming layers
+    pub snap_imports_to_palette: bool,
+    pub dither_imports: bool,
+    // Imported images add their most frequent colors to the used colors
+    pub add_import_colors: bool,
+    pub max_canvas_size: u32,
+    pub guide_color: [u8; 4],
+    pub canvas_border_color: [u8; 4],
+    pub outside_canvas_color: [u8; 4],
+    // Canvas size text being edited; `None` shows the current size
+    pub canvas_width_input: Option<String>,
+    pub canvas_height_input: Option<String>,
+    // Editing one canvas size field updates the other in proportion
+    pub canvas_aspect_locked: bool,
+    pub notification: Option<String>,
+    // An export is being encoded in the background
+    pub exporting: bool,
+    // A folder import loading its images one at a time
+    pub folder_import: Option<FolderImport>,
     pub is_selecting: bool,
     pub mirror_horizontal: bool,
     pub mirror_vertical: bool,
-    pub used_colors: Vec<Color>,
+    pub wrap_edges: bool,
+    // Tools never draw onto fully transparent pixels while set
+    pub preserve_transparency: bool,
+    // The canvas shows the active layer's alpha as greyscale, and pencil,
+    // eraser, shapes and fill write only alpha, leaving RGB untouched
+    pub alpha_edit: bool,
+    // Named copies of the whole document, oldest first
+    pub restore_points: Vec<RestorePoint>,
+    pub restore_point_name: String,
+    // Restore points are written into the layered PNG data
+    pub save_restore_points: bool,
+    pub fill_preview: Option<FillPreview>,
+    // Isolated pixels found by "Highlight stray pixels"; a view overlay only
+    pub stray_pixels: Option<Vec<(u32, u32)>>,
+    pub color_usage: Option<ColorUsage>,
+    pub comparison: Option<Comparison>,
+    pub color_picker: Option<ColorPickerState>,
+    // Bumped by every message that may change the document; keys caches
+    pub generation: u64,
+    pub nine_slice: Option<NineSlice>,
+    pub nine_slice_sidecar: bool,
+    pub dragging_slice_edge: Option<SliceEdge>,
+    // Tileset editing: a heavy grid line every tile, tile picking and copying
+    pub tile_mode: bool,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    // The next canvas click selects the tile under it
+    pub picking_tile: bool,
+    // A pixel in the tile being Ctrl-dragged onto another tile
+    pub dragging_tile: Option<(u32, u32)>,
+    // Pasted pixels not yet written to the active layer
+    pub floating_paste: Option<FloatingPaste>,
+    // Grab offset within the floating paste while it is dragged
+    pub dragging_paste: Option<(i32, i32)>,
+    // Clipboard shown under the cursor until a click stamps it down
+    pub pending_paste: Option<ClipboardData>,
+    // Text being typed with the text tool, not yet on the layer
+    pub pending_text: Option<PendingText>,
+    pub transparency_tolerance: u8,
+    // Oldest first; pinned entries are never evicted
+    pub used_colors: Vec<UsedColor>,
+    pub used_color_sort: UsedColorSort,
+    // Distinct colors recent strokes painted with, newest first
+    pub recent_colors: Vec<RecentColor>,
+    // Key that steps back through `recent_colors` (Shift steps forward)
+    pub color_cycle_key: char,
+    // Show the newly cycled-to color next to the cursor until the next click
+    pub recent_color_chip: bool,
+    // History revision when the current stroke began
+    pub stroke_revision: Option<u64>,
+    // Ordered shading ramps stepped through with `,` and `.`
+    pub ramps: Vec<Vec<[u8; 4]>>,
+    // Colors picked so far while building a new ramp
+    pub ramp_draft: Option<Vec<[u8; 4]>>,
+    // Settings of a ramp being generated from the primary and secondary
+    // colors, previewed until accepted
+    pub ramp_generator: Option<RampGenerator>,
+    // A ramp being shifted in hue, saturation and value, previewed until
+    // applied to the document
+    pub ramp_shift: Option<RampShift>,
+    // Set in indexed mode; entry 0 is always fully transparent
+    pub indexed_palette: Option<Vec<[u8; 4]>>,
+    pub selected_palette_index: Option<usize>,
+    pub continuous_edit: Option<ContinuousEdit>,
+    pub scale_factor_input: String,
+    pub active_filter: Option<Filter>,
+    pub rotation: Option<RotationPreview>,
+    pub line_shift: Option<LineShift>,
+    // Selected pixels Alt+drag clones, kept while the selection and active
+    // layer stay the same so repeated clones copy the original pixels
+    pub clone_source: Option<CloneSource>,
+    // Grab point and current offset of an Alt+drag clone in progress
+    pub clone_drag: Option<((u32, u32), (i32, i32))>,
+    // Grab point of a Move tool drag in progress
+    pub layer_move_anchor: Option<(u32, u32)>,
+    pub preview_overlay: Option<PreviewBuffer>,
+    pub color_vision: ColorVision,
+    // Draw only each visible layer's silhouette outline, one color per layer
+    pub outline_view: bool,
+    // Canvas offset by half its size to expose tiling seams, waiting for the
+    // offset back
+    pub seams_exposed: bool,
+    pub color_count: Option<usize>,
+    // Pixels per color found by the last color count
+    pub color_frequencies: HashMap<[u8; 4], usize>,
+    pub color_scan_pending: bool,
+    // Tolerance of the open near-duplicate color list
+    pub duplicate_tolerance: Option<u8>,
+    // Result of the last "Refresh" in the Document panel
+    pub document_scan: Option<DocumentScan>,
+    pub document_scan_pending: bool,
 }
 
 impl Default for EditorState {
@@ -33,26 +228,123 @@ impl Default for EditorState {
         let layers = vec![Layer::new("Layer 1".to_string(), width, height)];
 
         Self {
+            id: DocumentId::next(),
             canvas_width: width,
             canvas_height: height,
             current_tool: Tool::Pencil,
             primary_color: Color::BLACK,
             secondary_color: Color::WHITE,
-            brush_size: 1,
+            tool_options: ToolOptions::default(),
             zoom_level: 8.0,
+            view_flipped: false,
+            view_rotation: 0,
+            pan: (0.0, 0.0),
             grid_visible: true,
             layers,
             active_layer_index: 0,
             history: History::new(),
             selection: None,
-            clipboard: None,
+            selection_mask: None,
+            modifiers: iced::keyboard::Modifiers::default(),
+            selection_base: None,
+            file_path: None,
+            unsaved_changes: false,
             is_drawing: false,
             last_pixel: None,
-            selected_export_format: ExportFormat::Png,
+            last_pressure: None,
+            wheel_brush_modifier: WheelBrushModifier::default(),
+            isometric_stroke: None,
+            trim_on_copy: true,
+            undo_active_layer_only: false,
+            kiosk_mode: false,
+            warn_hidden_layer: true,
+            hidden_layer_warning: None,
+            kiosk_unlock_pressed: None,
+            shape_draft: None,
+            layer_drag: None,
+            safe_area: None,
+            custom_safe_areas: Vec::new(),
+            safe_area_draft: None,
+            renaming_layer: None,
+            last_layer_click: None,
+            hovered_layer: None,
+            solo_layer: None,
+            stroke_positions: VecDeque::new(),
+            stroke_pixels: HashSet::new(),
+            export: ExportSettings::default(),
+            export_profiles: Vec::new(),
+            palette_import_limit: 64,
+            snap_imports_to_palette: false,
+            dither_imports: false,
+            add_import_colors: false,
+            max_canvas_size: DEFAULT_MAX_CANVAS_SIZE,
+            guide_color: DEFAULT_GUIDE_COLOR,
+            canvas_border_color: DEFAULT_CANVAS_BORDER_COLOR,
+            outside_canvas_color: DEFAULT_OUTSIDE_CANVAS_COLOR,
+            canvas_width_input: None,
+            canvas_height_input: None,
+            canvas_aspect_locked: false,
+            notification: None,
+            exporting: false,
+            folder_import: None,
             is_selecting: false,
             mirror_horizontal: false,
             mirror_vertical: false,
-            used_colors: vec![Color::BLACK, Color::WHITE],
+            wrap_edges: false,
+            preserve_transparency: false,
+            alpha_edit: false,
+            restore_points: Vec::new(),
+            restore_point_name: String::new(),
+            save_restore_points: false,
+            fill_preview: None,
+            stray_pixels: None,
+            color_usage: None,
+            comparison: None,
+            color_picker: None,
+            generation: 0,
+            nine_slice: None,
+            nine_slice_sidecar: true,
+            dragging_slice_edge: None,
+            tile_mode: false,
+            tile_width: DEFAULT_TILE_SIZE,
+            tile_height: DEFAULT_TILE_SIZE,
+            picking_tile: false,
+            dragging_tile: None,
+            floating_paste: None,
+            dragging_paste: None,
+            pending_paste: None,
+            pending_text: None,
+            transparency_tolerance: 0,
+            used_colors: vec![UsedColor::new(Color::BLACK), UsedColor::new(Color::WHITE)],
+            recent_colors: Vec::new(),
+            color_cycle_key: 'c',
+            recent_color_chip: false,
+            stroke_revision: None,
+            used_color_sort: UsedColorSort::Recency,
+            ramps: Vec::new(),
+            ramp_draft: None,
+            ramp_generator: None,
+            ramp_shift: None,
+            indexed_palette: None,
+            selected_palette_index: None,
+            continuous_edit: None,
+            scale_factor_input: String::from("2"),
+            active_filter: None,
+            rotation: None,
+            line_shift: None,
+            clone_source: None,
+            clone_drag: None,
+            layer_move_anchor: None,
+            preview_overlay: None,
+            color_vision: ColorVision::Normal,
+            outline_view: false,
+            seams_exposed: false,
+            color_count: None,
+            color_frequencies: HashMap::new(),
+            duplicate_tolerance: None,
+            color_scan_pending: false,
+            document_scan: None,
+            document_scan_pending: false,
         }
     }
 }
@@ -67,14 +359,488 @@ impl EditorState {
         }
     }
 
+    /// A blank document that keeps this one's user preferences.
+    pub fn new_document(&self, width: u32, height: u32) -> Self {
+        Self {
+            tool_options: self.tool_options.clone(),
+            max_canvas_size: self.max_canvas_size,
+            guide_color: self.guide_color,
+            canvas_border_color: self.canvas_border_color,
+            outside_canvas_color: self.outside_canvas_color,
+            wheel_brush_modifier: self.wheel_brush_modifier,
+            trim_on_copy: self.trim_on_copy,
+            undo_active_layer_only: self.undo_active_layer_only,
+            kiosk_mode: self.kiosk_mode,
+            warn_hidden_layer: self.warn_hidden_layer,
+            custom_safe_areas: self.custom_safe_areas.clone(),
+            color_cycle_key: self.color_cycle_key,
+            ..Self::new(width, height)
+        }
+    }
+
+    /// Recompute which pixels differ from the comparison image. Drops the
+    /// comparison with a notice once the canvas no longer matches its size.
+    pub fn refresh_comparison(&mut self) {
+        let Some(mut comparison) = self.comparison.take() else {
+            return;
+        };
+        if (comparison.width, comparison.height) != (self.canvas_width, self.canvas_height) {
+            self.notification = Some(format!(
+                "Comparison closed: {} is {}x{} but the canvas is {}x{}",
+                comparison.path,
+                comparison.width,
+                comparison.height,
+                self.canvas_width,
+                self.canvas_height
+            ));
+            return;
+        }
+        comparison.differs = (0..self.canvas_height)
+            .flat_map(|y| (0..self.canvas_width).map(move |x| (x, y)))
+            .zip(comparison.pixels.chunks_exact(4))
+            .map(|((x, y), theirs)| self.get_rgba8(x, y) != theirs)
+            .collect();
+        comparison.differing = comparison.differs.iter().filter(|&&d| d).count();
+        comparison.revision = self.history.revision;
+        self.comparison = Some(comparison);
+    }
+
+    /// Copy for exporting off the UI thread, without the undo history, and
+    /// without the restore points unless they are saved with the project.
+    pub fn export_snapshot(&mut self) -> Self {
+        let history = std::mem::replace(&mut self.history, History::new());
+        let restore_points = std::mem::take(&mut self.restore_points);
+        let mut snapshot = self.clone();
+        if self.save_restore_points {
+            snapshot.restore_points = restore_points.clone();
+        }
+        self.history = history;
+        self.restore_points = restore_points;
+        snapshot
+    }
+
+    /// Name shown in the document tab.
+    pub fn display_name(&self) -> String {
+        self.file_path
+            .as_deref()
+            .and_then(|path| std::path::Path::new(path).file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled".to_string())
+    }
+
+    /// Whether the document changed since it was created or last saved.
+    pub fn is_dirty(&self) -> bool {
+        self.unsaved_changes || self.history.saved_index != Some(self.history.current_index)
+    }
+
+    /// Note a change to data saved in the project file that undo doesn't
+    /// track, such as the palette, guides, tile settings or layer list.
+    /// View-only changes like zoom, pan and grid visibility never call this.
+    pub fn mark_dirty(&mut self) {
+        self.unsaved_changes = true;
+    }
+
+    /// Approximate bytes held by the layer buffers and the undo history.
+    pub fn memory_usage(&self) -> usize {
+        let layers: usize = self
+            .layers
+            .iter()
+            .map(|layer| layer.pixels.len() + layer.indices.as_ref().map_or(0, Vec::len))
+            .sum();
+        layers + self.history.memory_size() + self.restore_points_memory()
+    }
+
+    /// Approximate bytes held by the restore points.
+    pub fn restore_points_memory(&self) -> usize {
+        self.restore_points
+            .iter()
+            .map(RestorePoint::memory_size)
+            .sum()
+    }
+
+    /// Store the document as a restore point named `name`, evicting the
+    /// oldest points past the count and memory limits. Returns the names of
+    /// the evicted points.
+    pub fn create_restore_point(&mut self, name: String) -> Vec<String> {
+        self.finish_continuous_edit();
+        self.restore_points.push(RestorePoint::capture(self, name));
+        let mut evicted = Vec::new();
+        while self.restore_points.len() > 1
+            && (self.restore_points.len() > MAX_RESTORE_POINTS
+                || self.restore_points_memory() > RESTORE_POINT_MEMORY_BUDGET)
+        {
+            evicted.push(self.restore_points.remove(0).name);
+        }
+        evicted
+    }
+
+    /// Swap the document for restore point `index` as one undo step.
+    pub fn restore_point(&mut self, index: usize) {
+        let Some(point) = self.restore_points.get(index).cloned() else {
+            return;
+        };
+        self.record_layers_change(|state| point.apply(state));
+    }
+
+    /// Run `change`, which may add, remove or reorder layers, as one undo
+    /// step holding the whole document before and after it.
+    pub fn record_layers_change(&mut self, change: impl FnOnce(&mut Self)) {
+        self.finish_continuous_edit();
+        self.cancel_preview();
+        let before = RestorePoint::capture(self, String::new());
+        change(self);
+        let after = RestorePoint::capture(self, String::new());
+        self.history.push(EditCommand::LayersReplaced {
+            before: Box::new(before),
+            after: Box::new(after),
+        });
+    }
+
+    /// Note that the document was just saved.
+    pub fn mark_saved(&mut self) {
+        self.unsaved_changes = false;
+        self.history.mark_saved();
+    }
+
+    /// Switch to indexed mode, quantizing every layer to at most 256 palette
+    /// entries (the first one transparent), as one undo step.
+    pub fn convert_to_indexed(&mut self) {
+        if self.indexed_palette.is_some() {
+            return;
+        }
+        let before = ColorMode::capture(self);
+        let palette = crate::palette::quantize_palette(
+            self.layers.iter().map(|layer| layer.pixels.as_slice()),
+            256,
+        );
+        self.indexed_palette = Some(palette);
+        self.sync_indexed();
+        self.push_color_mode(before);
+    }
+
+    /// Leave indexed mode as one undo step, keeping the current colors as
+    /// plain RGBA.
+    pub fn convert_to_rgba(&mut self) {
+        if self.indexed_palette.is_none() {
+            return;
+        }
+        let before = ColorMode::capture(self);
+        self.indexed_palette = None;
+        self.selected_palette_index = None;
+        for layer in &mut self.layers {
+            layer.indices = None;
+        }
+        self.push_color_mode(before);
+    }
+
+    fn push_color_mode(&mut self, before: ColorMode) {
+        let after = ColorMode::capture(self);
+        self.history.push(EditCommand::ColorModeChanged {
+            before: Box::new(before),
+            after: Box::new(after),
+        });
+    }
+
+    /// Bring every layer's index buffer in line with its RGBA pixels. Pixels
+    /// whose color no longer matches their palette entry (because a tool,
+    /// paste or undo wrote RGBA directly) are remapped to the nearest entry
+    /// and snapped to its color, so every write path paints indices.
+    pub fn sync_indexed(&mut self) {
+        let Some(palette) = &self.indexed_palette else {
+            return;
+        };
+        let mut nearest: HashMap<[u8; 4], u8> = HashMap::new();
+        for layer in &mut self.layers {
+            let count = layer.pixels.len() / 4;
+            let indices = layer.indices.get_or_insert_with(Vec::new);
+            if indices.len() != count {
+                // Force a full remap after a resize or on first sync
+                *indices = vec![u8::MAX; count];
+            }
+
+            for (pixel, index) in layer.pixels.chunks_exact_mut(4).zip(indices.iter_mut()) {
+                let rgba = [pixel[0], pixel[1], pixel[2], pixel[3]];
+                if palette.get(*index as usize) == Some(&rgba) {
+                    continue;
+                }
+                *index = *nearest
+                    .entry(rgba)
+                    .or_insert_with(|| crate::palette::nearest_index(palette, rgba));
+                pixel.copy_from_slice(&palette[*index as usize]);
+            }
+        }
+    }
+
+    /// Recolor every pixel on every layer that exactly matches `from[i]` to
+    /// `to[i]`, as one undo entry. Returns how many pixels each entry changed.
+    pub fn remap_colors(&mut self, from: &[[u8; 4]], to: &[[u8; 4]]) -> Vec<usize> {
+        let mut counts = vec![0; from.len()];
+        let before = DocSnapshot::capture(self);
+        for layer in &mut self.layers {
+            for pixel in layer.pixels.chunks_exact_mut(4) {
+                let rgba = [pixel[0], pixel[1], pixel[2], pixel[3]];
+                if let Some(index) = from.iter().position(|entry| *entry == rgba)
+                    && let Some(new) = to.get(index)
+                    && *new != rgba
+                {
+                    pixel.copy_from_slice(new);
+                    counts[index] += 1;
+                }
+            }
+        }
+        if counts.iter().any(|&count| count > 0) {
+            self.push_snapshot(before);
+        }
+        counts
+    }
+
+    /// Change a palette entry in indexed mode as one undo step, recoloring
+    /// every pixel that uses it.
+    pub fn recolor_palette_entry(&mut self, index: usize, rgba: [u8; 4]) {
+        self.finish_continuous_edit();
+        if let Some(old) = self.set_palette_color(index, rgba)
+            && old != rgba
+        {
+            self.history.push(EditCommand::PaletteEntryChanged {
+                index,
+                old,
+                new: rgba,
+            });
+        }
+    }
+
+    /// Change a palette entry in indexed mode, recoloring every pixel that
+    /// uses it, without recording it. Returns the entry's previous color.
+    pub fn set_palette_color(&mut self, index: usize, rgba: [u8; 4]) -> Option<[u8; 4]> {
+        // Entry 0 stays transparent so erasing always has somewhere to go
+        if index == 0 {
+            return None;
+        }
+        let entry = self.indexed_palette.as_mut()?.get_mut(index)?;
+        let old = std::mem::replace(entry, rgba);
+
+        for layer in &mut self.layers {
+            let Some(indices) = &layer.indices else {
+                continue;
+            };
+            for (pixel, &pixel_index) in layer.pixels.chunks_exact_mut(4).zip(indices) {
+                if pixel_index as usize == index {
+                    pixel.copy_from_slice(&rgba);
+                }
+            }
+        }
+        Some(old)
+    }
+
+    /// Brush options remembered for `tool`, falling back to defaults if never
+    /// changed.
+    pub fn options_for(&self, tool: Tool) -> BrushOptions {
+        self.tool_options
+            .brushes
+            .get(&tool)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn options_for_mut(&mut self, tool: Tool) -> &mut BrushOptions {
+        self.tool_options.brushes.entry(tool).or_default()
+    }
+
+    /// Brush size of the currently selected tool.
+    pub fn brush_size(&self) -> u32 {
+        self.options_for(self.current_tool).brush_size
+    }
+
+    /// Brush opacity of the currently selected tool.
+    pub fn brush_opacity(&self) -> f32 {
+        self.options_for(self.current_tool).opacity
+    }
+
     pub fn active_layer_mut(&mut self) -> Option<&mut Layer> {
         self.layers.get_mut(self.active_layer_index)
     }
 
+    /// Feed a stroke's cursor position through the stabilizer, returning the
+    /// average of it and up to `stabilizer` earlier positions. With the
+    /// stabilizer off the position passes through unchanged.
+    pub fn stabilize(&mut self, x: u32, y: u32) -> (u32, u32) {
+        let stabilizer = self.tool_options.freehand.stabilizer;
+        if stabilizer == 0 {
+            return (x, y);
+        }
+        self.stroke_positions.push_back((x, y));
+        while self.stroke_positions.len() > stabilizer as usize + 1 {
+            self.stroke_positions.pop_front();
+        }
+        let count = self.stroke_positions.len() as f32;
+        let (sum_x, sum_y) = self
+            .stroke_positions
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), &(px, py)| {
+                (sx + px as f32, sy + py as f32)
+            });
+        (
+            (sum_x / count).round() as u32,
+            (sum_y / count).round() as u32,
+        )
+    }
+
     pub fn active_layer(&self) -> Option<&Layer> {
         self.layers.get(self.active_layer_index)
     }
 
+    /// The tile containing (x, y) as `(start_x, start_y, end_x, end_y)` with
+    /// exclusive ends, clipped to the canvas.
+    pub fn tile_bounds(&self, x: u32, y: u32) -> (u32, u32, u32, u32) {
+        let (tile_width, tile_height) = (self.tile_width.max(1), self.tile_height.max(1));
+        let (start_x, start_y) = (x - x % tile_width, y - y % tile_height);
+        (
+            start_x,
+            start_y,
+            (start_x + tile_width).min(self.canvas_width),
+            (start_y + tile_height).min(self.canvas_height),
+        )
+    }
+
+    /// How a selection drag started now combines with the selection.
+    pub fn selection_mode(&self) -> SelectionMode {
+        SelectionMode::from_modifiers(self.modifiers)
+    }
+
+    /// The current selection as a mask, whether it is a combined selection or
+    /// a plain rectangle.
+    pub fn effective_selection(&self) -> Option<SelectionMask> {
+        match &self.selection_mask {
+            Some(mask) => Some(mask.clone()),
+            None => self.selection_bounds().map(|bounds| {
+                SelectionMask::from_rect(self.canvas_width, self.canvas_height, bounds)
+            }),
+        }
+    }
+
+    /// Whether (x, y) is selected. Always false without a selection.
+    pub fn is_selected(&self, x: u32, y: u32) -> bool {
+        match &self.selection_mask {
+            Some(mask) => mask.contains(x, y),
+            None => self
+                .selection_bounds()
+                .is_some_and(|(start_x, start_y, end_x, end_y)| {
+                    x >= start_x && x < end_x && y >= start_y && y < end_y
+                }),
+        }
+    }
+
+    /// Finish a Shift/Ctrl selection drag by combining the dragged rectangle
+    /// into the selection it started from.
+    pub fn finish_combined_selection(&mut self) {
+        let Some((mode, mut mask)) = self.selection_base.take() else {
+            return;
+        };
+        if let Some(bounds) = self.selection_bounds() {
+            let rect = SelectionMask::from_rect(self.canvas_width, self.canvas_height, bounds);
+            mask.combine(&rect, mode);
+        }
+        self.selection = mask
+            .bounds()
+            .map(|(start_x, start_y, end_x, end_y)| Rectangle {
+                x: start_x as f32,
+                y: start_y as f32,
+                width: (end_x - start_x) as f32,
+                height: (end_y - start_y) as f32,
+            });
+        self.selection_mask = self.selection.is_some().then_some(mask);
+    }
+
+    /// The selection clamped to the canvas as `(start_x, start_y, end_x, end_y)`
+    /// with exclusive ends, or `None` if there is no non-empty selection.
+    pub fn selection_bounds(&self) -> Option<(u32, u32, u32, u32)> {
+        let selection = self.selection?;
+        let start_x = crate::utils::clamp_u32(selection.x as i32, 0, self.canvas_width);
+        let start_y = crate::utils::clamp_u32(selection.y as i32, 0, self.canvas_height);
+        let end_x =
+            crate::utils::clamp_u32((selection.x + selection.width) as i32, 0, self.canvas_width);
+        let end_y = crate::utils::clamp_u32(
+            (selection.y + selection.height) as i32,
+            0,
+            self.canvas_height,
+        );
+        if start_x >= end_x || start_y >= end_y {
+            return None;
+        }
+        Some((start_x, start_y, end_x, end_y))
+    }
+
+    /// Composited RGBA bytes at (x, y). A single fully opaque visible layer
+    /// is read directly, skipping the float blend.
+    pub fn get_rgba8(&self, x: u32, y: u32) -> [u8; 4] {
+        let mut visible = self.visible_layers();
+        if let (Some((index, None)), None) = (visible.next(), visible.next())
+            && let Some(layer) = self.layers.get(index)
+            && layer.opacity >= 1.0
+        {
+            return layer.get_rgba8(x, y);
+        }
+        self.get_pixel(x, y).into_rgba8()
+    }
+
+    /// Visible layers bottom to top as `(index, clip base)`. A clipped
+    /// layer's base is the first unclipped layer below it; clipped layers are
+    /// hidden along with their base, and one with nothing below draws as if
+    /// unclipped. A soloed layer is the only one visible.
+    pub fn visible_layers(&self) -> impl Iterator<Item = (usize, Option<usize>)> + '_ {
+        let solo = self.solo_index();
+        let mut base = None;
+        self.layers
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, layer)| {
+                let clip = if layer.clipped {
+                    base
+                } else {
+                    base = Some(index);
+                    None
+                };
+                if let Some(solo) = solo {
+                    return (index == solo).then_some((index, clip));
+                }
+                let base_visible =
+                    clip.is_none_or(|base| self.layers.get(base).is_some_and(|base| base.visible));
+                (layer.visible && base_visible).then_some((index, clip))
+            })
+    }
+
+    /// Position of the soloed layer, if there is one.
+    pub fn solo_index(&self) -> Option<usize> {
+        let solo = self.solo_layer?;
+        self.layers.iter().position(|layer| layer.id == solo)
+    }
+
+    /// Why edits to layer `index` would not show on the canvas, if they
+    /// wouldn't: the layer is hidden (itself, by its clip base or by solo)
+    /// or its opacity is all but zero.
+    pub fn layer_hidden_reason(&self, index: usize) -> Option<&'static str> {
+        let layer = self.layers.get(index)?;
+        if !layer.visible {
+            Some("hidden layer")
+        } else if self.solo_index().is_some_and(|solo| solo != index) {
+            Some("layer hidden by solo")
+        } else if !self.visible_layers().any(|(visible, _)| visible == index) {
+            Some("layer clipped to a hidden layer")
+        } else if layer.opacity < HIDDEN_LAYER_OPACITY {
+            Some("fully transparent layer")
+        } else {
+            None
+        }
+    }
+
+    /// RGBA bytes of layer `index` at (x, y) with its clip base applied.
+    pub fn clipped_rgba8(&self, index: usize, clip: Option<usize>, x: u32, y: u32) -> [u8; 4] {
+        let Some(layer) = self.layers.get(index) else {
+            return [0; 4];
+        };
+        clip_rgba8(layer, clip.and_then(|base| self.layers.get(base)), x, y)
+    }
+
     pub fn get_pixel(&self, x: u32, y: u32) -> Color {
         if x >= self.canvas_width || y >= self.canvas_height {
             return Color::TRANSPARENT;
@@ -82,20 +848,53 @@ impl EditorState {
 
         // Composite all visible layers from bottom to top
         let mut result = Color::TRANSPARENT;
-        for layer in &self.layers {
-            if !layer.visible {
-                continue;
-            }
-            let pixel = layer.get_pixel(x, y);
-            result = blend_color(result, pixel, layer.opacity);
+        for (index, clip) in self.visible_layers() {
+            let pixel = self.clipped_rgba8(index, clip, x, y);
+            result = blend_rgba8(result, pixel, self.layers[index].opacity);
         }
         result
     }
 
-    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
-        if let Some(layer) = self.active_layer_mut() {
-            layer.set_pixel(x, y, color);
-            self.add_used_color(color);
+    /// Write a pixel on the active layer through the write mask: while
+    /// editing alpha only `color`'s alpha lands. Returns the color written,
+    /// or `None` when nothing was, either out of bounds or because
+    /// preserve-transparency mode protects the pixel. Callers record the
+    /// painted color in `used_colors` themselves, since blended colors
+    /// shouldn't end up there.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) -> Option<Color> {
+        let preserve = self.preserve_transparency;
+        let alpha_only = self.alpha_edit;
+        let layer = self.active_layer_mut()?;
+        if x >= layer.width || y >= layer.height {
+            return None;
+        }
+        let old = layer.get_pixel(x, y);
+        let color = if alpha_only {
+            Color { a: color.a, ..old }
+        } else {
+            color
+        };
+        if preserve && crate::utils::reveals_transparent(old, color) {
+            return None;
+        }
+        layer.set_pixel(x, y, color);
+        Some(color)
+    }
+
+    /// The color a brush of `color` at `opacity` leaves over `old`. While
+    /// editing alpha the brush paints the color's luminance into alpha.
+    pub fn brush_color(&self, old: Color, color: Color, opacity: f32) -> Color {
+        if self.alpha_edit {
+            let value = crate::filters::luminance(color.into_rgba8()) as f32 / 255.0;
+            return Color {
+                a: old.a + (value - old.a) * opacity,
+                ..old
+            };
+        }
+        if opacity < 1.0 {
+            blend_color(old, color, opacity)
+        } else {
+            color
         }
     }
 
@@ -105,55 +904,1398 @@ impl EditorState {
         self.active_layer_index = self.layers.len() - 1;
     }
 
+    /// Built-in and custom safe-area presets, in menu order.
+    pub fn safe_area_presets(&self) -> Vec<SafeAreaPreset> {
+        let mut presets = SafeAreaPreset::builtin();
+        presets.extend(self.custom_safe_areas.iter().cloned());
+        presets
+    }
+
+    /// Copy the active layer into a new layer directly above it and make the
+    /// copy active. Not recorded in history; see `begin_layer_copy`.
+    pub fn duplicate_active_layer(&mut self) {
+        let Some(layer) = self.active_layer() else {
+            return;
+        };
+        let mut copy = layer.clone();
+        copy.id = LayerId::next();
+        copy.name = format!("{} copy", layer.name);
+        let index = self.active_layer_index + 1;
+        self.layers.insert(index, copy);
+        self.active_layer_index = index;
+    }
+
+    /// Add `pixels` as a new top layer named `name`, centered on the canvas
+    /// and cropped where it is larger.
+    pub fn add_centered_layer(&mut self, name: String, width: u32, height: u32, pixels: &[u8]) {
+        let mut layer = Layer::new(name, self.canvas_width, self.canvas_height);
+        let offset_x = (self.canvas_width as i64 - width as i64) / 2;
+        let offset_y = (self.canvas_height as i64 - height as i64) / 2;
+        for y in 0..height {
+            let canvas_y = y as i64 + offset_y;
+            if canvas_y < 0 || canvas_y >= self.canvas_height as i64 {
+                continue;
+            }
+            for x in 0..width {
+                let canvas_x = x as i64 + offset_x;
+                if canvas_x < 0 || canvas_x >= self.canvas_width as i64 {
+                    continue;
+                }
+                let source = ((y * width + x) * 4) as usize;
+                let Some(rgba) = pixels.get(source..source + 4) else {
+                    continue;
+                };
+                let target = ((canvas_y as u32 * self.canvas_width + canvas_x as u32) * 4) as usize;
+                layer.pixels[target..target + 4].copy_from_slice(rgba);
+            }
+        }
+        self.layers.push(layer);
+        self.active_layer_index = self.layers.len() - 1;
+    }
+
+    /// Insert an opaque layer of `color` below all others, keeping the same
+    /// layer active.
+    pub fn add_background_layer(&mut self, color: Color) {
+        let mut layer = Layer::new(
+            "Background".to_string(),
+            self.canvas_width,
+            self.canvas_height,
+        );
+        let rgba = crate::utils::color_to_rgba8(color);
+        for pixel in layer.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[rgba[0], rgba[1], rgba[2], 255]);
+        }
+        self.layers.insert(0, layer);
+        self.active_layer_index += 1;
+    }
+
+    /// Remove the layer at `index` as one undo step.
     pub fn delete_layer(&mut self, index: usize) {
-        if self.layers.len() > 1 && index < self.layers.len() {
-            self.layers.remove(index);
-            if self.active_layer_index >= self.layers.len() {
-                self.active_layer_index = self.layers.len().saturating_sub(1);
+        if self.layers.len() <= 1 || index >= self.layers.len() {
+            return;
+        }
+        self.record_layers_change(|state| {
+            let removed = state.layers.remove(index);
+            if state.solo_layer == Some(removed.id) {
+                state.solo_layer = None;
+            }
+            if state.active_layer_index >= state.layers.len() {
+                state.active_layer_index = state.layers.len().saturating_sub(1);
+            }
+        });
+    }
+
+    /// Move the layer at `from` to `to` as one undo step, keeping the same
+    /// layer active.
+    pub fn move_layer(&mut self, from: usize, to: usize) {
+        if from >= self.layers.len() || to >= self.layers.len() {
+            return;
+        }
+        self.record_layers_change(|state| {
+            let layer = state.layers.remove(from);
+            state.layers.insert(to, layer);
+            state.active_layer_index = follow_layer_move(state.active_layer_index, from, to);
+        });
+    }
+
+    pub fn layer_property(&self, layer_index: usize, kind: LayerProperty) -> Option<LayerProperty> {
+        let layer = self.layers.get(layer_index)?;
+        Some(match kind {
+            LayerProperty::Opacity(_) => LayerProperty::Opacity(layer.opacity),
+        })
+    }
+
+    pub fn set_layer_property(&mut self, layer_index: usize, property: LayerProperty) {
+        if let Some(layer) = self.layers.get_mut(layer_index) {
+            match property {
+                LayerProperty::Opacity(opacity) => layer.opacity = opacity,
+            }
+        }
+    }
+
+    /// Start tracking a continuous edit (e.g. a slider drag). Only the first
+    /// call of a gesture records the starting value; if a different target was
+    /// being edited, that edit is committed first.
+    pub fn begin_continuous_edit(&mut self, edit: ContinuousEdit) {
+        match &self.continuous_edit {
+            Some(current) if current.same_target(&edit) => {}
+            Some(_) => {
+                self.finish_continuous_edit();
+                self.continuous_edit = Some(edit);
+            }
+            None => self.continuous_edit = Some(edit),
+        }
+    }
+
+    /// Commit the pending continuous edit as a single history entry, if the
+    /// value actually changed over the course of the gesture.
+    pub fn finish_continuous_edit(&mut self) {
+        let Some(edit) = self.continuous_edit.take() else {
+            return;
+        };
+        match edit {
+            ContinuousEdit::LayerProperty { layer_index, start } => {
+                if let Some(end) = self.layer_property(layer_index, start)
+                    && end != start
+                {
+                    self.history.push(EditCommand::LayerPropertyChanged {
+                        layer_index,
+                        old: start,
+                        new: end,
+                    });
+                }
+            }
+            ContinuousEdit::LayerCopy { before } => {
+                // The moved pixels land in the same step as the copy
+                if let Some(preview) = self.preview_overlay.take()
+                    && let Some(layer) = self.layers.get_mut(preview.layer_index)
+                {
+                    layer.pixels = preview.pixels;
+                }
+                let after = RestorePoint::capture(self, String::new());
+                self.history.push(EditCommand::LayersReplaced {
+                    before,
+                    after: Box::new(after),
+                });
+            }
+        }
+    }
+
+    /// Copy the active layer as the start of a Ctrl+drag with the Move tool.
+    /// The copy and its move become one undo step when the drag ends.
+    pub fn begin_layer_copy(&mut self) {
+        self.finish_continuous_edit();
+        let before = RestorePoint::capture(self, String::new());
+        self.duplicate_active_layer();
+        self.continuous_edit = Some(ContinuousEdit::LayerCopy {
+            before: Box::new(before),
+        });
+    }
+
+    /// Resample the canvas and every layer by `factor` using nearest neighbor,
+    /// recording the whole operation as a single history entry. Fails when
+    /// the result would exceed the maximum canvas size.
+    pub fn scale_canvas(&mut self, factor: ScaleFactor) -> Result<(), String> {
+        let max = self.max_canvas_size;
+        let (new_width, new_height) = factor
+            .apply(self.canvas_width, self.canvas_height)
+            .filter(|&(width, height)| width <= max && height <= max)
+            .ok_or_else(|| {
+                format!(
+                    "Scaling would exceed the maximum canvas size of {}x{}",
+                    max, max
+                )
+            })?;
+        if new_width == self.canvas_width && new_height == self.canvas_height {
+            return Ok(());
+        }
+
+        let before = DocSnapshot::capture(self);
+        for layer in &mut self.layers {
+            layer.pixels = crate::transform::scale_nearest(
+                &layer.pixels,
+                layer.width,
+                layer.height,
+                new_width,
+                new_height,
+            );
+            layer.width = new_width;
+            layer.height = new_height;
+        }
+        self.canvas_width = new_width;
+        self.canvas_height = new_height;
+        self.selection = None;
+        self.push_snapshot(before);
+        Ok(())
+    }
+
+    /// Replace a layer's pixels with `new_pixels` (same size) as a single
+    /// undoable change. Does nothing if no pixels differ.
+    pub fn replace_layer_pixels(&mut self, layer_index: usize, new_pixels: Vec<u8>) {
+        let Some(layer) = self.layers.get_mut(layer_index) else {
+            return;
+        };
+        if new_pixels.len() != layer.pixels.len() {
+            return;
+        }
+
+        let mut changes = Vec::new();
+        for y in 0..layer.height {
+            for x in 0..layer.width {
+                let index = ((y * layer.width + x) * 4) as usize;
+                if layer.pixels[index..index + 4] == new_pixels[index..index + 4] {
+                    continue;
+                }
+                let old_color = layer.get_pixel(x, y);
+                let new_color = crate::utils::rgba8_to_color([
+                    new_pixels[index],
+                    new_pixels[index + 1],
+                    new_pixels[index + 2],
+                    new_pixels[index + 3],
+                ]);
+                changes.push((x, y, old_color, new_color));
             }
         }
+        layer.pixels = new_pixels;
+
+        if let Some(command) = EditCommand::from_pixel_changes(layer_index, layer, changes) {
+            self.history.push(command);
+        }
     }
 
-    pub fn add_used_color(&mut self, color: Color) {
+    /// Show `pixels` in place of the active layer until the preview is
+    /// committed or cancelled.
+    pub fn set_preview(&mut self, pixels: Vec<u8>) {
+        self.preview_overlay = Some(PreviewBuffer {
+            layer_index: self.active_layer_index,
+            pixels,
+        });
+    }
+
+    /// Copy the preview into its layer as one undo entry and close the filter,
+    /// rotation or line shift that produced it. A selection grows to cover
+    /// rotated content.
+    pub fn commit_preview(&mut self) {
+        let rotation = self.rotation.take();
+        self.active_filter = None;
+        self.line_shift = None;
+        let Some(preview) = self.preview_overlay.take() else {
+            return;
+        };
+        self.replace_layer_pixels(preview.layer_index, preview.pixels);
+
+        if let Some(rotation) = rotation
+            && self.selection.is_some()
+        {
+            // The rotated content no longer matches a combined outline
+            self.selection_mask = None;
+            let (start_x, start_y, end_x, end_y) = rotation.bounds;
+            self.selection = Some(Rectangle {
+                x: start_x as f32,
+                y: start_y as f32,
+                width: (end_x - start_x) as f32,
+                height: (end_y - start_y) as f32,
+            });
+        }
+    }
+
+    /// Drop the preview without touching the document.
+    pub fn cancel_preview(&mut self) {
+        self.preview_overlay = None;
+        self.active_filter = None;
+        self.rotation = None;
+        self.line_shift = None;
+    }
+
+    /// Preview the selection (or the whole active layer) rotated `degrees`
+    /// clockwise.
+    pub fn set_rotation(&mut self, degrees: f32, algorithm: RotationAlgorithm) {
+        let region =
+            self.selection_bounds()
+                .unwrap_or((0, 0, self.canvas_width, self.canvas_height));
+        let Some(layer) = self.active_layer() else {
+            return;
+        };
+        let (pixels, bounds) = crate::transform::rotate_region(
+            &layer.pixels,
+            layer.width,
+            layer.height,
+            region,
+            degrees,
+            algorithm,
+        );
+        self.active_filter = None;
+        self.line_shift = None;
+        self.rotation = Some(RotationPreview {
+            degrees,
+            algorithm,
+            bounds,
+        });
+        self.set_preview(pixels);
+    }
+
+    /// Offset every layer by half the canvas size with wrap to expose the
+    /// tiling seams in the middle, or (`expose` false) offset back. Each
+    /// offset is its own undo step.
+    pub fn offset_seams(&mut self, expose: bool) {
+        if self.seams_exposed == expose {
+            return;
+        }
+        let (dx, dy) = (
+            (self.canvas_width / 2) as i32,
+            (self.canvas_height / 2) as i32,
+        );
+        let (dx, dy) = if expose { (dx, dy) } else { (-dx, -dy) };
+        self.finish_continuous_edit();
+        self.cancel_preview();
+        let before = DocSnapshot::capture(self);
+        for layer in &mut self.layers {
+            layer.pixels =
+                crate::transform::offset_wrapped(&layer.pixels, layer.width, layer.height, dx, dy);
+        }
+        self.seams_exposed = expose;
+        self.push_snapshot(before);
+    }
+
+    /// Preview row or column `index` of the active layer shifted by `offset`
+    /// with wrap. With a selection only the part of the line inside its
+    /// bounds moves, and lines missing the selection are refused.
+    pub fn set_line_shift(&mut self, axis: ShiftAxis, index: u32, offset: i32) {
+        let (length, lines) = match axis {
+            ShiftAxis::Row => (self.canvas_width, self.canvas_height),
+            ShiftAxis::Column => (self.canvas_height, self.canvas_width),
+        };
+        if index >= lines {
+            return;
+        }
+        let span = match (self.selection_bounds(), axis) {
+            (None, _) => (0, length),
+            (Some((start_x, start_y, end_x, end_y)), ShiftAxis::Row) => {
+                if !(start_y..end_y).contains(&index) {
+                    self.notification = Some(format!("Row {} is outside the selection", index));
+                    return;
+                }
+                (start_x, end_x)
+            }
+            (Some((start_x, start_y, end_x, end_y)), ShiftAxis::Column) => {
+                if !(start_x..end_x).contains(&index) {
+                    self.notification = Some(format!("Column {} is outside the selection", index));
+                    return;
+                }
+                (start_y, end_y)
+            }
+        };
+        let Some(layer) = self.active_layer() else {
+            return;
+        };
+        let pixels = crate::transform::shift_line(
+            &layer.pixels,
+            layer.width,
+            layer.height,
+            axis,
+            index,
+            span,
+            offset,
+        );
+        self.active_filter = None;
+        self.rotation = None;
+        self.line_shift = Some(LineShift {
+            axis,
+            index,
+            span,
+            offset,
+        });
+        self.set_preview(pixels);
+    }
+
+    /// Record a canvas-wide change. `before` must be captured before the
+    /// document was modified; the current document becomes the redo side.
+    pub fn push_snapshot(&mut self, before: DocSnapshot) {
+        let after = DocSnapshot::capture(self);
+        self.history
+            .push(EditCommand::DocumentSnapshot { before, after });
+    }
+
+    /// Put `color` at the front of the recent colors after a stroke painted
+    /// with it.
+    pub fn remember_stroke_color(&mut self, color: Color) {
+        self.recent_colors.retain(|recent| recent.color != color);
+        self.recent_colors.insert(
+            0,
+            RecentColor {
+                color,
+                history_index: self.history.current_index,
+            },
+        );
+        self.recent_colors.truncate(MAX_RECENT_COLORS);
+    }
+
+    /// Forget recent colors whose strokes were undone. Redoing them does not
+    /// bring the colors back, and strokes that merged with or outlived older
+    /// history entries are only tracked approximately.
+    pub fn forget_undone_colors(&mut self) {
+        let current = self.history.current_index;
+        self.recent_colors
+            .retain(|recent| recent.history_index <= current);
+    }
+
+    /// Switch the primary color to the recent color `step` places older
+    /// (newer when negative), wrapping around. A primary color that isn't
+    /// recent starts from the newest end.
+    pub fn step_recent_color(&mut self, step: i32) {
+        let count = self.recent_colors.len() as i32;
+        if count == 0 {
+            self.notification = Some("No recent colors yet".into());
+            return;
+        }
+        let index = match self
+            .recent_colors
+            .iter()
+            .position(|recent| recent.color == self.primary_color)
+        {
+            Some(index) => (index as i32 + step).rem_euclid(count),
+            None if step > 0 => 0,
+            None => count - 1,
+        };
+        self.primary_color = self.recent_colors[index as usize].color;
+        self.recent_color_chip = true;
+    }
+
+    /// Add `color` to the used colors unless it is transparent or already
+    /// there, dropping the oldest unpinned color past the limit. Returns
+    /// whether it was added.
+    pub fn add_used_color(&mut self, color: Color) -> bool {
+        if !self.insert_used_color(color) {
+            return false;
+        }
+        // Keep only the most recent 32 unpinned colors
+        let unpinned = self.used_colors.iter().filter(|used| !used.pinned).count();
+        if unpinned > MAX_USED_COLORS
+            && let Some(oldest) = self.used_colors.iter().position(|used| !used.pinned)
+        {
+            self.used_colors.remove(oldest);
+        }
+        true
+    }
+
+    /// Append `color` to the used colors unless it is transparent or already
+    /// there, without dropping any. Returns whether it was added.
+    fn insert_used_color(&mut self, color: Color) -> bool {
         // Don't add transparent colors
         if color.a < 0.01 {
-            return;
+            return false;
         }
 
         // Check if color already exists (with tolerance for floating point)
-        let exists = self.used_colors.iter().any(|c| {
+        let exists = self.used_colors.iter().any(|used| {
+            let c = used.color;
             (c.r - color.r).abs() < 0.01
                 && (c.g - color.g).abs() < 0.01
                 && (c.b - color.b).abs() < 0.01
                 && (c.a - color.a).abs() < 0.01
         });
         if !exists {
-            self.used_colors.push(color);
-            // Keep only the most recent 32 colors
-            if self.used_colors.len() > 32 {
-                self.used_colors.remove(0);
+            self.mark_dirty();
+            self.used_colors.push(UsedColor::new(color));
+        }
+        !exists
+    }
+
+    /// Add the most frequent colors of an imported image to the used colors,
+    /// at most `limit` (and never more than the used colors keep), sorted by
+    /// hue. Unlike painting with a color, importing never drops the colors
+    /// already there. Returns how many were new.
+    pub fn add_imported_colors(&mut self, pixels: &[u8], limit: usize) -> usize {
+        let mut colors = crate::palette::count_colors([pixels]);
+        colors.truncate(limit.min(MAX_USED_COLORS));
+        crate::palette::sort_colors(&mut colors, crate::palette::PaletteSort::Hue);
+        let mut added = 0;
+        for (rgba, _) in colors {
+            if self.insert_used_color(crate::utils::rgba8_to_color(rgba)) {
+                added += 1;
+            }
+        }
+        added
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Tool {
+    Pencil,
+    Eraser,
+    Fill,
+    Selection,
+    Eyedropper,
+    Text,
+    Line,
+    Rectangle,
+    Ellipse,
+    Curve,
+    Move,
+}
+
+/// Color-vision deficiency simulated when rendering the canvas. This is a
+/// view setting only; stored pixels and exports are never affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorVision {
+    Normal,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl ColorVision {
+    pub const ALL: [ColorVision; 4] = [
+        ColorVision::Normal,
+        ColorVision::Protanopia,
+        ColorVision::Deuteranopia,
+        ColorVision::Tritanopia,
+    ];
+
+    /// Simulation matrix in linear RGB, or `None` for normal vision.
+    pub fn matrix(self) -> Option<[[f32; 3]; 3]> {
+        match self {
+            ColorVision::Normal => None,
+            ColorVision::Protanopia => Some(crate::utils::PROTANOPIA_MATRIX),
+            ColorVision::Deuteranopia => Some(crate::utils::DEUTERANOPIA_MATRIX),
+            ColorVision::Tritanopia => Some(crate::utils::TRITANOPIA_MATRIX),
+        }
+    }
+}
+
+impl std::fmt::Display for ColorVision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorVision::Normal => write!(f, "Normal vision"),
+            ColorVision::Protanopia => write!(f, "Protanopia"),
+            ColorVision::Deuteranopia => write!(f, "Deuteranopia"),
+            ColorVision::Tritanopia => write!(f, "Tritanopia"),
+        }
+    }
+}
+
+/// A safe-area overlay: an optional centered box of a target aspect ratio
+/// and margins inset from it (or from the canvas), as percentages of its
+/// size. Only drawn on screen, never exported.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SafeAreaPreset {
+    pub name: String,
+    /// Width and height ratio of the target box, or `None` for the canvas.
+    #[serde(default)]
+    pub aspect: Option<(u32, u32)>,
+    /// Left, top, right and bottom margins in percent (0-45).
+    pub margins: [f32; 4],
+}
+
+impl SafeAreaPreset {
+    /// Presets for common targets, offered alongside the custom ones.
+    pub fn builtin() -> Vec<SafeAreaPreset> {
+        let preset = |name: &str, aspect, margin| SafeAreaPreset {
+            name: name.to_string(),
+            aspect,
+            margins: [margin; 4],
+        };
+        vec![
+            preset("16:9 action safe (5%)", Some((16, 9)), 5.0),
+            preset("16:9 title safe (10%)", Some((16, 9)), 10.0),
+            preset("Twitch emote padding (1px at 28)", None, 100.0 / 28.0),
+            preset("Steam header capsule (460:215)", Some((460, 215)), 0.0),
+            preset("Steam main capsule (616:353)", Some((616, 353)), 0.0),
+        ]
+    }
+
+    /// The target box and the safe area inside it, in canvas pixels as
+    /// `(x, y, width, height)`.
+    pub fn rects(&self, width: u32, height: u32) -> ([f32; 4], [f32; 4]) {
+        let (width, height) = (width as f32, height as f32);
+        let (box_width, box_height) = match self.aspect {
+            Some((w, h)) if w > 0 && h > 0 => {
+                let ratio = w as f32 / h as f32;
+                if width / height > ratio {
+                    (height * ratio, height)
+                } else {
+                    (width, width / ratio)
+                }
+            }
+            _ => (width, height),
+        };
+        let x = (width - box_width) / 2.0;
+        let y = (height - box_height) / 2.0;
+        let [left, top, right, bottom] = self.margins.map(|m| m.clamp(0.0, 45.0) / 100.0);
+        (
+            [x, y, box_width, box_height],
+            [
+                x + box_width * left,
+                y + box_height * top,
+                box_width * (1.0 - left - right),
+                box_height * (1.0 - top - bottom),
+            ],
+        )
+    }
+}
+
+/// 9-slice insets in pixels, measured inward from each canvas edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct NineSlice {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl NineSlice {
+    /// Default guides a quarter of the way in from each edge.
+    pub fn for_canvas(width: u32, height: u32) -> Self {
+        Self {
+            left: width / 4,
+            right: width / 4,
+            top: height / 4,
+            bottom: height / 4,
+        }
+    }
+
+    /// Shrink insets so opposite guides never cross and stay on the canvas.
+    pub fn clamped(self, width: u32, height: u32) -> Self {
+        let left = self.left.min(width);
+        let top = self.top.min(height);
+        Self {
+            left,
+            right: self.right.min(width - left),
+            top,
+            bottom: self.bottom.min(height - top),
+        }
+    }
+
+    /// Canvas boundary (0..=size) a guide sits on.
+    pub fn guide_position(&self, edge: SliceEdge, width: u32, height: u32) -> u32 {
+        match edge {
+            SliceEdge::Left => self.left,
+            SliceEdge::Right => width.saturating_sub(self.right),
+            SliceEdge::Top => self.top,
+            SliceEdge::Bottom => height.saturating_sub(self.bottom),
+        }
+    }
+
+    /// Move one guide to canvas boundary `position`, keeping it on its side of
+    /// the opposite guide.
+    pub fn with_guide(self, edge: SliceEdge, position: u32, width: u32, height: u32) -> Self {
+        let mut slice = self;
+        match edge {
+            SliceEdge::Left => slice.left = position.min(width.saturating_sub(self.right)),
+            SliceEdge::Right => {
+                slice.right = width.saturating_sub(position.clamp(self.left, width))
             }
+            SliceEdge::Top => slice.top = position.min(height.saturating_sub(self.bottom)),
+            SliceEdge::Bottom => {
+                slice.bottom = height.saturating_sub(position.clamp(self.top, height))
+            }
+        }
+        slice
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceEdge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl SliceEdge {
+    pub const ALL: [SliceEdge; 4] = [
+        SliceEdge::Left,
+        SliceEdge::Right,
+        SliceEdge::Top,
+        SliceEdge::Bottom,
+    ];
+
+    pub fn is_vertical(&self) -> bool {
+        matches!(self, SliceEdge::Left | SliceEdge::Right)
+    }
+}
+
+/// Region the fill tool would cover from the hovered pixel, cached until the
+/// document changes or the cursor leaves the region.
+#[derive(Debug, Clone)]
+pub struct FillPreview {
+    pub layer_index: usize,
+    pub generation: u64,
+    pub pixels: Vec<(u32, u32)>,
+    // One flag per canvas pixel, set for pixels in `pixels`
+    pub mask: Vec<bool>,
+}
+
+/// Temporary pixels drawn in place of a layer while a filter or rotation is
+/// being adjusted. The document itself is untouched until the preview is
+/// committed.
+#[derive(Debug, Clone)]
+pub struct PreviewBuffer {
+    pub layer_index: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// A copy of the selected pixels of one layer, the source of Alt+drag
+/// clones. `selected` marks which pixels of `bounds` were in the selection.
+#[derive(Debug, Clone)]
+pub struct CloneSource {
+    // History revision after the last clone; any other edit since then
+    // means the copy is stale
+    pub revision: u64,
+    pub layer_index: usize,
+    pub bounds: RegionBounds,
+    pub pixels: Vec<u8>,
+    pub selected: Vec<bool>,
+}
+
+/// Rotation being adjusted in the sidebar; its result lives in the preview
+/// overlay.
+#[derive(Debug, Clone)]
+pub struct RotationPreview {
+    pub degrees: f32,
+    pub algorithm: RotationAlgorithm,
+    // Rotated content (`start_x, start_y, end_x, end_y`, end exclusive)
+    pub bounds: (u32, u32, u32, u32),
+}
+
+/// Images queued by "Import folder...", loaded in order one per message so
+/// progress shows as they arrive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderImport {
+    pub paths: Vec<std::path::PathBuf>,
+    // Index of the image being loaded next
+    pub next: usize,
+    pub imported: usize,
+    // Off-palette colors snapped across all images so far
+    pub remapped_colors: HashSet<[u8; 4]>,
+    // Colors added to the used colors across all images so far
+    pub colors_added: usize,
+}
+
+/// Row or column being shifted in the sidebar; its result lives in the
+/// preview overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineShift {
+    pub axis: ShiftAxis,
+    pub index: u32,
+    // Part of the line that moves (`start, end`, end exclusive)
+    pub span: (u32, u32),
+    pub offset: i32,
+}
+
+/// Unpinned entries kept in the used colors panel.
+const MAX_USED_COLORS: usize = 32;
+
+/// Strokes remembered for the color history key.
+const MAX_RECENT_COLORS: usize = 8;
+
+/// A color a stroke painted with, cycled through with the color history key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecentColor {
+    pub color: Color,
+    // History position right after the stroke, so undoing past it can drop it
+    pub history_index: usize,
+}
+
+/// A swatch in the used colors panel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UsedColor {
+    pub color: Color,
+    pub pinned: bool,
+}
+
+impl UsedColor {
+    pub fn new(color: Color) -> Self {
+        Self {
+            color,
+            pinned: false,
+        }
+    }
+}
+
+/// Display order of the used colors panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsedColorSort {
+    Recency,
+    Hue,
+    Luminance,
+    // Most used in the document first, as of the last color count
+    Usage,
+}
+
+impl UsedColorSort {
+    pub const ALL: [UsedColorSort; 4] = [
+        UsedColorSort::Recency,
+        UsedColorSort::Hue,
+        UsedColorSort::Luminance,
+        UsedColorSort::Usage,
+    ];
+}
+
+impl std::fmt::Display for UsedColorSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsedColorSort::Recency => write!(f, "Recent"),
+            UsedColorSort::Hue => write!(f, "Hue"),
+            UsedColorSort::Luminance => write!(f, "Luminance"),
+            UsedColorSort::Usage => write!(f, "Usage"),
+        }
+    }
+}
+
+/// Default upper bound for canvas width and height.
+pub const DEFAULT_MAX_CANVAS_SIZE: u32 = 2048;
+
+/// Default tile width and height in tile mode.
+pub const DEFAULT_TILE_SIZE: u32 = 16;
+
+/// Default per-channel tolerance when listing near-duplicate colors.
+pub const DEFAULT_DUPLICATE_TOLERANCE: u8 = 8;
+
+/// Default RGBA color of the mirror axis guides.
+pub const DEFAULT_GUIDE_COLOR: [u8; 4] = [0, 180, 255, 200];
+
+/// Default RGBA color of the line around the canvas edge.
+pub const DEFAULT_CANVAS_BORDER_COLOR: [u8; 4] = [20, 20, 20, 255];
+
+/// Default RGBA color of the editor area outside the canvas.
+pub const DEFAULT_OUTSIDE_CANVAS_COLOR: [u8; 4] = [70, 70, 74, 255];
+
+/// Longest gap between two clicks on a layer name that counts as a
+/// double-click.
+pub const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Layer opacity below which strokes count as invisible.
+pub const HIDDEN_LAYER_OPACITY: f32 = 0.01;
+
+/// Largest pencil brightness jitter, in steps of 255 either way.
+pub const MAX_VALUE_JITTER: u32 = 64;
+
+/// Largest pencil hue jitter, in degrees either way.
+pub const MAX_HUE_JITTER: u32 = 60;
+
+/// How long the drawing mode unlock button must be held to leave the mode.
+pub const KIOSK_UNLOCK_HOLD: Duration = Duration::from_secs(2);
+
+/// Everything that shapes an export, edited together in the Export panel
+/// and passed whole to `file_io::save_export`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportSettings {
+    pub format: ExportFormat,
+    pub upscale: ExportUpscale,
+    pub scope: ExportScope,
+    /// Composite over `matte_color` instead of keeping transparency.
+    pub matte_enabled: bool,
+    pub matte_color: [u8; 4],
+    /// Embed 16, 32 and 48 pixel images in ICO exports.
+    pub ico_multi_size: bool,
+    /// Size of one pixel in SVG user units.
+    pub svg_unit_size: u32,
+    pub print: PrintOptions,
+    pub raw: RawExportOptions,
+    /// Export every visible layer even while one is soloed.
+    pub ignore_solo: bool,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Png,
+            upscale: ExportUpscale::None,
+            scope: ExportScope::Canvas,
+            matte_enabled: false,
+            matte_color: [255, 255, 255, 255],
+            ico_multi_size: true,
+            svg_unit_size: 1,
+            print: PrintOptions::default(),
+            raw: RawExportOptions::default(),
+            ignore_solo: false,
+        }
+    }
+}
+
+impl ExportSettings {
+    /// File extension for these exports; raw exports become C headers when
+    /// that option is set.
+    pub fn extension(&self) -> &'static str {
+        match self.format {
+            ExportFormat::Rgb565 | ExportFormat::Mono if self.raw.c_header => "h",
+            format => format.extension(),
+        }
+    }
+
+    /// Keep every option in range, whatever the panel or a project file
+    /// asked for.
+    pub fn clamped(self) -> Self {
+        Self {
+            svg_unit_size: self.svg_unit_size.clamp(1, 64),
+            print: PrintOptions {
+                scale: self.print.scale.clamp(1, 64),
+                ..self.print
+            },
+            ..self
+        }
+    }
+}
+
+/// A saved export target, re-run by "Export all".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportProfile {
+    pub path: String,
+    pub format: ExportFormat,
+    pub upscale: ExportUpscale,
+    pub scope: ExportScope,
+}
+
+impl ExportProfile {
+    /// `base` with this profile's format, upscale and scope.
+    pub fn settings(&self, base: &ExportSettings) -> ExportSettings {
+        ExportSettings {
+            format: self.format,
+            upscale: self.upscale,
+            scope: self.scope,
+            ..*base
+        }
+    }
+
+    /// File name shown in the profile list.
+    pub fn display_name(&self) -> String {
+        std::path::Path::new(&self.path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.clone())
+    }
+}
+
+/// Which part of the canvas an export profile writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportScope {
+    Canvas,
+    Selection,
+}
+
+impl ExportScope {
+    pub const ALL: [ExportScope; 2] = [ExportScope::Canvas, ExportScope::Selection];
+}
+
+impl std::fmt::Display for ExportScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportScope::Canvas => write!(f, "Canvas"),
+            ExportScope::Selection => write!(f, "Selection"),
+        }
+    }
+}
+
+/// Modifier that turns the mouse wheel into brush size control. Ctrl+wheel
+/// always zooms, and Shift+wheel pans sideways unless it is taken here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WheelBrushModifier {
+    #[default]
+    Alt,
+    Shift,
+}
+
+impl WheelBrushModifier {
+    pub const ALL: [WheelBrushModifier; 2] = [WheelBrushModifier::Alt, WheelBrushModifier::Shift];
+
+    pub fn is_held(self, modifiers: iced::keyboard::Modifiers) -> bool {
+        match self {
+            WheelBrushModifier::Alt => modifiers.alt(),
+            WheelBrushModifier::Shift => modifiers.shift(),
+        }
+    }
+}
+
+impl std::fmt::Display for WheelBrushModifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WheelBrushModifier::Alt => write!(f, "Alt+wheel"),
+            WheelBrushModifier::Shift => write!(f, "Shift+wheel"),
+        }
+    }
+}
+
+/// Pixels found by a color usage scan, shown as a view overlay.
+#[derive(Debug, Clone)]
+pub struct ColorUsage {
+    pub color: [u8; 4],
+    pub pixels: Vec<(u32, u32)>,
+    // `History::revision` at the scan; any later edit clears the overlay
+    pub revision: u64,
+}
+
+/// What the canvas shows while comparing against a reference image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareView {
+    Mine,
+    Theirs,
+    Diff,
+}
+
+impl CompareView {
+    pub const ALL: [CompareView; 3] = [CompareView::Mine, CompareView::Theirs, CompareView::Diff];
+}
+
+impl std::fmt::Display for CompareView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompareView::Mine => write!(f, "Mine"),
+            CompareView::Theirs => write!(f, "Theirs"),
+            CompareView::Diff => write!(f, "Diff"),
+        }
+    }
+}
+
+/// A reference image the document is compared against. View-only; the
+/// document itself is never touched.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub view: CompareView,
+    // Per pixel, whether the composited canvas differs from the reference
+    pub differs: Vec<bool>,
+    pub differing: usize,
+    // `History::revision` the diff was taken at
+    pub revision: u64,
+}
+
+/// Options for generating a ramp between the primary and secondary colors.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RampGenerator {
+    /// Colors in the ramp, endpoints included (3-9).
+    pub steps: u32,
+    /// Extra hue rotation in degrees per step toward the middle.
+    pub hue_shift: f32,
+}
+
+impl Default for RampGenerator {
+    fn default() -> Self {
+        Self {
+            steps: 5,
+            hue_shift: 0.0,
+        }
+    }
+}
+
+impl RampGenerator {
+    pub fn generate(&self, from: Color, to: Color) -> Vec<[u8; 4]> {
+        crate::palette::generate_ramp(
+            crate::utils::color_to_rgba8(from),
+            crate::utils::color_to_rgba8(to),
+            self.steps.clamp(3, 9) as usize,
+            self.hue_shift,
+        )
+    }
+}
+
+/// A hue, saturation and value change for every entry of one ramp.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RampShift {
+    pub ramp: usize,
+    /// Hue rotation in degrees (-180 to 180).
+    pub hue: f32,
+    /// Added to each entry's saturation (-1.0 to 1.0).
+    pub saturation: f32,
+    /// Added to each entry's value (-1.0 to 1.0).
+    pub value: f32,
+}
+
+impl RampShift {
+    pub fn new(ramp: usize) -> Self {
+        Self {
+            ramp,
+            hue: 0.0,
+            saturation: 0.0,
+            value: 0.0,
+        }
+    }
+
+    /// The shifted ramp, or `None` if the ramp no longer exists.
+    pub fn shifted(&self, ramps: &[Vec<[u8; 4]>]) -> Option<Vec<[u8; 4]>> {
+        let entries = ramps.get(self.ramp)?;
+        Some(crate::palette::shift_colors(
+            entries,
+            self.hue,
+            self.saturation,
+            self.value,
+        ))
+    }
+}
+
+/// A layer card being dragged to a new place in the layer panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerDrag {
+    pub from: usize,
+    // Index the layer moves to if dropped now, while over another card
+    pub target: Option<usize>,
+}
+
+/// A line, rectangle, ellipse or curve being dragged out; painted on release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShapeDraft {
+    pub tool: Tool,
+    pub anchor: (u32, u32),
+    pub current: (u32, u32),
+    // Bend point of a curve, set once its ends are placed
+    pub control: Option<(u32, u32)>,
+}
+
+/// Progress of a pencil or eraser stroke drawn with the isometric assist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsometricStroke {
+    // Start of the current straight segment
+    pub anchor: (u32, u32),
+    // Run pattern of the current segment, once it has one
+    pub direction: Option<(i32, i32)>,
+    // Last pixel painted
+    pub end: (u32, u32),
+}
+
+/// Which color a picker popup edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorTarget {
+    Primary,
+    Secondary,
+}
+
+/// An open color picker popup. Edits stay here until confirmed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorPickerState {
+    pub target: ColorTarget,
+    pub original: Color,
+    // Hue in degrees; saturation, value and alpha in 0.0-1.0
+    pub hue: f32,
+    pub saturation: f32,
+    pub value: f32,
+    pub alpha: f32,
+    pub hex_input: String,
+}
+
+impl ColorPickerState {
+    pub fn new(target: ColorTarget, color: Color) -> Self {
+        let rgba = crate::utils::color_to_rgba8(color);
+        let (hue, saturation, value) = crate::utils::rgb_to_hsv(rgba);
+        Self {
+            target,
+            original: color,
+            hue,
+            saturation,
+            value,
+            alpha: color.a,
+            hex_input: crate::utils::hex_color(rgba),
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        let [r, g, b] = crate::utils::hsv_to_rgb(self.hue, self.saturation, self.value);
+        Color::from_rgba8(r, g, b, self.alpha)
+    }
+
+    /// Refresh the hex field after the color was changed some other way.
+    pub fn sync_hex(&mut self) {
+        self.hex_input = crate::utils::hex_color(crate::utils::color_to_rgba8(self.color()));
+    }
+}
+
+/// Options for printable PNG export (charts for cross-stitch and bead work).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrintOptions {
+    /// Integer upscale factor; 1 exports at canvas size.
+    pub scale: u32,
+    /// Burn in cell grid lines, with major lines every 10 cells.
+    pub grid: bool,
+    /// Draw the palette index of each cell's color.
+    pub index_numbers: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1,
+            grid: false,
+            index_numbers: false,
+        }
+    }
+}
+
+/// Options for the raw RGB565 and 1-bit exports used by embedded displays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RawExportOptions {
+    /// Write RGB565 pixels high byte first.
+    pub big_endian: bool,
+    /// Luminance (0-255) at or above which a 1-bit pixel is lit.
+    pub threshold: u8,
+    /// Wrap the bytes in a C header instead of writing them raw.
+    pub c_header: bool,
+}
+
+impl Default for RawExportOptions {
+    fn default() -> Self {
+        Self {
+            big_endian: false,
+            threshold: 128,
+            c_header: false,
+        }
+    }
+}
+
+/// Factor used by "Scale image".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScaleFactor {
+    Half,
+    Times(u32),
+}
+
+impl ScaleFactor {
+    /// New canvas dimensions after scaling, never smaller than 1x1, or
+    /// `None` when they would overflow.
+    pub fn apply(self, width: u32, height: u32) -> Option<(u32, u32)> {
+        match self {
+            ScaleFactor::Half => Some(((width / 2).max(1), (height / 2).max(1))),
+            ScaleFactor::Times(n) => {
+                Some((width.checked_mul(n.max(1))?, height.checked_mul(n.max(1))?))
+            }
+        }
+    }
+}
+
+/// Tool settings, grouped by the tools they apply to. The Tool options
+/// section of the sidebar shows only the groups of the current tool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolOptions {
+    /// Size and opacity, remembered separately for each tool.
+    pub brushes: HashMap<Tool, BrushOptions>,
+    pub freehand: FreehandOptions,
+    pub jitter: JitterOptions,
+    pub fill: FillOptions,
+    pub text: TextOptions,
+    pub curve: CurveOptions,
+    pub eyedropper: EyedropperOptions,
+    /// Brush strokes, shapes, text and fill seeds align to blocks of this
+    /// size (1 = off).
+    pub pixel_snap: u32,
+}
+
+impl Default for ToolOptions {
+    fn default() -> Self {
+        Self {
+            brushes: HashMap::new(),
+            freehand: FreehandOptions::default(),
+            jitter: JitterOptions::default(),
+            fill: FillOptions::default(),
+            text: TextOptions::default(),
+            curve: CurveOptions::default(),
+            eyedropper: EyedropperOptions::default(),
+            pixel_snap: 1,
+        }
+    }
+}
+
+/// Settings that are remembered separately for each tool.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrushOptions {
+    pub brush_size: u32,
+    /// Brush opacity (0.0-1.0), applied once per pixel per stroke on top of
+    /// the color's own alpha. At 1.0 pixels are replaced outright.
+    pub opacity: f32,
+}
+
+impl Default for BrushOptions {
+    fn default() -> Self {
+        Self {
+            brush_size: 1,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// Pencil and eraser strokes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FreehandOptions {
+    /// Pen pressure scales the brush size when the platform reports it.
+    pub pressure_sensitive: bool,
+    /// How many earlier cursor positions strokes average in (0-10).
+    pub stabilizer: u32,
+    /// Strokes snap to isometric slopes.
+    pub isometric_assist: bool,
+}
+
+/// Random variation of the pencil color, picked once per brush stamp.
+/// Zero strengths paint the primary color exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct JitterOptions {
+    /// Brightness range, up to this many steps (of 255) either way.
+    pub value: u32,
+    /// Hue range, up to this many degrees either way.
+    pub hue: u32,
+    pub seed: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FillOptions {
+    /// Tile the clipboard instead of filling with a solid color.
+    pub pattern: bool,
+    /// Highlight the region a click would fill while hovering.
+    pub preview: bool,
+    /// Find the region by the composited image rather than the active
+    /// layer's own pixels.
+    pub sample_merged: bool,
+}
+
+impl Default for FillOptions {
+    fn default() -> Self {
+        Self {
+            pattern: false,
+            preview: true,
+            sample_merged: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EyedropperOptions {
+    /// Pick the composited color rather than the active layer's own.
+    pub sample_merged: bool,
+}
+
+impl Default for EyedropperOptions {
+    fn default() -> Self {
+        Self {
+            sample_merged: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextOptions {
+    pub font: BitmapFont,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self {
+            font: BitmapFont::Medium,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Tool {
-    Pencil,
-    Eraser,
-    Fill,
-    Selection,
-    Eyedropper,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurveOptions {
+    /// Drop the middle pixel of L-shaped corners.
+    pub pixel_perfect: bool,
+}
+
+impl Default for CurveOptions {
+    fn default() -> Self {
+        Self {
+            pixel_perfect: true,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Layer {
+    // Fresh for every new layer, copies included
+    pub id: LayerId,
     pub name: String,
     pub pixels: Vec<u8>, // RGBA format
+    // Palette index per pixel in indexed documents; `pixels` then caches the
+    // resolved colors for rendering
+    pub indices: Option<Vec<u8>>,
     pub width: u32,
     pub height: u32,
     pub visible: bool,
     pub opacity: f32,
+    // Only shows where the first unclipped layer below has alpha
+    pub clipped: bool,
 }
 
 impl Layer {
@@ -162,48 +2304,134 @@ impl Layer {
         let pixels = vec![0u8; size];
         // Initialize with transparent pixels (all zeros)
         Self {
+            id: LayerId::next(),
             name,
             pixels,
+            indices: None,
             width,
             height,
             visible: true,
             opacity: 1.0,
+            clipped: false,
         }
     }
 
-    pub fn get_pixel(&self, x: u32, y: u32) -> Color {
+    /// RGBA bytes at (x, y), transparent outside the layer. Prefer this over
+    /// `get_pixel` in per-pixel loops that don't need float math.
+    pub fn get_rgba8(&self, x: u32, y: u32) -> [u8; 4] {
         if x >= self.width || y >= self.height {
-            return Color::TRANSPARENT;
+            return [0; 4];
         }
         let index = ((y * self.width + x) * 4) as usize;
-        if index + 3 < self.pixels.len() {
-            Color::from_rgba8(
-                self.pixels[index],
-                self.pixels[index + 1],
-                self.pixels[index + 2],
-                self.pixels[index + 3] as f32 / 255.0,
-            )
-        } else {
-            Color::TRANSPARENT
+        match self.pixels.get(index..index + 4) {
+            Some(pixel) => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            None => [0; 4],
         }
     }
 
-    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+    pub fn set_rgba8(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
         if x >= self.width || y >= self.height {
             return;
         }
         let index = ((y * self.width + x) * 4) as usize;
-        if index + 3 < self.pixels.len() {
-            let rgba = color.into_rgba8();
-            self.pixels[index] = rgba[0];
-            self.pixels[index + 1] = rgba[1];
-            self.pixels[index + 2] = rgba[2];
-            self.pixels[index + 3] = rgba[3];
+        if let Some(pixel) = self.pixels.get_mut(index..index + 4) {
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> Color {
+        crate::utils::rgba8_to_color(self.get_rgba8(x, y))
+    }
+
+    /// Crop or pad the layer to `width` x `height`, keeping the pixels at
+    /// the top-left corner in place; new area is transparent.
+    pub fn resize_anchored(&mut self, width: u32, height: u32) {
+        let copy = |source: &[u8], channels: usize| {
+            let mut target = vec![0u8; width as usize * height as usize * channels];
+            let row = self.width.min(width) as usize * channels;
+            for y in 0..self.height.min(height) as usize {
+                let from = y * self.width as usize * channels;
+                let to = y * width as usize * channels;
+                if let Some(line) = source.get(from..from + row) {
+                    target[to..to + row].copy_from_slice(line);
+                }
+            }
+            target
+        };
+        self.pixels = copy(&self.pixels, 4);
+        self.indices = self.indices.as_deref().map(|indices| copy(indices, 1));
+        self.width = width;
+        self.height = height;
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        self.set_rgba8(x, y, color.into_rgba8());
+    }
+
+    /// Copy the RGBA bytes of a rectangle (clipped to the layer) into a new buffer.
+    pub fn read_region(&self, bounds: RegionBounds) -> Box<[u8]> {
+        let mut buffer = vec![0u8; (bounds.width * bounds.height * 4) as usize];
+        for row in 0..bounds.height {
+            let y = bounds.y + row;
+            if y >= self.height {
+                break;
+            }
+            let columns = bounds.width.min(self.width.saturating_sub(bounds.x));
+            let src = ((y * self.width + bounds.x) * 4) as usize;
+            let dst = (row * bounds.width * 4) as usize;
+            let len = (columns * 4) as usize;
+            buffer[dst..dst + len].copy_from_slice(&self.pixels[src..src + len]);
+        }
+        buffer.into_boxed_slice()
+    }
+
+    /// Blit a buffer produced by `read_region` back into the layer.
+    pub fn write_region(&mut self, bounds: RegionBounds, buffer: &[u8]) {
+        for row in 0..bounds.height {
+            let y = bounds.y + row;
+            if y >= self.height {
+                break;
+            }
+            let columns = bounds.width.min(self.width.saturating_sub(bounds.x));
+            let dst = ((y * self.width + bounds.x) * 4) as usize;
+            let src = (row * bounds.width * 4) as usize;
+            let len = (columns * 4) as usize;
+            self.pixels[dst..dst + len].copy_from_slice(&buffer[src..src + len]);
         }
     }
+}
+
+/// Most restore points kept per document; creating more evicts the oldest.
+pub const MAX_RESTORE_POINTS: usize = 10;
+
+/// Approximate number of bytes the restore points of a document may hold.
+pub const RESTORE_POINT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Approximate number of bytes the undo history may hold.
+pub const HISTORY_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Most commands the undo history keeps.
+pub const MAX_HISTORY_COMMANDS: usize = 100;
+
+/// Pixel counts too slow to keep live, gathered on demand for the Document
+/// panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocumentScan {
+    // History revision the scan saw, so the panel can mark it out of date
+    pub revision: u64,
+    pub opaque_pixels: usize,
+    pub unique_colors: usize,
+}
 
-    pub fn get_pixel_buffer(&self) -> &[u8] {
-        &self.pixels
+impl DocumentScan {
+    /// Count non-transparent pixels and distinct colors across `buffers`.
+    pub fn scan(revision: u64, buffers: &[Vec<u8>]) -> Self {
+        let colors = crate::palette::count_colors(buffers.iter().map(Vec::as_slice));
+        Self {
+            revision,
+            opaque_pixels: colors.iter().map(|(_, count)| count).sum(),
+            unique_colors: colors.len(),
+        }
     }
 }
 
@@ -211,6 +2439,11 @@ impl Layer {
 pub struct History {
     pub commands: Vec<EditCommand>,
     pub current_index: usize,
+    // `current_index` at the last save, or `None` once that state is gone
+    pub saved_index: Option<usize>,
+    // Bumped by every push, undo and redo, so views can tell the document
+    // changed
+    pub revision: u64,
 }
 
 impl History {
@@ -218,19 +2451,76 @@ impl History {
         Self {
             commands: Vec::new(),
             current_index: 0,
+            saved_index: Some(0),
+            revision: 0,
         }
     }
 
     pub fn push(&mut self, command: EditCommand) {
+        self.revision += 1;
         // Remove any commands after current_index (when undoing and then doing new action)
         self.commands.truncate(self.current_index);
+        if self
+            .saved_index
+            .is_some_and(|saved| saved > self.current_index)
+        {
+            self.saved_index = None;
+        }
         self.commands.push(command);
         self.current_index += 1;
         // Limit history size
-        if self.commands.len() > 100 {
-            self.commands.remove(0);
-            self.current_index -= 1;
+        if self.commands.len() > MAX_HISTORY_COMMANDS {
+            self.evict_oldest();
+        }
+        // Evict the oldest entries once large snapshots exceed the memory
+        // budget, always keeping the newest command
+        let mut total = self.memory_size();
+        while total > HISTORY_MEMORY_BUDGET && self.commands.len() > 1 {
+            total -= self.evict_oldest().memory_size();
+        }
+    }
+
+    fn evict_oldest(&mut self) -> EditCommand {
+        self.current_index -= 1;
+        self.saved_index = self.saved_index.and_then(|saved| saved.checked_sub(1));
+        self.commands.remove(0)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_index = Some(self.current_index);
+    }
+
+    /// Approximate bytes held by every undo and redo step.
+    pub fn memory_size(&self) -> usize {
+        self.commands.iter().map(EditCommand::memory_size).sum()
+    }
+
+    /// Drop every command that edits a layer at or past `layer_count`, which
+    /// could no longer be undone or redone.
+    pub fn remove_missing_layers(&mut self, layer_count: usize) {
+        self.retain(|command| command.layer_index().is_none_or(|i| i < layer_count));
+    }
+
+    /// Keep only the commands matching `keep`. Indices into the history
+    /// shift down past each dropped command. Returns how many were dropped.
+    fn retain(&mut self, keep: impl Fn(&EditCommand) -> bool) -> usize {
+        let kept_before = |commands: &[EditCommand], index: usize| {
+            commands[..index.min(commands.len())]
+                .iter()
+                .filter(|command| keep(command))
+                .count()
+        };
+        self.current_index = kept_before(&self.commands, self.current_index);
+        self.saved_index = self
+            .saved_index
+            .map(|saved| kept_before(&self.commands, saved));
+        let count = self.commands.len();
+        self.commands.retain(|command| keep(command));
+        let dropped = count - self.commands.len();
+        if dropped > 0 {
+            self.revision += 1;
         }
+        dropped
     }
 
     pub fn can_undo(&self) -> bool {
@@ -242,6 +2532,7 @@ impl History {
     }
 
     pub fn undo(&mut self) -> Option<EditCommand> {
+        self.revision += 1;
         if self.can_undo() {
             self.current_index -= 1;
             Some(self.commands[self.current_index].clone())
@@ -250,7 +2541,42 @@ impl History {
         }
     }
 
+    /// Undo the newest command on `layer_index`, first moving it past the
+    /// newer commands on other layers so they stay applied. Returns `Ok(None)`
+    /// when nothing on the layer is left to undo, and an error, leaving the
+    /// history untouched, when a newer command might touch the same pixels.
+    pub fn undo_layer(&mut self, layer_index: usize) -> Result<Option<EditCommand>, String> {
+        let newer_start = match self.commands[..self.current_index]
+            .iter()
+            .rposition(|command| command.layer_index() == Some(layer_index))
+        {
+            Some(target) => target + 1,
+            None => return Ok(None),
+        };
+        let target = &self.commands[newer_start - 1];
+        if self.commands[newer_start..self.current_index]
+            .iter()
+            .any(|newer| newer.conflicts_with(target))
+        {
+            return Err("A newer change may overlap this layer's last change".to_string());
+        }
+        if newer_start < self.current_index {
+            let command = self.commands.remove(newer_start - 1);
+            self.commands.insert(self.current_index - 1, command);
+            // Saves taken between the two positions no longer match any
+            // point in the reordered history
+            if self
+                .saved_index
+                .is_some_and(|saved| saved >= newer_start && saved < self.current_index)
+            {
+                self.saved_index = None;
+            }
+        }
+        Ok(self.undo())
+    }
+
     pub fn redo(&mut self) -> Option<EditCommand> {
+        self.revision += 1;
         if self.can_redo() {
             let command = self.commands[self.current_index].clone();
             self.current_index += 1;
@@ -261,6 +2587,282 @@ impl History {
     }
 }
 
+/// A layer attribute that can be changed (and undone) independently of pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerProperty {
+    Opacity(f32),
+}
+
+/// An edit driven by a continuous control. Intermediate values are applied
+/// directly to the document; history only sees the start and end values.
+#[derive(Debug, Clone)]
+pub enum ContinuousEdit {
+    LayerProperty {
+        layer_index: usize,
+        start: LayerProperty,
+    },
+    // A Ctrl+drag with the Move tool, which copies the active layer and
+    // moves the copy; `before` is the document before the copy
+    LayerCopy {
+        before: Box<RestorePoint>,
+    },
+}
+
+impl ContinuousEdit {
+    fn same_target(&self, other: &ContinuousEdit) -> bool {
+        match (self, other) {
+            (
+                ContinuousEdit::LayerProperty {
+                    layer_index: a,
+                    start: start_a,
+                },
+                ContinuousEdit::LayerProperty {
+                    layer_index: b,
+                    start: start_b,
+                },
+            ) => a == b && std::mem::discriminant(start_a) == std::mem::discriminant(start_b),
+            (ContinuousEdit::LayerCopy { .. }, ContinuousEdit::LayerCopy { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Rectangle of layer pixels covered by a `RegionChange`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionBounds {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RegionBounds {
+    pub fn overlaps(&self, other: &RegionBounds) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+}
+
+/// Run-length encoded RGBA buffer. Pixel art is dominated by runs of the same
+/// color, so whole-layer snapshots usually shrink to a small fraction of
+/// their raw size.
+#[derive(Debug, Clone)]
+pub struct CompressedPixels {
+    runs: Box<[(u32, [u8; 4])]>,
+}
+
+impl CompressedPixels {
+    pub fn compress(pixels: &[u8]) -> Self {
+        let mut runs: Vec<(u32, [u8; 4])> = Vec::new();
+        for pixel in pixels.chunks_exact(4) {
+            let rgba = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            match runs.last_mut() {
+                Some((count, color)) if *color == rgba => *count += 1,
+                _ => runs.push((1, rgba)),
+            }
+        }
+        Self {
+            runs: runs.into_boxed_slice(),
+        }
+    }
+
+    pub fn decompress(&self) -> Vec<u8> {
+        let mut pixels = Vec::new();
+        for (count, rgba) in self.runs.iter() {
+            for _ in 0..*count {
+                pixels.extend_from_slice(rgba);
+            }
+        }
+        pixels
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.runs.len() * std::mem::size_of::<(u32, [u8; 4])>()
+    }
+}
+
+/// Canvas dimensions and every layer's pixels, used to undo canvas-wide
+/// operations such as resizing and scaling.
+#[derive(Debug, Clone)]
+pub struct DocSnapshot {
+    pub width: u32,
+    pub height: u32,
+    // By layer position; `None` for layers added since the capture
+    pub layers: Vec<Option<CompressedPixels>>,
+    // Whether the canvas was offset to expose tiling seams, so undoing
+    // either offset also brings back the matching step
+    pub seams_exposed: bool,
+}
+
+impl DocSnapshot {
+    pub fn capture(state: &EditorState) -> Self {
+        Self {
+            width: state.canvas_width,
+            height: state.canvas_height,
+            layers: state
+                .layers
+                .iter()
+                .map(|layer| Some(CompressedPixels::compress(&layer.pixels)))
+                .collect(),
+            seams_exposed: state.seams_exposed,
+        }
+    }
+
+    /// Restore the canvas size and layer pixels. Layers added since the
+    /// snapshot keep their pixels, cropped or padded to the restored size.
+    /// Clears the selection, which may no longer fit the canvas.
+    pub fn restore(&self, state: &mut EditorState) {
+        state.canvas_width = self.width;
+        state.canvas_height = self.height;
+        for (index, layer) in state.layers.iter_mut().enumerate() {
+            match self.layers.get(index).and_then(Option::as_ref) {
+                Some(pixels) => {
+                    layer.pixels = pixels.decompress();
+                    layer.width = self.width;
+                    layer.height = self.height;
+                }
+                None => layer.resize_anchored(self.width, self.height),
+            }
+        }
+        state.seams_exposed = self.seams_exposed;
+        state.selection = None;
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.layers
+            .iter()
+            .flatten()
+            .map(CompressedPixels::memory_size)
+            .sum()
+    }
+}
+
+/// The color mode with everything switching it changes: the indexed
+/// palette, each layer's palette indices and the pixels quantizing snapped
+/// to palette entries.
+#[derive(Debug, Clone)]
+pub struct ColorMode {
+    pub palette: Option<Vec<[u8; 4]>>,
+    // By layer position
+    pub indices: Vec<Option<Vec<u8>>>,
+    pub snapshot: DocSnapshot,
+}
+
+impl ColorMode {
+    pub fn capture(state: &EditorState) -> Self {
+        Self {
+            palette: state.indexed_palette.clone(),
+            indices: state
+                .layers
+                .iter()
+                .map(|layer| layer.indices.clone())
+                .collect(),
+            snapshot: DocSnapshot::capture(state),
+        }
+    }
+
+    pub fn restore(&self, state: &mut EditorState) {
+        self.snapshot.restore(state);
+        state.indexed_palette = self.palette.clone();
+        if self.palette.is_none() {
+            state.selected_palette_index = None;
+        }
+        for (index, layer) in state.layers.iter_mut().enumerate() {
+            layer.indices = self.indices.get(index).cloned().flatten();
+        }
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.palette.as_ref().map_or(0, |palette| palette.len() * 4)
+            + self.indices.iter().flatten().map(Vec::len).sum::<usize>()
+            + self.snapshot.memory_size()
+    }
+}
+
+/// A named copy of the whole document, kept outside the undo history so it
+/// can be returned to after any number of edits.
+#[derive(Debug, Clone)]
+pub struct RestorePoint {
+    pub name: String,
+    pub snapshot: DocSnapshot,
+    pub layers: Vec<RestoreLayer>,
+    pub active_layer_index: usize,
+}
+
+/// The properties of a layer in a restore point; its pixels are in the
+/// point's snapshot.
+#[derive(Debug, Clone)]
+pub struct RestoreLayer {
+    pub id: LayerId,
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    pub clipped: bool,
+}
+
+impl RestorePoint {
+    pub fn capture(state: &EditorState, name: String) -> Self {
+        Self {
+            name,
+            snapshot: DocSnapshot::capture(state),
+            layers: state
+                .layers
+                .iter()
+                .map(|layer| RestoreLayer {
+                    id: layer.id,
+                    name: layer.name.clone(),
+                    visible: layer.visible,
+                    opacity: layer.opacity,
+                    clipped: layer.clipped,
+                })
+                .collect(),
+            active_layer_index: state.active_layer_index,
+        }
+    }
+
+    /// Replace the canvas size and every layer with the stored ones. Solo
+    /// stays on if its layer is among them; the selection is dropped, since
+    /// it may not fit.
+    pub fn apply(&self, state: &mut EditorState) {
+        let (width, height) = (self.snapshot.width, self.snapshot.height);
+        state.canvas_width = width;
+        state.canvas_height = height;
+        state.layers = self
+            .layers
+            .iter()
+            .zip(self.snapshot.layers.iter().flatten())
+            .map(|(properties, pixels)| {
+                let mut layer = Layer::new(properties.name.clone(), width, height);
+                layer.id = properties.id;
+                layer.pixels = pixels.decompress();
+                layer.visible = properties.visible;
+                layer.opacity = properties.opacity;
+                layer.clipped = properties.clipped;
+                layer
+            })
+            .collect();
+        state.active_layer_index = self
+            .active_layer_index
+            .min(state.layers.len().saturating_sub(1));
+        state.seams_exposed = self.snapshot.seams_exposed;
+        if state.solo_index().is_none() {
+            state.solo_layer = None;
+        }
+        state.renaming_layer = None;
+        state.selection = None;
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.snapshot.memory_size()
+    }
+}
+
+/// Commands touching more pixels than this are stored as before/after buffers
+/// of their bounding box instead of one entry per pixel.
+const REGION_CHANGE_THRESHOLD: usize = 64;
+
 #[derive(Debug, Clone)]
 pub enum EditCommand {
     PixelChange {
@@ -274,32 +2876,248 @@ pub enum EditCommand {
         layer_index: usize,
         changes: Vec<(u32, u32, Color, Color)>, // (x, y, old_color, new_color)
     },
+    LayerPropertyChanged {
+        layer_index: usize,
+        old: LayerProperty,
+        new: LayerProperty,
+    },
+    RegionChange {
+        layer_index: usize,
+        bounds: RegionBounds,
+        old: Box<[u8]>, // RGBA bytes of `bounds` before the change
+        new: Box<[u8]>,
+    },
+    DocumentSnapshot {
+        before: DocSnapshot,
+        after: DocSnapshot,
+    },
+    // An indexed palette entry, and with it every pixel using it
+    PaletteEntryChanged {
+        index: usize,
+        old: [u8; 4],
+        new: [u8; 4],
+    },
+    // Switching between indexed and RGBA mode
+    ColorModeChanged {
+        before: Box<ColorMode>,
+        after: Box<ColorMode>,
+    },
+    // Changes to the layer list itself: adding, deleting or moving a layer,
+    // or restoring a restore point. Every such change is one of these, so
+    // undoing back past it brings back the layers older commands refer to
+    LayersReplaced {
+        before: Box<RestorePoint>,
+        after: Box<RestorePoint>,
+    },
 }
 
-fn blend_color(bottom: Color, top: Color, opacity: f32) -> Color {
-    let bottom_rgba = bottom.into_rgba8();
-    let top_rgba = top.into_rgba8();
+impl EditCommand {
+    /// Approximate heap and inline size, used to enforce the history budget.
+    pub fn memory_size(&self) -> usize {
+        let heap = match self {
+            EditCommand::PixelChange { .. }
+            | EditCommand::LayerPropertyChanged { .. }
+            | EditCommand::PaletteEntryChanged { .. } => 0,
+            EditCommand::MultiPixelChange { changes, .. } => {
+                changes.len() * std::mem::size_of::<(u32, u32, Color, Color)>()
+            }
+            EditCommand::RegionChange { old, new, .. } => old.len() + new.len(),
+            EditCommand::DocumentSnapshot { before, after } => {
+                before.memory_size() + after.memory_size()
+            }
+            EditCommand::ColorModeChanged { before, after } => {
+                before.memory_size() + after.memory_size()
+            }
+            EditCommand::LayersReplaced { before, after } => {
+                before.memory_size() + after.memory_size()
+            }
+        };
+        std::mem::size_of::<EditCommand>() + heap
+    }
+
+    /// The layer this command edits, or `None` for whole-document snapshots.
+    pub fn layer_index(&self) -> Option<usize> {
+        match self {
+            EditCommand::PixelChange { layer_index, .. }
+            | EditCommand::MultiPixelChange { layer_index, .. }
+            | EditCommand::LayerPropertyChanged { layer_index, .. }
+            | EditCommand::RegionChange { layer_index, .. } => Some(*layer_index),
+            EditCommand::DocumentSnapshot { .. }
+            | EditCommand::PaletteEntryChanged { .. }
+            | EditCommand::ColorModeChanged { .. }
+            | EditCommand::LayersReplaced { .. } => None,
+        }
+    }
+
+    /// Bounding box of the layer pixels this command changes, or `None` for
+    /// commands that aren't limited to pixels.
+    pub fn pixel_bounds(&self) -> Option<RegionBounds> {
+        match self {
+            EditCommand::PixelChange { x, y, .. } => Some(RegionBounds {
+                x: *x,
+                y: *y,
+                width: 1,
+                height: 1,
+            }),
+            EditCommand::MultiPixelChange { changes, .. } => {
+                let (&(x, y, ..), rest) = changes.split_first()?;
+                let (start_x, start_y, end_x, end_y) = rest.iter().fold(
+                    (x, y, x + 1, y + 1),
+                    |(start_x, start_y, end_x, end_y), &(x, y, ..)| {
+                        (
+                            start_x.min(x),
+                            start_y.min(y),
+                            end_x.max(x + 1),
+                            end_y.max(y + 1),
+                        )
+                    },
+                );
+                Some(RegionBounds {
+                    x: start_x,
+                    y: start_y,
+                    width: end_x - start_x,
+                    height: end_y - start_y,
+                })
+            }
+            EditCommand::RegionChange { bounds, .. } => Some(*bounds),
+            EditCommand::LayerPropertyChanged { .. }
+            | EditCommand::DocumentSnapshot { .. }
+            | EditCommand::PaletteEntryChanged { .. }
+            | EditCommand::ColorModeChanged { .. }
+            | EditCommand::LayersReplaced { .. } => None,
+        }
+    }
+
+    /// Whether undoing `self` and `other` in either order could give
+    /// different results. Commands on different layers never conflict, and
+    /// pixel commands on the same layer only when their bounding boxes meet;
+    /// anything else is assumed to.
+    pub fn conflicts_with(&self, other: &EditCommand) -> bool {
+        match (self.layer_index(), other.layer_index()) {
+            (Some(a), Some(b)) if a != b => false,
+            (Some(_), Some(_)) => match (self.pixel_bounds(), other.pixel_bounds()) {
+                (Some(a), Some(b)) => a.overlaps(&b),
+                _ => true,
+            },
+            _ => true,
+        }
+    }
+
+    /// Build the history entry for pixel changes that have already been
+    /// applied to `layer`, choosing the most compact representation. Changes
+    /// that leave a pixel byte-identical are dropped, and `None` is returned
+    /// when nothing is left to record.
+    pub fn from_pixel_changes(
+        layer_index: usize,
+        layer: &Layer,
+        mut changes: Vec<(u32, u32, Color, Color)>,
+    ) -> Option<EditCommand> {
+        changes.retain(|&(_, _, old_color, new_color)| {
+            old_color.into_rgba8() != new_color.into_rgba8()
+        });
+        match changes.len() {
+            0 => None,
+            1 => {
+                let (x, y, old_color, new_color) = changes[0];
+                Some(EditCommand::PixelChange {
+                    layer_index,
+                    x,
+                    y,
+                    old_color,
+                    new_color,
+                })
+            }
+            n if n <= REGION_CHANGE_THRESHOLD => Some(EditCommand::MultiPixelChange {
+                layer_index,
+                changes,
+            }),
+            _ => {
+                let min_x = changes.iter().map(|c| c.0).min()?;
+                let min_y = changes.iter().map(|c| c.1).min()?;
+                let max_x = changes.iter().map(|c| c.0).max()?;
+                let max_y = changes.iter().map(|c| c.1).max()?;
+                let bounds = RegionBounds {
+                    x: min_x,
+                    y: min_y,
+                    width: max_x - min_x + 1,
+                    height: max_y - min_y + 1,
+                };
+
+                // Pixels inside the bounds that were not touched are identical
+                // in both buffers, so `old` starts as a copy of `new`
+                let new = layer.read_region(bounds);
+                let mut old = new.clone();
+                for (x, y, old_color, _) in changes {
+                    let index = (((y - bounds.y) * bounds.width + (x - bounds.x)) * 4) as usize;
+                    old[index..index + 4].copy_from_slice(&old_color.into_rgba8());
+                }
+
+                Some(EditCommand::RegionChange {
+                    layer_index,
+                    bounds,
+                    old,
+                    new,
+                })
+            }
+        }
+    }
+}
 
-    let br = bottom_rgba[0] as f32 / 255.0;
-    let bg = bottom_rgba[1] as f32 / 255.0;
-    let bb = bottom_rgba[2] as f32 / 255.0;
-    let ba = bottom_rgba[3] as f32 / 255.0;
+/// Where the layer at `index` ends up after the layer at `from` moves to
+/// `to`: layers between the two positions shift by one toward `from`.
+pub fn follow_layer_move(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
 
-    let tr = top_rgba[0] as f32 / 255.0;
-    let tg = top_rgba[1] as f32 / 255.0;
-    let tb = top_rgba[2] as f32 / 255.0;
-    let ta = top_rgba[3] as f32 / 255.0;
+/// RGBA bytes of `layer` at (x, y), with the alpha multiplied by that of
+/// `base` when the layer is clipped to it.
+pub fn clip_rgba8(layer: &Layer, base: Option<&Layer>, x: u32, y: u32) -> [u8; 4] {
+    let [r, g, b, a] = layer.get_rgba8(x, y);
+    match base {
+        Some(base) if a > 0 => {
+            let base_alpha = base.get_rgba8(x, y)[3];
+            [r, g, b, (a as u16 * base_alpha as u16 / 255) as u8]
+        }
+        _ => [r, g, b, a],
+    }
+}
 
-    let final_alpha = ta * opacity + ba * (1.0 - ta * opacity);
-    if final_alpha == 0.0 {
+/// Composite `top`, with its alpha scaled by `opacity`, over `bottom` using
+/// the straight-alpha "over" operator. Stays in f32 throughout so stacks of
+/// translucent layers don't drift from rounding.
+pub fn blend_color(bottom: Color, top: Color, opacity: f32) -> Color {
+    let top_alpha = top.a * opacity;
+    let final_alpha = top_alpha + bottom.a * (1.0 - top_alpha);
+    if final_alpha <= 0.0 {
         return Color::TRANSPARENT;
     }
 
-    let r = (tr * ta * opacity + br * ba * (1.0 - ta * opacity)) / final_alpha;
-    let g = (tg * ta * opacity + bg * ba * (1.0 - ta * opacity)) / final_alpha;
-    let b = (tb * ta * opacity + bb * ba * (1.0 - ta * opacity)) / final_alpha;
+    let over = |t: f32, b: f32| (t * top_alpha + b * bottom.a * (1.0 - top_alpha)) / final_alpha;
+    Color::from_rgba(
+        over(top.r, bottom.r),
+        over(top.g, bottom.g),
+        over(top.b, bottom.b),
+        final_alpha,
+    )
+}
 
-    Color::from_rgba(r, g, b, final_alpha)
+/// `blend_color` for a byte pixel. Empty pixels leave `bottom` as it is and
+/// opaque ones at full opacity replace it, so only translucent pixels pay
+/// for the float blend.
+pub fn blend_rgba8(bottom: Color, top: [u8; 4], opacity: f32) -> Color {
+    match top[3] {
+        0 => bottom,
+        255 if opacity >= 1.0 => crate::utils::rgba8_to_color(top),
+        _ => blend_color(bottom, crate::utils::rgba8_to_color(top), opacity),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -307,4 +3125,250 @@ pub struct ClipboardData {
     pub pixels: Vec<u8>,
     pub width: u32,
     pub height: u32,
+    // Where the copied pixels sat within the selection when a transparent
+    // border was trimmed, so pastes over the selection land in place
+    pub offset_x: u32,
+    pub offset_y: u32,
+}
+
+/// A paste that follows arrow keys and drags until it is committed to the
+/// active layer. The position may lie partly off the canvas.
+#[derive(Debug, Clone)]
+pub struct FloatingPaste {
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Text placed with the text tool, top-left at (x, y), previewed until it is
+/// committed.
+#[derive(Debug, Clone)]
+pub struct PendingText {
+    pub x: u32,
+    pub y: u32,
+    pub text: String,
+}
+
+impl FloatingPaste {
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        let (x, y) = (x as i32, y as i32);
+        x >= self.x
+            && y >= self.y
+            && x < self.x + self.width as i32
+            && y < self.y + self.height as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel_change(layer_index: usize, x: u32) -> EditCommand {
+        EditCommand::PixelChange {
+            layer_index,
+            x,
+            y: 0,
+            old_color: Color::TRANSPARENT,
+            new_color: Color::BLACK,
+        }
+    }
+
+    #[test]
+    fn background_layer_undoes_as_one_step() {
+        let mut state = EditorState::new(2, 2);
+        state.layers[0].set_rgba8(0, 0, [1, 2, 3, 255]);
+        state.history.push(pixel_change(0, 0));
+
+        state.record_layers_change(|state| state.add_background_layer(Color::WHITE));
+        assert_eq!(state.layers.len(), 2);
+        assert_eq!(state.layers[0].get_rgba8(1, 1), [255, 255, 255, 255]);
+
+        let Some(EditCommand::LayersReplaced { before, .. }) = state.history.undo() else {
+            panic!("expected a layers change");
+        };
+        before.apply(&mut state);
+        assert_eq!(state.layers.len(), 1);
+        assert_eq!(state.layers[0].get_rgba8(0, 0), [1, 2, 3, 255]);
+        // The stroke before it still points at the original layer
+        assert_eq!(state.history.undo().and_then(|c| c.layer_index()), Some(0));
+    }
+
+    #[test]
+    fn layer_copy_and_its_move_undo_as_one_step() {
+        let mut state = EditorState::new(2, 1);
+        state.layers[0].set_rgba8(0, 0, [5, 5, 5, 255]);
+
+        state.begin_layer_copy();
+        state.set_preview(vec![0, 0, 0, 0, 5, 5, 5, 255]);
+        state.finish_continuous_edit();
+
+        assert_eq!(state.layers.len(), 2);
+        assert_eq!(state.layers[1].get_rgba8(1, 0), [5, 5, 5, 255]);
+        assert_eq!(state.layers[0].get_rgba8(0, 0), [5, 5, 5, 255]);
+        assert_eq!(state.history.commands.len(), 1);
+
+        let Some(EditCommand::LayersReplaced { before, .. }) = state.history.undo() else {
+            panic!("expected a layers change");
+        };
+        before.apply(&mut state);
+        assert_eq!(state.layers.len(), 1);
+        assert_eq!(state.layers[0].get_rgba8(0, 0), [5, 5, 5, 255]);
+    }
+
+    #[test]
+    fn palette_recolor_is_recorded_with_its_old_color() {
+        let mut state = EditorState::new(2, 1);
+        state.layers[0].set_rgba8(0, 0, [200, 0, 0, 255]);
+        state.convert_to_indexed();
+        let commands = state.history.commands.len();
+        let index = state.layers[0]
+            .indices
+            .as_ref()
+            .map_or(0, |indices| indices[0]) as usize;
+
+        state.recolor_palette_entry(index, [0, 0, 200, 255]);
+        assert_eq!(state.layers[0].get_rgba8(0, 0), [0, 0, 200, 255]);
+        assert_eq!(state.history.commands.len(), commands + 1);
+
+        let Some(EditCommand::PaletteEntryChanged { index, old, .. }) = state.history.undo() else {
+            panic!("expected a palette change");
+        };
+        state.set_palette_color(index, old);
+        assert_eq!(state.layers[0].get_rgba8(0, 0), [200, 0, 0, 255]);
+
+        // Setting the entry to its current color records nothing, so the
+        // undone recolor can still be redone
+        state.recolor_palette_entry(index, old);
+        assert!(state.history.can_redo());
+    }
+
+    #[test]
+    fn scaling_round_trips_through_undo() {
+        let mut state = EditorState::new(2, 2);
+        state.layers[0].set_rgba8(1, 0, [1, 2, 3, 255]);
+        let original = state.layers[0].pixels.clone();
+
+        assert_eq!(state.scale_canvas(ScaleFactor::Times(3)), Ok(()));
+        assert_eq!((state.canvas_width, state.canvas_height), (6, 6));
+        assert_eq!(state.layers[0].get_rgba8(5, 2), [1, 2, 3, 255]);
+        assert_eq!(state.layers[0].get_rgba8(2, 0), [0; 4]);
+
+        let Some(EditCommand::DocumentSnapshot { before, after }) = state.history.undo() else {
+            panic!("expected a snapshot");
+        };
+        before.restore(&mut state);
+        assert_eq!((state.canvas_width, state.canvas_height), (2, 2));
+        assert_eq!(state.layers[0].pixels, original);
+
+        after.restore(&mut state);
+        assert_eq!(state.layers[0].width, 6);
+        assert_eq!(state.layers[0].get_rgba8(3, 0), [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn scaling_past_the_maximum_canvas_size_is_rejected() {
+        let mut state = EditorState::new(4, 4);
+        state.max_canvas_size = 64;
+
+        assert!(state.scale_canvas(ScaleFactor::Times(17)).is_err());
+        assert!(state.scale_canvas(ScaleFactor::Times(u32::MAX)).is_err());
+        assert_eq!((state.canvas_width, state.canvas_height), (4, 4));
+        assert!(!state.history.can_undo());
+
+        assert_eq!(state.scale_canvas(ScaleFactor::Times(16)), Ok(()));
+        assert_eq!(state.canvas_width, 64);
+    }
+
+    #[test]
+    fn layer_moves_follow_every_position() {
+        let moved: Vec<_> = (0..4).map(|index| follow_layer_move(index, 1, 3)).collect();
+        assert_eq!(moved, [0, 3, 1, 2]);
+        let moved: Vec<_> = (0..4).map(|index| follow_layer_move(index, 3, 1)).collect();
+        assert_eq!(moved, [0, 2, 3, 1]);
+    }
+
+    #[test]
+    fn only_overlapping_changes_on_one_layer_conflict() {
+        assert!(!pixel_change(0, 1).conflicts_with(&pixel_change(1, 1)));
+        assert!(!pixel_change(0, 1).conflicts_with(&pixel_change(0, 3)));
+        assert!(pixel_change(0, 1).conflicts_with(&pixel_change(0, 1)));
+
+        // Changes without pixel bounds are assumed to overlap
+        let opacity = EditCommand::LayerPropertyChanged {
+            layer_index: 0,
+            old: LayerProperty::Opacity(1.0),
+            new: LayerProperty::Opacity(0.5),
+        };
+        assert!(opacity.conflicts_with(&pixel_change(0, 3)));
+        assert!(!opacity.conflicts_with(&pixel_change(1, 3)));
+
+        let state = EditorState::new(4, 4);
+        let snapshot = EditCommand::DocumentSnapshot {
+            before: DocSnapshot::capture(&state),
+            after: DocSnapshot::capture(&state),
+        };
+        assert!(snapshot.conflicts_with(&pixel_change(2, 0)));
+    }
+
+    #[test]
+    fn undoing_one_layer_skips_past_the_others() {
+        let mut history = History::new();
+        history.push(pixel_change(0, 0));
+        history.push(pixel_change(1, 0));
+        history.push(pixel_change(1, 1));
+
+        let undone = history.undo_layer(0);
+        assert!(matches!(
+            undone,
+            Ok(Some(EditCommand::PixelChange { layer_index: 0, .. }))
+        ));
+        // The other layer's changes stay applied, now ahead of it
+        assert_eq!(history.current_index, 2);
+        let layers: Vec<_> = history
+            .commands
+            .iter()
+            .map(EditCommand::layer_index)
+            .collect();
+        assert_eq!(layers, [Some(1), Some(1), Some(0)]);
+        assert!(matches!(history.undo_layer(0), Ok(None)));
+        assert!(matches!(history.undo_layer(2), Ok(None)));
+    }
+
+    #[test]
+    fn undoing_one_layer_refuses_to_skip_overlapping_changes() {
+        let state = EditorState::new(4, 4);
+        let mut history = History::new();
+        history.push(pixel_change(0, 0));
+        history.push(EditCommand::DocumentSnapshot {
+            before: DocSnapshot::capture(&state),
+            after: DocSnapshot::capture(&state),
+        });
+
+        assert!(history.undo_layer(0).is_err());
+        assert_eq!(history.current_index, 2);
+        assert_eq!(history.commands[0].layer_index(), Some(0));
+    }
+
+    #[test]
+    fn importing_colors_keeps_every_used_color() {
+        let mut state = EditorState::new(2, 2);
+        state.used_colors.clear();
+        for value in 0..MAX_USED_COLORS as u8 {
+            state.add_used_color(Color::from_rgb8(value * 8, 0, 0));
+        }
+        let mine = state.used_colors.clone();
+
+        let pixels = [
+            [0, 0, 0, 255],
+            [0, 200, 0, 255],
+            [0, 0, 200, 255],
+            [0, 0, 200, 255],
+        ]
+        .concat();
+        assert_eq!(state.add_imported_colors(&pixels, 8), 2);
+        assert_eq!(state.used_colors.len(), MAX_USED_COLORS + 2);
+        assert_eq!(state.used_colors[..MAX_USED_COLORS], mine[..]);
+    }
 }
@@ -1,24 +1,71 @@
-use crate::state::EditorState;
+use crate::font;
+use crate::state::{
+    ClipboardData, CloneSource, EditCommand, EditorState, FillPreview, FloatingPaste,
+    JitterOptions, RegionBounds, ShapeDraft, Tool,
+};
 use crate::utils;
 use iced::{Color, Rectangle};
 
+/// Map a possibly out-of-range coordinate onto the canvas. With `wrap` the
+/// position continues from the opposite edge, otherwise it is clipped.
+pub fn resolve_position(
+    x: i32,
+    y: i32,
+    canvas_width: u32,
+    canvas_height: u32,
+    wrap: bool,
+) -> Option<(u32, u32)> {
+    if canvas_width == 0 || canvas_height == 0 {
+        return None;
+    }
+    if wrap {
+        Some((
+            x.rem_euclid(canvas_width as i32) as u32,
+            y.rem_euclid(canvas_height as i32) as u32,
+        ))
+    } else if x >= 0 && y >= 0 && x < canvas_width as i32 && y < canvas_height as i32 {
+        Some((x as u32, y as u32))
+    } else {
+        None
+    }
+}
+
+/// Pixels covered by a square brush of `size` centered on (x, y). With a
+/// `snap` above 1 the brush works on aligned `snap` x `snap` blocks: it is
+/// centered on the block containing (x, y) and every covered block is
+/// painted whole.
 fn get_brush_pixels(
     x: u32,
     y: u32,
     size: u32,
+    snap: u32,
     canvas_width: u32,
     canvas_height: u32,
+    wrap: bool,
 ) -> Vec<(u32, u32)> {
     let mut pixels = Vec::new();
     let radius = (size / 2) as i32;
+    let snap = snap.max(1) as i32;
+    let block_x = x as i32 / snap;
+    let block_y = y as i32 / snap;
 
     for dy in -(radius)..=(radius) {
         for dx in -(radius)..=(radius) {
-            let px = x as i32 + dx;
-            let py = y as i32 + dy;
+            let origin_x = (block_x + dx) * snap;
+            let origin_y = (block_y + dy) * snap;
 
-            if px >= 0 && py >= 0 && px < canvas_width as i32 && py < canvas_height as i32 {
-                pixels.push((px as u32, py as u32));
+            for oy in 0..snap {
+                for ox in 0..snap {
+                    if let Some(position) = resolve_position(
+                        origin_x + ox,
+                        origin_y + oy,
+                        canvas_width,
+                        canvas_height,
+                        wrap,
+                    ) {
+                        pixels.push(position);
+                    }
+                }
             }
         }
     }
@@ -51,19 +98,381 @@ fn get_mirrored_positions(state: &EditorState, x: u32, y: u32) -> Vec<(u32, u32)
     positions
 }
 
-pub fn apply_pencil(state: &mut EditorState, x: u32, y: u32) {
-    if x >= state.canvas_width || y >= state.canvas_height {
+/// Brush size scaled by pen pressure, never below 1.
+fn pressed_brush_size(size: u32, pressure: Option<f32>) -> u32 {
+    match pressure {
+        Some(pressure) => ((size as f32 * pressure.clamp(0.0, 1.0)).round() as u32).max(1),
+        None => size,
+    }
+}
+
+/// Points of the line from `from` toward `to`, snapped to the nearest
+/// pixel-art isometric slope: horizontal, 2:1, 1:1, 1:2 or vertical. The 2:1
+/// and 1:2 slopes advance in runs of exactly two pixels. Also returns the
+/// chosen run pattern, signed by direction, so callers can tell when a
+/// stroke turns.
+pub fn isometric_line(from: (u32, u32), to: (u32, u32)) -> ((i32, i32), Vec<(i32, i32)>) {
+    let (dx, dy) = (to.0 as i32 - from.0 as i32, to.1 as i32 - from.1 as i32);
+    let (sx, sy) = (if dx < 0 { -1 } else { 1 }, if dy < 0 { -1 } else { 1 });
+    let (ax, ay) = (dx.abs(), dy.abs());
+
+    // Runs along the major axis per step on the minor one, picked by the
+    // nearest angle; 0 stands for a straight line
+    let angle = (ay as f32).atan2(ax as f32).to_degrees();
+    let slopes: [((i32, i32), f32); 5] = [
+        ((1, 0), 0.0),
+        ((2, 1), 26.565),
+        ((1, 1), 45.0),
+        ((1, 2), 63.435),
+        ((0, 1), 90.0),
+    ];
+    let ((run_x, run_y), _) = slopes
+        .into_iter()
+        .min_by(|a, b| (a.1 - angle).abs().total_cmp(&(b.1 - angle).abs()))
+        .unwrap_or(slopes[0]);
+
+    // Stop 2:1 and 1:2 lines after a whole run so they never end on a
+    // single pixel
+    let whole_runs = |length: i32| {
+        if length > 0 && length % 2 == 0 {
+            length - 1
+        } else {
+            length
+        }
+    };
+    let (from_x, from_y) = (from.0 as i32, from.1 as i32);
+    let points = match (run_x, run_y) {
+        (1, 0) => (0..=ax).map(|i| (from_x + i * sx, from_y)).collect(),
+        (0, 1) => (0..=ay).map(|i| (from_x, from_y + i * sy)).collect(),
+        (1, 1) => {
+            let steps = (ax + ay + 1) / 2;
+            (0..=steps)
+                .map(|i| (from_x + i * sx, from_y + i * sy))
+                .collect()
+        }
+        (2, 1) => (0..=whole_runs(ax))
+            .map(|i| (from_x + i * sx, from_y + i / 2 * sy))
+            .collect(),
+        _ => (0..=whole_runs(ay))
+            .map(|i| (from_x + i / 2 * sx, from_y + i * sy))
+            .collect(),
+    };
+    ((run_x * sx, run_y * sy), points)
+}
+
+/// Continue an isometric-assisted pencil or eraser stroke toward (x, y).
+/// The stroke runs straight from its anchor along the nearest isometric
+/// slope, and starts a new segment from where it got to when the slope
+/// changes.
+pub fn apply_isometric_stroke(state: &mut EditorState, x: u32, y: u32, pressure: Option<f32>) {
+    let apply = match state.current_tool {
+        Tool::Pencil => apply_pencil,
+        Tool::Eraser => apply_eraser,
+        _ => return,
+    };
+    let Some(mut stroke) = state.isometric_stroke else {
         return;
+    };
+
+    let (direction, _) = isometric_line(stroke.anchor, (x, y));
+    if stroke.direction.is_some_and(|current| current != direction) {
+        stroke.anchor = stroke.end;
+    }
+    let (direction, points) = isometric_line(stroke.anchor, (x, y));
+    let pressure = pressure.filter(|_| state.tool_options.freehand.pressure_sensitive);
+    for (px, py) in points {
+        let Some((px, py)) = resolve_position(
+            px,
+            py,
+            state.canvas_width,
+            state.canvas_height,
+            state.wrap_edges,
+        ) else {
+            continue;
+        };
+        apply(state, px, py, pressure);
+        stroke.end = (px, py);
+    }
+    stroke.direction = Some(direction);
+    state.isometric_stroke = Some(stroke);
+}
+
+/// Stamp the pencil or eraser at (x, y). Pressure is ignored unless enabled;
+/// when present, stamps are interpolated from the previous position with the
+/// pressure ramping between samples, so the size changes smoothly along fast
+/// strokes. Without pressure this is a single stamp, as before.
+pub fn apply_brush_stroke(state: &mut EditorState, x: u32, y: u32, pressure: Option<f32>) {
+    let apply = match state.current_tool {
+        Tool::Pencil => apply_pencil,
+        Tool::Eraser => apply_eraser,
+        _ => return,
+    };
+    let Some(pressure) = pressure.filter(|_| state.tool_options.freehand.pressure_sensitive) else {
+        apply(state, x, y, None);
+        return;
+    };
+
+    let ((from_x, from_y), from_pressure) = match (state.last_pixel, state.last_pressure) {
+        (Some(from), Some(from_pressure)) => (from, from_pressure),
+        _ => ((x, y), pressure),
+    };
+    let (dx, dy) = (x as f32 - from_x as f32, y as f32 - from_y as f32);
+    let steps = dx.abs().max(dy.abs()).max(1.0) as u32;
+    for step in 1..=steps {
+        let t = step as f32 / steps as f32;
+        apply(
+            state,
+            (from_x as f32 + dx * t).round() as u32,
+            (from_y as f32 + dy * t).round() as u32,
+            Some(from_pressure + (pressure - from_pressure) * t),
+        );
+    }
+    state.last_pressure = Some(pressure);
+}
+
+/// One-pixel outline of the line, rectangle or ellipse spanning `anchor` to
+/// `current`.
+pub fn shape_outline(tool: Tool, anchor: (u32, u32), current: (u32, u32)) -> Vec<(u32, u32)> {
+    let (x0, y0) = (anchor.0.min(current.0), anchor.1.min(current.1));
+    let (x1, y1) = (anchor.0.max(current.0), anchor.1.max(current.1));
+    let mut points = Vec::new();
+    match tool {
+        Tool::Line => {
+            // Bresenham from the anchor, so the line starts where the drag did
+            let (mut x, mut y) = (anchor.0 as i32, anchor.1 as i32);
+            let (end_x, end_y) = (current.0 as i32, current.1 as i32);
+            let (dx, dy) = ((end_x - x).abs(), -(end_y - y).abs());
+            let (sx, sy) = ((end_x - x).signum(), (end_y - y).signum());
+            let mut error = dx + dy;
+            loop {
+                points.push((x as u32, y as u32));
+                if x == end_x && y == end_y {
+                    break;
+                }
+                let doubled = 2 * error;
+                if doubled >= dy {
+                    error += dy;
+                    x += sx;
+                }
+                if doubled <= dx {
+                    error += dx;
+                    y += sy;
+                }
+            }
+        }
+        Tool::Rectangle => {
+            for x in x0..=x1 {
+                points.push((x, y0));
+                points.push((x, y1));
+            }
+            for y in y0..=y1 {
+                points.push((x0, y));
+                points.push((x1, y));
+            }
+        }
+        Tool::Ellipse => {
+            // Sample the edge once per column and once per row so steep and
+            // flat parts both stay connected; each far side mirrors the near
+            // one so the outline is symmetric
+            let (cx, cy) = ((x0 + x1) as f32 / 2.0, (y0 + y1) as f32 / 2.0);
+            let (rx, ry) = ((x1 - x0) as f32 / 2.0 + 0.5, (y1 - y0) as f32 / 2.0 + 0.5);
+            for x in x0..=x1 {
+                let t = (x as f32 - cx) / rx;
+                let top = (cy - ry * (1.0 - t * t).max(0.0).sqrt()).round() as u32;
+                let top = top.clamp(y0, y1);
+                points.push((x, top));
+                points.push((x, y0 + y1 - top));
+            }
+            for y in y0..=y1 {
+                let t = (y as f32 - cy) / ry;
+                let left = (cx - rx * (1.0 - t * t).max(0.0).sqrt()).round() as u32;
+                let left = left.clamp(x0, x1);
+                points.push((left, y));
+                points.push((x0 + x1 - left, y));
+            }
+        }
+        _ => {}
+    }
+    points.sort();
+    points.dedup();
+    points
+}
+
+/// One-pixel quadratic Bézier from `anchor` to `end` bent towards
+/// `control`. With `pixel_perfect`, the middle pixel of each L-shaped corner
+/// is dropped so the curve steps diagonally instead of doubling up.
+pub fn curve_outline(
+    anchor: (u32, u32),
+    control: (u32, u32),
+    end: (u32, u32),
+    pixel_perfect: bool,
+) -> Vec<(u32, u32)> {
+    let point = |t: f32| {
+        let u = 1.0 - t;
+        let blend = |a: u32, c: u32, b: u32| {
+            (u * u * a as f32 + 2.0 * u * t * c as f32 + t * t * b as f32).round() as i32
+        };
+        (
+            blend(anchor.0, control.0, end.0),
+            blend(anchor.1, control.1, end.1),
+        )
+    };
+
+    // Sample far more often than the curve is long so no pixel is skipped,
+    // then fill any remaining gap with a straight step
+    let span = |a: u32, b: u32| a.abs_diff(b);
+    let length = span(anchor.0, control.0)
+        + span(control.0, end.0)
+        + span(anchor.1, control.1)
+        + span(control.1, end.1);
+    let samples = (length * 4).max(1);
+    let mut points: Vec<(i32, i32)> = Vec::new();
+    for step in 0..=samples {
+        let (x, y) = point(step as f32 / samples as f32);
+        while let Some(&(px, py)) = points.last() {
+            if (x - px).abs() <= 1 && (y - py).abs() <= 1 {
+                break;
+            }
+            points.push((px + (x - px).signum(), py + (y - py).signum()));
+        }
+        if points.last() != Some(&(x, y)) {
+            points.push((x, y));
+        }
     }
 
+    if pixel_perfect {
+        let mut cleaned: Vec<(i32, i32)> = Vec::with_capacity(points.len());
+        for &(x, y) in &points {
+            // Drop the previous pixel when it sits in the corner between its
+            // neighbors, which themselves touch diagonally
+            if cleaned.len() >= 2 {
+                let (px, py) = cleaned[cleaned.len() - 2];
+                if (x - px).abs() == 1 && (y - py).abs() == 1 {
+                    cleaned.pop();
+                }
+            }
+            cleaned.push((x, y));
+        }
+        points = cleaned;
+    }
+
+    points
+        .into_iter()
+        .map(|(x, y)| (x as u32, y as u32))
+        .collect()
+}
+
+/// Pixels a shape draft paints: its outline stamped with the shape tool's
+/// brush and mirrored like pencil strokes. Both the drag preview and
+/// `commit_shape` use this, so the preview is exactly what gets painted.
+pub fn shape_pixels(state: &EditorState, draft: &ShapeDraft) -> Vec<(u32, u32)> {
+    let brush_size = state.options_for(draft.tool).brush_size;
+    let mut pixels = Vec::new();
+    let outline = if draft.tool == Tool::Curve {
+        // Until it is bent a curve is a straight line between its ends
+        let control = draft.control.unwrap_or((
+            (draft.anchor.0 + draft.current.0) / 2,
+            (draft.anchor.1 + draft.current.1) / 2,
+        ));
+        curve_outline(
+            draft.anchor,
+            control,
+            draft.current,
+            state.tool_options.curve.pixel_perfect,
+        )
+    } else {
+        shape_outline(draft.tool, draft.anchor, draft.current)
+    };
+    for (x, y) in outline {
+        for (bx, by) in get_brush_pixels(
+            x,
+            y,
+            brush_size,
+            state.tool_options.pixel_snap,
+            state.canvas_width,
+            state.canvas_height,
+            state.wrap_edges,
+        ) {
+            pixels.extend(get_mirrored_positions(state, bx, by));
+        }
+    }
+    pixels.sort();
+    pixels.dedup();
+    pixels
+}
+
+/// Paint the shape being dragged out, if any, in the primary color as a
+/// single undo step.
+pub fn commit_shape(state: &mut EditorState) {
+    let Some(draft) = state.shape_draft.take() else {
+        return;
+    };
     let primary_color = state.primary_color;
+    let opacity = state.options_for(draft.tool).opacity;
+    let layer_index = state.active_layer_index;
+
+    let mut changes = Vec::new();
+    for (x, y) in shape_pixels(state, &draft) {
+        let Some(old_color) = state.active_layer().map(|layer| layer.get_pixel(x, y)) else {
+            return;
+        };
+        let new_color = state.brush_color(old_color, primary_color, opacity);
+        if let Some(new_color) = state.set_pixel(x, y, new_color) {
+            changes.push((x, y, old_color, new_color));
+        }
+    }
+
+    if !changes.is_empty() {
+        state.add_used_color(primary_color);
+    }
+    if let Some(layer) = state.active_layer()
+        && let Some(command) = EditCommand::from_pixel_changes(layer_index, layer, changes)
+    {
+        state.history.push(command);
+    }
+}
+
+/// `color` varied for the brush stamp at (x, y) by the jitter settings:
+/// value and hue offsets drawn from the seed and position, so the same
+/// stroke always paints the same colors. Zero strengths return `color`.
+fn jittered_color(color: Color, jitter: JitterOptions, x: u32, y: u32) -> Color {
+    if jitter.value == 0 && jitter.hue == 0 {
+        return color;
+    }
+    let hash = crate::filters::noise_hash(jitter.seed, x, y);
+    let offset = |bits: u32, range: u32| (bits % (2 * range + 1)) as f32 - range as f32;
+    let rgba = color.into_rgba8();
+    let (hue, saturation, value) = utils::rgb_to_hsv(rgba);
+    let hue = hue + offset(hash >> 16, jitter.hue);
+    let value = (value + offset(hash & 0xFFFF, jitter.value) / 255.0).clamp(0.0, 1.0);
+    let [r, g, b] = utils::hsv_to_rgb(hue, saturation, value);
+    Color::from_rgba8(r, g, b, color.a)
+}
+
+pub fn apply_pencil(state: &mut EditorState, x: u32, y: u32, pressure: Option<f32>) {
+    if x >= state.canvas_width || y >= state.canvas_height {
+        return;
+    }
+
+    // Each stamp paints one jittered color; the palette keeps the primary.
+    let primary_color = jittered_color(state.primary_color, state.tool_options.jitter, x, y);
     let layer_index = state.active_layer_index;
-    let brush_size = state.brush_size;
+    let options = state.options_for(Tool::Pencil);
+    let brush_size = options.brush_size;
+    let opacity = options.opacity;
 
     let mut all_positions = Vec::new();
 
     // Get brush pixels
-    let brush_pixels = get_brush_pixels(x, y, brush_size, state.canvas_width, state.canvas_height);
+    let brush_pixels = get_brush_pixels(
+        x,
+        y,
+        pressed_brush_size(brush_size, pressure),
+        state.tool_options.pixel_snap,
+        state.canvas_width,
+        state.canvas_height,
+        state.wrap_edges,
+    );
 
     // Apply mirroring to each brush pixel
     for (bx, by) in brush_pixels {
@@ -83,51 +492,60 @@ pub fn apply_pencil(state: &mut EditorState, x: u32, y: u32) {
             continue;
         }
 
+        // Each pixel is painted at most once per stroke, so overlapping
+        // stamps don't build up opacity within a single stroke
+        if !state.stroke_pixels.insert((px, py)) {
+            continue;
+        }
+
         let old_color = if let Some(layer) = state.active_layer() {
             layer.get_pixel(px, py)
         } else {
             continue;
         };
 
-        // Use EditorState::set_pixel for consistency
-        state.set_pixel(px, py, primary_color);
+        let new_color = state.brush_color(old_color, primary_color, opacity);
+        let Some(new_color) = state.set_pixel(px, py, new_color) else {
+            continue;
+        };
 
-        changes.push((px, py, old_color, primary_color));
+        changes.push((px, py, old_color, new_color));
+    }
+
+    if !changes.is_empty() {
+        state.add_used_color(state.primary_color);
     }
 
     // Record changes for undo
-    if changes.len() == 1 {
-        let (px, py, old_color, new_color) = changes[0];
-        state.history.push(crate::state::EditCommand::PixelChange {
-            layer_index,
-            x: px,
-            y: py,
-            old_color,
-            new_color,
-        });
-    } else if !changes.is_empty() {
-        state
-            .history
-            .push(crate::state::EditCommand::MultiPixelChange {
-                layer_index,
-                changes: changes.into_iter().collect(),
-            });
+    if let Some(layer) = state.active_layer()
+        && let Some(command) = EditCommand::from_pixel_changes(layer_index, layer, changes)
+    {
+        state.history.push(command);
     }
 }
 
-pub fn apply_eraser(state: &mut EditorState, x: u32, y: u32) {
+pub fn apply_eraser(state: &mut EditorState, x: u32, y: u32, pressure: Option<f32>) {
     if x >= state.canvas_width || y >= state.canvas_height {
         return;
     }
 
     let layer_index = state.active_layer_index;
-    let brush_size = state.brush_size;
-    let new_color = Color::TRANSPARENT;
+    let options = state.options_for(Tool::Eraser);
+    let brush_size = options.brush_size;
+    let opacity = options.opacity;
 
     let mut all_positions = Vec::new();
 
     // Get brush pixels
-    let brush_pixels = get_brush_pixels(x, y, brush_size, state.canvas_width, state.canvas_height);
+    let brush_pixels = get_brush_pixels(
+        x,
+        y,
+        pressed_brush_size(brush_size, pressure),
+        state.tool_options.pixel_snap,
+        state.canvas_width,
+        state.canvas_height,
+        state.wrap_edges,
+    );
 
     // Apply mirroring to each brush pixel
     for (bx, by) in brush_pixels {
@@ -147,35 +565,38 @@ pub fn apply_eraser(state: &mut EditorState, x: u32, y: u32) {
             continue;
         }
 
+        if !state.stroke_pixels.insert((px, py)) {
+            continue;
+        }
+
         let old_color = if let Some(layer) = state.active_layer() {
             layer.get_pixel(px, py)
         } else {
             continue;
         };
 
-        // Use EditorState::set_pixel for consistency
-        state.set_pixel(px, py, new_color);
+        // A partial-opacity eraser fades the pixel's alpha instead of clearing it
+        let new_color = if opacity < 1.0 {
+            Color {
+                a: old_color.a * (1.0 - opacity),
+                ..old_color
+            }
+        } else {
+            Color::TRANSPARENT
+        };
+
+        let Some(new_color) = state.set_pixel(px, py, new_color) else {
+            continue;
+        };
 
         changes.push((px, py, old_color, new_color));
     }
 
     // Record changes for undo
-    if changes.len() == 1 {
-        let (px, py, old_color, new_color) = changes[0];
-        state.history.push(crate::state::EditCommand::PixelChange {
-            layer_index,
-            x: px,
-            y: py,
-            old_color,
-            new_color,
-        });
-    } else if !changes.is_empty() {
-        state
-            .history
-            .push(crate::state::EditCommand::MultiPixelChange {
-                layer_index,
-                changes: changes.into_iter().collect(),
-            });
+    if let Some(layer) = state.active_layer()
+        && let Some(command) = EditCommand::from_pixel_changes(layer_index, layer, changes)
+    {
+        state.history.push(command);
     }
 }
 
@@ -184,8 +605,15 @@ pub fn apply_eyedropper(state: &mut EditorState, x: u32, y: u32) {
         return;
     }
 
-    // Get the composited color at this position
-    let color = state.get_pixel(x, y);
+    // The composited color, or the active layer's own with sample merged off
+    let color = if state.tool_options.eyedropper.sample_merged {
+        state.get_pixel(x, y)
+    } else {
+        match state.active_layer() {
+            Some(layer) => layer.get_pixel(x, y),
+            None => return,
+        }
+    };
 
     // Only pick non-transparent colors
     if color.a > 0.01 {
@@ -194,69 +622,273 @@ pub fn apply_eyedropper(state: &mut EditorState, x: u32, y: u32) {
     }
 }
 
-pub fn apply_fill(state: &mut EditorState, x: u32, y: u32) {
-    if x >= state.canvas_width || y >= state.canvas_height {
-        return;
+/// Pixels a flood fill seeded at (x, y) in a `width` x `height` RGBA buffer
+/// would cover: the 4-connected region of pixels exactly matching the seed
+/// color. Uses a scanline walk, filling whole horizontal spans before moving
+/// to the rows above and below.
+pub fn fill_region(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    wrap: bool,
+) -> Vec<(u32, u32)> {
+    if x >= width || y >= height {
+        return Vec::new();
     }
 
-    let primary_color = state.primary_color;
-    let canvas_width = state.canvas_width;
-    let canvas_height = state.canvas_height;
-    let layer_index = state.active_layer_index;
+    let color_at = |x: u32, y: u32| {
+        let index = ((y * width + x) * 4) as usize;
+        pixels.get(index..index + 4)
+    };
+    let target = color_at(x, y);
+    let mut filled = vec![false; (width * height) as usize];
+    let matches = |filled: &[bool], x: u32, y: u32| {
+        !filled[(y * width + x) as usize] && color_at(x, y) == target
+    };
+    let step = |x: u32, delta: i32| resolve_position(x as i32 + delta, 0, width, height, wrap);
 
-    if let Some(layer) = state.active_layer_mut() {
-        let target_color = layer.get_pixel(x, y);
-
-        // Don't fill if target is already the fill color
-        if target_color == primary_color {
-            return;
+    let mut region = Vec::new();
+    let mut stack = vec![(x, y)];
+    while let Some((seed_x, row)) = stack.pop() {
+        if !matches(&filled, seed_x, row) {
+            continue;
         }
 
-        // Flood fill using BFS
-        let mut changes = Vec::new();
-        let mut queue = std::collections::VecDeque::new();
-        let mut visited = std::collections::HashSet::new();
-
-        queue.push_back((x, y));
-        visited.insert((x, y));
-
-        while let Some((cx, cy)) = queue.pop_front() {
-            if cx >= canvas_width || cy >= canvas_height {
-                continue;
+        // Extend the span left and right of the seed. With wrap the span can
+        // cross the edge, but never laps back onto itself.
+        let mut left = seed_x;
+        while let Some((next, _)) = step(left, -1) {
+            if next == seed_x || !matches(&filled, next, row) {
+                break;
             }
-
-            let current_color = layer.get_pixel(cx, cy);
-            if current_color != target_color {
-                continue;
+            left = next;
+        }
+        let mut right = seed_x;
+        while let Some((next, _)) = step(right, 1) {
+            if next == left || !matches(&filled, next, row) {
+                break;
             }
+            right = next;
+        }
 
-            let old_color = current_color;
-            changes.push((cx, cy, old_color, primary_color));
-            layer.set_pixel(cx, cy, primary_color);
-
-            // Add neighbors
-            for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
-                let nx = cx as i32 + dx;
-                let ny = cy as i32 + dy;
-                if nx >= 0 && ny >= 0 {
-                    let nx = nx as u32;
-                    let ny = ny as u32;
-                    if !visited.contains(&(nx, ny)) && nx < canvas_width && ny < canvas_height {
-                        visited.insert((nx, ny));
-                        queue.push_back((nx, ny));
-                    }
+        let mut span_x = left;
+        loop {
+            filled[(row * width + span_x) as usize] = true;
+            region.push((span_x, row));
+            for dy in [-1, 1] {
+                if let Some(neighbor) =
+                    resolve_position(span_x as i32, row as i32 + dy, width, height, wrap)
+                    && matches(&filled, neighbor.0, neighbor.1)
+                {
+                    stack.push(neighbor);
                 }
             }
+            if span_x == right {
+                break;
+            }
+            span_x = (span_x + 1) % width;
         }
+    }
+
+    region
+}
 
-        if !changes.is_empty() {
-            state
-                .history
-                .push(crate::state::EditCommand::MultiPixelChange {
-                    layer_index,
-                    changes,
-                });
+/// Refresh the fill preview for the hovered pixel. The cached region is
+/// reused while the cursor stays inside it and the document is unchanged.
+pub fn update_fill_preview(state: &mut EditorState, x: u32, y: u32) {
+    if !state.tool_options.fill.preview || state.current_tool != Tool::Fill || state.is_drawing {
+        state.fill_preview = None;
+        return;
+    }
+    if x >= state.canvas_width || y >= state.canvas_height {
+        return;
+    }
+    let snap = state.tool_options.pixel_snap.max(1);
+    let (x, y) = (x - x % snap, y - y % snap);
+    let index = (y * state.canvas_width + x) as usize;
+
+    if let Some(preview) = &state.fill_preview
+        && preview.layer_index == state.active_layer_index
+        && preview.generation == state.generation
+        && preview.mask.get(index) == Some(&true)
+    {
+        return;
+    }
+
+    let mut pixels = fill_target_region(state, x, y);
+    if state.selection.is_some() {
+        pixels.retain(|&(px, py)| state.is_selected(px, py));
+    }
+    let mut mask = vec![false; (state.canvas_width * state.canvas_height) as usize];
+    for &(px, py) in &pixels {
+        mask[(py * state.canvas_width + px) as usize] = true;
+    }
+    state.fill_preview = Some(FillPreview {
+        layer_index: state.active_layer_index,
+        generation: state.generation,
+        pixels,
+        mask,
+    });
+}
+
+/// The region a fill seeded at (x, y) covers. With "sample merged" it
+/// follows the colors as composited on screen, otherwise the active layer's
+/// own pixels; either way only the active layer is painted.
+pub fn fill_target_region(state: &EditorState, x: u32, y: u32) -> Vec<(u32, u32)> {
+    let (width, height, wrap) = (state.canvas_width, state.canvas_height, state.wrap_edges);
+    if state.tool_options.fill.sample_merged {
+        let composite = crate::file_io::composite_layers(state);
+        fill_region(&composite, width, height, x, y, wrap)
+    } else {
+        state
+            .active_layer()
+            .map(|layer| fill_region(&layer.pixels, layer.width, layer.height, x, y, wrap))
+            .unwrap_or_default()
+    }
+}
+
+/// Pixels a fill changed on the active layer, as `(x, y, old, new)`. The
+/// layer is already updated; `record_fill` turns this into one undo entry.
+pub struct FillSummary {
+    pub layer_index: usize,
+    pub changes: Vec<(u32, u32, Color, Color)>,
+}
+
+impl FillSummary {
+    /// Box around the changed pixels as `(start_x, start_y, end_x, end_y)`,
+    /// end exclusive.
+    pub fn bounds(&self) -> Option<(u32, u32, u32, u32)> {
+        let (&(x, y, _, _), rest) = self.changes.split_first()?;
+        Some(rest.iter().fold(
+            (x, y, x + 1, y + 1),
+            |(start_x, start_y, end_x, end_y), &(x, y, _, _)| {
+                (
+                    start_x.min(x),
+                    start_y.min(y),
+                    end_x.max(x + 1),
+                    end_y.max(y + 1),
+                )
+            },
+        ))
+    }
+}
+
+/// Paint `pixels` of the active layer with the primary color, or with
+/// `pattern` tiled from the canvas origin, skipping pixels outside the
+/// selection and those preserve transparency protects.
+fn fill_pixels(
+    state: &mut EditorState,
+    pixels: impl IntoIterator<Item = (u32, u32)>,
+    pattern: Option<&ClipboardData>,
+) -> Option<FillSummary> {
+    let pattern = pattern.filter(|p| p.width > 0 && p.height > 0);
+    let primary = utils::color_to_rgba8(state.primary_color);
+    let layer_index = state.active_layer_index;
+    let preserve = state.preserve_transparency;
+    let alpha_only = state.alpha_edit;
+    // Fills stay inside the selection, when there is one
+    let selection = state.effective_selection();
+    let layer = state.active_layer_mut()?;
+
+    let mut changes = Vec::new();
+    for (cx, cy) in pixels {
+        if selection
+            .as_ref()
+            .is_some_and(|mask| !mask.contains(cx, cy))
+        {
+            continue;
         }
+        let fill = match pattern {
+            Some(pattern) => {
+                let index =
+                    (((cy % pattern.height) * pattern.width + cx % pattern.width) * 4) as usize;
+                [
+                    pattern.pixels[index],
+                    pattern.pixels[index + 1],
+                    pattern.pixels[index + 2],
+                    pattern.pixels[index + 3],
+                ]
+            }
+            None => primary,
+        };
+        let old = layer.get_rgba8(cx, cy);
+        // Editing alpha fills with the color's luminance as alpha
+        let fill = if alpha_only {
+            [old[0], old[1], old[2], crate::filters::luminance(fill)]
+        } else {
+            fill
+        };
+        if preserve && utils::reveals_transparent_rgba8(old, fill) {
+            continue;
+        }
+        if fill != old {
+            changes.push((
+                cx,
+                cy,
+                utils::rgba8_to_color(old),
+                utils::rgba8_to_color(fill),
+            ));
+            layer.set_rgba8(cx, cy, fill);
+        }
+    }
+    Some(FillSummary {
+        layer_index,
+        changes,
+    })
+}
+
+/// Flood fill from (x, y) on the active layer. With a `pattern`, each filled
+/// pixel takes its color from the pattern tiled from canvas (0, 0), so
+/// neighboring fills line up. Returns what changed without touching the
+/// history.
+pub fn apply_fill(
+    state: &mut EditorState,
+    x: u32,
+    y: u32,
+    pattern: Option<&ClipboardData>,
+) -> Option<FillSummary> {
+    if x >= state.canvas_width || y >= state.canvas_height {
+        return None;
+    }
+    // Seed from the snap block's origin
+    let snap = state.tool_options.pixel_snap.max(1);
+    let (x, y) = (x - x % snap, y - y % snap);
+    let region = fill_target_region(state, x, y);
+    fill_pixels(state, region, pattern)
+}
+
+/// Paint every selected pixel of the active layer with the primary color,
+/// whatever color it had. Returns `None` without a selection.
+pub fn fill_selection(state: &mut EditorState) -> Option<FillSummary> {
+    let (start_x, start_y, end_x, end_y) = state.selection_bounds()?;
+    let pixels: Vec<(u32, u32)> = (start_y..end_y)
+        .flat_map(|y| (start_x..end_x).map(move |x| (x, y)))
+        .collect();
+    fill_pixels(state, pixels, None)
+}
+
+/// Record a fill as one undo entry and report how many pixels it changed
+/// and where, so an accidentally huge fill is obvious straight away.
+pub fn record_fill(state: &mut EditorState, summary: FillSummary) {
+    state.notification = Some(match summary.bounds() {
+        Some((start_x, start_y, end_x, end_y)) => format!(
+            "Filled {} pixel{} in {}x{} at ({}, {})",
+            summary.changes.len(),
+            if summary.changes.len() == 1 { "" } else { "s" },
+            end_x - start_x,
+            end_y - start_y,
+            start_x,
+            start_y
+        ),
+        None => "Fill changed no pixels".to_string(),
+    });
+    if let Some(layer) = state.layers.get(summary.layer_index)
+        && let Some(command) =
+            EditCommand::from_pixel_changes(summary.layer_index, layer, summary.changes)
+    {
+        state.history.push(command);
     }
 }
 
@@ -284,14 +916,13 @@ pub fn get_selection_pixels(state: &EditorState, selection: Rectangle) -> Option
 
     for y in start_y..end_y {
         for x in start_x..end_x {
-            let color = state.get_pixel(x, y);
-            let rgba = utils::color_to_rgba8(color);
+            // Pixels outside a combined selection stay transparent
+            if state.selection_mask.is_some() && !state.is_selected(x, y) {
+                continue;
+            }
             let index = (((y - start_y) * width + (x - start_x)) * 4) as usize;
-            if index + 3 < pixels.len() {
-                pixels[index] = rgba[0];
-                pixels[index + 1] = rgba[1];
-                pixels[index + 2] = rgba[2];
-                pixels[index + 3] = rgba[3];
+            if let Some(pixel) = pixels.get_mut(index..index + 4) {
+                pixel.copy_from_slice(&state.get_rgba8(x, y));
             }
         }
     }
@@ -299,54 +930,497 @@ pub fn get_selection_pixels(state: &EditorState, selection: Rectangle) -> Option
     Some(pixels)
 }
 
-pub fn paste_pixels(
+/// Shrink a clipboard buffer to the bounding box of its non-transparent
+/// pixels, adding the trimmed amount to its offset. `None` if every pixel is
+/// transparent.
+pub fn trim_clipboard(data: &ClipboardData) -> Option<ClipboardData> {
+    let opaque = |x: u32, y: u32| data.pixels[((y * data.width + x) * 4 + 3) as usize] != 0;
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+    for y in 0..data.height {
+        for x in 0..data.width {
+            if opaque(x, y) {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+    }
+    if min_x > max_x || min_y > max_y {
+        return None;
+    }
+
+    let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in min_y..=max_y {
+        let start = ((y * data.width + min_x) * 4) as usize;
+        pixels.extend_from_slice(&data.pixels[start..start + (width * 4) as usize]);
+    }
+    Some(ClipboardData {
+        pixels,
+        width,
+        height,
+        offset_x: data.offset_x + min_x,
+        offset_y: data.offset_y + min_y,
+    })
+}
+
+/// Copy the selected pixels to `clipboard`, trimmed when trim on copy is
+/// set. A selection with nothing opaque in it then empties the clipboard
+/// with a notice. Returns the selection's bounds on the canvas, or `None`
+/// if nothing on the canvas is selected.
+pub fn copy_selection(
     state: &mut EditorState,
-    pixels: &[u8],
-    start_x: u32,
-    start_y: u32,
-    width: u32,
-    height: u32,
-) {
-    let canvas_width = state.canvas_width;
-    let canvas_height = state.canvas_height;
+    clipboard: &mut Option<ClipboardData>,
+) -> Option<(u32, u32, u32, u32)> {
+    let selection = state.selection?;
+    let bounds = state.selection_bounds()?;
+    let pixels = get_selection_pixels(state, selection)?;
+    let data = ClipboardData {
+        pixels,
+        width: bounds.2 - bounds.0,
+        height: bounds.3 - bounds.1,
+        offset_x: 0,
+        offset_y: 0,
+    };
+    *clipboard = if state.trim_on_copy {
+        let trimmed = trim_clipboard(&data);
+        if trimmed.is_none() {
+            state.notification =
+                Some("Selection is fully transparent; clipboard emptied".to_string());
+        }
+        trimmed
+    } else {
+        Some(data)
+    };
+    Some(bounds)
+}
+
+/// Scale an RGBA image with nearest neighbor to exactly cover the selection
+/// and write it into the active layer as one undo entry.
+pub fn fill_selection_with_image(state: &mut EditorState, pixels: &[u8], width: u32, height: u32) {
+    let Some((start_x, start_y, end_x, end_y)) = state.selection_bounds() else {
+        return;
+    };
+    let (region_width, region_height) = (end_x - start_x, end_y - start_y);
+    let scaled =
+        crate::transform::scale_nearest(pixels, width, height, region_width, region_height);
+    let preserve = state.preserve_transparency;
+    let Some(layer) = state.active_layer() else {
+        return;
+    };
+
+    let mut result = layer.pixels.clone();
+    for y in 0..region_height {
+        for x in 0..region_width {
+            let (canvas_x, canvas_y) = (start_x + x, start_y + y);
+            let src = ((y * region_width + x) * 4) as usize;
+            let dst = ((canvas_y * layer.width + canvas_x) * 4) as usize;
+            if dst + 3 >= result.len() {
+                continue;
+            }
+            let new = [
+                scaled[src],
+                scaled[src + 1],
+                scaled[src + 2],
+                scaled[src + 3],
+            ];
+            if preserve
+                && utils::reveals_transparent_rgba8(layer.get_rgba8(canvas_x, canvas_y), new)
+            {
+                continue;
+            }
+            result[dst..dst + 4].copy_from_slice(&scaled[src..src + 4]);
+        }
+    }
+
     let layer_index = state.active_layer_index;
+    state.replace_layer_pixels(layer_index, result);
+}
 
-    if let Some(layer) = state.active_layer_mut() {
-        let mut changes = Vec::new();
+/// The active layer's pixels with the floating paste written over them, as
+/// committing it would leave them. Respects wrap-around and preserve
+/// transparency.
+pub fn floating_paste_pixels(state: &EditorState) -> Option<Vec<u8>> {
+    let paste = state.floating_paste.as_ref()?;
+    let layer = state.active_layer()?;
+    let mut result = layer.pixels.clone();
+    // A wrapped paste larger than the canvas would overlap itself; the first
+    // write to each pixel wins
+    let mut written = std::collections::HashSet::new();
 
-        for y in 0..height {
-            for x in 0..width {
-                let canvas_x = start_x + x;
-                let canvas_y = start_y + y;
+    for y in 0..paste.height {
+        for x in 0..paste.width {
+            let Some((canvas_x, canvas_y)) = resolve_position(
+                paste.x + x as i32,
+                paste.y + y as i32,
+                state.canvas_width,
+                state.canvas_height,
+                state.wrap_edges,
+            ) else {
+                continue;
+            };
+            if !written.insert((canvas_x, canvas_y)) {
+                continue;
+            }
 
-                if canvas_x >= canvas_width || canvas_y >= canvas_height {
-                    continue;
-                }
+            let src = ((y * paste.width + x) * 4) as usize;
+            let dst = ((canvas_y * layer.width + canvas_x) * 4) as usize;
+            if src + 3 >= paste.pixels.len() || dst + 3 >= result.len() {
+                continue;
+            }
+            let new = [
+                paste.pixels[src],
+                paste.pixels[src + 1],
+                paste.pixels[src + 2],
+                paste.pixels[src + 3],
+            ];
+            if state.preserve_transparency
+                && utils::reveals_transparent_rgba8(layer.get_rgba8(canvas_x, canvas_y), new)
+            {
+                continue;
+            }
+            result[dst..dst + 4].copy_from_slice(&paste.pixels[src..src + 4]);
+        }
+    }
 
-                let index = ((y * width + x) * 4) as usize;
-                if index + 3 < pixels.len() {
-                    let old_color = layer.get_pixel(canvas_x, canvas_y);
-                    let rgba = [
-                        pixels[index],
-                        pixels[index + 1],
-                        pixels[index + 2],
-                        pixels[index + 3],
-                    ];
-                    let new_color = utils::rgba8_to_color(rgba);
-
-                    changes.push((canvas_x, canvas_y, old_color, new_color));
-                    layer.set_pixel(canvas_x, canvas_y, new_color);
-                }
+    Some(result)
+}
+
+/// Where the pending paste lands when the cursor is over (x, y): centered on
+/// the cursor, with its corner snapped to the pixel snap grid.
+pub fn pending_paste_origin(state: &EditorState, x: u32, y: u32) -> Option<(i32, i32)> {
+    let paste = state.pending_paste.as_ref()?;
+    let snap = state.tool_options.pixel_snap.max(1) as i32;
+    let x = x as i32 - paste.width as i32 / 2;
+    let y = y as i32 - paste.height as i32 / 2;
+    Some((x - x.rem_euclid(snap), y - y.rem_euclid(snap)))
+}
+
+/// Rasterize the pending text in the primary color and stamp it onto the
+/// active layer as one undo entry.
+pub fn commit_pending_text(state: &mut EditorState) {
+    let Some(text) = state.pending_text.take() else {
+        return;
+    };
+    let color = utils::color_to_rgba8(state.primary_color);
+    let (width, height, pixels) =
+        font::rasterize_text(&text.text, state.tool_options.text.font, color);
+    if width == 0 {
+        return;
+    }
+    commit_floating_paste(state);
+    state.floating_paste = Some(FloatingPaste {
+        pixels,
+        width,
+        height,
+        x: text.x as i32,
+        y: text.y as i32,
+    });
+    commit_floating_paste(state);
+}
+
+/// Copy the active layer's tile containing `from` over the tile containing
+/// `to` as one undo entry. Tiles cut short by the canvas edge copy only the
+/// part both tiles have.
+pub fn duplicate_tile(state: &mut EditorState, from: (u32, u32), to: (u32, u32)) {
+    let (src_x, src_y, src_end_x, src_end_y) = state.tile_bounds(from.0, from.1);
+    let (dst_x, dst_y, dst_end_x, dst_end_y) = state.tile_bounds(to.0, to.1);
+    if (src_x, src_y) == (dst_x, dst_y) {
+        return;
+    }
+    let width = (src_end_x - src_x).min(dst_end_x - dst_x);
+    let height = (src_end_y - src_y).min(dst_end_y - dst_y);
+    let Some(layer) = state.active_layer() else {
+        return;
+    };
+    let pixels = layer
+        .read_region(RegionBounds {
+            x: src_x,
+            y: src_y,
+            width,
+            height,
+        })
+        .into_vec();
+
+    commit_floating_paste(state);
+    state.floating_paste = Some(FloatingPaste {
+        pixels,
+        width,
+        height,
+        x: dst_x as i32,
+        y: dst_y as i32,
+    });
+    commit_floating_paste(state);
+}
+
+/// The clone source for the current selection on the active layer, reusing
+/// the remembered copy while only clones have changed the document since.
+pub fn clone_source(state: &mut EditorState) -> Option<CloneSource> {
+    let (start_x, start_y, end_x, end_y) = state.selection_bounds()?;
+    let bounds = RegionBounds {
+        x: start_x,
+        y: start_y,
+        width: end_x - start_x,
+        height: end_y - start_y,
+    };
+    if let Some(source) = &state.clone_source
+        && source.layer_index == state.active_layer_index
+        && source.bounds == bounds
+        && source.revision == state.history.revision
+    {
+        return Some(source.clone());
+    }
+    let selected = (start_y..end_y)
+        .flat_map(|y| (start_x..end_x).map(move |x| (x, y)))
+        .map(|(x, y)| state.is_selected(x, y))
+        .collect();
+    let source = CloneSource {
+        revision: state.history.revision,
+        layer_index: state.active_layer_index,
+        bounds,
+        pixels: state.active_layer()?.read_region(bounds).into_vec(),
+        selected,
+    };
+    state.clone_source = Some(source.clone());
+    Some(source)
+}
+
+/// The source layer with the clone source stamped `offset` pixels away from
+/// where it was copied. Respects wrap-around and preserve transparency.
+pub fn cloned_pixels(
+    state: &EditorState,
+    source: &CloneSource,
+    offset: (i32, i32),
+) -> Option<Vec<u8>> {
+    let layer = state.layers.get(source.layer_index)?;
+    let mut result = layer.pixels.clone();
+    let bounds = source.bounds;
+    for y in 0..bounds.height {
+        for x in 0..bounds.width {
+            let index = (y * bounds.width + x) as usize;
+            if !source.selected[index] {
+                continue;
             }
+            let Some((canvas_x, canvas_y)) = resolve_position(
+                (bounds.x + x) as i32 + offset.0,
+                (bounds.y + y) as i32 + offset.1,
+                state.canvas_width,
+                state.canvas_height,
+                state.wrap_edges,
+            ) else {
+                continue;
+            };
+            let src = index * 4;
+            let new = [
+                source.pixels[src],
+                source.pixels[src + 1],
+                source.pixels[src + 2],
+                source.pixels[src + 3],
+            ];
+            if state.preserve_transparency
+                && utils::reveals_transparent_rgba8(layer.get_rgba8(canvas_x, canvas_y), new)
+            {
+                continue;
+            }
+            let dst = ((canvas_y * layer.width + canvas_x) * 4) as usize;
+            result[dst..dst + 4].copy_from_slice(&new);
         }
+    }
+    Some(result)
+}
+
+/// Write the floating paste into the active layer as one undo entry.
+pub fn commit_floating_paste(state: &mut EditorState) {
+    if let Some(pixels) = floating_paste_pixels(state) {
+        let layer_index = state.active_layer_index;
+        state.replace_layer_pixels(layer_index, pixels);
+    }
+    state.floating_paste = None;
+    state.dragging_paste = None;
+}
+
+/// The active layer shifted `offset` pixels, for the Move tool. Content
+/// pushed past an edge wraps around when wrap-around is on and is dropped
+/// otherwise.
+pub fn moved_pixels(state: &EditorState, offset: (i32, i32)) -> Option<Vec<u8>> {
+    let layer = state.active_layer()?;
+    let mut result = vec![0u8; layer.pixels.len()];
+    for y in 0..layer.height {
+        for x in 0..layer.width {
+            let Some((target_x, target_y)) = resolve_position(
+                x as i32 + offset.0,
+                y as i32 + offset.1,
+                layer.width,
+                layer.height,
+                state.wrap_edges,
+            ) else {
+                continue;
+            };
+            let src = ((y * layer.width + x) * 4) as usize;
+            let dst = ((target_y * layer.width + target_x) * 4) as usize;
+            result[dst..dst + 4].copy_from_slice(&layer.pixels[src..src + 4]);
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+    use crate::update::{apply, dot};
+
+    /// A white-and-black bottom layer under an active, transparent layer at
+    /// 50% opacity.
+    fn half_opacity_document(width: u32) -> EditorState {
+        let mut state = EditorState::new(width, 1);
+        for x in 0..width {
+            state.layers[0].set_rgba8(x, 0, [255, 255, 255, 255]);
+        }
+        state.layers[0].set_rgba8(0, 0, [0, 0, 0, 255]);
+        state.add_layer("Top".to_string());
+        state.layers[1].opacity = 0.5;
+        state
+    }
+
+    #[test]
+    fn fill_samples_the_active_layer_by_default() {
+        let state = half_opacity_document(3);
+        assert!(!state.tool_options.fill.sample_merged);
+
+        let mut region = fill_target_region(&state, 2, 0);
+        region.sort();
+        assert_eq!(region, [(0, 0), (1, 0), (2, 0)]);
+    }
 
-        if !changes.is_empty() {
-            state
-                .history
-                .push(crate::state::EditCommand::MultiPixelChange {
-                    layer_index,
-                    changes,
-                });
+    #[test]
+    fn fill_sample_merged_follows_the_composite() {
+        let mut state = half_opacity_document(3);
+        state.tool_options.fill.sample_merged = true;
+
+        let mut region = fill_target_region(&state, 2, 0);
+        region.sort();
+        assert_eq!(region, [(1, 0), (2, 0)]);
+
+        // Only the active layer is painted
+        state.primary_color = Color::from_rgb8(255, 0, 0);
+        let summary = apply_fill(&mut state, 2, 0, None);
+        assert_eq!(summary.map(|summary| summary.changes.len()), Some(2));
+        assert_eq!(state.layers[1].get_rgba8(2, 0), [255, 0, 0, 255]);
+        assert_eq!(state.layers[0].get_rgba8(2, 0), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn eyedropper_picks_the_composite_unless_sampling_the_layer() {
+        let mut state = half_opacity_document(1);
+        state.layers[0].set_rgba8(0, 0, [255, 255, 255, 255]);
+        state.layers[1].set_rgba8(0, 0, [255, 0, 0, 255]);
+
+        apply_eyedropper(&mut state, 0, 0);
+        let [r, g, b, a] = state.primary_color.into_rgba8();
+        assert_eq!((r, a), (255, 255));
+        assert!((127..=128).contains(&g) && g == b);
+
+        state.tool_options.eyedropper.sample_merged = false;
+        apply_eyedropper(&mut state, 0, 0);
+        assert_eq!(state.primary_color.into_rgba8(), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn wrapped_brushes_continue_past_the_edges() {
+        let mut state = EditorState::new(5, 5);
+        let red = Color::from_rgb8(255, 0, 0);
+        apply(&mut state, [Message::BrushSizeChanged(3)]);
+        apply(&mut state, dot(0, 0, red));
+        assert_eq!(state.layers[0].get_rgba8(4, 0), [0; 4]);
+
+        let mut state = EditorState::new(5, 5);
+        apply(
+            &mut state,
+            [Message::BrushSizeChanged(3), Message::WrapEdgesToggled],
+        );
+        apply(&mut state, dot(0, 0, red));
+        let painted: Vec<(u32, u32)> = (0..5)
+            .flat_map(|y| (0..5).map(move |x| (x, y)))
+            .filter(|&(x, y)| state.layers[0].get_rgba8(x, y)[3] > 0)
+            .collect();
+        assert_eq!(
+            painted,
+            [
+                (0, 0),
+                (1, 0),
+                (4, 0),
+                (0, 1),
+                (1, 1),
+                (4, 1),
+                (0, 4),
+                (1, 4),
+                (4, 4)
+            ]
+        );
+    }
+
+    /// Whether `points` form a 2:1 staircase: each row holds exactly two
+    /// consecutive pixels and every row steps one further than the last.
+    fn is_two_to_one_staircase(points: &[(i32, i32)]) -> bool {
+        points.chunks(2).enumerate().all(|(step, run)| {
+            let (x, y) = run[0];
+            run.len() == 2
+                && run[1] == (x + 1, y)
+                && x == points[0].0 + step as i32 * 2
+                && y == points[0].1 + step as i32
+        })
+    }
+
+    #[test]
+    fn roughly_two_to_one_drags_make_an_even_staircase() {
+        for to in [(11, 5), (12, 6), (13, 7)] {
+            let (run, points) = isometric_line((0, 0), to);
+            assert_eq!(run, (2, 1), "to {:?}", to);
+            assert!(
+                is_two_to_one_staircase(&points),
+                "to {:?}: {:?}",
+                to,
+                points
+            );
         }
+
+        // Backwards, the same staircase runs the other way
+        let (run, points) = isometric_line((12, 6), (0, 0));
+        assert_eq!(run, (-2, -1));
+        let mut reversed: Vec<(i32, i32)> = points.iter().map(|&(x, y)| (12 - x, 6 - y)).collect();
+        reversed.sort();
+        assert!(is_two_to_one_staircase(&reversed), "{:?}", points);
+    }
+
+    #[test]
+    fn wobbly_isometric_strokes_stay_on_the_staircase() {
+        let mut state = EditorState::new(16, 8);
+        let mut stroke = vec![
+            Message::IsometricAssistToggled(true),
+            Message::DrawingStarted {
+                x: 0,
+                y: 0,
+                pressure: None,
+            },
+        ];
+        stroke.extend(
+            [(5, 2), (7, 4), (10, 4), (12, 6)].map(|(x, y)| Message::PixelDrawn {
+                x,
+                y,
+                pressure: None,
+            }),
+        );
+        stroke.push(Message::DrawingEnded);
+        apply(&mut state, stroke);
+
+        let mut painted: Vec<(i32, i32)> = (0..8)
+            .flat_map(|y| (0..16).map(move |x| (x, y)))
+            .filter(|&(x, y)| state.layers[0].get_rgba8(x as u32, y as u32)[3] > 0)
+            .collect();
+        painted.sort();
+        assert!(painted.len() >= 8, "{:?}", painted);
+        assert!(is_two_to_one_staircase(&painted), "{:?}", painted);
     }
 }
@@ -0,0 +1,480 @@
+use serde::{Deserialize, Serialize};
+
+/// Resample an RGBA buffer to `new_width` x `new_height` using nearest neighbor.
+///
+/// Each destination pixel samples the source pixel at
+/// `floor(dst * src_size / dst_size)`. When halving, this picks the top-left
+/// pixel of every 2x2 block instead of averaging, so no new colors are ever
+/// introduced into the artwork.
+pub fn scale_nearest(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+) -> Vec<u8> {
+    // Sizes in usize so large targets can't overflow u32 math
+    let (width, height) = (width as usize, height as usize);
+    let (new_width, new_height) = (new_width as usize, new_height as usize);
+    let mut result = vec![0u8; new_width * new_height * 4];
+    if width == 0 || height == 0 {
+        return result;
+    }
+
+    for y in 0..new_height {
+        let src_y = y * height / new_height;
+        for x in 0..new_width {
+            let src_x = x * width / new_width;
+            let src_index = (src_y * width + src_x) * 4;
+            let dst_index = (y * new_width + x) * 4;
+            if src_index + 3 < pixels.len() {
+                result[dst_index..dst_index + 4].copy_from_slice(&pixels[src_index..src_index + 4]);
+            }
+        }
+    }
+
+    result
+}
+
+/// Resampling used when rotating by an arbitrary angle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RotationAlgorithm {
+    NearestNeighbor,
+    /// Upscale 8x with Scale2x, rotate, then sample back down. Keeps pixel-art
+    /// edges cleaner than plain nearest neighbor at odd angles.
+    RotSprite,
+}
+
+impl RotationAlgorithm {
+    pub const ALL: [RotationAlgorithm; 2] = [
+        RotationAlgorithm::NearestNeighbor,
+        RotationAlgorithm::RotSprite,
+    ];
+}
+
+impl std::fmt::Display for RotationAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RotationAlgorithm::NearestNeighbor => write!(f, "Nearest neighbor"),
+            RotationAlgorithm::RotSprite => write!(f, "RotSprite"),
+        }
+    }
+}
+
+/// Sine and cosine of `degrees`, exact for quarter turns so they never pick
+/// up floating point drift.
+fn sin_cos(degrees: f32) -> (f32, f32) {
+    let degrees = degrees.rem_euclid(360.0);
+    if degrees == 0.0 {
+        (0.0, 1.0)
+    } else if degrees == 90.0 {
+        (1.0, 0.0)
+    } else if degrees == 180.0 {
+        (0.0, -1.0)
+    } else if degrees == 270.0 {
+        (-1.0, 0.0)
+    } else {
+        degrees.to_radians().sin_cos()
+    }
+}
+
+/// Rotate the `region` (`start_x, start_y, end_x, end_y`, end exclusive) of an
+/// RGBA layer clockwise by `degrees` around the region's center.
+///
+/// The region is cleared and the rotated content drawn over the result, with
+/// transparent source pixels leaving the cleared area untouched. Content that
+/// lands outside the layer is clipped. Returns the new layer buffer and the
+/// bounds of the rotated content, which may be larger than `region`.
+pub fn rotate_region(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    region: (u32, u32, u32, u32),
+    degrees: f32,
+    algorithm: RotationAlgorithm,
+) -> (Vec<u8>, (u32, u32, u32, u32)) {
+    let (start_x, start_y, end_x, end_y) = region;
+    let region_width = end_x.saturating_sub(start_x);
+    let region_height = end_y.saturating_sub(start_y);
+    let mut result = pixels.to_vec();
+    if region_width == 0 || region_height == 0 {
+        return (result, region);
+    }
+
+    // Copy the source out of the layer and clear it in the result
+    let mut source = Vec::with_capacity((region_width * region_height * 4) as usize);
+    for y in start_y..end_y {
+        let row = ((y * width + start_x) * 4) as usize;
+        let row_end = row + (region_width * 4) as usize;
+        source.extend_from_slice(&pixels[row..row_end]);
+        result[row..row_end].fill(0);
+    }
+
+    let (factor, source) = match algorithm {
+        RotationAlgorithm::NearestNeighbor => (1, source),
+        RotationAlgorithm::RotSprite => {
+            let mut scaled = source;
+            for pass in 0..3 {
+                scaled =
+                    crate::scaling::scale2x(&scaled, region_width << pass, region_height << pass);
+            }
+            (8, scaled)
+        }
+    };
+    let source_width = region_width * factor;
+
+    // Size the destination from the rotated extents, rounded to whole pixels
+    // and centered on the region's center. For quarter turns this lands every
+    // destination pixel center exactly on a source pixel center.
+    let (sin, cos) = sin_cos(degrees);
+    let (w, h) = (region_width as f32, region_height as f32);
+    let rotated_width = ((w * cos.abs() + h * sin.abs()) - 1e-3).ceil().max(1.0);
+    let rotated_height = ((w * sin.abs() + h * cos.abs()) - 1e-3).ceil().max(1.0);
+    let center_x = start_x as f32 + w / 2.0;
+    let center_y = start_y as f32 + h / 2.0;
+    let left = (center_x - rotated_width / 2.0).round();
+    let top = (center_y - rotated_height / 2.0).round();
+    let dest_center_x = left + rotated_width / 2.0;
+    let dest_center_y = top + rotated_height / 2.0;
+
+    let bounds = (
+        left.clamp(0.0, width as f32) as u32,
+        top.clamp(0.0, height as f32) as u32,
+        (left + rotated_width).clamp(0.0, width as f32) as u32,
+        (top + rotated_height).clamp(0.0, height as f32) as u32,
+    );
+
+    for y in bounds.1..bounds.3 {
+        for x in bounds.0..bounds.2 {
+            // Map the destination pixel center back into the source
+            let dx = x as f32 + 0.5 - dest_center_x;
+            let dy = y as f32 + 0.5 - dest_center_y;
+            let u = dx * cos + dy * sin + w / 2.0;
+            let v = -dx * sin + dy * cos + h / 2.0;
+            if u < 0.0 || v < 0.0 || u >= w || v >= h {
+                continue;
+            }
+
+            let sample_x = ((u * factor as f32) as u32).min(source_width - 1);
+            let sample_y = ((v * factor as f32) as u32).min(region_height * factor - 1);
+            let src_index = ((sample_y * source_width + sample_x) * 4) as usize;
+            if source[src_index + 3] == 0 {
+                continue;
+            }
+            let dst_index = ((y * width + x) * 4) as usize;
+            result[dst_index..dst_index + 4].copy_from_slice(&source[src_index..src_index + 4]);
+        }
+    }
+
+    (result, bounds)
+}
+
+/// A quick transform of a whole RGBA buffer, such as the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BufferTransform {
+    FlipHorizontal,
+    FlipVertical,
+    RotateClockwise,
+}
+
+impl BufferTransform {
+    /// Apply to a `width` x `height` buffer, returning the new buffer and its
+    /// size (swapped by rotation).
+    pub fn apply(self, pixels: &[u8], width: u32, height: u32) -> (Vec<u8>, u32, u32) {
+        match self {
+            BufferTransform::FlipHorizontal => {
+                (flip_horizontal(pixels, width, height), width, height)
+            }
+            BufferTransform::FlipVertical => (flip_vertical(pixels, width, height), width, height),
+            BufferTransform::RotateClockwise => {
+                (rotate_clockwise(pixels, width, height), height, width)
+            }
+        }
+    }
+}
+
+/// Mirror an RGBA buffer left to right.
+pub fn flip_horizontal(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut result = Vec::with_capacity(pixels.len());
+    for row in pixels
+        .chunks_exact((width * 4) as usize)
+        .take(height as usize)
+    {
+        for pixel in row.chunks_exact(4).rev() {
+            result.extend_from_slice(pixel);
+        }
+    }
+    result
+}
+
+/// Mirror an RGBA buffer top to bottom.
+pub fn flip_vertical(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut result = Vec::with_capacity(pixels.len());
+    for row in pixels
+        .chunks_exact((width * 4) as usize)
+        .take(height as usize)
+        .rev()
+    {
+        result.extend_from_slice(row);
+    }
+    result
+}
+
+/// Turn an RGBA buffer a quarter turn clockwise. The result is `height`
+/// pixels wide and `width` pixels tall.
+pub fn rotate_clockwise(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut result = vec![0u8; (width * height * 4) as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let src = ((y * width + x) * 4) as usize;
+            let dst = ((x * height + (height - 1 - y)) * 4) as usize;
+            if let Some(pixel) = pixels.get(src..src + 4) {
+                result[dst..dst + 4].copy_from_slice(pixel);
+            }
+        }
+    }
+    result
+}
+
+/// Move a whole RGBA buffer `dx` pixels right and `dy` pixels down, wrapping
+/// what leaves one edge around to the opposite one.
+pub fn offset_wrapped(pixels: &[u8], width: u32, height: u32, dx: i32, dy: i32) -> Vec<u8> {
+    let mut result = vec![0u8; pixels.len()];
+    if width == 0 || height == 0 {
+        return result;
+    }
+    for y in 0..height {
+        let target_y = (y as i64 + dy as i64).rem_euclid(height as i64) as u32;
+        for x in 0..width {
+            let target_x = (x as i64 + dx as i64).rem_euclid(width as i64) as u32;
+            let src = ((y * width + x) * 4) as usize;
+            let dst = ((target_y * width + target_x) * 4) as usize;
+            if let Some(pixel) = pixels.get(src..src + 4) {
+                result[dst..dst + 4].copy_from_slice(pixel);
+            }
+        }
+    }
+    result
+}
+
+/// Whether a line shift moves a row sideways or a column up and down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShiftAxis {
+    Row,
+    Column,
+}
+
+impl std::fmt::Display for ShiftAxis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShiftAxis::Row => write!(f, "Row"),
+            ShiftAxis::Column => write!(f, "Column"),
+        }
+    }
+}
+
+/// Shift row or column `index` of an RGBA buffer by `offset` pixels
+/// (right or down when positive), wrapping around within `span` (`start,
+/// end` along the line, end exclusive). The rest of the buffer is copied
+/// unchanged.
+pub fn shift_line(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    axis: ShiftAxis,
+    index: u32,
+    span: (u32, u32),
+    offset: i32,
+) -> Vec<u8> {
+    let mut result = pixels.to_vec();
+    let (length, lines) = match axis {
+        ShiftAxis::Row => (width, height),
+        ShiftAxis::Column => (height, width),
+    };
+    let (start, end) = (span.0.min(length), span.1.min(length));
+    if index >= lines || start >= end {
+        return result;
+    }
+    let position = |along: u32| -> usize {
+        let (x, y) = match axis {
+            ShiftAxis::Row => (along, index),
+            ShiftAxis::Column => (index, along),
+        };
+        ((y * width + x) * 4) as usize
+    };
+
+    let count = (end - start) as i64;
+    for along in start..end {
+        let source = start as i64 + (along as i64 - start as i64 - offset as i64).rem_euclid(count);
+        let (from, to) = (position(source as u32), position(along));
+        if let Some(pixel) = pixels.get(from..from + 4) {
+            result[to..to + 4].copy_from_slice(pixel);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_scaling_repeats_and_drops_pixels() {
+        // 2x1: red, blue
+        let pixels = [255, 0, 0, 255, 0, 0, 255, 255];
+        let doubled = scale_nearest(&pixels, 2, 1, 4, 2);
+        let row = [
+            255, 0, 0, 255, 255, 0, 0, 255, 0, 0, 255, 255, 0, 0, 255, 255,
+        ];
+        assert_eq!(doubled, [row, row].concat());
+
+        let halved = scale_nearest(&doubled, 4, 2, 2, 1);
+        assert_eq!(halved, pixels);
+    }
+
+    /// A `width` x `height` buffer where every pixel has its own color.
+    fn numbered(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [i as u8, 100, 200 - i as u8, 255])
+            .collect()
+    }
+
+    #[test]
+    fn quarter_turns_match_the_exact_rotation() {
+        let square = numbered(4, 4);
+        let (rotated, bounds) = rotate_region(
+            &square,
+            4,
+            4,
+            (0, 0, 4, 4),
+            90.0,
+            RotationAlgorithm::NearestNeighbor,
+        );
+        assert_eq!(rotated, rotate_clockwise(&square, 4, 4));
+        assert_eq!(bounds, (0, 0, 4, 4));
+
+        // A 4x2 region in the middle of a 6x6 layer turns into a 2x4 one
+        // around the same center
+        let mut layer = vec![0; 6 * 6 * 4];
+        let source = numbered(4, 2);
+        for (row, pixels) in source.chunks_exact(4 * 4).enumerate() {
+            let start = ((2 + row) * 6 + 1) * 4;
+            layer[start..start + 16].copy_from_slice(pixels);
+        }
+        let (rotated, bounds) = rotate_region(
+            &layer,
+            6,
+            6,
+            (1, 2, 5, 4),
+            90.0,
+            RotationAlgorithm::NearestNeighbor,
+        );
+        let mut expected = vec![0; 6 * 6 * 4];
+        for (row, pixels) in rotate_clockwise(&source, 4, 2)
+            .chunks_exact(2 * 4)
+            .enumerate()
+        {
+            let start = ((1 + row) * 6 + 2) * 4;
+            expected[start..start + 8].copy_from_slice(pixels);
+        }
+        assert_eq!(rotated, expected);
+        assert_eq!(bounds, (2, 1, 4, 5));
+    }
+
+    #[test]
+    fn eighth_turns_make_a_diamond() {
+        let red = [255, 0, 0, 255];
+        let mut layer = vec![0; 5 * 5 * 4];
+        for y in 1..4 {
+            for x in 1..4 {
+                let start = (y * 5 + x) * 4;
+                layer[start..start + 4].copy_from_slice(&red);
+            }
+        }
+        for algorithm in RotationAlgorithm::ALL {
+            let (rotated, bounds) = rotate_region(&layer, 5, 5, (1, 1, 4, 4), 45.0, algorithm);
+            let mask: Vec<String> = rotated
+                .chunks_exact(5 * 4)
+                .map(|row| {
+                    row.chunks_exact(4)
+                        .map(|pixel| match pixel {
+                            [0, 0, 0, 0] => '.',
+                            pixel if pixel == red => '#',
+                            _ => '?',
+                        })
+                        .collect()
+                })
+                .collect();
+            assert_eq!(
+                mask,
+                ["..#..", ".###.", "#####", ".###.", "..#.."],
+                "{:?}",
+                algorithm
+            );
+            assert_eq!(bounds, (0, 0, 5, 5));
+        }
+    }
+
+    #[test]
+    fn clipboard_transforms_handle_non_square_buffers() {
+        // 3x2, labelled by the red channel:
+        //   0 1 2
+        //   3 4 5
+        let pixels = numbered(3, 2);
+        let labels = |pixels: &[u8]| -> Vec<u8> { pixels.chunks_exact(4).map(|p| p[0]).collect() };
+
+        let (flipped, width, height) = BufferTransform::FlipHorizontal.apply(&pixels, 3, 2);
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(labels(&flipped), [2, 1, 0, 5, 4, 3]);
+
+        let (flipped, width, height) = BufferTransform::FlipVertical.apply(&pixels, 3, 2);
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(labels(&flipped), [3, 4, 5, 0, 1, 2]);
+
+        // A quarter turn clockwise leaves a 2x3 buffer:
+        //   3 0
+        //   4 1
+        //   5 2
+        let (turned, width, height) = BufferTransform::RotateClockwise.apply(&pixels, 3, 2);
+        assert_eq!((width, height), (2, 3));
+        assert_eq!(labels(&turned), [3, 0, 4, 1, 5, 2]);
+
+        let (mut buffer, mut width, mut height) = (pixels.clone(), 3, 2);
+        for _ in 0..4 {
+            (buffer, width, height) =
+                BufferTransform::RotateClockwise.apply(&buffer, width, height);
+        }
+        assert_eq!((buffer, width, height), (pixels, 3, 2));
+    }
+
+    #[test]
+    fn line_shifts_wrap_within_their_span() {
+        // 4x3, labelled 0..12 row by row
+        let pixels = numbered(4, 3);
+        let labels = |pixels: &[u8]| -> Vec<u8> { pixels.chunks_exact(4).map(|p| p[0]).collect() };
+
+        let shifted = shift_line(&pixels, 4, 3, ShiftAxis::Row, 1, (0, 4), 1);
+        assert_eq!(labels(&shifted), [0, 1, 2, 3, 7, 4, 5, 6, 8, 9, 10, 11]);
+
+        // Offsets past the length wrap, and negative ones go the other way
+        assert_eq!(
+            shift_line(&pixels, 4, 3, ShiftAxis::Row, 1, (0, 4), 5),
+            shifted
+        );
+        let back = shift_line(&pixels, 4, 3, ShiftAxis::Row, 1, (0, 4), -3);
+        assert_eq!(back, shifted);
+
+        let shifted = shift_line(&pixels, 4, 3, ShiftAxis::Column, 2, (0, 3), -1);
+        assert_eq!(labels(&shifted), [0, 1, 6, 3, 4, 5, 10, 7, 8, 9, 2, 11]);
+
+        // With a selection only the span inside it moves
+        let shifted = shift_line(&pixels, 4, 3, ShiftAxis::Row, 0, (1, 3), 1);
+        assert_eq!(labels(&shifted), [0, 2, 1, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+
+        // Lines off the buffer leave it alone
+        assert_eq!(
+            shift_line(&pixels, 4, 3, ShiftAxis::Row, 3, (0, 4), 1),
+            pixels
+        );
+    }
+}
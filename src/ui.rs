@@ -1,29 +1,72 @@
+use crate::app::App;
 use crate::canvas::CanvasProgram;
-use crate::message::{ExportFormat, Message};
-use crate::state::{EditorState, Tool};
+use crate::color_picker::{HueStrip, SaturationValueSquare};
+use crate::filters::{Filter, NoiseMode};
+use crate::font::BitmapFont;
+use crate::message::{ExportFormat, ExportUpscale, Message};
+use crate::palette::PaletteSort;
+use crate::selection::SelectionMode;
+use crate::state::{
+    ColorPickerState, ColorTarget, ColorVision, CompareView, EditorState, ExportProfile,
+    ExportScope, ExportSettings, HISTORY_MEMORY_BUDGET, JitterOptions, MAX_HISTORY_COMMANDS,
+    MAX_HUE_JITTER, MAX_RESTORE_POINTS, MAX_VALUE_JITTER, PrintOptions,
+    RESTORE_POINT_MEMORY_BUDGET, RampGenerator, RampShift, RawExportOptions, SafeAreaPreset,
+    ScaleFactor, Tool, UsedColor, UsedColorSort, WheelBrushModifier,
+};
+use crate::transform::{BufferTransform, RotationAlgorithm};
 use iced::widget;
 use iced::{Alignment, Color, Element, Length};
 
-pub fn view(state: &EditorState) -> Element<'_, Message> {
-    let mut canvas_program = CanvasProgram::new(state.clone());
-    canvas_program.update_state(state.clone());
+pub fn view(app: &App) -> Element<'_, Message> {
+    let state = app.active_document();
+    let canvas_program = CanvasProgram::new(state, app.scale_factor);
 
-    widget::column![
+    if state.kiosk_mode {
+        return widget::column![
+            kiosk_toolbar(state),
+            notification_bar(state),
+            widget::row![
+                kiosk_sidebar(state),
+                widget::container(
+                    iced::widget::canvas(canvas_program)
+                        .width(Length::Fill)
+                        .height(Length::Fill)
+                )
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .style(widget::container::rounded_box),
+            ]
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .spacing(10)
+            .padding(10),
+        ]
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .into();
+    }
+
+    let main = widget::column![
         // Top toolbar
         toolbar(state),
+        notification_bar(state),
         // Main content area
         widget::row![
             // Left sidebar
             left_sidebar(state),
             // Canvas area
-            widget::container(
-                iced::widget::canvas(canvas_program)
-                    .width(Length::Fill)
-                    .height(Length::Fill)
-            )
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .style(widget::container::rounded_box),
+            widget::column![
+                document_tabs(app),
+                widget::container(
+                    iced::widget::canvas(canvas_program)
+                        .width(Length::Fill)
+                        .height(Length::Fill)
+                )
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .style(widget::container::rounded_box),
+            ]
+            .spacing(5),
             // Right sidebar
             right_sidebar(state),
         ]
@@ -31,23 +74,209 @@ pub fn view(state: &EditorState) -> Element<'_, Message> {
         .height(Length::Fill)
         .spacing(10)
         .padding(10),
+        status_bar(state),
+    ]
+    .width(Length::Fill)
+    .height(Length::Fill);
+
+    let Some(picker) = &state.color_picker else {
+        return main.into();
+    };
+    // Clicks on the dimmed backdrop close the popup; the card itself
+    // swallows clicks so they do not reach the backdrop
+    widget::stack![
+        main,
+        widget::mouse_area(
+            widget::container(widget::text(""))
+                .style(|_theme| widget::container::Style {
+                    background: Some(Color::from_rgba(0.0, 0.0, 0.0, 0.4).into()),
+                    ..Default::default()
+                })
+                .width(Length::Fill)
+                .height(Length::Fill),
+        )
+        .on_press(Message::ColorPickerCancelled),
+        widget::container(widget::mouse_area(color_picker_popup(picker)).on_press(Message::None))
+            .center(Length::Fill),
+    ]
+    .into()
+}
+
+fn status_bar(state: &EditorState) -> Element<'_, Message> {
+    let primary = state.primary_color.into_rgba8();
+    let ramp_text = match crate::palette::ramp_position(&state.ramps, primary) {
+        Some((ramp, index)) => format!(
+            "Ramp {}: {}/{}{}",
+            ramp + 1,
+            index + 1,
+            state.ramps[ramp].len(),
+            if state.ramps[ramp][index] == primary {
+                ""
+            } else {
+                " (nearest)"
+            }
+        ),
+        None => String::new(),
+    };
+
+    let mirror_text = match (state.mirror_horizontal, state.mirror_vertical) {
+        (true, true) => "Mirror: H V",
+        (true, false) => "Mirror: H",
+        (false, true) => "Mirror: V",
+        (false, false) => "",
+    };
+
+    let selection_text = match state.selection_mode() {
+        SelectionMode::Add if state.current_tool == Tool::Selection => "Selection: add",
+        SelectionMode::Subtract if state.current_tool == Tool::Selection => "Selection: subtract",
+        _ => "",
+    };
+
+    widget::container(
+        widget::row![
+            widget::text(format!("{}x{}", state.canvas_width, state.canvas_height)).size(12),
+            widget::text(if state.preserve_transparency {
+                "Preserve transparency"
+            } else {
+                ""
+            })
+            .size(12),
+            widget::text(mirror_text).size(12),
+            widget::text(selection_text).size(12),
+            widget::text(if state.exporting { "Exporting…" } else { "" }).size(12),
+            widget::horizontal_space(),
+            widget::text(ramp_text).size(12),
+        ]
+        .spacing(10),
+    )
+    .padding([2, 10])
+    .width(Length::Fill)
+    .into()
+}
+
+fn document_tabs(app: &App) -> Element<'_, Message> {
+    let mut tabs = widget::row![].spacing(5).align_y(Alignment::Center);
+    for (index, document) in app.documents.iter().enumerate() {
+        let label = if document.is_dirty() {
+            format!("{} *", document.display_name())
+        } else {
+            document.display_name()
+        };
+        let style = if index == app.active {
+            widget::button::primary
+        } else {
+            widget::button::secondary
+        };
+        tabs = tabs.push(
+            widget::row![
+                widget::button(widget::text(label).size(12))
+                    .on_press(Message::DocumentSelected(index))
+                    .style(style),
+                widget::button(widget::text("x").size(12))
+                    .on_press(Message::DocumentCloseRequested(index))
+                    .style(widget::button::text),
+            ]
+            .align_y(Alignment::Center),
+        );
+    }
+    tabs = tabs.push(widget::button(widget::text("+").size(12)).on_press(Message::DocumentAdded));
+
+    widget::row![
+        widget::scrollable(tabs)
+            .direction(widget::scrollable::Direction::Horizontal(
+                widget::scrollable::Scrollbar::new()
+                    .width(4)
+                    .scroller_width(4),
+            ))
+            .width(Length::Fill),
+        if app.recording.is_some() {
+            widget::button(widget::text("Stop recording...").size(12))
+                .on_press(Message::RecordingStopped)
+                .style(widget::button::danger)
+        } else {
+            widget::button(widget::text("Record session").size(12))
+                .on_press(Message::RecordingStarted)
+        },
+        widget::button(widget::text("Replay session...").size(12))
+            .on_press(Message::ReplayRequested),
+        widget::checkbox("Reopen last session", app.reopen_last_session)
+            .on_toggle(Message::ReopenSessionToggled)
+            .size(14)
+            .text_size(12),
     ]
+    .spacing(10)
+    .align_y(Alignment::Center)
+    .into()
+}
+
+fn notification_bar(state: &EditorState) -> Element<'_, Message> {
+    let Some(notification) = &state.notification else {
+        return widget::vertical_space().height(0).into();
+    };
+
+    // The hidden-layer warning offers to show the layer, as long as it is
+    // still the notice on screen
+    let show_layer: Element<'_, Message> = match &state.hidden_layer_warning {
+        Some((index, text)) if text == notification => widget::button("Show layer")
+            .on_press(Message::HiddenLayerShown(*index))
+            .style(widget::button::primary)
+            .into(),
+        _ => widget::horizontal_space().width(0).into(),
+    };
+
+    widget::container(
+        widget::row![
+            widget::text(notification).color(Color::from_rgb(0.9, 0.5, 0.1)),
+            widget::horizontal_space(),
+            show_layer,
+            widget::button("Dismiss")
+                .on_press(Message::NotificationDismissed)
+                .style(widget::button::secondary),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center),
+    )
+    .padding([5, 10])
     .width(Length::Fill)
-    .height(Length::Fill)
     .into()
 }
 
 fn toolbar(state: &EditorState) -> Element<'_, Message> {
+    let simulation_indicator: Element<'_, Message> = if state.color_vision == ColorVision::Normal {
+        widget::horizontal_space().width(0).into()
+    } else {
+        widget::text(format!("Simulating {}", state.color_vision))
+            .color(Color::from_rgb(0.9, 0.5, 0.1))
+            .into()
+    };
+
     widget::row![
         widget::button("New").on_press(Message::FileNew),
         widget::button("Open").on_press(Message::FileOpen),
-        widget::button("Save").on_press(Message::FileSave),
-        widget::pick_list(
-            [ExportFormat::Png, ExportFormat::Gif, ExportFormat::Bmp].as_slice(),
-            Some(state.selected_export_format),
-            Message::ExportFormatSelected,
+        widget::button("Import folder...").on_press_maybe(
+            state
+                .folder_import
+                .is_none()
+                .then_some(Message::FolderImportRequested)
         ),
+        widget::button(widget::text(format!("Save {}", state.export.format)))
+            .on_press(Message::FileSave),
+        widget::button("Preserve transparency (/)")
+            .on_press(Message::PreserveTransparencyToggled)
+            .style(if state.preserve_transparency {
+                widget::button::primary
+            } else {
+                widget::button::secondary
+            }),
         widget::horizontal_space(),
+        widget::button("Drawing mode").on_press(Message::KioskModeEntered),
+        view_orientation(state),
+        simulation_indicator,
+        widget::pick_list(
+            ColorVision::ALL.as_slice(),
+            Some(state.color_vision),
+            Message::ColorVisionChanged,
+        ),
         widget::text(format!("Zoom: {:.0}%", state.zoom_level * 100.0 / 8.0)),
         widget::slider(1.0..=32.0, state.zoom_level, Message::ZoomChanged),
         widget::button("+").on_press(Message::ZoomIn),
@@ -59,17 +288,432 @@ fn toolbar(state: &EditorState) -> Element<'_, Message> {
     .into()
 }
 
+/// Tools offered in drawing mode.
+const KIOSK_TOOLS: [(Tool, &str); 5] = [
+    (Tool::Pencil, "Pencil"),
+    (Tool::Eraser, "Eraser"),
+    (Tool::Fill, "Fill"),
+    (Tool::Line, "Line"),
+    (Tool::Ellipse, "Ellipse"),
+];
+
+/// The fixed swatches of drawing mode (the PICO-8 palette).
+const KIOSK_PALETTE: [[u8; 4]; 16] = [
+    [0, 0, 0, 255],
+    [29, 43, 83, 255],
+    [126, 37, 83, 255],
+    [0, 135, 81, 255],
+    [171, 82, 54, 255],
+    [95, 87, 79, 255],
+    [194, 195, 199, 255],
+    [255, 241, 232, 255],
+    [255, 0, 77, 255],
+    [255, 163, 0, 255],
+    [255, 236, 39, 255],
+    [0, 228, 54, 255],
+    [41, 173, 255, 255],
+    [131, 118, 156, 255],
+    [255, 119, 168, 255],
+    [255, 204, 170, 255],
+];
+
+fn kiosk_toolbar(state: &EditorState) -> Element<'_, Message> {
+    // Leaving takes a deliberate press and hold, so stray taps can't
+    let unlock = widget::mouse_area(
+        widget::container(widget::text("Hold to unlock").size(12))
+            .padding([4, 8])
+            .style(widget::container::bordered_box),
+    )
+    .on_press(Message::KioskUnlockPressed)
+    .on_release(Message::KioskUnlockReleased);
+
+    widget::row![
+        widget::button("Undo").on_press(Message::Undo),
+        widget::button("Redo").on_press(Message::Redo),
+        widget::button(widget::text(format!("Save {}", state.export.format)))
+            .on_press(Message::FileSave),
+        widget::horizontal_space(),
+        unlock,
+    ]
+    .spacing(10)
+    .padding(10)
+    .align_y(Alignment::Center)
+    .into()
+}
+
+fn kiosk_sidebar(state: &EditorState) -> Element<'_, Message> {
+    let tools = KIOSK_TOOLS
+        .iter()
+        .fold(widget::column![].spacing(5), |column, &(tool, label)| {
+            column.push(
+                widget::button(label)
+                    .width(Length::Fill)
+                    .on_press(Message::ToolSelected(tool))
+                    .style(if state.current_tool == tool {
+                        widget::button::primary
+                    } else {
+                        widget::button::secondary
+                    }),
+            )
+        });
+
+    let primary = state.primary_color.into_rgba8();
+    let swatches = KIOSK_PALETTE
+        .chunks(4)
+        .fold(widget::column![].spacing(5), |column, row| {
+            column.push(row.iter().fold(widget::row![].spacing(5), |row, &rgba| {
+                let color = crate::utils::rgba8_to_color(rgba);
+                let selected = rgba == primary;
+                row.push(
+                    widget::button(widget::text(""))
+                        .width(Length::Fill)
+                        .height(Length::Fixed(32.0))
+                        .on_press(Message::PrimaryColorChanged(color))
+                        .style(move |_theme, _status| widget::button::Style {
+                            background: Some(color.into()),
+                            border: iced::border::Border {
+                                radius: iced::border::Radius::from(5.0),
+                                width: if selected { 3.0 } else { 1.0 },
+                                color: Color::BLACK,
+                            },
+                            ..Default::default()
+                        }),
+                )
+            }))
+        });
+
+    widget::container(widget::scrollable(
+        widget::column![
+            widget::text("Tools").size(16),
+            tools,
+            widget::horizontal_rule(10),
+            widget::text(format!("Brush Size: {}px", state.brush_size())).size(16),
+            widget::slider(1.0..=20.0, state.brush_size() as f32, |v| {
+                Message::BrushSizeChanged(v as u32)
+            }),
+            widget::horizontal_rule(10),
+            widget::text("Color").size(16),
+            swatches,
+        ]
+        .spacing(10)
+        .padding(iced::Padding::new(10.0).right(20.0)),
+    ))
+    .width(Length::Fixed(200.0))
+    .into()
+}
+
+fn view_orientation(state: &EditorState) -> Element<'_, Message> {
+    let mut row = widget::row![
+        widget::button("Flip view").on_press(Message::ViewFlipped),
+        widget::button("Rotate view").on_press(Message::ViewRotated),
+    ]
+    .spacing(5)
+    .align_y(Alignment::Center);
+
+    if state.view_flipped || state.view_rotation != 0 {
+        let mut description = Vec::new();
+        if state.view_flipped {
+            description.push("flipped".to_string());
+        }
+        if state.view_rotation != 0 {
+            description.push(format!("rotated {}°", state.view_rotation as u32 * 90));
+        }
+        row = row
+            .push(
+                widget::text(format!("View {}", description.join(", ")))
+                    .color(Color::from_rgb(0.9, 0.5, 0.1)),
+            )
+            .push(widget::button("Reset view").on_press(Message::ViewReset));
+    }
+    row.into()
+}
+
+fn document_stats(state: &EditorState) -> Element<'_, Message> {
+    let megabytes = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+    let history = &state.history;
+    let scanned = match state.document_scan {
+        Some(scan) => {
+            let stale = if scan.revision == history.revision {
+                ""
+            } else {
+                " (out of date)"
+            };
+            format!(
+                "Non-transparent pixels: {}{}\nUnique colors: {}{}",
+                scan.opaque_pixels, stale, scan.unique_colors, stale
+            )
+        }
+        None => "Non-transparent pixels: -\nUnique colors: -".to_string(),
+    };
+    widget::column![
+        widget::text(format!(
+            "Canvas: {}x{}\nLayers: {}\nMemory: {:.1} MB (history {:.1} of {:.0} MB)\nHistory: {} of {} steps",
+            state.canvas_width,
+            state.canvas_height,
+            state.layers.len(),
+            megabytes(state.memory_usage()),
+            megabytes(history.memory_size()),
+            megabytes(HISTORY_MEMORY_BUDGET),
+            history.commands.len(),
+            MAX_HISTORY_COMMANDS
+        ))
+        .size(12),
+        widget::text(scanned).size(12),
+        widget::button(if state.document_scan_pending {
+            "Scanning..."
+        } else {
+            "Refresh"
+        })
+        .on_press_maybe((!state.document_scan_pending).then_some(Message::DocumentScanRequested)),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn restore_points_control(state: &EditorState) -> Element<'_, Message> {
+    let mut points = widget::column![].spacing(5);
+    for (index, point) in state.restore_points.iter().enumerate().rev() {
+        points = points.push(
+            widget::row![
+                widget::text(format!(
+                    "{} ({}x{}, {} KB)",
+                    point.name,
+                    point.snapshot.width,
+                    point.snapshot.height,
+                    point.memory_size().div_ceil(1024)
+                ))
+                .size(12)
+                .width(Length::Fill),
+                widget::button(widget::text("Restore").size(12))
+                    .on_press(Message::RestorePointRestored(index)),
+                widget::button(widget::text("x").size(12))
+                    .on_press(Message::RestorePointDeleted(index))
+                    .style(widget::button::danger),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center),
+        );
+    }
+    widget::column![
+        widget::row![
+            widget::text_input("Name", &state.restore_point_name)
+                .on_input(Message::RestorePointNameChanged)
+                .on_submit(Message::RestorePointCreated)
+                .size(12),
+            widget::button(widget::text("Create snapshot").size(12))
+                .on_press(Message::RestorePointCreated),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+        points,
+        widget::text(format!(
+            "{} of {} kept, {:.1} of {} MB",
+            state.restore_points.len(),
+            MAX_RESTORE_POINTS,
+            state.restore_points_memory() as f64 / (1024.0 * 1024.0),
+            RESTORE_POINT_MEMORY_BUDGET / (1024 * 1024)
+        ))
+        .size(12),
+        widget::checkbox("Save with the layered PNG data", state.save_restore_points)
+            .on_toggle(Message::RestorePointsSavedToggled),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn export_panel(state: &EditorState) -> Element<'_, Message> {
+    let settings = state.export;
+    widget::column![
+        widget::pick_list(
+            ExportFormat::ALL.as_slice(),
+            Some(settings.format),
+            move |format| { Message::ExportSettingsChanged(ExportSettings { format, ..settings }) }
+        ),
+        widget::row![
+            widget::pick_list(
+                ExportUpscale::ALL.as_slice(),
+                Some(settings.upscale),
+                move |upscale| Message::ExportSettingsChanged(ExportSettings {
+                    upscale,
+                    ..settings
+                }),
+            ),
+            widget::pick_list(
+                ExportScope::ALL.as_slice(),
+                Some(settings.scope),
+                move |scope| {
+                    Message::ExportSettingsChanged(ExportSettings { scope, ..settings })
+                }
+            ),
+        ]
+        .spacing(5),
+        export_options(settings),
+        matte_control(settings, state.primary_color),
+    ]
+    .push_maybe(state.solo_index().map(|index| {
+        let name = &state.layers[index].name;
+        widget::column![
+            widget::text(if settings.ignore_solo {
+                format!("Solo: \"{}\" is soloed, but exports show every layer", name)
+            } else {
+                format!("Solo: exports show only \"{}\"", name)
+            })
+            .size(12),
+            widget::checkbox("Ignore solo", settings.ignore_solo).on_toggle(move |ignore_solo| {
+                Message::ExportSettingsChanged(ExportSettings {
+                    ignore_solo,
+                    ..settings
+                })
+            }),
+        ]
+        .spacing(5)
+    }))
+    .spacing(5)
+    .into()
+}
+
+/// Options that only apply to the chosen format.
+fn export_options<'a>(settings: ExportSettings) -> Element<'a, Message> {
+    match settings.format {
+        ExportFormat::Ico => widget::checkbox("16/32/48 sizes", settings.ico_multi_size)
+            .on_toggle(move |ico_multi_size| {
+                Message::ExportSettingsChanged(ExportSettings {
+                    ico_multi_size,
+                    ..settings
+                })
+            })
+            .into(),
+        ExportFormat::Svg => widget::row![
+            widget::text(format!("Unit: {}", settings.svg_unit_size)).size(12),
+            widget::slider(1.0..=64.0, settings.svg_unit_size as f32, move |v| {
+                Message::ExportSettingsChanged(ExportSettings {
+                    svg_unit_size: v as u32,
+                    ..settings
+                })
+            }),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center)
+        .into(),
+        ExportFormat::Png => {
+            let print = settings.print;
+            let print_changed = move |print: PrintOptions| {
+                Message::ExportSettingsChanged(ExportSettings { print, ..settings })
+            };
+            widget::column![
+                widget::row![
+                    widget::text(format!("Scale: {}x", print.scale)).size(12),
+                    widget::slider(1.0..=32.0, print.scale as f32, move |v| {
+                        print_changed(PrintOptions {
+                            scale: v as u32,
+                            ..print
+                        })
+                    }),
+                ]
+                .spacing(5)
+                .align_y(Alignment::Center),
+                widget::row![
+                    widget::checkbox("Grid", print.grid)
+                        .on_toggle(move |grid| print_changed(PrintOptions { grid, ..print })),
+                    widget::checkbox("Index #", print.index_numbers).on_toggle(
+                        move |index_numbers| print_changed(PrintOptions {
+                            index_numbers,
+                            ..print
+                        })
+                    ),
+                ]
+                .spacing(5),
+                widget::button(widget::text("Pattern sheet...").size(12))
+                    .on_press(Message::PatternSheetExportRequested),
+            ]
+            .spacing(5)
+            .into()
+        }
+        ExportFormat::Rgb565 | ExportFormat::Mono => {
+            let raw = settings.raw;
+            let raw_changed = move |raw: RawExportOptions| {
+                Message::ExportSettingsChanged(ExportSettings { raw, ..settings })
+            };
+            let format_option: Element<'_, Message> = if settings.format == ExportFormat::Rgb565 {
+                widget::checkbox("Big-endian", raw.big_endian)
+                    .on_toggle(move |big_endian| {
+                        raw_changed(RawExportOptions { big_endian, ..raw })
+                    })
+                    .into()
+            } else {
+                widget::row![
+                    widget::text(format!("Threshold: {}", raw.threshold)).size(12),
+                    widget::slider(0.0..=255.0, raw.threshold as f32, move |v| {
+                        raw_changed(RawExportOptions {
+                            threshold: v as u8,
+                            ..raw
+                        })
+                    }),
+                ]
+                .spacing(5)
+                .align_y(Alignment::Center)
+                .into()
+            };
+            widget::column![
+                format_option,
+                widget::checkbox("C header", raw.c_header).on_toggle(move |c_header| {
+                    raw_changed(RawExportOptions { c_header, ..raw })
+                }),
+            ]
+            .spacing(5)
+            .into()
+        }
+        _ => widget::horizontal_space().width(0).into(),
+    }
+}
+
+fn matte_control<'a>(settings: ExportSettings, primary: Color) -> Element<'a, Message> {
+    let matte_color = crate::utils::rgba8_to_color(settings.matte_color);
+    widget::row![
+        widget::checkbox("Matte", settings.matte_enabled).on_toggle(move |matte_enabled| {
+            Message::ExportSettingsChanged(ExportSettings {
+                matte_enabled,
+                ..settings
+            })
+        }),
+        // Clicking the swatch takes the primary color
+        widget::button(
+            widget::container(widget::text(""))
+                .width(Length::Fixed(18.0))
+                .height(Length::Fixed(18.0))
+                .style(move |_theme| widget::container::Style {
+                    background: Some(matte_color.into()),
+                    border: iced::border::Border {
+                        radius: iced::border::Radius::from(2.0),
+                        width: 1.0,
+                        color: Color::BLACK,
+                    },
+                    ..Default::default()
+                }),
+        )
+        .on_press(Message::ExportSettingsChanged(ExportSettings {
+            matte_color: crate::utils::color_to_rgba8(primary),
+            ..settings
+        }))
+        .padding(0),
+        widget::button("Flatten").on_press(Message::FlattenAgainstMatte),
+    ]
+    .spacing(5)
+    .align_y(Alignment::Center)
+    .into()
+}
+
 fn left_sidebar(state: &EditorState) -> Element<'_, Message> {
     widget::container(widget::scrollable(
         widget::column![
             widget::text("Tools").size(16),
             tool_buttons(state),
             widget::horizontal_rule(10),
-            widget::text("Brush Size").size(16),
-            brush_size_control(state),
+            widget::text("Tool options").size(16),
+            tool_options_section(state),
             widget::horizontal_rule(10),
             widget::text("Color").size(16),
-            color_picker(state),
+            color_buttons(state),
             widget::horizontal_rule(10),
             widget::text("Layers").size(16),
             layer_list(state),
@@ -113,155 +757,461 @@ fn tool_buttons(state: &EditorState) -> Element<'_, Message> {
             "Eyedropper"
         })
         .on_press(Message::ToolSelected(Tool::Eyedropper)),
+        widget::button(if state.current_tool == Tool::Text {
+            "[T] Text"
+        } else {
+            "Text"
+        })
+        .on_press(Message::ToolSelected(Tool::Text)),
+        widget::button(if state.current_tool == Tool::Line {
+            "[L] Line"
+        } else {
+            "Line"
+        })
+        .on_press(Message::ToolSelected(Tool::Line)),
+        widget::button(if state.current_tool == Tool::Rectangle {
+            "[R] Rectangle"
+        } else {
+            "Rectangle"
+        })
+        .on_press(Message::ToolSelected(Tool::Rectangle)),
+        widget::button(if state.current_tool == Tool::Ellipse {
+            "[O] Ellipse"
+        } else {
+            "Ellipse"
+        })
+        .on_press(Message::ToolSelected(Tool::Ellipse)),
+        widget::button(if state.current_tool == Tool::Curve {
+            "[C] Curve"
+        } else {
+            "Curve"
+        })
+        .on_press(Message::ToolSelected(Tool::Curve)),
+        widget::button(if state.current_tool == Tool::Move {
+            "[M] Move"
+        } else {
+            "Move"
+        })
+        .on_press(Message::ToolSelected(Tool::Move)),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn sample_merged_control(tool: Tool, enabled: bool) -> Element<'static, Message> {
+    widget::column![
+        widget::checkbox("Sample merged", enabled)
+            .on_toggle(move |enabled| Message::SampleMergedToggled(tool, enabled)),
+        widget::text(if enabled {
+            "Reads colors as shown, across all visible layers"
+        } else {
+            "Reads the active layer's own colors"
+        })
+        .size(12),
     ]
     .spacing(5)
     .into()
 }
 
-fn brush_size_control(state: &EditorState) -> Element<'_, Message> {
+/// Options for the current tool only; each group appears under the tools it
+/// affects.
+fn tool_options_section(state: &EditorState) -> Element<'_, Message> {
+    let options = &state.tool_options;
+    let controls: Vec<Element<'_, Message>> = match state.current_tool {
+        Tool::Pencil | Tool::Eraser => vec![
+            brush_controls(state),
+            widget::checkbox(
+                "Pen pressure sets size",
+                options.freehand.pressure_sensitive,
+            )
+            .on_toggle(Message::PressureSensitivityToggled)
+            .into(),
+            widget::checkbox("Isometric lines", options.freehand.isometric_assist)
+                .on_toggle(Message::IsometricAssistToggled)
+                .into(),
+            stabilizer_control(state),
+            pixel_snap_control(state),
+        ]
+        .into_iter()
+        .chain((state.current_tool == Tool::Pencil).then(|| jitter_control(state)))
+        .collect(),
+        Tool::Line | Tool::Rectangle | Tool::Ellipse => {
+            vec![brush_controls(state), pixel_snap_control(state)]
+        }
+        Tool::Curve => vec![
+            brush_controls(state),
+            pixel_snap_control(state),
+            widget::checkbox("Pixel-perfect corners", options.curve.pixel_perfect)
+                .on_toggle(Message::PixelPerfectCurvesToggled)
+                .into(),
+            widget::text("Drag to set the ends, then drag again to bend; Escape cancels")
+                .size(12)
+                .into(),
+        ],
+        Tool::Fill => vec![
+            widget::checkbox("Pattern (tile clipboard)", options.fill.pattern)
+                .on_toggle(Message::FillPatternToggled)
+                .into(),
+            widget::checkbox("Preview fill region", options.fill.preview)
+                .on_toggle(Message::FillPreviewToggled)
+                .into(),
+            sample_merged_control(Tool::Fill, options.fill.sample_merged),
+            pixel_snap_control(state),
+        ],
+        Tool::Eyedropper => vec![sample_merged_control(
+            Tool::Eyedropper,
+            options.eyedropper.sample_merged,
+        )],
+        Tool::Text => vec![
+            widget::row![
+                widget::text("Font:"),
+                widget::horizontal_space(),
+                widget::pick_list(
+                    BitmapFont::ALL,
+                    Some(options.text.font),
+                    Message::TextFontSelected
+                ),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center)
+            .into(),
+            pixel_snap_control(state),
+            widget::text("Click to place, type, Enter to commit, Escape to cancel")
+                .size(12)
+                .into(),
+        ],
+        Tool::Selection | Tool::Move => {
+            vec![widget::text("No options for this tool").size(12).into()]
+        }
+    };
+    widget::column(controls).spacing(5).into()
+}
+
+/// Size and opacity of the current tool, and the wheel modifier that
+/// changes the size.
+fn brush_controls(state: &EditorState) -> Element<'_, Message> {
     widget::column![
         widget::row![
             widget::text("Size:"),
             widget::horizontal_space(),
-            widget::text(format!("{}px", state.brush_size)),
+            widget::text(format!("{}px", state.brush_size())),
         ]
         .spacing(5)
         .align_y(Alignment::Center),
-        widget::slider(1.0..=20.0, state.brush_size as f32, |v| {
+        widget::slider(1.0..=20.0, state.brush_size() as f32, |v| {
             Message::BrushSizeChanged(v as u32)
         }),
+        widget::row![
+            widget::text("Opacity:"),
+            widget::horizontal_space(),
+            widget::text(format!("{:.0}%", state.brush_opacity() * 100.0)),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+        widget::slider(0.0..=100.0, state.brush_opacity() * 100.0, |v| {
+            Message::BrushOpacityChanged(v / 100.0)
+        }),
+        widget::row![
+            widget::text("Brush size:"),
+            widget::horizontal_space(),
+            widget::pick_list(
+                WheelBrushModifier::ALL.as_slice(),
+                Some(state.wheel_brush_modifier),
+                Message::WheelBrushModifierChanged,
+            ),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
     ]
     .spacing(5)
     .into()
 }
 
-fn color_picker(state: &EditorState) -> Element<'_, Message> {
-    let rgba = state.primary_color.into_rgba8();
-    let r = rgba[0];
-    let g = rgba[1];
-    let b = rgba[2];
-
-    let sec_rgba = state.secondary_color.into_rgba8();
-    let sec_r = sec_rgba[0];
-    let sec_g = sec_rgba[1];
-    let sec_b = sec_rgba[2];
-
-    widget::column![
-        // Primary color preview (clickable to pick color)
-        widget::text("Primary"),
-        widget::button(
-            widget::container(
-                widget::text("")
-                    .width(Length::Fill)
-                    .height(Length::Fixed(50.0))
-            )
-            .style(|_theme| {
-                widget::container::Style {
-                    background: Some(state.primary_color.into()),
-                    border: iced::border::Border {
-                        radius: iced::border::Radius::from(5.0),
-                        width: 1.0,
-                        color: Color::BLACK,
-                    },
-                    ..Default::default()
+/// Per-stamp color variation for the pencil.
+fn jitter_control(state: &EditorState) -> Element<'_, Message> {
+    let jitter = state.tool_options.jitter;
+    let slider =
+        |label: String, value: u32, max: u32, set: fn(JitterOptions, u32) -> JitterOptions| {
+            widget::column![
+                widget::row![
+                    widget::text(label),
+                    widget::horizontal_space(),
+                    widget::text(if value > 0 {
+                        format!("±{}", value)
+                    } else {
+                        "Off".to_string()
+                    }),
+                ]
+                .spacing(5)
+                .align_y(Alignment::Center),
+                widget::slider(0.0..=max as f32, value as f32, move |v| {
+                    Message::PencilJitterChanged(set(jitter, v as u32))
+                }),
+            ]
+            .spacing(5)
+        };
+    widget::column![
+        slider(
+            "Value jitter:".to_string(),
+            jitter.value,
+            MAX_VALUE_JITTER,
+            |jitter, value| JitterOptions { value, ..jitter }
+        ),
+        slider(
+            "Hue jitter:".to_string(),
+            jitter.hue,
+            MAX_HUE_JITTER,
+            |jitter, hue| JitterOptions { hue, ..jitter }
+        ),
+        widget::row![
+            widget::text("Seed:"),
+            widget::horizontal_space(),
+            widget::text(jitter.seed.to_string()),
+            widget::button(widget::text("New").size(12)).on_press(Message::PencilJitterChanged(
+                JitterOptions {
+                    seed: jitter.seed.wrapping_add(1),
+                    ..jitter
                 }
-            })
-            .width(Length::Fill)
-            .height(Length::Fixed(50.0))
-        )
-        .on_press(Message::ColorPicked(state.primary_color)),
-        // RGB sliders
-        widget::text("Red"),
-        widget::slider(0.0..=255.0, r as f32, move |v| {
-            let rgba = state.primary_color.into_rgba8();
-            Message::PrimaryColorChanged(Color::from_rgba8(
-                v as u8,
-                rgba[1],
-                rgba[2],
-                rgba[3] as f32 / 255.0,
-            ))
-        }),
-        widget::text("Green"),
-        widget::slider(0.0..=255.0, g as f32, move |v| {
-            let rgba = state.primary_color.into_rgba8();
-            Message::PrimaryColorChanged(Color::from_rgba8(
-                rgba[0],
-                v as u8,
-                rgba[2],
-                rgba[3] as f32 / 255.0,
-            ))
+            )),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn stabilizer_control(state: &EditorState) -> Element<'_, Message> {
+    let stabilizer = state.tool_options.freehand.stabilizer;
+    widget::column![
+        widget::row![
+            widget::text("Stabilizer:"),
+            widget::horizontal_space(),
+            widget::text(if stabilizer > 0 {
+                stabilizer.to_string()
+            } else {
+                "Off".to_string()
+            }),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+        widget::slider(0.0..=10.0, stabilizer as f32, |v| {
+            Message::StabilizerChanged(v as u32)
         }),
-        widget::text("Blue"),
-        widget::slider(0.0..=255.0, b as f32, move |v| {
-            let rgba = state.primary_color.into_rgba8();
-            Message::PrimaryColorChanged(Color::from_rgba8(
-                rgba[0],
-                rgba[1],
-                v as u8,
-                rgba[3] as f32 / 255.0,
-            ))
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn pixel_snap_control(state: &EditorState) -> Element<'_, Message> {
+    widget::column![
+        widget::row![
+            widget::text("Pixel snap:"),
+            widget::horizontal_space(),
+            widget::text(if state.tool_options.pixel_snap > 1 {
+                format!("{0}x{0}", state.tool_options.pixel_snap)
+            } else {
+                "Off".to_string()
+            }),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+        widget::slider(1.0..=16.0, state.tool_options.pixel_snap as f32, |v| {
+            Message::PixelSnapChanged(v as u32)
         }),
-        widget::horizontal_rule(5),
-        // Secondary color preview
-        widget::text("Secondary"),
-        widget::container(
-            widget::text("")
-                .width(Length::Fill)
-                .height(Length::Fixed(50.0))
-        )
-        .style(|_theme| {
-            widget::container::Style {
-                background: Some(state.secondary_color.into()),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn color_swatch(color: Color, target: ColorTarget) -> Element<'static, Message> {
+    widget::button(
+        widget::container(widget::text(""))
+            .style(move |_theme| widget::container::Style {
+                background: Some(color.into()),
                 border: iced::border::Border {
                     radius: iced::border::Radius::from(5.0),
                     width: 1.0,
                     color: Color::BLACK,
                 },
                 ..Default::default()
-            }
-        })
-        .width(Length::Fill)
-        .height(Length::Fixed(50.0)),
-        widget::text("Red"),
-        widget::slider(0.0..=255.0, sec_r as f32, move |v| {
-            let rgba = state.secondary_color.into_rgba8();
-            Message::SecondaryColorChanged(Color::from_rgba8(
-                v as u8,
-                rgba[1],
-                rgba[2],
-                rgba[3] as f32 / 255.0,
-            ))
-        }),
-        widget::text("Green"),
-        widget::slider(0.0..=255.0, sec_g as f32, move |v| {
-            let rgba = state.secondary_color.into_rgba8();
-            Message::SecondaryColorChanged(Color::from_rgba8(
-                rgba[0],
-                v as u8,
-                rgba[2],
-                rgba[3] as f32 / 255.0,
-            ))
-        }),
-        widget::text("Blue"),
-        widget::slider(0.0..=255.0, sec_b as f32, move |v| {
-            let rgba = state.secondary_color.into_rgba8();
-            Message::SecondaryColorChanged(Color::from_rgba8(
-                rgba[0],
-                rgba[1],
-                v as u8,
-                rgba[3] as f32 / 255.0,
-            ))
-        }),
+            })
+            .width(Length::Fill)
+            .height(Length::Fixed(32.0)),
+    )
+    .padding(0)
+    .on_press(Message::ColorPickerOpened(target))
+    .into()
+}
+
+fn color_buttons(state: &EditorState) -> Element<'_, Message> {
+    // Clicking a preview opens the picker popup for that color
+    widget::row![
+        widget::column![
+            widget::text("Primary").size(12),
+            color_swatch(state.primary_color, ColorTarget::Primary),
+        ]
+        .spacing(3)
+        .width(Length::Fill),
+        widget::column![
+            widget::text("Secondary").size(12),
+            color_swatch(state.secondary_color, ColorTarget::Secondary),
+        ]
+        .spacing(3)
+        .width(Length::Fill),
     ]
     .spacing(5)
     .into()
 }
 
+fn color_picker_popup(picker: &ColorPickerState) -> Element<'_, Message> {
+    let preview = |color: Color| {
+        widget::container(widget::text(""))
+            .style(move |_theme| widget::container::Style {
+                background: Some(color.into()),
+                border: iced::border::Border {
+                    radius: iced::border::Radius::from(3.0),
+                    width: 1.0,
+                    color: Color::BLACK,
+                },
+                ..Default::default()
+            })
+            .width(Length::Fill)
+            .height(Length::Fixed(24.0))
+    };
+
+    widget::container(
+        widget::column![
+            widget::text(match picker.target {
+                ColorTarget::Primary => "Primary color",
+                ColorTarget::Secondary => "Secondary color",
+            })
+            .size(16),
+            widget::canvas(SaturationValueSquare {
+                hue: picker.hue,
+                saturation: picker.saturation,
+                value: picker.value,
+            })
+            .width(Length::Fixed(200.0))
+            .height(Length::Fixed(200.0)),
+            widget::canvas(HueStrip { hue: picker.hue })
+                .width(Length::Fixed(200.0))
+                .height(Length::Fixed(16.0)),
+            widget::row![
+                widget::text("Alpha").size(12),
+                widget::slider(0.0..=255.0, (picker.alpha * 255.0).round(), |v| {
+                    Message::ColorPickerAlphaChanged(v / 255.0)
+                }),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center),
+            widget::text_input("#RRGGBBAA", &picker.hex_input)
+                .on_input(Message::ColorPickerHexChanged)
+                .on_submit(Message::ColorPickerConfirmed)
+                .size(12),
+            widget::row![preview(picker.original), preview(picker.color())].spacing(5),
+            widget::row![
+                widget::horizontal_space(),
+                widget::button(widget::text("Cancel").size(12))
+                    .on_press(Message::ColorPickerCancelled)
+                    .style(widget::button::secondary),
+                widget::button(widget::text("OK").size(12)).on_press(Message::ColorPickerConfirmed),
+            ]
+            .spacing(5),
+        ]
+        .spacing(8)
+        .width(Length::Fixed(200.0)),
+    )
+    .padding(12)
+    .style(widget::container::rounded_box)
+    .into()
+}
+
+fn layer_drop_line() -> Element<'static, Message> {
+    widget::container(widget::horizontal_space())
+        .style(|_theme| widget::container::Style {
+            background: Some(Color::from_rgb(0.9, 0.7, 0.2).into()),
+            ..Default::default()
+        })
+        .width(Length::Fill)
+        .height(Length::Fixed(3.0))
+        .into()
+}
+
+/// The text input of a layer being renamed in its card.
+pub fn layer_rename_input() -> widget::text_input::Id {
+    widget::text_input::Id::new("layer-rename")
+}
+
+/// A layer's name: a text input while it is being renamed, otherwise a
+/// button that selects the layer, or renames it on double-click. The
+/// hovered card's name carries a tooltip with the layer's details.
+fn layer_name(state: &EditorState, index: usize, is_active: bool) -> Element<'_, Message> {
+    let layer = &state.layers[index];
+    if let Some((renaming, name)) = &state.renaming_layer
+        && *renaming == index
+    {
+        return widget::row![
+            widget::text_input("Layer name", name)
+                .id(layer_rename_input())
+                .on_input(Message::LayerRenameEdited)
+                .on_submit(Message::LayerRenameCommitted)
+                .size(12),
+            widget::button(widget::text("x").size(12))
+                .on_press(Message::LayerRenameCancelled)
+                .style(widget::button::text),
+        ]
+        .align_y(Alignment::Center)
+        .into();
+    }
+
+    let button = widget::button(widget::text(if layer.clipped {
+        format!("↳ {}", layer.name)
+    } else {
+        layer.name.clone()
+    }))
+    .on_press(Message::LayerNameClicked(index))
+    .padding([4, 8])
+    .style(if is_active {
+        widget::button::primary
+    } else {
+        widget::button::text
+    });
+    if state.hovered_layer != Some(index) {
+        return button.into();
+    }
+
+    let painted = layer
+        .pixels
+        .chunks_exact(4)
+        .filter(|pixel| pixel[3] > 0)
+        .count();
+    let details = format!(
+        "{}x{}, opacity {:.0}%, {} painted pixel{}",
+        layer.width,
+        layer.height,
+        layer.opacity * 100.0,
+        painted,
+        if painted == 1 { "" } else { "s" }
+    );
+    widget::tooltip(
+        button,
+        widget::container(widget::text(details).size(12))
+            .padding(5)
+            .style(widget::container::bordered_box),
+        widget::tooltip::Position::Bottom,
+    )
+    .into()
+}
+
 fn layer_list(state: &EditorState) -> Element<'_, Message> {
     let mut layer_widgets: Vec<Element<Message>> = Vec::new();
+    let drag = state.layer_drag;
+    let solo_modifier = state.modifiers.alt();
 
     for (index, layer) in state.layers.iter().enumerate().rev() {
         let is_active = index == state.active_layer_index;
+        let is_dragged = drag.is_some_and(|drag| drag.from == index);
         let layer_opacity = layer.opacity;
         let layer_index = index;
 
@@ -271,6 +1221,7 @@ fn layer_list(state: &EditorState) -> Element<'_, Message> {
                 opacity: v,
             }
         })
+        .on_release(Message::ContinuousEditFinished)
         .width(Length::Fill)
         .step(0.01);
 
@@ -278,26 +1229,20 @@ fn layer_list(state: &EditorState) -> Element<'_, Message> {
             widget::column![
                 // First line: Checkbox and Layer name
                 widget::row![
-                    widget::checkbox("", layer.visible)
-                        .on_toggle(move |_| Message::LayerVisibilityToggled(layer_index)),
-                    widget::button(&*layer.name)
-                        .on_press(Message::LayerSelected(layer_index))
-                        .padding([4, 8])
-                        .style(if is_active {
-                            widget::button::primary
-                        } else {
-                            widget::button::text
-                        }),
+                    // Alt+click solos the layer instead
+                    widget::checkbox("", layer.visible).on_toggle(move |_| if solo_modifier {
+                        Message::LayerSoloToggled(layer_index)
+                    } else {
+                        Message::LayerVisibilityToggled(layer_index)
+                    }),
+                    layer_name(state, layer_index, is_active),
                 ]
                 .spacing(5)
                 .align_y(Alignment::Center)
                 .width(Length::Fill),
                 // Second line: Action buttons
                 widget::row![
-                    widget::button("E").on_press(Message::LayerRenamed {
-                        index: layer_index,
-                        name: layer.name.clone(),
-                    }),
+                    widget::button("E").on_press(Message::LayerRenameStarted(layer_index)),
                     widget::button("^").on_press(if layer_index > 0 {
                         Message::LayerMoved {
                             from: layer_index,
@@ -314,6 +1259,22 @@ fn layer_list(state: &EditorState) -> Element<'_, Message> {
                     } else {
                         Message::None
                     }),
+                    widget::button("S")
+                        .on_press(Message::LayerSoloToggled(layer_index))
+                        .style(if state.solo_index() == Some(layer_index) {
+                            widget::button::primary
+                        } else {
+                            widget::button::secondary
+                        }),
+                    widget::button("Clip")
+                        .on_press_maybe(
+                            (layer_index > 0).then_some(Message::LayerClippingToggled(layer_index))
+                        )
+                        .style(if layer.clipped {
+                            widget::button::primary
+                        } else {
+                            widget::button::secondary
+                        }),
                     if state.layers.len() > 1 {
                         widget::button("X")
                             .on_press(Message::LayerDeleted(layer_index))
@@ -342,15 +1303,19 @@ fn layer_list(state: &EditorState) -> Element<'_, Message> {
             .width(Length::Fill),
         )
         .style(move |_theme| widget::container::Style {
-            background: if is_active {
+            background: if is_dragged {
+                Some(Color::from_rgba(0.9, 0.7, 0.2, 0.2).into())
+            } else if is_active {
                 Some(Color::from_rgba(0.1, 0.3, 0.6, 0.3).into())
             } else {
                 Some(Color::from_rgba(0.2, 0.2, 0.2, 0.3).into())
             },
             border: iced::border::Border {
                 radius: iced::border::Radius::from(5.0),
-                width: if is_active { 2.0 } else { 1.0 },
-                color: if is_active {
+                width: if is_active || is_dragged { 2.0 } else { 1.0 },
+                color: if is_dragged {
+                    Color::from_rgb(0.9, 0.7, 0.2)
+                } else if is_active {
                     Color::from_rgba(0.2, 0.5, 0.9, 1.0)
                 } else {
                     Color::from_rgba(0.4, 0.4, 0.4, 0.5)
@@ -361,15 +1326,66 @@ fn layer_list(state: &EditorState) -> Element<'_, Message> {
         .padding(8)
         .width(Length::Fill);
 
-        layer_widgets.push(layer_card.into());
+        // Pressing the card itself, outside its controls, starts a drag;
+        // a middle-click anywhere on it toggles visibility
+        let mut layer_card = widget::mouse_area(layer_card)
+            .on_press(Message::LayerDragStarted(layer_index))
+            .on_middle_press(Message::LayerVisibilityToggled(layer_index))
+            .interaction(if drag.is_some() {
+                iced::mouse::Interaction::Grabbing
+            } else {
+                iced::mouse::Interaction::Grab
+            });
+        layer_card = if drag.is_some() {
+            layer_card.on_enter(Message::LayerDragHovered(Some(layer_index)))
+        } else {
+            layer_card
+                .on_enter(Message::LayerHovered(Some(layer_index)))
+                .on_exit(Message::LayerHovered(None))
+        };
+
+        // Clipped layers are indented under their base
+        let layer_card: Element<Message> = if layer.clipped {
+            widget::row![widget::horizontal_space().width(16), layer_card].into()
+        } else {
+            layer_card.into()
+        };
+
+        // The drop line goes on the side of the hovered card the dragged
+        // layer would end up on; the list runs top layer first
+        let drop_line = drag
+            .filter(|drag| drag.target == Some(layer_index) && drag.from != layer_index)
+            .map(|drag| drag.from < layer_index);
+        if drop_line == Some(true) {
+            layer_widgets.push(layer_drop_line());
+        }
+        layer_widgets.push(layer_card);
+        if drop_line == Some(false) {
+            layer_widgets.push(layer_drop_line());
+        }
+    }
+
+    let mut layers = widget::mouse_area(widget::column(layer_widgets).spacing(8));
+    if drag.is_some() {
+        layers = layers.on_exit(Message::LayerDragHovered(None));
     }
 
     widget::column![
-        widget::column(layer_widgets).spacing(8),
+        layers,
         widget::button("+ Add Layer").on_press(Message::LayerAdded(format!(
             "Layer {}",
             state.layers.len() + 1
         ))),
+        widget::checkbox(
+            "Undo affects active layer only",
+            state.undo_active_layer_only
+        )
+        .on_toggle(Message::UndoActiveLayerOnlyToggled),
+        widget::checkbox(
+            "Warn when drawing on hidden layers",
+            state.warn_hidden_layer
+        )
+        .on_toggle(Message::HiddenLayerWarningToggled),
     ]
     .spacing(8)
     .into()
@@ -378,33 +1394,84 @@ fn layer_list(state: &EditorState) -> Element<'_, Message> {
 fn right_sidebar(state: &EditorState) -> Element<'_, Message> {
     let mut used_colors_grid = widget::column![].spacing(5);
 
+    // Pair each color with its storage index; the hue and luminance sorts
+    // ignore the second field, so it carries the index through the
+    // reordering
+    let mut order: Vec<([u8; 4], usize)> = state
+        .used_colors
+        .iter()
+        .enumerate()
+        .map(|(index, used)| (used.color.into_rgba8(), index))
+        .collect();
+    match state.used_color_sort {
+        UsedColorSort::Recency => {}
+        UsedColorSort::Hue => crate::palette::sort_colors(&mut order, PaletteSort::Hue),
+        UsedColorSort::Luminance => crate::palette::sort_colors(&mut order, PaletteSort::Luminance),
+        UsedColorSort::Usage => order.sort_by_key(|(rgba, _)| {
+            std::cmp::Reverse(state.color_frequencies.get(rgba).copied().unwrap_or(0))
+        }),
+    }
+
     // Create grid of used colors (4 per row)
     let mut current_row = widget::row![].spacing(5);
-    for (i, color) in state.used_colors.iter().enumerate() {
+    for (i, &(_, index)) in order.iter().enumerate() {
         // Start a new row every 4 colors (after completing a full row)
         if i > 0 && i % 4 == 0 {
             used_colors_grid = used_colors_grid.push(current_row);
             current_row = widget::row![].spacing(5);
         }
 
+        let UsedColor { color, pinned } = state.used_colors[index];
+        let in_draft = state
+            .ramp_draft
+            .as_ref()
+            .is_some_and(|draft| draft.contains(&color.into_rgba8()));
+        // The highlighted color shows its usage count in place of the pin dot
+        let label = match &state.color_usage {
+            Some(usage) if usage.color == color.into_rgba8() => usage.pixels.len().to_string(),
+            _ if pinned => "•".to_string(),
+            _ => String::new(),
+        };
         let color_button = widget::button(
-            widget::container(widget::text(""))
+            widget::container(widget::text(label).size(10))
                 .width(Length::Fixed(30.0))
                 .height(Length::Fixed(30.0))
                 .style(move |_theme| widget::container::Style {
-                    background: Some((*color).into()),
+                    background: Some(color.into()),
+                    text_color: Some(if crate::filters::luminance(color.into_rgba8()) > 128 {
+                        Color::BLACK
+                    } else {
+                        Color::WHITE
+                    }),
                     border: iced::border::Border {
                         radius: iced::border::Radius::from(3.0),
-                        width: 1.0,
-                        color: Color::BLACK,
+                        width: if in_draft || pinned { 3.0 } else { 1.0 },
+                        color: if in_draft {
+                            Color::from_rgb(0.0, 0.5, 1.0)
+                        } else if pinned {
+                            Color::from_rgb(0.9, 0.7, 0.1)
+                        } else {
+                            Color::BLACK
+                        },
                     },
                     ..Default::default()
                 }),
         )
-        .on_press(Message::UsedColorPicked(*color))
+        .on_press(if state.ramp_draft.is_some() {
+            Message::RampDraftColorToggled(color)
+        } else if state.modifiers.alt() {
+            Message::ColorUsageHighlighted(color)
+        } else {
+            Message::UsedColorPicked(color)
+        })
         .padding(0);
 
-        current_row = current_row.push(color_button);
+        // Right-click pins, middle-click removes
+        current_row = current_row.push(
+            widget::mouse_area(color_button)
+                .on_right_press(Message::UsedColorPinned(index))
+                .on_middle_press(Message::UsedColorRemoved(index)),
+        );
     }
 
     // Always add the last row if there are any colors (it will contain the remaining colors)
@@ -416,41 +1483,125 @@ fn right_sidebar(state: &EditorState) -> Element<'_, Message> {
         widget::column![
             widget::text("Properties").size(16),
             widget::horizontal_rule(10),
-            widget::text("Used Colors").size(14),
+            widget::row![
+                widget::text("Used Colors").size(14),
+                widget::horizontal_space(),
+                widget::pick_list(
+                    UsedColorSort::ALL.as_slice(),
+                    Some(state.used_color_sort),
+                    Message::UsedColorSortChanged,
+                )
+                .text_size(12),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center),
             widget::scrollable(used_colors_grid).height(Length::Fixed(150.0)),
-            widget::horizontal_rule(10),
-            widget::text("Canvas Size"),
             widget::row![
-                widget::text_input("Width", &state.canvas_width.to_string()).on_input(move |s| {
-                    s.parse::<u32>()
-                        .ok()
-                        .map(|w| Message::CanvasResized {
-                            width: w,
-                            height: state.canvas_height,
-                        })
-                        .unwrap_or(Message::None)
-                }),
-                widget::text("x"),
-                widget::text_input("Height", &state.canvas_height.to_string()).on_input(move |s| {
-                    s.parse::<u32>()
-                        .ok()
-                        .map(|h| Message::CanvasResized {
-                            width: state.canvas_width,
-                            height: h,
-                        })
-                        .unwrap_or(Message::None)
-                }),
+                widget::text("Right-click pins, middle-click removes, Alt+click finds pixels")
+                    .size(11),
+                widget::horizontal_space(),
+                widget::button(widget::text("Clear unpinned").size(12))
+                    .on_press(Message::UsedColorsCleared)
+                    .style(widget::button::secondary),
             ]
-            .spacing(5),
+            .spacing(5)
+            .align_y(Alignment::Center),
+            near_duplicates_control(state),
+            ramp_control(state),
+            color_count_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Indexed Color"),
+            indexed_mode_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Canvas Size"),
+            canvas_size_control(state),
             widget::button("Clear Canvas").on_press(Message::CanvasCleared),
             widget::horizontal_rule(10),
+            widget::text("Filters"),
+            filter_panel(state),
+            widget::horizontal_rule(10),
+            widget::text("Make Color Transparent"),
+            transparency_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Alpha Channel"),
+            alpha_channel_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Stray Pixels"),
+            stray_pixels_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Compare"),
+            compare_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Scale Image"),
+            scale_image_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Rotate"),
+            rotation_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Shift Row/Column"),
+            line_shift_control(state),
+            widget::horizontal_rule(10),
+            widget::text("9-Slice"),
+            nine_slice_control(state),
+            widget::horizontal_rule(10),
             widget::text("Grid"),
             widget::toggler(state.grid_visible).on_toggle(|_| Message::GridToggled),
             widget::horizontal_rule(10),
+            widget::text("Safe Areas"),
+            safe_area_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Outlines"),
+            outline_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Tiles"),
+            tile_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Make Tileable"),
+            tileable_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Document"),
+            document_stats(state),
+            widget::horizontal_rule(10),
+            widget::text("Restore Points"),
+            restore_points_control(state),
+            widget::horizontal_rule(10),
+            widget::text("Export"),
+            export_panel(state),
+            widget::horizontal_rule(10),
+            widget::text("Export Profiles"),
+            export_profiles_control(state),
+            widget::horizontal_rule(10),
             widget::text("Selection"),
             widget::button("Copy (Ctrl+C)").on_press(Message::CopySelection),
             widget::button("Cut (Ctrl+X)").on_press(Message::CutSelection),
+            widget::button("Paste at cursor (Ctrl+Shift+V)").on_press(Message::PastePreviewStarted),
+            widget::row![
+                widget::button(widget::text("Flip H").size(12)).on_press(
+                    Message::ClipboardTransformed(BufferTransform::FlipHorizontal)
+                ),
+                widget::button(widget::text("Flip V").size(12))
+                    .on_press(Message::ClipboardTransformed(BufferTransform::FlipVertical)),
+                widget::button(widget::text("Rotate 90°").size(12)).on_press(
+                    Message::ClipboardTransformed(BufferTransform::RotateClockwise)
+                ),
+            ]
+            .spacing(5),
+            widget::text("Clipboard transforms; H, V and R while pasting at cursor").size(12),
+            widget::checkbox("Trim transparent border on copy", state.trim_on_copy)
+                .on_toggle(Message::TrimOnCopyToggled)
+                .size(14)
+                .text_size(12),
             widget::button("Clear").on_press(Message::SelectionCleared),
+            widget::button("Export selection...").on_press_maybe(
+                state
+                    .selection_bounds()
+                    .map(|_| Message::SelectionExportRequested)
+            ),
+            widget::button("Import image into selection...").on_press_maybe(
+                state
+                    .selection_bounds()
+                    .map(|_| Message::SelectionImportRequested)
+            ),
             widget::horizontal_rule(10),
             widget::text("Mirror Mode"),
             widget::row![
@@ -469,6 +1620,14 @@ fn right_sidebar(state: &EditorState) -> Element<'_, Message> {
             ]
             .spacing(5)
             .width(Length::Fill),
+            widget::horizontal_rule(10),
+            widget::row![
+                widget::text("Wrap edges"),
+                widget::horizontal_space(),
+                widget::toggler(state.wrap_edges).on_toggle(|_| Message::WrapEdgesToggled),
+            ]
+            .spacing(5)
+            .width(Length::Fill),
         ]
         .spacing(10)
         .padding(iced::Padding::new(10.0).right(20.0)),
@@ -476,3 +1635,1025 @@ fn right_sidebar(state: &EditorState) -> Element<'_, Message> {
     .width(Length::Fixed(200.0))
     .into()
 }
+
+fn scale_image_control(state: &EditorState) -> Element<'_, Message> {
+    let custom_factor = state
+        .scale_factor_input
+        .parse::<u32>()
+        .ok()
+        .filter(|&n| n >= 1);
+
+    widget::column![
+        widget::row![
+            widget::button("0.5x").on_press(Message::ImageScaled(ScaleFactor::Half)),
+            widget::button("2x").on_press(Message::ImageScaled(ScaleFactor::Times(2))),
+            widget::button("3x").on_press(Message::ImageScaled(ScaleFactor::Times(3))),
+            widget::button("4x").on_press(Message::ImageScaled(ScaleFactor::Times(4))),
+        ]
+        .spacing(5),
+        widget::row![
+            widget::text_input("Factor", &state.scale_factor_input)
+                .on_input(Message::ScaleFactorInputChanged)
+                .width(Length::Fixed(50.0)),
+            widget::text("x"),
+            widget::button("Scale")
+                .on_press_maybe(custom_factor.map(|n| Message::ImageScaled(ScaleFactor::Times(n)))),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn transparency_control(state: &EditorState) -> Element<'_, Message> {
+    widget::column![
+        widget::row![
+            widget::text(format!("Tolerance: {}", state.transparency_tolerance)).size(12),
+            widget::slider(0.0..=255.0, state.transparency_tolerance as f32, |v| {
+                Message::TransparencyToleranceChanged(v as u8)
+            }),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+        widget::row![
+            widget::button("Top-left pixel").on_press(Message::ColorMadeTransparent(None)),
+            widget::button("Primary color")
+                .on_press(Message::ColorMadeTransparent(Some(state.primary_color))),
+        ]
+        .spacing(5),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn alpha_channel_control(state: &EditorState) -> Element<'_, Message> {
+    widget::column![
+        widget::checkbox("Edit alpha channel", state.alpha_edit)
+            .on_toggle(Message::AlphaEditToggled),
+        widget::text(if state.alpha_edit {
+            "Showing the active layer's alpha (white = opaque); pencil, eraser, \
+             shapes and fill paint the primary color's grey value into alpha"
+        } else {
+            "Show and paint the active layer's alpha as greyscale"
+        })
+        .size(12),
+        widget::row![
+            widget::button("Luminance to alpha").on_press(Message::LuminanceToAlpha),
+            widget::button("Alpha to luminance").on_press(Message::AlphaToLuminance),
+        ]
+        .spacing(5),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn stray_pixels_control(state: &EditorState) -> Element<'_, Message> {
+    let highlight = if state.stray_pixels.is_some() {
+        widget::button("Hide highlight").on_press(Message::StrayPixelsHidden)
+    } else {
+        widget::button("Highlight stray pixels").on_press(Message::StrayPixelsHighlighted)
+    };
+    widget::row![
+        highlight,
+        widget::button("Delete all strays")
+            .on_press(Message::StrayPixelsDeleted)
+            .style(widget::button::danger),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn compare_control(state: &EditorState) -> Element<'_, Message> {
+    let Some(comparison) = &state.comparison else {
+        return widget::button("Compare with file...")
+            .on_press(Message::CompareRequested)
+            .into();
+    };
+
+    let mut views = widget::row![].spacing(5);
+    for view in CompareView::ALL {
+        views = views.push(
+            widget::button(widget::text(view.to_string()).size(12))
+                .on_press(Message::CompareViewChanged(view))
+                .style(if comparison.view == view {
+                    widget::button::primary
+                } else {
+                    widget::button::secondary
+                }),
+        );
+    }
+    widget::column![
+        views,
+        widget::text(if comparison.differing == 1 {
+            "1 pixel differs".to_string()
+        } else {
+            format!("{} pixels differ", comparison.differing)
+        })
+        .size(12),
+        widget::row![
+            widget::button("Compare with file...").on_press(Message::CompareRequested),
+            widget::button("Close").on_press(Message::CompareClosed),
+        ]
+        .spacing(5),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn tileable_control(state: &EditorState) -> Element<'_, Message> {
+    if state.seams_exposed {
+        widget::column![
+            widget::text("Step 2 of 2: paint over the seams marked in orange").size(12),
+            widget::button("Offset back").on_press(Message::SeamsRestored),
+        ]
+        .spacing(5)
+        .into()
+    } else {
+        widget::column![
+            widget::text("Step 1 of 2: move the edges to the middle").size(12),
+            widget::button("Offset by half").on_press(Message::SeamsExposed),
+        ]
+        .spacing(5)
+        .into()
+    }
+}
+
+fn tile_control(state: &EditorState) -> Element<'_, Message> {
+    let toggle = widget::toggler(state.tile_mode)
+        .label("Tile mode")
+        .on_toggle(Message::TileModeToggled);
+    if !state.tile_mode {
+        return toggle.into();
+    }
+
+    let (tile_width, tile_height) = (state.tile_width, state.tile_height);
+    widget::column![
+        toggle,
+        widget::row![
+            widget::text("Width:"),
+            widget::horizontal_space(),
+            widget::text(format!("{}px", tile_width)),
+        ]
+        .spacing(5),
+        widget::slider(1.0..=64.0, tile_width as f32, move |v| {
+            Message::TileSizeChanged {
+                width: v as u32,
+                height: tile_height,
+            }
+        }),
+        widget::row![
+            widget::text("Height:"),
+            widget::horizontal_space(),
+            widget::text(format!("{}px", tile_height)),
+        ]
+        .spacing(5),
+        widget::slider(1.0..=64.0, tile_height as f32, move |v| {
+            Message::TileSizeChanged {
+                width: tile_width,
+                height: v as u32,
+            }
+        }),
+        widget::button(if state.picking_tile {
+            "Click a tile..."
+        } else {
+            "Select tile"
+        })
+        .on_press(Message::TilePickRequested),
+        widget::button("Export tiles...").on_press(Message::TilesExportRequested),
+        widget::text("Ctrl+drag a tile onto another to copy it").size(12),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn export_profiles_control(state: &EditorState) -> Element<'_, Message> {
+    let mut column = widget::column![].spacing(5);
+    for (index, profile) in state.export_profiles.iter().enumerate() {
+        let upscale_profile = profile.clone();
+        let scope_profile = profile.clone();
+        column = column.push(
+            widget::row![
+                widget::text(format!("{} ({})", profile.display_name(), profile.format)).size(12),
+                widget::horizontal_space(),
+                widget::button("x").on_press(Message::ExportProfileRemoved(index)),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center),
+        );
+        column = column.push(
+            widget::row![
+                widget::pick_list(
+                    ExportUpscale::ALL.as_slice(),
+                    Some(profile.upscale),
+                    move |upscale| Message::ExportProfileChanged(
+                        index,
+                        ExportProfile {
+                            upscale,
+                            ..upscale_profile.clone()
+                        }
+                    ),
+                ),
+                widget::pick_list(
+                    ExportScope::ALL.as_slice(),
+                    Some(profile.scope),
+                    move |scope| Message::ExportProfileChanged(
+                        index,
+                        ExportProfile {
+                            scope,
+                            ..scope_profile.clone()
+                        }
+                    ),
+                ),
+            ]
+            .spacing(5),
+        );
+    }
+
+    column
+        .push(widget::button("Add profile...").on_press(Message::ExportProfileAddRequested))
+        .push(
+            widget::button("Export all (Ctrl+E)").on_press_maybe(
+                (!state.export_profiles.is_empty() && !state.exporting)
+                    .then_some(Message::ExportAllRequested),
+            ),
+        )
+        .into()
+}
+
+fn nine_slice_control(state: &EditorState) -> Element<'_, Message> {
+    let toggle = widget::toggler(state.nine_slice.is_some())
+        .label("Show guides")
+        .on_toggle(Message::NineSliceToggled);
+    let Some(slice) = state.nine_slice else {
+        return toggle.into();
+    };
+
+    widget::column![
+        toggle,
+        widget::text(format!(
+            "L {}  R {}  T {}  B {}",
+            slice.left, slice.right, slice.top, slice.bottom
+        ))
+        .size(12),
+        widget::checkbox("Export JSON sidecar", state.nine_slice_sidecar)
+            .on_toggle(Message::NineSliceSidecarToggled),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn rotation_control(state: &EditorState) -> Element<'_, Message> {
+    let Some(rotation) = &state.rotation else {
+        return widget::row![
+            widget::button("90°").on_press(Message::RotationChanged {
+                degrees: 90.0,
+                algorithm: RotationAlgorithm::NearestNeighbor,
+            }),
+            widget::button("Rotate...").on_press(Message::RotationChanged {
+                degrees: 0.0,
+                algorithm: RotationAlgorithm::NearestNeighbor,
+            }),
+        ]
+        .spacing(5)
+        .into();
+    };
+
+    let algorithm = rotation.algorithm;
+    widget::column![
+        widget::text(format!(
+            "Angle: {:.0}° ({})",
+            rotation.degrees,
+            if state.selection_bounds().is_some() {
+                "selection"
+            } else {
+                "layer"
+            }
+        )),
+        widget::slider(-180.0..=180.0, rotation.degrees, move |degrees| {
+            Message::RotationChanged { degrees, algorithm }
+        })
+        .step(1.0),
+        widget::pick_list(RotationAlgorithm::ALL, Some(algorithm), {
+            let degrees = rotation.degrees;
+            move |algorithm| Message::RotationChanged { degrees, algorithm }
+        }),
+        widget::row![
+            widget::button("Apply").on_press(Message::RotationApplied),
+            widget::button("Cancel")
+                .on_press(Message::RotationCancelled)
+                .style(widget::button::secondary),
+        ]
+        .spacing(5),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn safe_area_control(state: &EditorState) -> Element<'_, Message> {
+    if let Some(draft) = &state.safe_area_draft {
+        let margin_slider = |label: &'static str, side: usize| {
+            let value = draft.margins[side];
+            let draft = draft.clone();
+            widget::row![
+                widget::text(format!("{}: {:.0}%", label, value))
+                    .size(12)
+                    .width(Length::Fixed(80.0)),
+                widget::slider(0.0..=45.0, value, move |v| {
+                    let mut margins = draft.margins;
+                    margins[side] = v;
+                    Message::SafeAreaDraftChanged(SafeAreaPreset {
+                        margins,
+                        ..draft.clone()
+                    })
+                }),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center)
+        };
+        let name_draft = draft.clone();
+        return widget::column![
+            widget::text_input("Preset name", &draft.name)
+                .on_input(move |name| Message::SafeAreaDraftChanged(SafeAreaPreset {
+                    name,
+                    ..name_draft.clone()
+                }))
+                .on_submit(Message::SafeAreaDraftSaved),
+            margin_slider("Left", 0),
+            margin_slider("Top", 1),
+            margin_slider("Right", 2),
+            margin_slider("Bottom", 3),
+            widget::row![
+                widget::button("Save preset").on_press(Message::SafeAreaDraftSaved),
+                widget::button("Cancel")
+                    .on_press(Message::SafeAreaDraftCancelled)
+                    .style(widget::button::secondary),
+            ]
+            .spacing(5),
+        ]
+        .spacing(5)
+        .into();
+    }
+
+    let names: Vec<String> = state
+        .safe_area_presets()
+        .into_iter()
+        .map(|preset| preset.name)
+        .collect();
+    let custom = state.safe_area.as_ref().filter(|name| {
+        state
+            .custom_safe_areas
+            .iter()
+            .any(|preset| &preset.name == *name)
+    });
+    widget::column![
+        widget::pick_list(names, state.safe_area.clone(), Message::SafeAreaSelected)
+            .placeholder("Choose a target")
+            .text_size(12),
+        widget::row![
+            widget::button(widget::text("Hide").size(12))
+                .on_press_maybe(state.safe_area.is_some().then_some(Message::SafeAreaHidden)),
+            widget::button(widget::text("New preset...").size(12))
+                .on_press(Message::SafeAreaDraftStarted),
+        ]
+        .push_maybe(custom.map(|name| {
+            widget::button(widget::text("Delete").size(12))
+                .on_press(Message::SafeAreaDeleted(name.clone()))
+                .style(widget::button::danger)
+        }))
+        .spacing(5),
+        widget::text("Shown on screen only, never exported").size(12),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn outline_control(state: &EditorState) -> Element<'_, Message> {
+    let mut column = widget::column![
+        widget::checkbox("Outline view", state.outline_view).on_toggle(Message::OutlineViewToggled),
+        widget::button("Export outlines...").on_press(Message::OutlinesExportRequested),
+    ]
+    .spacing(5);
+    if state.outline_view {
+        // Key to which color traces which layer
+        for (index, _) in state.visible_layers() {
+            if let Some(layer) = state.layers.get(index) {
+                column = column.push(
+                    widget::row![
+                        ramp_swatch(crate::filters::outline_color(index)),
+                        widget::text(&layer.name).size(12),
+                    ]
+                    .spacing(5)
+                    .align_y(Alignment::Center),
+                );
+            }
+        }
+    }
+    column.into()
+}
+
+fn line_shift_control(state: &EditorState) -> Element<'_, Message> {
+    let Some(shift) = state.line_shift else {
+        return widget::text("Alt+click a row or Alt+Shift+click a column to shift it")
+            .size(12)
+            .into();
+    };
+
+    let limit = (shift.span.1 - shift.span.0).saturating_sub(1) as f32;
+    widget::column![
+        widget::text(format!(
+            "{} {}: {:+}px{}",
+            shift.axis,
+            shift.index,
+            shift.offset,
+            if state.selection_bounds().is_some() {
+                " (selection)"
+            } else {
+                ""
+            }
+        )),
+        widget::slider(-limit..=limit, shift.offset as f32, move |offset| {
+            Message::LineShiftChanged {
+                axis: shift.axis,
+                index: shift.index,
+                offset: offset as i32,
+            }
+        })
+        .step(1.0),
+        widget::row![
+            widget::button("Apply").on_press(Message::LineShiftApplied),
+            widget::button("Cancel")
+                .on_press(Message::LineShiftCancelled)
+                .style(widget::button::secondary),
+        ]
+        .spacing(5),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn filter_panel(state: &EditorState) -> Element<'_, Message> {
+    let Some(filter) = &state.active_filter else {
+        return widget::column![
+            widget::button("Posterize")
+                .on_press(Message::FilterOpened(Filter::Posterize { levels: 4 })),
+            widget::button("Grayscale")
+                .on_press(Message::FilterOpened(Filter::Grayscale { levels: None })),
+            widget::button("Gradient map").on_press(Message::FilterOpened(Filter::GradientMap {
+                ramp: Vec::new(),
+                stepped: true,
+            })),
+            widget::button("Noise").on_press(Message::FilterOpened(Filter::Noise {
+                mode: NoiseMode::Jitter,
+                amount: 16,
+                seed: 1,
+                fill_transparent: false,
+            })),
+        ]
+        .spacing(5)
+        .into();
+    };
+
+    let controls: Element<'_, Message> = match filter {
+        &Filter::Posterize { levels } => widget::column![
+            widget::text(format!("Levels: {}", levels)),
+            widget::slider(2.0..=16.0, levels as f32, |v| {
+                Message::FilterChanged(Filter::Posterize { levels: v as u32 })
+            }),
+        ]
+        .spacing(5)
+        .into(),
+        &Filter::Grayscale { levels } => {
+            let mut column =
+                widget::column![widget::checkbox("Levels", levels.is_some()).on_toggle(
+                    |enabled| {
+                        Message::FilterChanged(Filter::Grayscale {
+                            levels: enabled.then_some(4),
+                        })
+                    }
+                )]
+                .spacing(5);
+            if let Some(levels) = levels {
+                column = column
+                    .push(widget::text(format!("Levels: {}", levels)))
+                    .push(widget::slider(2.0..=16.0, levels as f32, |v| {
+                        Message::FilterChanged(Filter::Grayscale {
+                            levels: Some(v as u32),
+                        })
+                    }));
+            }
+            column.into()
+        }
+        Filter::GradientMap { ramp, stepped } => {
+            let stepped = *stepped;
+            let mut swatches = widget::row![].spacing(3);
+            for (index, rgba) in ramp.iter().enumerate() {
+                let color = crate::utils::rgba8_to_color(*rgba);
+                swatches = swatches.push(
+                    widget::button(
+                        widget::container(widget::text(""))
+                            .width(Length::Fixed(18.0))
+                            .height(Length::Fixed(18.0))
+                            .style(move |_theme| widget::container::Style {
+                                background: Some(color.into()),
+                                border: iced::border::Border {
+                                    radius: iced::border::Radius::from(2.0),
+                                    width: 1.0,
+                                    color: Color::BLACK,
+                                },
+                                ..Default::default()
+                            }),
+                    )
+                    .on_press(Message::GradientMapColorRemoved(index))
+                    .padding(0),
+                );
+            }
+            let ramp_for_toggle = ramp.clone();
+
+            widget::column![
+                widget::text("Ramp (dark to light, click to remove)").size(12),
+                widget::scrollable(swatches).direction(widget::scrollable::Direction::Horizontal(
+                    widget::scrollable::Scrollbar::default()
+                )),
+                widget::button("Add primary color")
+                    .on_press(Message::GradientMapColorAdded(state.primary_color)),
+                widget::checkbox("Smooth", !stepped).on_toggle(move |smooth| {
+                    Message::FilterChanged(Filter::GradientMap {
+                        ramp: ramp_for_toggle.clone(),
+                        stepped: !smooth,
+                    })
+                }),
+            ]
+            .spacing(5)
+            .into()
+        }
+        &Filter::Noise {
+            mode,
+            amount,
+            seed,
+            fill_transparent,
+        } => {
+            let noise = move |mode, amount, seed, fill_transparent| {
+                Message::FilterChanged(Filter::Noise {
+                    mode,
+                    amount,
+                    seed,
+                    fill_transparent,
+                })
+            };
+            let scatter_colors = [
+                state.primary_color.into_rgba8(),
+                state.secondary_color.into_rgba8(),
+            ];
+            let scatter = matches!(mode, NoiseMode::Scatter { .. });
+
+            let mut column = widget::column![
+                widget::checkbox("Scatter primary/secondary", scatter).on_toggle(move |enabled| {
+                    // Jitter tops out lower than scatter density
+                    let (mode, amount) = if enabled {
+                        (
+                            NoiseMode::Scatter {
+                                colors: scatter_colors,
+                            },
+                            amount,
+                        )
+                    } else {
+                        (NoiseMode::Jitter, amount.min(64))
+                    };
+                    noise(mode, amount, seed, fill_transparent)
+                }),
+                widget::text(if scatter {
+                    format!("Density: {}%", amount)
+                } else {
+                    format!("Jitter: ±{}", amount)
+                }),
+                widget::slider(
+                    1.0..=if scatter { 100.0 } else { 64.0 },
+                    amount as f32,
+                    move |v| noise(mode, v as u32, seed, fill_transparent)
+                ),
+            ]
+            .spacing(5);
+            if scatter {
+                column = column.push(
+                    widget::checkbox("Fill transparent", fill_transparent)
+                        .on_toggle(move |enabled| noise(mode, amount, seed, enabled)),
+                );
+            }
+            column
+                .push(
+                    widget::row![
+                        widget::text(format!("Seed: {}", seed)),
+                        widget::horizontal_space(),
+                        widget::button("Reseed").on_press(noise(
+                            mode,
+                            amount,
+                            seed.wrapping_add(1),
+                            fill_transparent
+                        )),
+                    ]
+                    .spacing(5)
+                    .align_y(Alignment::Center),
+                )
+                .into()
+        }
+    };
+
+    widget::column![
+        widget::text(format!(
+            "{} ({})",
+            filter.name(),
+            if state.selection_bounds().is_some() {
+                "selection"
+            } else {
+                "layer"
+            }
+        )),
+        controls,
+        widget::row![
+            widget::button("Apply").on_press(Message::FilterApplied),
+            widget::button("Cancel")
+                .on_press(Message::FilterCancelled)
+                .style(widget::button::secondary),
+        ]
+        .spacing(5),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn canvas_size_control(state: &EditorState) -> Element<'_, Message> {
+    let width = state
+        .canvas_width_input
+        .clone()
+        .unwrap_or_else(|| state.canvas_width.to_string());
+    let height = state
+        .canvas_height_input
+        .clone()
+        .unwrap_or_else(|| state.canvas_height.to_string());
+
+    widget::column![
+        widget::row![
+            widget::text_input("Width", &width)
+                .on_input(Message::CanvasWidthInputChanged)
+                .on_submit(Message::CanvasSizeSubmitted),
+            widget::text("x"),
+            widget::text_input("Height", &height)
+                .on_input(Message::CanvasHeightInputChanged)
+                .on_submit(Message::CanvasSizeSubmitted),
+            widget::button("Apply").on_press(Message::CanvasSizeSubmitted),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+        widget::checkbox("Lock aspect ratio", state.canvas_aspect_locked)
+            .on_toggle(Message::CanvasAspectLockToggled),
+    ]
+    .push_maybe(state.tile_mode.then(|| {
+        widget::text(format!(
+            "Snaps to multiples of the {}x{} tile size",
+            state.tile_width, state.tile_height
+        ))
+        .size(12)
+    }))
+    .spacing(5)
+    .into()
+}
+
+fn ramp_swatch<'a>(rgba: [u8; 4]) -> Element<'a, Message> {
+    widget::container(widget::text(""))
+        .width(Length::Fixed(14.0))
+        .height(Length::Fixed(14.0))
+        .style(move |_theme| widget::container::Style {
+            background: Some(crate::utils::rgba8_to_color(rgba).into()),
+            ..Default::default()
+        })
+        .into()
+}
+
+fn near_duplicates_control(state: &EditorState) -> Element<'_, Message> {
+    let Some(tolerance) = state.duplicate_tolerance else {
+        return widget::button(widget::text("Find near-duplicates").size(12))
+            .on_press(Message::NearDuplicatesOpened)
+            .style(widget::button::secondary)
+            .into();
+    };
+
+    let colors: Vec<[u8; 4]> = state
+        .used_colors
+        .iter()
+        .map(|used| used.color.into_rgba8())
+        .collect();
+    let pairs = crate::palette::near_duplicates(&colors, tolerance);
+    let mut column = widget::column![
+        widget::row![
+            widget::text(format!("Near-duplicates ({})", pairs.len())).size(12),
+            widget::horizontal_space(),
+            widget::button(widget::text("Close").size(12))
+                .on_press(Message::NearDuplicatesClosed)
+                .style(widget::button::secondary),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+        widget::row![
+            widget::text(format!("Tolerance: {}", tolerance)).size(12),
+            widget::slider(1.0..=64.0, tolerance as f32, |v| {
+                Message::NearDuplicatesToleranceChanged(v as u8)
+            }),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+    ]
+    .spacing(5);
+    if pairs.is_empty() {
+        column = column.push(widget::text("No colors this close").size(12));
+    }
+    // Each merge recolors the document's pixels of one color to the other
+    // and drops the merged color from the palette
+    for (a, b, distance) in pairs {
+        column = column.push(
+            widget::row![
+                ramp_swatch(a),
+                ramp_swatch(b),
+                widget::text(format!("±{}", distance)).size(12),
+                widget::horizontal_space(),
+                widget::button(widget::text("Keep left").size(12))
+                    .on_press(Message::NearDuplicateMerged { from: b, into: a })
+                    .style(widget::button::text),
+                widget::button(widget::text("Keep right").size(12))
+                    .on_press(Message::NearDuplicateMerged { from: a, into: b })
+                    .style(widget::button::text),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center),
+        );
+    }
+    column.into()
+}
+
+fn ramp_control(state: &EditorState) -> Element<'_, Message> {
+    if let Some(draft) = &state.ramp_draft {
+        return widget::column![
+            widget::text(format!(
+                "Click colors dark to light ({} selected)",
+                draft.len()
+            ))
+            .size(12),
+            widget::row![
+                widget::button("Group as ramp")
+                    .on_press_maybe((draft.len() >= 2).then_some(Message::RampDraftFinished)),
+                widget::button("Cancel")
+                    .on_press(Message::RampDraftCancelled)
+                    .style(widget::button::secondary),
+            ]
+            .spacing(5),
+        ]
+        .spacing(5)
+        .into();
+    }
+
+    if let Some(shift) = state.ramp_shift
+        && let Some(shifted) = shift.shifted(&state.ramps)
+    {
+        let swatches = |colors: &[[u8; 4]]| {
+            colors.iter().fold(widget::row![].spacing(1), |row, rgba| {
+                row.push(ramp_swatch(*rgba))
+            })
+        };
+        return widget::column![
+            widget::text(format!("Shift ramp {}", shift.ramp + 1)).size(12),
+            swatches(&state.ramps[shift.ramp]),
+            swatches(&shifted),
+            widget::row![
+                widget::text(format!("Hue: {:+.0}°", shift.hue)).size(12),
+                widget::slider(-180.0..=180.0, shift.hue, move |hue| {
+                    Message::RampShiftChanged(RampShift { hue, ..shift })
+                }),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center),
+            widget::row![
+                widget::text(format!("Saturation: {:+.0}%", shift.saturation * 100.0)).size(12),
+                widget::slider(-1.0..=1.0, shift.saturation, move |saturation| {
+                    Message::RampShiftChanged(RampShift {
+                        saturation,
+                        ..shift
+                    })
+                })
+                .step(0.01),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center),
+            widget::row![
+                widget::text(format!("Value: {:+.0}%", shift.value * 100.0)).size(12),
+                widget::slider(-1.0..=1.0, shift.value, move |value| {
+                    Message::RampShiftChanged(RampShift { value, ..shift })
+                })
+                .step(0.01),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center),
+            widget::text("Recolors exact matches on every layer").size(12),
+            widget::row![
+                widget::button("Apply").on_press(Message::RampShiftApplied),
+                widget::button("Cancel")
+                    .on_press(Message::RampShiftCancelled)
+                    .style(widget::button::secondary),
+            ]
+            .spacing(5),
+        ]
+        .spacing(5)
+        .into();
+    }
+
+    if let Some(generator) = state.ramp_generator {
+        let preview = generator
+            .generate(state.primary_color, state.secondary_color)
+            .into_iter()
+            .fold(widget::row![].spacing(1), |row, rgba| {
+                row.push(ramp_swatch(rgba))
+            });
+        return widget::column![
+            widget::text("Primary to secondary").size(12),
+            preview,
+            widget::row![
+                widget::text(format!("Steps: {}", generator.steps)).size(12),
+                widget::slider(3.0..=9.0, generator.steps as f32, move |v| {
+                    Message::RampGeneratorChanged(RampGenerator {
+                        steps: v as u32,
+                        ..generator
+                    })
+                }),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center),
+            widget::row![
+                widget::text(format!("Hue shift: {:+.0}°", generator.hue_shift)).size(12),
+                widget::slider(-30.0..=30.0, generator.hue_shift, move |hue_shift| {
+                    Message::RampGeneratorChanged(RampGenerator {
+                        hue_shift,
+                        ..generator
+                    })
+                }),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center),
+            widget::row![
+                widget::button("Add ramp").on_press(Message::RampGeneratorAccepted),
+                widget::button("Cancel")
+                    .on_press(Message::RampGeneratorCancelled)
+                    .style(widget::button::secondary),
+            ]
+            .spacing(5),
+        ]
+        .spacing(5)
+        .into();
+    }
+
+    let mut column = widget::column![
+        widget::row![
+            widget::text("Ramps (, and . to step)").size(12),
+            widget::horizontal_space(),
+            widget::button("New ramp").on_press(Message::RampDraftStarted),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+        widget::button("Create ramp from primary/secondary...")
+            .on_press(Message::RampGeneratorOpened),
+    ]
+    .spacing(5);
+    for (index, ramp) in state.ramps.iter().enumerate() {
+        let swatches = ramp.iter().fold(widget::row![].spacing(1), |row, rgba| {
+            row.push(ramp_swatch(*rgba))
+        });
+        column = column.push(
+            widget::row![
+                swatches,
+                widget::horizontal_space(),
+                widget::button(widget::text("Shift").size(12))
+                    .on_press(Message::RampShiftOpened(index))
+                    .style(widget::button::text),
+                widget::button(widget::text("x").size(12))
+                    .on_press(Message::RampDeleted(index))
+                    .style(widget::button::text),
+            ]
+            .align_y(Alignment::Center),
+        );
+    }
+    column.into()
+}
+
+fn indexed_mode_control(state: &EditorState) -> Element<'_, Message> {
+    let toggle = widget::toggler(state.indexed_palette.is_some())
+        .label("Indexed mode")
+        .on_toggle(Message::IndexedModeToggled);
+    let Some(palette) = &state.indexed_palette else {
+        return toggle.into();
+    };
+
+    let mut grid = widget::column![].spacing(2);
+    for (row_index, row) in palette.chunks(8).enumerate() {
+        let mut swatches = widget::row![].spacing(2);
+        for (column, rgba) in row.iter().enumerate() {
+            let index = row_index * 8 + column;
+            let selected = state.selected_palette_index == Some(index);
+            let color = crate::utils::rgba8_to_color(*rgba);
+            swatches = swatches.push(
+                widget::button(
+                    widget::container(widget::text(""))
+                        .width(Length::Fixed(18.0))
+                        .height(Length::Fixed(18.0))
+                        .style(move |_theme| widget::container::Style {
+                            background: Some(color.into()),
+                            border: iced::border::Border {
+                                radius: iced::border::Radius::from(2.0),
+                                width: if selected { 2.0 } else { 1.0 },
+                                color: if selected {
+                                    Color::from_rgb(0.0, 0.5, 1.0)
+                                } else {
+                                    Color::BLACK
+                                },
+                            },
+                            ..Default::default()
+                        }),
+                )
+                .on_press(if state.modifiers.alt() {
+                    Message::ColorUsageHighlighted(color)
+                } else {
+                    Message::PaletteEntrySelected(index)
+                })
+                .padding(0),
+            );
+        }
+        grid = grid.push(swatches);
+    }
+
+    // Entry 0 is the fixed transparent entry
+    let recolor = state
+        .selected_palette_index
+        .filter(|&index| index > 0)
+        .map(|index| Message::PaletteEntryRecolored {
+            index,
+            color: state.primary_color,
+        });
+
+    widget::column![
+        toggle,
+        widget::text(format!("{} entries", palette.len())).size(12),
+        widget::scrollable(grid).height(Length::Shrink),
+        widget::button("Set entry to primary color").on_press_maybe(recolor),
+    ]
+    .spacing(5)
+    .into()
+}
+
+fn color_count_control(state: &EditorState) -> Element<'_, Message> {
+    let count_text = if state.color_scan_pending {
+        "Scanning...".to_string()
+    } else {
+        match state.color_count {
+            Some(count) => format!("Color count: {}", count),
+            None => "Color count: -".to_string(),
+        }
+    };
+    let scan =
+        |rebuild| (!state.color_scan_pending).then_some(Message::ColorScanRequested { rebuild });
+
+    widget::column![
+        widget::row![
+            widget::text(count_text).size(12),
+            widget::horizontal_space(),
+            widget::button("Count").on_press_maybe(scan(None)),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+        widget::text("Rebuild palette from image").size(12),
+        widget::row![
+            widget::button("By frequency").on_press_maybe(scan(Some(PaletteSort::Frequency))),
+            widget::button("By hue").on_press_maybe(scan(Some(PaletteSort::Hue))),
+        ]
+        .spacing(5),
+        widget::button("Load palette from image...").on_press(Message::PaletteImportRequested),
+        widget::row![
+            widget::text(format!("Max colors: {}", state.palette_import_limit)).size(12),
+            widget::slider(2.0..=256.0, state.palette_import_limit as f32, |v| {
+                Message::PaletteImportLimitChanged(v as u32)
+            },),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+        widget::checkbox(
+            "Snap imported colors to palette",
+            state.snap_imports_to_palette
+        )
+        .on_toggle(Message::ImportSnapToggled),
+        widget::checkbox("Dither", state.dither_imports).on_toggle_maybe(
+            state
+                .snap_imports_to_palette
+                .then_some(Message::ImportDitherToggled)
+        ),
+        widget::checkbox("Add imported colors to palette", state.add_import_colors)
+            .on_toggle(Message::ImportColorsToggled),
+    ]
+    .spacing(5)
+    .into()
+}
@@ -0,0 +1,412 @@
+mod adjust;
+mod color;
+mod drawing;
+mod file;
+mod history;
+mod layers;
+mod selection;
+mod tiles;
+mod view;
+
+use crate::message::Message;
+use crate::state::{self, EditorState};
+use iced::Task;
+
+/// Apply `message` to one document, handing it to the reducer for its
+/// domain.
+pub fn update_document(
+    state: &mut EditorState,
+    clipboard: &mut Option<state::ClipboardData>,
+    message: Message,
+) -> Task<Message> {
+    if !matches!(
+        message,
+        Message::FillHovered { .. } | Message::FillHoverEnded | Message::None
+    ) {
+        state.generation = state.generation.wrapping_add(1);
+    }
+
+    match message {
+        Message::ToolSelected(..)
+        | Message::BrushSizeChanged(..)
+        | Message::BrushOpacityChanged(..)
+        | Message::DrawingStarted { .. }
+        | Message::PixelDrawn { .. }
+        | Message::DrawingEnded
+        | Message::CanvasEvent(..)
+        | Message::MirrorHorizontalToggled
+        | Message::MirrorVerticalToggled
+        | Message::PressureSensitivityToggled(..)
+        | Message::TextFontSelected(..)
+        | Message::TextTyped(..)
+        | Message::TextBackspaced
+        | Message::TextCommitted
+        | Message::TextCancelled
+        | Message::StabilizerChanged(..)
+        | Message::PencilJitterChanged(..)
+        | Message::PixelPerfectCurvesToggled(..)
+        | Message::SampleMergedToggled(..)
+        | Message::WheelBrushModifierChanged(..)
+        | Message::IsometricAssistToggled(..)
+        | Message::PixelSnapChanged(..)
+        | Message::FillHovered { .. }
+        | Message::FillHoverEnded
+        | Message::FillPreviewToggled(..)
+        | Message::FillPatternToggled(..)
+        | Message::WrapEdgesToggled
+        | Message::PreserveTransparencyToggled => drawing::update(state, clipboard, message),
+        Message::PrimaryColorChanged(..)
+        | Message::SecondaryColorChanged(..)
+        | Message::ColorPickerOpened(..)
+        | Message::ColorPickerSaturationValueChanged { .. }
+        | Message::ColorPickerHueChanged(..)
+        | Message::ColorPickerAlphaChanged(..)
+        | Message::ColorPickerHexChanged(..)
+        | Message::ColorPickerConfirmed
+        | Message::ColorPickerCancelled
+        | Message::ColorPicked(..)
+        | Message::RecentColorKeyPressed { .. }
+        | Message::UsedColorPicked(..)
+        | Message::ColorUsageHighlighted(..)
+        | Message::UsedColorPinned(..)
+        | Message::UsedColorRemoved(..)
+        | Message::UsedColorsCleared
+        | Message::UsedColorSortChanged(..)
+        | Message::NearDuplicatesOpened
+        | Message::NearDuplicatesToleranceChanged(..)
+        | Message::NearDuplicatesClosed
+        | Message::NearDuplicateMerged { .. }
+        | Message::IndexedModeToggled(..)
+        | Message::PaletteEntrySelected(..)
+        | Message::PaletteEntryRecolored { .. }
+        | Message::RampDraftStarted
+        | Message::RampDraftColorToggled(..)
+        | Message::RampDraftFinished
+        | Message::RampDraftCancelled
+        | Message::RampGeneratorOpened
+        | Message::RampGeneratorChanged(..)
+        | Message::RampGeneratorAccepted
+        | Message::RampGeneratorCancelled
+        | Message::RampDeleted(..)
+        | Message::RampStepped(..)
+        | Message::RampShiftOpened(..)
+        | Message::RampShiftChanged(..)
+        | Message::RampShiftApplied
+        | Message::RampShiftCancelled
+        | Message::ColorScanRequested { .. }
+        | Message::ColorScanCompleted { .. }
+        | Message::PaletteImportRequested
+        | Message::PaletteImported(..)
+        | Message::PaletteImportLimitChanged(..) => color::update(state, message),
+        Message::LayerAdded(..)
+        | Message::LayerDeleted(..)
+        | Message::LayerMoved { .. }
+        | Message::LayerDragStarted(..)
+        | Message::LayerDragHovered(..)
+        | Message::LayerDragEnded
+        | Message::LayerVisibilityToggled(..)
+        | Message::LayerSoloToggled(..)
+        | Message::HiddenLayerShown(..)
+        | Message::HiddenLayerWarningToggled(..)
+        | Message::LayerNameClicked(..)
+        | Message::LayerHovered(..)
+        | Message::LayerRenameStarted(..)
+        | Message::LayerRenameEdited(..)
+        | Message::LayerRenameCommitted
+        | Message::LayerRenameCancelled
+        | Message::LayerClippingToggled(..)
+        | Message::LayerSelected(..)
+        | Message::LayerOpacityChanged { .. }
+        | Message::ContinuousEditFinished
+        | Message::LayerRenamed { .. } => layers::update(state, message),
+        Message::SelectionStarted { .. }
+        | Message::SelectionUpdated { .. }
+        | Message::SelectionEnded
+        | Message::SelectionCloneStarted { .. }
+        | Message::SelectionCloneMoved { .. }
+        | Message::SelectionCloneEnded
+        | Message::ModifiersChanged(..)
+        | Message::SelectionCleared
+        | Message::CopySelection
+        | Message::PasteSelection
+        | Message::ClipboardTransformed(..)
+        | Message::PastePreviewStarted
+        | Message::PastePreviewPlaced { .. }
+        | Message::FloatingPasteNudged { .. }
+        | Message::FloatingPasteDragStarted { .. }
+        | Message::FloatingPasteMoved { .. }
+        | Message::FloatingPasteDragEnded
+        | Message::FloatingPasteCommitted
+        | Message::EscapePressed
+        | Message::CutSelection
+        | Message::TrimOnCopyToggled(..) => selection::update(state, clipboard, message),
+        Message::TileModeToggled(..)
+        | Message::TileSizeChanged { .. }
+        | Message::TilePickRequested
+        | Message::TileSelected { .. }
+        | Message::TileDragStarted { .. }
+        | Message::TileDropped(..)
+        | Message::SeamsExposed
+        | Message::SeamsRestored => tiles::update(state, message),
+        Message::CanvasResized { .. }
+        | Message::CanvasWidthInputChanged(..)
+        | Message::CanvasHeightInputChanged(..)
+        | Message::CanvasAspectLockToggled(..)
+        | Message::CanvasSizeSubmitted
+        | Message::ImageScaled(..)
+        | Message::ScaleFactorInputChanged(..)
+        | Message::FilterOpened(..)
+        | Message::FilterChanged(..)
+        | Message::ColorMadeTransparent(..)
+        | Message::StrayPixelsHighlighted
+        | Message::StrayPixelsHidden
+        | Message::StrayPixelsDeleted
+        | Message::AlphaEditToggled(..)
+        | Message::LuminanceToAlpha
+        | Message::AlphaToLuminance
+        | Message::TransparencyToleranceChanged(..)
+        | Message::FilterApplied
+        | Message::RotationApplied
+        | Message::GradientMapColorAdded(..)
+        | Message::GradientMapColorRemoved(..)
+        | Message::FilterCancelled
+        | Message::RotationCancelled
+        | Message::RotationChanged { .. }
+        | Message::LineShiftChanged { .. }
+        | Message::LineShiftApplied
+        | Message::LineShiftCancelled
+        | Message::CanvasCleared => adjust::update(state, message),
+        Message::NotificationDismissed
+        | Message::ZoomChanged(..)
+        | Message::ZoomIn
+        | Message::ZoomOut
+        | Message::ViewFlipped
+        | Message::ViewRotated
+        | Message::ZoomedAt { .. }
+        | Message::ViewPanned { .. }
+        | Message::ViewReset
+        | Message::SafeAreaSelected(..)
+        | Message::SafeAreaHidden
+        | Message::SafeAreaDraftStarted
+        | Message::SafeAreaDraftChanged(..)
+        | Message::SafeAreaDraftSaved
+        | Message::SafeAreaDraftCancelled
+        | Message::SafeAreaDeleted(..)
+        | Message::KioskModeEntered
+        | Message::KioskUnlockPressed
+        | Message::KioskUnlockReleased
+        | Message::ColorVisionChanged(..)
+        | Message::OutlineViewToggled(..)
+        | Message::DocumentScanRequested
+        | Message::DocumentScanned(..)
+        | Message::GridToggled
+        | Message::PanChanged { .. }
+        | Message::NineSliceToggled(..)
+        | Message::NineSliceSidecarToggled(..)
+        | Message::SliceGuideDragStarted(..)
+        | Message::SliceGuideMoved { .. }
+        | Message::SliceGuideDragEnded => view::update(state, message),
+        Message::Undo
+        | Message::Redo
+        | Message::UndoActiveLayerOnlyToggled(_)
+        | Message::RestorePointNameChanged(_)
+        | Message::RestorePointCreated
+        | Message::RestorePointRestored(_)
+        | Message::RestorePointDeleted(_)
+        | Message::RestorePointsSavedToggled(_) => history::update(state, message),
+        Message::FileNew
+        | Message::FileOpen
+        | Message::FileSave
+        | Message::FileSaveDialogResult { .. }
+        | Message::ExportProfileAddRequested
+        | Message::ExportProfileAdded { .. }
+        | Message::ExportProfileChanged(..)
+        | Message::ExportProfileRemoved(..)
+        | Message::ExportAllRequested
+        | Message::ExportAllFinished(..)
+        | Message::SelectionExportRequested
+        | Message::SelectionExportDialogResult { .. }
+        | Message::SelectionImportRequested
+        | Message::SelectionImageLoaded { .. }
+        | Message::ExportSettingsChanged(..)
+        | Message::FlattenAgainstMatte
+        | Message::FileLoaded { .. }
+        | Message::ProjectLoaded { .. }
+        | Message::FileSaved { .. }
+        | Message::FolderImportRequested
+        | Message::FolderImportStarted(..)
+        | Message::FolderImageLoaded { .. }
+        | Message::FolderImportFailed(..)
+        | Message::ImportSnapToggled(..)
+        | Message::ImportDitherToggled(..)
+        | Message::ImportColorsToggled(..)
+        | Message::ExportFailed(..)
+        | Message::CompareRequested
+        | Message::CompareLoaded { .. }
+        | Message::CompareFailed(..)
+        | Message::CompareViewChanged(..)
+        | Message::CompareClosed
+        | Message::TilesExportRequested
+        | Message::TilesExportFolderSelected(..)
+        | Message::OutlinesExportRequested
+        | Message::OutlinesExportPathSelected(..)
+        | Message::PatternSheetExportRequested
+        | Message::PatternSheetExportPathSelected(..) => file::update(state, message),
+        Message::DocumentAdded
+        | Message::DocumentSelected(_)
+        | Message::DocumentCloseRequested(_)
+        | Message::DocumentClosed(_)
+        | Message::ReopenSessionToggled(_)
+        | Message::RecordingStarted
+        | Message::RecordingStopped
+        | Message::RecordingSaved(_)
+        | Message::RecordingFailed(_)
+        | Message::ReplayRequested
+        | Message::ReplayLoaded(_)
+        | Message::WindowResized(..)
+        | Message::WindowMoved(_)
+        | Message::ScaleFactorChanged(_)
+        | Message::WindowCloseRequested(_) => {
+            // Handled at the app level in `update`
+            Task::none()
+        }
+        Message::None => Task::none(),
+    }
+}
+
+/// Run `messages` through `update_document` in order, as the app would,
+/// dropping the tasks they return.
+#[cfg(test)]
+pub fn apply(state: &mut EditorState, messages: impl IntoIterator<Item = Message>) {
+    let mut clipboard = None;
+    for message in messages {
+        let _ = update_document(state, &mut clipboard, message);
+    }
+}
+
+/// A one-pixel pencil stroke at (x, y) in `color`.
+#[cfg(test)]
+pub fn dot(x: u32, y: u32, color: iced::Color) -> [Message; 3] {
+    [
+        Message::PrimaryColorChanged(color),
+        Message::DrawingStarted {
+            x,
+            y,
+            pressure: None,
+        },
+        Message::DrawingEnded,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{ColorVision, SliceEdge, Tool};
+
+    fn dirtied_by(messages: impl IntoIterator<Item = Message>) -> bool {
+        let mut state = EditorState::new(8, 8);
+        state.add_layer("Top".to_string());
+        state.mark_saved();
+        apply(&mut state, messages);
+        state.is_dirty()
+    }
+
+    #[test]
+    fn saved_changes_mark_the_document_dirty() {
+        let cases: Vec<(&str, Vec<Message>)> = vec![
+            ("stroke", dot(1, 1, iced::Color::BLACK).to_vec()),
+            ("layer added", vec![Message::LayerAdded("New".to_string())]),
+            ("layer hidden", vec![Message::LayerVisibilityToggled(0)]),
+            ("layer clipped", vec![Message::LayerClippingToggled(1)]),
+            (
+                "layer renamed",
+                vec![Message::LayerRenamed {
+                    index: 0,
+                    name: "Base".to_string(),
+                }],
+            ),
+            (
+                "layer opacity",
+                vec![
+                    Message::LayerOpacityChanged {
+                        index: 1,
+                        opacity: 0.5,
+                    },
+                    Message::ContinuousEditFinished,
+                ],
+            ),
+            (
+                "palette imported",
+                vec![Message::PaletteImported(
+                    state::DocumentId::next(),
+                    vec![[255, 0, 0, 255]],
+                )],
+            ),
+            (
+                "tile size",
+                vec![Message::TileSizeChanged {
+                    width: 4,
+                    height: 4,
+                }],
+            ),
+            ("9-slice guides", vec![Message::NineSliceToggled(true)]),
+            (
+                "9-slice guide moved",
+                vec![
+                    Message::NineSliceToggled(true),
+                    Message::SliceGuideMoved {
+                        edge: SliceEdge::Left,
+                        position: 3,
+                    },
+                ],
+            ),
+        ];
+        for (name, messages) in cases {
+            assert!(
+                dirtied_by(messages),
+                "{} should mark the document dirty",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn view_changes_leave_the_document_clean() {
+        let cases: Vec<(&str, Vec<Message>)> = vec![
+            ("zoom", vec![Message::ZoomChanged(4.0), Message::ZoomIn]),
+            (
+                "pan",
+                vec![
+                    Message::ViewPanned { dx: 5.0, dy: 2.0 },
+                    Message::PanChanged { x: 1.0, y: 1.0 },
+                ],
+            ),
+            ("grid", vec![Message::GridToggled]),
+            (
+                "flip and rotate",
+                vec![Message::ViewFlipped, Message::ViewRotated],
+            ),
+            (
+                "color vision",
+                vec![Message::ColorVisionChanged(ColorVision::Protanopia)],
+            ),
+            ("outline view", vec![Message::OutlineViewToggled(true)]),
+            ("tile mode", vec![Message::TileModeToggled(true)]),
+            ("layer selected", vec![Message::LayerSelected(0)]),
+            (
+                "tool options",
+                vec![
+                    Message::ToolSelected(Tool::Eraser),
+                    Message::BrushSizeChanged(3),
+                ],
+            ),
+        ];
+        for (name, messages) in cases {
+            assert!(
+                !dirtied_by(messages),
+                "{} should not mark the document dirty",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use crate::message::Message;
+use crate::state::{self, EditorState};
+use crate::{filters, utils};
+use iced::Task;
+
+/// Whole-image edits: canvas size, scaling, filters, rotation and pixel
+/// cleanup.
+pub fn update(state: &mut EditorState, message: Message) -> Task<Message> {
+    match message {
+        Message::CanvasResized { width, height } => {
+            if width == 0 || height == 0 {
+                state.notification = Some("Canvas width and height must be at least 1".into());
+                return Task::none();
+            }
+            let max = state.max_canvas_size;
+            // Tilesets only stay intact at whole multiples of the tile size
+            let (width, height) = if state.tile_mode {
+                let snapped = (
+                    utils::snap_to_multiple(width, state.tile_width, max),
+                    utils::snap_to_multiple(height, state.tile_height, max),
+                );
+                if snapped != (width, height) {
+                    state.notification = Some(format!(
+                        "Snapped {}x{} to {}x{} to fit {}x{} tiles",
+                        width, height, snapped.0, snapped.1, state.tile_width, state.tile_height
+                    ));
+                }
+                snapped
+            } else {
+                (width, height)
+            };
+            if width > max || height > max {
+                state.notification = Some(format!(
+                    "Canvas size is limited to {}x{}; {}x{} was clamped",
+                    max, max, width, height
+                ));
+            }
+            let (width, height) = (width.min(max), height.min(max));
+            let before = state::DocSnapshot::capture(state);
+            state.canvas_width = width;
+            state.canvas_height = height;
+            // Resize all layers
+            for layer in &mut state.layers {
+                let new_pixels = vec![0u8; (width * height * 4) as usize];
+                layer.pixels = new_pixels;
+                layer.width = width;
+                layer.height = height;
+            }
+            state.push_snapshot(before);
+        }
+        Message::CanvasWidthInputChanged(input) => {
+            if state.canvas_aspect_locked
+                && let Ok(width) = input.trim().parse::<u32>()
+            {
+                let height = proportional(width, state.canvas_height, state.canvas_width);
+                state.canvas_height_input = Some(height.to_string());
+            }
+            state.canvas_width_input = Some(input);
+        }
+        Message::CanvasHeightInputChanged(input) => {
+            if state.canvas_aspect_locked
+                && let Ok(height) = input.trim().parse::<u32>()
+            {
+                let width = proportional(height, state.canvas_width, state.canvas_height);
+                state.canvas_width_input = Some(width.to_string());
+            }
+            state.canvas_height_input = Some(input);
+        }
+        Message::CanvasAspectLockToggled(locked) => {
+            state.canvas_aspect_locked = locked;
+        }
+        Message::CanvasSizeSubmitted => {
+            let width = state.canvas_width_input.take();
+            let height = state.canvas_height_input.take();
+            let parse = |input: Option<String>, current: u32| match input {
+                Some(text) => text.trim().parse::<u32>().ok(),
+                None => Some(current),
+            };
+            match (
+                parse(width, state.canvas_width),
+                parse(height, state.canvas_height),
+            ) {
+                (Some(width), Some(height)) => {
+                    if (width, height) != (state.canvas_width, state.canvas_height) {
+                        // Resize through the message path so recordings see it
+                        return Task::done(Message::CanvasResized { width, height });
+                    }
+                }
+                _ => {
+                    state.notification =
+                        Some("Canvas width and height must be whole numbers".into());
+                }
+            }
+        }
+        Message::ImageScaled(factor) => {
+            if let Err(e) = state.scale_canvas(factor) {
+                state.notification = Some(e);
+            }
+        }
+        Message::ScaleFactorInputChanged(input) => {
+            state.scale_factor_input = input;
+        }
+        Message::FilterOpened(filter) | Message::FilterChanged(filter) => {
+            filters::preview(state, filter);
+        }
+        Message::ColorMadeTransparent(color) => {
+            state.cancel_preview();
+            let key = match color {
+                Some(color) => Some(utils::color_to_rgba8(color)),
+                None => state
+                    .active_layer()
+                    .map(|layer| utils::color_to_rgba8(layer.get_pixel(0, 0))),
+            };
+            if let Some(key) = key {
+                let tolerance = state.transparency_tolerance;
+                let cleared = filters::make_color_transparent(state, key, tolerance);
+                state.notification = Some(format!(
+                    "Made {} pixel{} transparent",
+                    cleared,
+                    if cleared == 1 { "" } else { "s" }
+                ));
+            }
+        }
+        Message::StrayPixelsHighlighted => {
+            let strays = state
+                .active_layer()
+                .map(filters::find_stray_pixels)
+                .unwrap_or_default();
+            state.notification = Some(format!(
+                "Found {} stray pixel{}",
+                strays.len(),
+                if strays.len() == 1 { "" } else { "s" }
+            ));
+            state.stray_pixels = Some(strays);
+        }
+        Message::StrayPixelsHidden => {
+            state.stray_pixels = None;
+        }
+        Message::StrayPixelsDeleted => {
+            state.cancel_preview();
+            let cleared = filters::delete_stray_pixels(state);
+            state.stray_pixels = None;
+            state.notification = Some(format!(
+                "Deleted {} stray pixel{}",
+                cleared,
+                if cleared == 1 { "" } else { "s" }
+            ));
+        }
+        Message::AlphaEditToggled(enabled) => {
+            state.alpha_edit = enabled;
+        }
+        Message::LuminanceToAlpha => {
+            state.cancel_preview();
+            filters::luminance_to_alpha(state);
+        }
+        Message::AlphaToLuminance => {
+            state.cancel_preview();
+            filters::alpha_to_luminance(state);
+        }
+        Message::TransparencyToleranceChanged(tolerance) => {
+            state.transparency_tolerance = tolerance;
+        }
+        Message::FilterApplied | Message::RotationApplied | Message::LineShiftApplied => {
+            state.commit_preview();
+        }
+        Message::GradientMapColorAdded(color) => {
+            if let Some(filters::Filter::GradientMap { mut ramp, stepped }) =
+                state.active_filter.clone()
+            {
+                ramp.push(utils::color_to_rgba8(color));
+                filters::preview(state, filters::Filter::GradientMap { ramp, stepped });
+            }
+        }
+        Message::GradientMapColorRemoved(index) => {
+            if let Some(filters::Filter::GradientMap { mut ramp, stepped }) =
+                state.active_filter.clone()
+                && index < ramp.len()
+            {
+                ramp.remove(index);
+                filters::preview(state, filters::Filter::GradientMap { ramp, stepped });
+            }
+        }
+        Message::FilterCancelled | Message::RotationCancelled | Message::LineShiftCancelled => {
+            state.cancel_preview();
+        }
+        Message::RotationChanged { degrees, algorithm } => {
+            state.set_rotation(degrees.clamp(-180.0, 180.0), algorithm);
+        }
+        Message::LineShiftChanged {
+            axis,
+            index,
+            offset,
+        } => {
+            state.set_line_shift(axis, index, offset);
+        }
+        Message::CanvasCleared => {
+            for layer in &mut state.layers {
+                layer.pixels.fill(0);
+            }
+            state.mark_dirty();
+        }
+        _ => {}
+    }
+
+    Task::none()
+}
+
+/// `value` scaled by `numerator / denominator`, rounded, and at least 1.
+fn proportional(value: u32, numerator: u32, denominator: u32) -> u32 {
+    ((value as u64 * numerator as u64 + denominator as u64 / 2) / denominator.max(1) as u64).max(1)
+        as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::update::{apply, dot};
+    use iced::Color;
+
+    #[test]
+    fn resizing_undoes_to_the_old_size_and_pixels() {
+        let mut state = EditorState::new(4, 4);
+        apply(&mut state, dot(3, 3, Color::from_rgb8(255, 0, 0)));
+        let before = state.layers[0].pixels.clone();
+
+        apply(
+            &mut state,
+            [Message::CanvasResized {
+                width: 6,
+                height: 2,
+            }],
+        );
+        assert_eq!((state.canvas_width, state.canvas_height), (6, 2));
+        assert_eq!(state.layers[0].pixels.len(), 6 * 2 * 4);
+
+        apply(&mut state, [Message::Undo]);
+        assert_eq!((state.canvas_width, state.canvas_height), (4, 4));
+        assert_eq!(state.layers[0].pixels, before);
+        apply(&mut state, [Message::Redo]);
+        assert_eq!((state.canvas_width, state.canvas_height), (6, 2));
+    }
+
+    #[test]
+    fn empty_canvas_sizes_are_rejected() {
+        let mut state = EditorState::new(4, 4);
+        apply(
+            &mut state,
+            [Message::CanvasResized {
+                width: 0,
+                height: 3,
+            }],
+        );
+        assert_eq!((state.canvas_width, state.canvas_height), (4, 4));
+        assert!(state.notification.is_some());
+    }
+}
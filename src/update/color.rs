@@ -0,0 +1,423 @@
+use crate::message::Message;
+use crate::state::{self, EditorState};
+use crate::{file_io, palette, utils};
+use iced::Task;
+
+/// Primary and secondary colors, the color picker, used colors, the
+/// indexed palette and ramps.
+pub fn update(state: &mut EditorState, message: Message) -> Task<Message> {
+    match message {
+        Message::PrimaryColorChanged(color) => {
+            state.primary_color = color;
+        }
+        Message::SecondaryColorChanged(color) => {
+            state.secondary_color = color;
+        }
+        Message::ColorPickerOpened(target) => {
+            let color = match target {
+                state::ColorTarget::Primary => state.primary_color,
+                state::ColorTarget::Secondary => state.secondary_color,
+            };
+            state.color_picker = Some(state::ColorPickerState::new(target, color));
+        }
+        Message::ColorPickerSaturationValueChanged { saturation, value } => {
+            if let Some(picker) = &mut state.color_picker {
+                picker.saturation = saturation.clamp(0.0, 1.0);
+                picker.value = value.clamp(0.0, 1.0);
+                picker.sync_hex();
+            }
+        }
+        Message::ColorPickerHueChanged(hue) => {
+            if let Some(picker) = &mut state.color_picker {
+                picker.hue = hue.clamp(0.0, 360.0);
+                picker.sync_hex();
+            }
+        }
+        Message::ColorPickerAlphaChanged(alpha) => {
+            if let Some(picker) = &mut state.color_picker {
+                picker.alpha = alpha.clamp(0.0, 1.0);
+                picker.sync_hex();
+            }
+        }
+        Message::ColorPickerHexChanged(text) => {
+            if let Some(picker) = &mut state.color_picker {
+                // Half-typed values stay in the field without moving the color
+                if let Some(rgba) = utils::parse_hex_color(&text) {
+                    let target = picker.target;
+                    let original = picker.original;
+                    *picker = state::ColorPickerState::new(target, utils::rgba8_to_color(rgba));
+                    picker.original = original;
+                }
+                picker.hex_input = text;
+            }
+        }
+        Message::ColorPickerConfirmed => {
+            if let Some(picker) = state.color_picker.take() {
+                // Goes through the regular color messages so recordings
+                // capture it
+                let color = picker.color();
+                return Task::done(match picker.target {
+                    state::ColorTarget::Primary => Message::PrimaryColorChanged(color),
+                    state::ColorTarget::Secondary => Message::SecondaryColorChanged(color),
+                });
+            }
+        }
+        Message::ColorPickerCancelled => {
+            state.color_picker = None;
+        }
+        Message::ColorPicked(color) => {
+            // Color picker clicked - swap primary and secondary or set primary
+            state.primary_color = color;
+        }
+        Message::RecentColorKeyPressed { key, shift } if key == state.color_cycle_key => {
+            state.step_recent_color(if shift { -1 } else { 1 });
+        }
+        Message::UsedColorPicked(color) => {
+            state.primary_color = color;
+        }
+        Message::ColorUsageHighlighted(color) => {
+            let rgba = utils::color_to_rgba8(color);
+            let pixels = palette::find_color(
+                state
+                    .layers
+                    .iter()
+                    .filter(|layer| layer.visible)
+                    .map(|layer| layer.pixels.as_slice()),
+                state.canvas_width,
+                rgba,
+            );
+            state.notification = Some(format!(
+                "{} pixel{} use #{:02X}{:02X}{:02X}{:02X} (Escape clears the highlight)",
+                pixels.len(),
+                if pixels.len() == 1 { "" } else { "s" },
+                rgba[0],
+                rgba[1],
+                rgba[2],
+                rgba[3]
+            ));
+            state.color_usage = Some(state::ColorUsage {
+                color: rgba,
+                pixels,
+                revision: state.history.revision,
+            });
+        }
+        Message::UsedColorPinned(index) => {
+            if let Some(used) = state.used_colors.get_mut(index) {
+                used.pinned = !used.pinned;
+                state.mark_dirty();
+            }
+        }
+        Message::UsedColorRemoved(index) if index < state.used_colors.len() => {
+            state.used_colors.remove(index);
+            state.mark_dirty();
+        }
+        Message::UsedColorsCleared => {
+            state.used_colors.retain(|used| used.pinned);
+            state.mark_dirty();
+        }
+        Message::UsedColorSortChanged(sort) => {
+            state.used_color_sort = sort;
+            // Usage order needs fresh counts
+            if sort == state::UsedColorSort::Usage && !state.color_scan_pending {
+                return Task::done(Message::ColorScanRequested { rebuild: None });
+            }
+        }
+        Message::NearDuplicatesOpened => {
+            state.duplicate_tolerance = Some(state::DEFAULT_DUPLICATE_TOLERANCE);
+        }
+        Message::NearDuplicatesToleranceChanged(tolerance) => {
+            state.duplicate_tolerance = Some(tolerance.max(1));
+        }
+        Message::NearDuplicatesClosed => {
+            state.duplicate_tolerance = None;
+        }
+        Message::NearDuplicateMerged { from, into } => {
+            if state.indexed_palette.is_some() {
+                state.notification = Some(
+                    "Colors can't be merged in indexed mode; recolor the palette instead".into(),
+                );
+                return Task::none();
+            }
+            let Some(index) = state
+                .used_colors
+                .iter()
+                .position(|used| used.color.into_rgba8() == from)
+            else {
+                return Task::none();
+            };
+            let removed = state.used_colors.remove(index);
+            if removed.pinned
+                && let Some(kept) = state
+                    .used_colors
+                    .iter_mut()
+                    .find(|used| used.color.into_rgba8() == into)
+            {
+                kept.pinned = true;
+            }
+            let recolored = state.remap_colors(&[from], &[into])[0];
+            if let Some(count) = state.color_frequencies.remove(&from) {
+                *state.color_frequencies.entry(into).or_insert(0) += count;
+            }
+            state.mark_dirty();
+            state.notification = Some(format!(
+                "Merged {} into {}, recoloring {} pixels",
+                utils::hex_color(from),
+                utils::hex_color(into),
+                recolored
+            ));
+        }
+        Message::IndexedModeToggled(enabled) => {
+            state.cancel_preview();
+            if enabled {
+                state.convert_to_indexed();
+            } else {
+                state.convert_to_rgba();
+            }
+            state.mark_dirty();
+        }
+        Message::PaletteEntrySelected(index) => {
+            if let Some(palette) = &state.indexed_palette
+                && let Some(rgba) = palette.get(index)
+            {
+                state.selected_palette_index = Some(index);
+                state.primary_color = utils::rgba8_to_color(*rgba);
+            }
+        }
+        Message::PaletteEntryRecolored { index, color } => {
+            state.recolor_palette_entry(index, utils::color_to_rgba8(color));
+            state.mark_dirty();
+        }
+        Message::RampDraftStarted => {
+            state.ramp_draft = Some(Vec::new());
+        }
+        Message::RampDraftColorToggled(color) => {
+            if let Some(draft) = &mut state.ramp_draft {
+                let rgba = utils::color_to_rgba8(color);
+                if let Some(index) = draft.iter().position(|entry| *entry == rgba) {
+                    draft.remove(index);
+                } else {
+                    draft.push(rgba);
+                }
+            }
+        }
+        Message::RampDraftFinished => {
+            if let Some(draft) = state.ramp_draft.take()
+                && draft.len() >= 2
+            {
+                state.ramps.push(draft);
+                state.mark_dirty();
+            }
+        }
+        Message::RampDraftCancelled => {
+            state.ramp_draft = None;
+        }
+        Message::RampGeneratorOpened => {
+            state.ramp_generator = Some(state::RampGenerator::default());
+        }
+        Message::RampGeneratorChanged(generator) => {
+            state.ramp_generator = Some(state::RampGenerator {
+                steps: generator.steps.clamp(3, 9),
+                hue_shift: generator.hue_shift.clamp(-30.0, 30.0),
+            });
+        }
+        Message::RampGeneratorAccepted => {
+            if let Some(generator) = state.ramp_generator.take() {
+                let ramp = generator.generate(state.primary_color, state.secondary_color);
+                for rgba in &ramp {
+                    state.add_used_color(utils::rgba8_to_color(*rgba));
+                }
+                state.ramps.push(ramp);
+                state.mark_dirty();
+            }
+        }
+        Message::RampGeneratorCancelled => {
+            state.ramp_generator = None;
+        }
+        Message::RampDeleted(index) if index < state.ramps.len() => {
+            state.ramps.remove(index);
+            state.ramp_shift = None;
+            state.mark_dirty();
+        }
+        Message::RampShiftOpened(ramp) if ramp < state.ramps.len() => {
+            state.ramp_shift = Some(state::RampShift::new(ramp));
+        }
+        Message::RampShiftChanged(shift) => {
+            state.ramp_shift = Some(state::RampShift {
+                hue: shift.hue.clamp(-180.0, 180.0),
+                saturation: shift.saturation.clamp(-1.0, 1.0),
+                value: shift.value.clamp(-1.0, 1.0),
+                ..shift
+            });
+        }
+        Message::RampShiftApplied => {
+            if state.indexed_palette.is_some() {
+                state.notification = Some(
+                    "Ramps can't be shifted in indexed mode; recolor the palette instead".into(),
+                );
+                return Task::none();
+            }
+            let Some(shift) = state.ramp_shift.take() else {
+                return Task::none();
+            };
+            let Some(shifted) = shift.shifted(&state.ramps) else {
+                return Task::none();
+            };
+            let original = state.ramps[shift.ramp].clone();
+            let counts = state.remap_colors(&original, &shifted);
+            let total: usize = counts.iter().sum();
+            for rgba in &shifted {
+                state.add_used_color(utils::rgba8_to_color(*rgba));
+            }
+            // The original ramp stays so the shift can be compared or redone
+            if !state.ramps.contains(&shifted) {
+                state.ramps.push(shifted);
+                state.mark_dirty();
+            }
+            let per_entry = counts
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            state.notification = Some(format!(
+                "Shifted ramp {}: {} pixels changed ({} per entry)",
+                shift.ramp + 1,
+                total,
+                per_entry
+            ));
+        }
+        Message::RampShiftCancelled => {
+            state.ramp_shift = None;
+        }
+        Message::RampStepped(step) => {
+            let primary = utils::color_to_rgba8(state.primary_color);
+            if let Some(color) = palette::step_ramp(&state.ramps, primary, step) {
+                state.primary_color = utils::rgba8_to_color(color);
+            }
+        }
+        Message::ColorScanRequested { rebuild } => {
+            if state.color_scan_pending {
+                return Task::none();
+            }
+            state.color_scan_pending = true;
+            let document = state.id;
+            let buffers: Vec<Vec<u8>> = state
+                .layers
+                .iter()
+                .filter(|layer| layer.visible)
+                .map(|layer| layer.pixels.clone())
+                .collect();
+            return Task::perform(
+                async move { palette::count_colors(buffers.iter().map(Vec::as_slice)) },
+                move |colors| Message::ColorScanCompleted {
+                    document,
+                    colors,
+                    rebuild,
+                },
+            );
+        }
+        Message::ColorScanCompleted {
+            mut colors,
+            rebuild,
+            ..
+        } => {
+            state.color_scan_pending = false;
+            state.color_count = Some(colors.len());
+            state.color_frequencies = colors.iter().copied().collect();
+            if let Some(sort) = rebuild {
+                palette::sort_colors(&mut colors, sort);
+                state.used_colors = colors
+                    .into_iter()
+                    .map(|(rgba, _)| state::UsedColor::new(utils::rgba8_to_color(rgba)))
+                    .collect();
+                state.mark_dirty();
+            }
+        }
+        Message::PaletteImportRequested => {
+            let max_colors = state.palette_import_limit as usize;
+            let document = state.id;
+            return Task::perform(
+                async move {
+                    let file = rfd::AsyncFileDialog::new()
+                        .add_filter(
+                            "Image files",
+                            &["png", "jpg", "jpeg", "gif", "bmp", "webp", "tga"],
+                        )
+                        .pick_file()
+                        .await;
+
+                    if let Some(file) = file {
+                        match file_io::load_palette(file.path(), max_colors) {
+                            Ok(colors) => Message::PaletteImported(document, colors),
+                            Err(e) => {
+                                eprintln!("Failed to load palette: {}", e);
+                                Message::None
+                            }
+                        }
+                    } else {
+                        Message::None
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::PaletteImported(_, colors) => {
+            state.used_colors = colors
+                .into_iter()
+                .map(|rgba| state::UsedColor::new(utils::rgba8_to_color(rgba)))
+                .collect();
+            state.mark_dirty();
+        }
+        Message::PaletteImportLimitChanged(limit) => {
+            state.palette_import_limit = limit.clamp(2, 256);
+        }
+        _ => {}
+    }
+
+    Task::none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::update::apply;
+
+    // Apply one message the way the app does, snapping written pixels back
+    // to palette entries afterwards
+    fn send(state: &mut EditorState, message: Message) {
+        apply(state, [message]);
+        state.sync_indexed();
+    }
+
+    #[test]
+    fn converting_to_indexed_and_back_undoes_in_one_step() {
+        // 400 distinct colors, more than a palette holds
+        let mut state = EditorState::new(20, 20);
+        for y in 0..20 {
+            for x in 0..20 {
+                state.layers[0].set_rgba8(x, y, [x as u8 * 12, y as u8 * 12, 128, 255]);
+            }
+        }
+        let original = state.layers[0].pixels.clone();
+
+        send(&mut state, Message::IndexedModeToggled(true));
+        assert!(state.indexed_palette.is_some());
+        let quantized = state.layers[0].pixels.clone();
+        assert_ne!(quantized, original);
+
+        send(&mut state, Message::Undo);
+        assert!(state.indexed_palette.is_none());
+        assert!(state.layers[0].indices.is_none());
+        assert_eq!(state.layers[0].pixels, original);
+
+        send(&mut state, Message::Redo);
+        assert!(state.indexed_palette.is_some());
+        assert_eq!(state.layers[0].pixels, quantized);
+
+        send(&mut state, Message::IndexedModeToggled(false));
+        assert!(state.indexed_palette.is_none());
+        send(&mut state, Message::Undo);
+        assert!(state.indexed_palette.is_some());
+        assert!(state.layers[0].indices.is_some());
+        assert_eq!(state.layers[0].pixels, quantized);
+    }
+}
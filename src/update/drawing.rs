@@ -0,0 +1,461 @@
+use crate::message::Message;
+use crate::state::{self, EditorState};
+use crate::{selection, tools, utils};
+use iced::Task;
+
+/// Tool choice, brush settings, strokes, shapes, text and fill.
+pub fn update(
+    state: &mut EditorState,
+    clipboard: &mut Option<state::ClipboardData>,
+    message: Message,
+) -> Task<Message> {
+    match message {
+        Message::ToolSelected(tool) => {
+            tools::commit_floating_paste(state);
+            tools::commit_pending_text(state);
+            state.current_tool = tool;
+            state.fill_preview = None;
+            state.shape_draft = None;
+        }
+        Message::BrushSizeChanged(size) => {
+            let tool = state.current_tool;
+            state.options_for_mut(tool).brush_size = size.clamp(1, 20);
+        }
+        Message::BrushOpacityChanged(opacity) => {
+            let tool = state.current_tool;
+            state.options_for_mut(tool).opacity = utils::clamp_f32(opacity, 0.0, 1.0);
+        }
+        Message::DrawingStarted { x, y, pressure } => {
+            let is_selection_tool = matches!(state.current_tool, state::Tool::Selection);
+            let is_eyedropper = matches!(state.current_tool, state::Tool::Eyedropper);
+            let is_text = matches!(state.current_tool, state::Tool::Text);
+
+            if state.current_tool == state::Tool::Move {
+                state.cancel_preview();
+            }
+            state.is_drawing = !is_selection_tool && !is_eyedropper && !is_text;
+            state.is_selecting = is_selection_tool;
+            state.last_pixel = Some((x, y));
+            state.last_pressure = None;
+            state.stroke_positions.clear();
+            state.stroke_pixels.clear();
+            state.fill_preview = None;
+            // The highlight goes stale as soon as the layer is edited
+            state.stray_pixels = None;
+            state.recent_color_chip = false;
+            state.stroke_revision = Some(state.history.revision);
+            if state.is_drawing {
+                warn_if_hidden(state);
+            }
+
+            match state.current_tool {
+                state::Tool::Pencil | state::Tool::Eraser => {
+                    state.stabilize(x, y);
+                    tools::apply_brush_stroke(state, x, y, pressure);
+                    state.isometric_stroke =
+                        state.tool_options.freehand.isometric_assist.then_some(
+                            state::IsometricStroke {
+                                anchor: (x, y),
+                                direction: None,
+                                end: (x, y),
+                            },
+                        );
+                }
+                // Shift-click paints the whole selection, whatever its colors
+                state::Tool::Fill if state.modifiers.shift() => {
+                    match tools::fill_selection(state) {
+                        Some(summary) => tools::record_fill(state, summary),
+                        None => {
+                            state.notification = Some(
+                                "Shift-click fills the selection; select an area first".into(),
+                            );
+                        }
+                    }
+                }
+                state::Tool::Fill => {
+                    let pattern = state
+                        .tool_options
+                        .fill
+                        .pattern
+                        .then_some(clipboard.as_ref())
+                        .flatten();
+                    if let Some(summary) = tools::apply_fill(state, x, y, pattern) {
+                        tools::record_fill(state, summary);
+                    }
+                    if state.tool_options.fill.pattern && clipboard.is_none() {
+                        let summary = state.notification.take().unwrap_or_default();
+                        state.notification = Some(format!(
+                            "{}; the clipboard is empty, so the primary color was used",
+                            summary
+                        ));
+                    }
+                }
+                state::Tool::Selection => {
+                    // Shift/Ctrl drags combine with the current selection;
+                    // a plain drag starts over
+                    state.selection_base = match state.selection_mode() {
+                        selection::SelectionMode::Replace => None,
+                        mode => state.effective_selection().map(|mask| (mode, mask)),
+                    };
+                    state.selection_mask = None;
+                    state.selection = Some(iced::Rectangle {
+                        x: x as f32,
+                        y: y as f32,
+                        width: 0.0,
+                        height: 0.0,
+                    });
+                }
+                state::Tool::Eyedropper => {
+                    tools::apply_eyedropper(state, x, y);
+                }
+                state::Tool::Line | state::Tool::Rectangle | state::Tool::Ellipse => {
+                    state.shape_draft = Some(state::ShapeDraft {
+                        tool: state.current_tool,
+                        anchor: (x, y),
+                        current: (x, y),
+                        control: None,
+                    });
+                }
+                state::Tool::Curve => match &mut state.shape_draft {
+                    // Second drag: bend the curve placed by the first
+                    Some(draft) if draft.tool == state::Tool::Curve && draft.control.is_some() => {
+                        draft.control = Some((x, y));
+                    }
+                    _ => {
+                        state.shape_draft = Some(state::ShapeDraft {
+                            tool: state::Tool::Curve,
+                            anchor: (x, y),
+                            current: (x, y),
+                            control: None,
+                        });
+                    }
+                },
+                state::Tool::Move => {
+                    // Ctrl at drag start moves a copy and leaves the original
+                    if state.modifiers.command() {
+                        state.begin_layer_copy();
+                        state.mark_dirty();
+                    }
+                    state.layer_move_anchor = Some((x, y));
+                }
+                state::Tool::Text => {
+                    // Clicking elsewhere finishes the text being typed
+                    tools::commit_pending_text(state);
+                    state.pending_text = Some(state::PendingText {
+                        x,
+                        y,
+                        text: String::new(),
+                    });
+                }
+            }
+        }
+        Message::PixelDrawn { x, y, pressure } => {
+            let (x, y) = match state.current_tool {
+                state::Tool::Pencil | state::Tool::Eraser if state.is_drawing => {
+                    state.stabilize(x, y)
+                }
+                _ => (x, y),
+            };
+            if state.is_drawing {
+                // Prevent drawing the same pixel twice in a row
+                if state.last_pixel != Some((x, y)) {
+                    match state.current_tool {
+                        state::Tool::Pencil | state::Tool::Eraser
+                            if state.isometric_stroke.is_some() =>
+                        {
+                            tools::apply_isometric_stroke(state, x, y, pressure);
+                        }
+                        state::Tool::Pencil | state::Tool::Eraser => {
+                            tools::apply_brush_stroke(state, x, y, pressure);
+                        }
+                        state::Tool::Line | state::Tool::Rectangle | state::Tool::Ellipse => {
+                            // Only the preview moves; pixels land on release
+                            if let Some(draft) = &mut state.shape_draft {
+                                draft.current = (x, y);
+                            }
+                        }
+                        state::Tool::Curve => {
+                            if let Some(draft) = &mut state.shape_draft {
+                                match &mut draft.control {
+                                    Some(control) => *control = (x, y),
+                                    None => draft.current = (x, y),
+                                }
+                            }
+                        }
+                        state::Tool::Move => {
+                            if let Some((anchor_x, anchor_y)) = state.layer_move_anchor {
+                                let offset =
+                                    (x as i32 - anchor_x as i32, y as i32 - anchor_y as i32);
+                                if let Some(pixels) = tools::moved_pixels(state, offset) {
+                                    state.set_preview(pixels);
+                                }
+                            }
+                        }
+                        state::Tool::Fill
+                        | state::Tool::Selection
+                        | state::Tool::Eyedropper
+                        | state::Tool::Text => {
+                            // Fill only happens on click, not drag
+                            // Selection is handled by SelectionUpdated messages
+                            // Eyedropper only works on click
+                        }
+                    }
+                    // Updated after painting so strokes can interpolate from
+                    // the previous position
+                    state.last_pixel = Some((x, y));
+                }
+            }
+        }
+        Message::DrawingEnded => {
+            match &mut state.shape_draft {
+                // The first drag of a curve only places its ends; it starts
+                // straight and waits for a second drag to bend it
+                Some(draft)
+                    if draft.tool == state::Tool::Curve
+                        && draft.control.is_none()
+                        && draft.anchor != draft.current =>
+                {
+                    draft.control = Some((
+                        (draft.anchor.0 + draft.current.0) / 2,
+                        (draft.anchor.1 + draft.current.1) / 2,
+                    ));
+                }
+                _ => tools::commit_shape(state),
+            }
+            if state.layer_move_anchor.take().is_some() {
+                // Ends a Ctrl+drag copy, taking the moved pixels with it
+                state.finish_continuous_edit();
+                state.commit_preview();
+            }
+            // Remember the color once a stroke has actually painted with it
+            if state
+                .stroke_revision
+                .take()
+                .is_some_and(|start| start != state.history.revision)
+                && matches!(
+                    state.current_tool,
+                    state::Tool::Pencil
+                        | state::Tool::Fill
+                        | state::Tool::Line
+                        | state::Tool::Rectangle
+                        | state::Tool::Ellipse
+                        | state::Tool::Curve
+                )
+            {
+                state.remember_stroke_color(state.primary_color);
+            }
+            state.isometric_stroke = None;
+            state.is_drawing = false;
+            state.last_pixel = None;
+            state.last_pressure = None;
+            state.stroke_positions.clear();
+            state.stroke_pixels.clear();
+            if !matches!(state.current_tool, state::Tool::Selection) {
+                state.is_selecting = false;
+            }
+        }
+        Message::CanvasEvent(event) => {
+            // Forward canvas events if needed
+            // Most are handled directly by canvas program
+            // Handle any additional canvas events here if needed
+            let _ = event;
+        }
+        Message::MirrorHorizontalToggled => {
+            state.mirror_horizontal = !state.mirror_horizontal;
+        }
+        Message::MirrorVerticalToggled => {
+            state.mirror_vertical = !state.mirror_vertical;
+        }
+        Message::PressureSensitivityToggled(enabled) => {
+            state.tool_options.freehand.pressure_sensitive = enabled;
+        }
+        Message::TextFontSelected(font) => {
+            state.tool_options.text.font = font;
+        }
+        Message::TextTyped(text) => {
+            if let Some(pending) = &mut state.pending_text {
+                pending.text.push_str(&text);
+            }
+        }
+        Message::TextBackspaced => {
+            if let Some(pending) = &mut state.pending_text {
+                pending.text.pop();
+            }
+        }
+        Message::TextCommitted => {
+            tools::commit_pending_text(state);
+        }
+        Message::TextCancelled => {
+            state.pending_text = None;
+        }
+        Message::StabilizerChanged(strength) => {
+            state.tool_options.freehand.stabilizer = strength.min(10);
+        }
+        Message::PencilJitterChanged(jitter) => {
+            state.tool_options.jitter = state::JitterOptions {
+                value: jitter.value.min(state::MAX_VALUE_JITTER),
+                hue: jitter.hue.min(state::MAX_HUE_JITTER),
+                ..jitter
+            };
+        }
+        Message::PixelPerfectCurvesToggled(enabled) => {
+            state.tool_options.curve.pixel_perfect = enabled;
+        }
+        Message::SampleMergedToggled(tool, enabled) => match tool {
+            state::Tool::Fill => {
+                state.tool_options.fill.sample_merged = enabled;
+                state.fill_preview = None;
+            }
+            state::Tool::Eyedropper => state.tool_options.eyedropper.sample_merged = enabled,
+            _ => {}
+        },
+        Message::WheelBrushModifierChanged(modifier) => {
+            state.wheel_brush_modifier = modifier;
+        }
+        Message::IsometricAssistToggled(enabled) => {
+            state.tool_options.freehand.isometric_assist = enabled;
+        }
+        Message::PixelSnapChanged(snap) => {
+            state.tool_options.pixel_snap = snap.clamp(1, 16);
+        }
+        Message::FillHovered { x, y } => {
+            tools::update_fill_preview(state, x, y);
+        }
+        Message::FillHoverEnded => {
+            state.fill_preview = None;
+        }
+        Message::FillPreviewToggled(enabled) => {
+            state.tool_options.fill.preview = enabled;
+            state.fill_preview = None;
+        }
+        Message::FillPatternToggled(enabled) => {
+            state.tool_options.fill.pattern = enabled;
+        }
+        Message::WrapEdgesToggled => {
+            state.wrap_edges = !state.wrap_edges;
+        }
+        Message::PreserveTransparencyToggled => {
+            state.preserve_transparency = !state.preserve_transparency;
+            state.mark_dirty();
+        }
+        _ => {}
+    }
+
+    Task::none()
+}
+
+/// Warn that a stroke is starting on a layer that won't show it. The warning
+/// is raised again only once it has been dismissed or the active layer has
+/// changed, so repeated strokes don't keep replacing other notices.
+fn warn_if_hidden(state: &mut EditorState) {
+    let index = state.active_layer_index;
+    let Some(reason) = state
+        .warn_hidden_layer
+        .then(|| state.layer_hidden_reason(index))
+        .flatten()
+    else {
+        return;
+    };
+    if state
+        .hidden_layer_warning
+        .as_ref()
+        .is_some_and(|(warned, text)| *warned == index && state.notification.as_ref() == Some(text))
+    {
+        return;
+    }
+    let text = format!("Drawing on {} '{}'", reason, state.layers[index].name);
+    state.notification = Some(text.clone());
+    state.hidden_layer_warning = Some((index, text));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::update::{apply, dot};
+
+    #[test]
+    fn clicks_that_change_nothing_leave_history_alone() {
+        let mut state = EditorState::new(4, 4);
+        let red = iced::Color::from_rgb8(255, 0, 0);
+        apply(&mut state, dot(1, 1, red));
+        assert_eq!(state.history.commands.len(), 1);
+        for _ in 0..10 {
+            apply(&mut state, dot(1, 1, red));
+        }
+        assert_eq!(state.history.commands.len(), 1);
+    }
+
+    #[test]
+    fn repainting_a_pixel_within_a_stroke_is_skipped() {
+        let mut state = EditorState::new(2, 1);
+        state.primary_color = iced::Color::from_rgb8(255, 0, 0);
+        apply(
+            &mut state,
+            [
+                Message::DrawingStarted {
+                    x: 0,
+                    y: 0,
+                    pressure: None,
+                },
+                Message::PixelDrawn {
+                    x: 1,
+                    y: 0,
+                    pressure: None,
+                },
+                Message::PixelDrawn {
+                    x: 1,
+                    y: 0,
+                    pressure: None,
+                },
+                Message::DrawingEnded,
+            ],
+        );
+        // One command per pixel, none for painting (1, 0) again
+        assert_eq!(state.history.commands.len(), 2);
+
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(state.layers[0].get_rgba8(1, 0), [0; 4]);
+        assert_eq!(state.layers[0].get_rgba8(0, 0), [255, 0, 0, 255]);
+    }
+
+    const ZIGZAG: [(u32, u32); 6] = [(1, 1), (3, 4), (5, 1), (7, 5), (9, 2), (10, 6)];
+
+    /// A jittery freehand stroke through `ZIGZAG`.
+    fn zigzag() -> Vec<Message> {
+        let mut stroke = vec![Message::DrawingStarted {
+            x: ZIGZAG[0].0,
+            y: ZIGZAG[0].1,
+            pressure: None,
+        }];
+        stroke.extend(ZIGZAG[1..].iter().map(|&(x, y)| Message::PixelDrawn {
+            x,
+            y,
+            pressure: None,
+        }));
+        stroke.push(Message::DrawingEnded);
+        stroke
+    }
+
+    #[test]
+    fn stabilizer_at_zero_paints_the_raw_stroke() {
+        let mut plain = EditorState::new(12, 8);
+        apply(&mut plain, zigzag());
+        // Every cursor position is painted exactly where it landed
+        for (x, y) in ZIGZAG {
+            assert_ne!(plain.layers[0].get_rgba8(x, y)[3], 0);
+        }
+
+        let mut reset = EditorState::new(12, 8);
+        apply(
+            &mut reset,
+            [Message::StabilizerChanged(5), Message::StabilizerChanged(0)],
+        );
+        apply(&mut reset, zigzag());
+        assert_eq!(reset.layers[0].pixels, plain.layers[0].pixels);
+
+        let mut smoothed = EditorState::new(12, 8);
+        apply(&mut smoothed, [Message::StabilizerChanged(5)]);
+        apply(&mut smoothed, zigzag());
+        assert_ne!(smoothed.layers[0].pixels, plain.layers[0].pixels);
+    }
+}
@@ -0,0 +1,835 @@
+use crate::message::Message;
+use crate::state::{self, EditorState, ExportSettings};
+use crate::{file_io, filters, tools};
+use iced::Task;
+use std::collections::HashSet;
+
+/// Opening, saving, importing and exporting, and comparing with a file.
+pub fn update(state: &mut EditorState, message: Message) -> Task<Message> {
+    match message {
+        Message::FileNew => {
+            *state = state.new_document(32, 32);
+        }
+        Message::FileOpen => {
+            let document = state.id;
+            return Task::perform(
+                async move {
+                    let file = rfd::AsyncFileDialog::new()
+                        .add_filter(
+                            "Image files",
+                            &["png", "jpg", "jpeg", "gif", "bmp", "webp", "tga"],
+                        )
+                        .add_filter("PNG", &["png"])
+                        .add_filter("JPEG", &["jpg", "jpeg"])
+                        .add_filter("GIF", &["gif"])
+                        .add_filter("BMP", &["bmp"])
+                        .add_filter("WebP", &["webp"])
+                        .add_filter("TGA", &["tga"])
+                        .pick_file()
+                        .await;
+
+                    if let Some(file) = file {
+                        let path = file.path().to_string_lossy().to_string();
+                        let path_clone = path.clone();
+                        match file_io::load_project(file.path()) {
+                            Ok(Some(project)) => {
+                                let restore = rfd::AsyncMessageDialog::new()
+                                    .set_title("Restore layers")
+                                    .set_description(
+                                        "This PNG contains pxrs layer data. Restore the layered \
+                                         document instead of importing a flattened image?",
+                                    )
+                                    .set_buttons(rfd::MessageButtons::YesNo)
+                                    .show()
+                                    .await;
+                                if restore == rfd::MessageDialogResult::Yes {
+                                    return Message::ProjectLoaded {
+                                        document,
+                                        path,
+                                        project: Box::new(project),
+                                    };
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => eprintln!("Ignoring embedded layer data: {}", e),
+                        }
+                        match file_io::load_image(file.path()) {
+                            Ok(image) => Message::FileLoaded {
+                                document,
+                                path: path_clone,
+                                image: Some(image),
+                            },
+                            Err(e) => {
+                                eprintln!("Failed to load image: {}", e);
+                                Message::None
+                            }
+                        }
+                    } else {
+                        Message::None
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::FileSave => {
+            if state.exporting {
+                state.notification = Some("An export is already in progress".into());
+                return Task::none();
+            }
+            let settings = state.export;
+            let extension = settings.extension();
+            let document = state.id;
+
+            return Task::perform(
+                async move {
+                    let file = rfd::AsyncFileDialog::new()
+                        .add_filter(format!("{} files", extension.to_uppercase()), &[extension])
+                        .add_filter("All files", &["*"])
+                        .set_file_name(format!("output.{}", extension))
+                        .save_file()
+                        .await;
+
+                    if let Some(file) = file {
+                        let path = file.path().to_string_lossy().to_string();
+                        Message::FileSaveDialogResult {
+                            document,
+                            path,
+                            settings,
+                        }
+                    } else {
+                        Message::None
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::FileSaveDialogResult { path, settings, .. } => {
+            if state.exporting {
+                state.notification = Some("An export is already in progress".into());
+                return Task::none();
+            }
+            // Upscaling and encoding big canvases is slow, so export a
+            // snapshot off the UI thread
+            let snapshot = state.export_snapshot();
+            let document = state.id;
+            state.exporting = true;
+            return Task::perform(
+                async move {
+                    file_io::save_export(&snapshot, std::path::Path::new(&path), &settings)
+                        .map(|warning| (path, warning))
+                },
+                move |result| match result {
+                    Ok((path, warning)) => Message::FileSaved {
+                        document,
+                        path,
+                        warning,
+                    },
+                    Err(e) => Message::ExportFailed(document, e),
+                },
+            );
+        }
+        Message::ExportProfileAddRequested => {
+            let settings = state.export;
+            let extension = settings.extension();
+            return Task::perform(
+                async move {
+                    let file = rfd::AsyncFileDialog::new()
+                        .add_filter(format!("{} files", extension.to_uppercase()), &[extension])
+                        .set_file_name(format!("output.{}", extension))
+                        .save_file()
+                        .await;
+
+                    match file {
+                        Some(file) => Message::ExportProfileAdded {
+                            path: file.path().to_string_lossy().to_string(),
+                            settings,
+                        },
+                        None => Message::None,
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::ExportProfileAdded { path, settings } => {
+            let scope = if state.selection_bounds().is_some() {
+                state::ExportScope::Selection
+            } else {
+                settings.scope
+            };
+            state.export_profiles.push(state::ExportProfile {
+                path,
+                format: settings.format,
+                upscale: settings.upscale,
+                scope,
+            });
+            state.mark_dirty();
+        }
+        Message::ExportProfileChanged(index, profile) => {
+            if let Some(existing) = state.export_profiles.get_mut(index) {
+                *existing = profile;
+                state.mark_dirty();
+            }
+        }
+        Message::ExportProfileRemoved(index) if index < state.export_profiles.len() => {
+            state.export_profiles.remove(index);
+            state.mark_dirty();
+        }
+        Message::ExportAllRequested => {
+            if state.exporting {
+                state.notification = Some("An export is already in progress".into());
+                return Task::none();
+            }
+            if state.export_profiles.is_empty() {
+                state.notification = Some("No export profiles to run".into());
+                return Task::none();
+            }
+            let profiles = state.export_profiles.clone();
+            let snapshot = state.export_snapshot();
+            let document = state.id;
+            state.exporting = true;
+            return Task::perform(
+                async move {
+                    profiles
+                        .iter()
+                        .map(|profile| {
+                            let name = profile.display_name();
+                            match file_io::save_profile(&snapshot, profile) {
+                                Ok(None) => format!("{}: exported", name),
+                                Ok(Some(warning)) => format!("{}: exported, but {}", name, warning),
+                                Err(e) => format!("{}: {}", name, e),
+                            }
+                        })
+                        .collect()
+                },
+                move |results| Message::ExportAllFinished(document, results),
+            );
+        }
+        Message::ExportAllFinished(_, results) => {
+            for result in &results {
+                eprintln!("Export all: {}", result);
+            }
+            state.exporting = false;
+            state.notification = Some(results.join("; "));
+        }
+        Message::SelectionExportRequested => {
+            if state.selection_bounds().is_none() {
+                return Task::none();
+            }
+            let settings = ExportSettings {
+                scope: state::ExportScope::Selection,
+                ..state.export
+            };
+            let extension = settings.extension();
+            let document = state.id;
+            return Task::perform(
+                async move {
+                    let file = rfd::AsyncFileDialog::new()
+                        .add_filter(format!("{} files", extension.to_uppercase()), &[extension])
+                        .add_filter("All files", &["*"])
+                        .set_file_name(format!("selection.{}", extension))
+                        .save_file()
+                        .await;
+
+                    match file {
+                        Some(file) => Message::SelectionExportDialogResult {
+                            document,
+                            path: file.path().to_string_lossy().to_string(),
+                            settings,
+                        },
+                        None => Message::None,
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::SelectionExportDialogResult { path, settings, .. } => {
+            match file_io::save_export(state, std::path::Path::new(&path), &settings) {
+                Ok(None) => {}
+                Ok(Some(warning)) => {
+                    state.notification = Some(format!("Exported the selection, but {}", warning));
+                }
+                Err(e) => {
+                    eprintln!("Failed to export selection: {}", e);
+                    state.notification = Some(format!("Failed to export selection: {}", e));
+                }
+            }
+        }
+        Message::SelectionImportRequested => {
+            if state.selection_bounds().is_none() {
+                return Task::none();
+            }
+            let document = state.id;
+            return Task::perform(
+                async move {
+                    let file = rfd::AsyncFileDialog::new()
+                        .add_filter(
+                            "Image files",
+                            &["png", "jpg", "jpeg", "gif", "bmp", "webp", "tga"],
+                        )
+                        .pick_file()
+                        .await;
+                    let Some(file) = file else {
+                        return Message::None;
+                    };
+                    match file_io::load_image(file.path()) {
+                        Ok((width, height, pixels)) => Message::SelectionImageLoaded {
+                            document,
+                            path: file.path().to_string_lossy().to_string(),
+                            width,
+                            height,
+                            pixels,
+                        },
+                        Err(e) => {
+                            eprintln!("Failed to load image: {}", e);
+                            Message::None
+                        }
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::SelectionImageLoaded {
+            width,
+            height,
+            pixels,
+            ..
+        } => {
+            tools::fill_selection_with_image(state, &pixels, width, height);
+        }
+        Message::ExportSettingsChanged(settings) => {
+            state.export = settings.clamped();
+            state.mark_dirty();
+        }
+        Message::FlattenAgainstMatte => {
+            let color = crate::utils::rgba8_to_color(state.export.matte_color);
+            state.record_layers_change(|state| state.add_background_layer(color));
+            state.mark_dirty();
+        }
+        Message::FileLoaded { path, image, .. } => {
+            let image = image
+                .map(Ok)
+                .unwrap_or_else(|| file_io::load_image(std::path::Path::new(&path)));
+            let (width, height, pixels) = match image {
+                Ok(image) => image,
+                Err(e) => {
+                    eprintln!("Failed to load image: {}", e);
+                    return Task::none();
+                }
+            };
+            let mut pixels = pixels;
+            let mut notices = Vec::new();
+            if let Some(remapped) = snap_import(state, &mut pixels, width) {
+                notices.push(format!(
+                    "Snapped {} off-palette colors to the palette",
+                    remapped.len()
+                ));
+            }
+            if state.add_import_colors {
+                let added = add_import_colors(state, &pixels);
+                notices.push(format!("Added {} colors to the palette", added));
+            }
+            // Add the image as a new layer, growing the canvas to fit it, as
+            // one undo step
+            state.record_layers_change(|state| {
+                let mut new_layer = state::Layer::new("Imported".to_string(), width, height);
+                new_layer.pixels = pixels;
+                state.layers.push(new_layer);
+                state.active_layer_index = state.layers.len() - 1;
+                state.canvas_width = width.max(state.canvas_width);
+                state.canvas_height = height.max(state.canvas_height);
+                // Fit every layer to the canvas, keeping its pixels: the
+                // others when the image grew it, else the smaller imported one
+                for layer in &mut state.layers {
+                    if layer.width != state.canvas_width || layer.height != state.canvas_height {
+                        layer.resize_anchored(state.canvas_width, state.canvas_height);
+                    }
+                }
+            });
+            state.mark_dirty();
+            if !notices.is_empty() {
+                state.notification = Some(notices.join("; "));
+            }
+        }
+        Message::ProjectLoaded { path, project, .. } => {
+            state.finish_continuous_edit();
+            state.cancel_preview();
+            project.apply_to(state);
+            state.file_path = Some(path);
+        }
+        Message::FileSaved { path, warning, .. } => {
+            // File saved successfully - log the path
+            eprintln!("File saved successfully: {}", path);
+            state.exporting = false;
+            state.notification = Some(match warning {
+                Some(warning) => format!("Exported {}, but {}", path, warning),
+                None => format!("Exported {}", path),
+            });
+            state.file_path = Some(path);
+            state.mark_saved();
+        }
+        Message::FolderImportRequested => {
+            if state.folder_import.is_some() {
+                state.notification = Some("A folder import is already running".into());
+                return Task::none();
+            }
+            let (canvas_width, canvas_height) = (state.canvas_width, state.canvas_height);
+            let snap = state.snap_imports_to_palette;
+            let add_colors = state.add_import_colors;
+            return Task::perform(
+                async move {
+                    let Some(folder) = rfd::AsyncFileDialog::new().pick_folder().await else {
+                        return Message::None;
+                    };
+                    let images = match file_io::list_images(folder.path()) {
+                        Ok(images) if images.is_empty() => {
+                            return Message::FolderImportFailed(format!(
+                                "No images found in {}",
+                                folder.path().display()
+                            ));
+                        }
+                        Ok(images) => images,
+                        Err(e) => return Message::FolderImportFailed(e),
+                    };
+
+                    // Distinct sizes with how many images have each
+                    let mut sizes: Vec<((u32, u32), usize)> = Vec::new();
+                    for &(_, width, height) in &images {
+                        match sizes.iter_mut().find(|(size, _)| *size == (width, height)) {
+                            Some((_, count)) => *count += 1,
+                            None => sizes.push(((width, height), 1)),
+                        }
+                    }
+                    let size_list = sizes
+                        .iter()
+                        .map(|((width, height), count)| format!("{}x{} ({})", width, height, count))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let mismatched = sizes
+                        .iter()
+                        .any(|(size, _)| *size != (canvas_width, canvas_height));
+                    let mut description = format!(
+                        "Import {} images from {} as layers, in name order?\n\nSizes: {}",
+                        images.len(),
+                        folder.path().display(),
+                        size_list
+                    );
+                    if snap {
+                        description.push_str("\n\nColors will be snapped to the palette.");
+                    }
+                    if add_colors {
+                        description
+                            .push_str("\n\nTheir most frequent colors will join the palette.");
+                    }
+                    let buttons = if mismatched {
+                        description.push_str(&format!(
+                            "\n\nNot every image is {}x{}. Center them on the canvas, \
+                             cropping any that are larger, or cancel the import.",
+                            canvas_width, canvas_height
+                        ));
+                        rfd::MessageButtons::OkCancelCustom(
+                            "Center on canvas".into(),
+                            "Cancel".into(),
+                        )
+                    } else {
+                        rfd::MessageButtons::OkCancel
+                    };
+                    let result = rfd::AsyncMessageDialog::new()
+                        .set_title("Import folder")
+                        .set_description(description)
+                        .set_buttons(buttons)
+                        .show()
+                        .await;
+                    let confirmed = match result {
+                        rfd::MessageDialogResult::Ok => true,
+                        rfd::MessageDialogResult::Custom(label) => label == "Center on canvas",
+                        _ => false,
+                    };
+                    if confirmed {
+                        Message::FolderImportStarted(
+                            images.into_iter().map(|(path, ..)| path).collect(),
+                        )
+                    } else {
+                        Message::None
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::FolderImportStarted(paths) => {
+            state.folder_import = Some(state::FolderImport {
+                paths,
+                next: 0,
+                imported: 0,
+                remapped_colors: HashSet::new(),
+                colors_added: 0,
+            });
+            return load_next_folder_image(state);
+        }
+        Message::FolderImageLoaded { path, result, .. } => {
+            let Some(import) = &mut state.folder_import else {
+                return Task::none();
+            };
+            // Results for any other image are stale, like the ones a replay
+            // loads again after its recorded results
+            if import.paths.get(import.next) != Some(&path) {
+                return Task::none();
+            }
+            import.next += 1;
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_else(|| "Imported".to_string());
+            match result {
+                Ok((width, height, mut pixels)) => {
+                    import.imported += 1;
+                    if let Some(remapped) = snap_import(state, &mut pixels, width)
+                        && let Some(import) = &mut state.folder_import
+                    {
+                        import.remapped_colors.extend(remapped);
+                    }
+                    let added = add_import_colors(state, &pixels);
+                    if let Some(import) = &mut state.folder_import {
+                        import.colors_added += added;
+                    }
+                    state.record_layers_change(|state| {
+                        state.add_centered_layer(name, width, height, &pixels)
+                    });
+                    state.mark_dirty();
+                }
+                Err(e) => eprintln!("Skipping {}: {}", path.display(), e),
+            }
+            return load_next_folder_image(state);
+        }
+        Message::ImportSnapToggled(enabled) => {
+            state.snap_imports_to_palette = enabled;
+        }
+        Message::ImportDitherToggled(enabled) => {
+            state.dither_imports = enabled;
+        }
+        Message::ImportColorsToggled(enabled) => {
+            state.add_import_colors = enabled;
+        }
+        Message::FolderImportFailed(e) => {
+            eprintln!("Failed to import folder: {}", e);
+            state.notification = Some(e);
+        }
+        Message::ExportFailed(_, e) => {
+            eprintln!("Failed to save: {}", e);
+            state.exporting = false;
+            state.notification = Some(format!("Failed to save: {}", e));
+        }
+        Message::CompareRequested => {
+            let document = state.id;
+            return Task::perform(
+                async move {
+                    let file = rfd::AsyncFileDialog::new()
+                        .add_filter(
+                            "Image files",
+                            &["png", "jpg", "jpeg", "gif", "bmp", "webp", "tga"],
+                        )
+                        .pick_file()
+                        .await;
+
+                    match file {
+                        Some(file) => match file_io::load_image(file.path()) {
+                            Ok((width, height, pixels)) => Message::CompareLoaded {
+                                document,
+                                path: file.path().to_string_lossy().to_string(),
+                                width,
+                                height,
+                                pixels,
+                            },
+                            Err(e) => Message::CompareFailed(e),
+                        },
+                        None => Message::None,
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::CompareLoaded {
+            path,
+            width,
+            height,
+            pixels,
+            ..
+        } => {
+            if (width, height) != (state.canvas_width, state.canvas_height) {
+                state.notification = Some(format!(
+                    "Cannot compare: {} is {}x{} but the canvas is {}x{}",
+                    path, width, height, state.canvas_width, state.canvas_height
+                ));
+                return Task::none();
+            }
+            state.comparison = Some(state::Comparison {
+                path,
+                width,
+                height,
+                pixels,
+                view: state::CompareView::Diff,
+                differs: Vec::new(),
+                differing: 0,
+                revision: 0,
+            });
+            state.refresh_comparison();
+        }
+        Message::CompareFailed(e) => {
+            eprintln!("Failed to load comparison image: {}", e);
+            state.notification = Some(e);
+        }
+        Message::CompareViewChanged(view) => {
+            if let Some(comparison) = &mut state.comparison {
+                comparison.view = view;
+            }
+        }
+        Message::CompareClosed => {
+            state.comparison = None;
+        }
+        Message::TilesExportRequested => {
+            let document = state.id;
+            return Task::perform(
+                async move {
+                    match rfd::AsyncFileDialog::new().pick_folder().await {
+                        Some(folder) => Message::TilesExportFolderSelected(
+                            document,
+                            folder.path().to_string_lossy().to_string(),
+                        ),
+                        None => Message::None,
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::TilesExportFolderSelected(_, path) => {
+            match file_io::save_tiles(state, std::path::Path::new(&path)) {
+                Ok(count) => {
+                    state.notification = Some(format!("Exported {} tiles to {}", count, path));
+                }
+                Err(e) => {
+                    eprintln!("Failed to export tiles: {}", e);
+                    state.notification = Some(format!("Failed to export tiles: {}", e));
+                }
+            }
+        }
+        Message::OutlinesExportRequested => {
+            let document = state.id;
+            return Task::perform(
+                async move {
+                    match rfd::AsyncFileDialog::new()
+                        .add_filter("PNG", &["png"])
+                        .set_file_name("outlines.png")
+                        .save_file()
+                        .await
+                    {
+                        Some(file) => Message::OutlinesExportPathSelected(
+                            document,
+                            file.path().to_string_lossy().to_string(),
+                        ),
+                        None => Message::None,
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::OutlinesExportPathSelected(_, path) => {
+            match file_io::save_outlines(state, std::path::Path::new(&path)) {
+                Ok(()) => {
+                    state.notification = Some(format!("Exported outlines to {}", path));
+                }
+                Err(e) => {
+                    eprintln!("Failed to export outlines: {}", e);
+                    state.notification = Some(format!("Failed to export outlines: {}", e));
+                }
+            }
+        }
+        Message::PatternSheetExportRequested => {
+            let document = state.id;
+            return Task::perform(
+                async move {
+                    match rfd::AsyncFileDialog::new()
+                        .add_filter("PNG", &["png"])
+                        .set_file_name("pattern.png")
+                        .save_file()
+                        .await
+                    {
+                        Some(file) => Message::PatternSheetExportPathSelected(
+                            document,
+                            file.path().to_string_lossy().to_string(),
+                        ),
+                        None => Message::None,
+                    }
+                },
+                |msg| msg,
+            );
+        }
+        Message::PatternSheetExportPathSelected(_, path) => {
+            match file_io::export_pattern_sheet(state, std::path::Path::new(&path)) {
+                Ok(colors) => {
+                    state.notification = Some(format!(
+                        "Exported a pattern sheet with {} color{} to {}",
+                        colors,
+                        if colors == 1 { "" } else { "s" },
+                        path
+                    ));
+                }
+                Err(e) => {
+                    eprintln!("Failed to export pattern sheet: {}", e);
+                    state.notification = Some(format!("Failed to export pattern sheet: {}", e));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Task::none()
+}
+
+/// Snap an imported image to the palette when that option is on, returning
+/// the distinct colors that were remapped.
+fn snap_import(state: &mut EditorState, pixels: &mut [u8], width: u32) -> Option<HashSet<[u8; 4]>> {
+    if !state.snap_imports_to_palette {
+        return None;
+    }
+    let palette = filters::import_palette(state);
+    if palette.iter().all(|entry| entry[3] == 0) {
+        state.notification = Some("No palette colors to snap the import to".to_string());
+        return None;
+    }
+    Some(filters::snap_to_palette(
+        pixels,
+        width,
+        &palette,
+        state.dither_imports,
+    ))
+}
+
+/// Add an imported image's most frequent colors to the used colors if that
+/// import option is on, capped by the palette import limit. Returns how many
+/// were added.
+fn add_import_colors(state: &mut EditorState, pixels: &[u8]) -> usize {
+    if !state.add_import_colors {
+        return 0;
+    }
+    let limit = state.palette_import_limit as usize;
+    state.add_imported_colors(pixels, limit)
+}
+
+/// Load the folder import's next image, reporting progress, or finish the
+/// import once every image has been tried.
+fn load_next_folder_image(state: &mut EditorState) -> Task<Message> {
+    let Some(import) = &state.folder_import else {
+        return Task::none();
+    };
+    let total = import.paths.len();
+    let Some(path) = import.paths.get(import.next).cloned() else {
+        let mut notice = if import.imported == total {
+            format!("Imported {} images as layers", total)
+        } else {
+            format!(
+                "Imported {} of {} images as layers; see the log for the rest",
+                import.imported, total
+            )
+        };
+        if state.snap_imports_to_palette {
+            notice.push_str(&format!(
+                ", snapping {} off-palette colors",
+                import.remapped_colors.len()
+            ));
+        }
+        if state.add_import_colors {
+            notice.push_str(&format!(
+                ", adding {} colors to the palette",
+                import.colors_added
+            ));
+        }
+        state.notification = Some(notice);
+        state.folder_import = None;
+        return Task::none();
+    };
+    state.notification = Some(format!(
+        "Importing folder: {} of {}",
+        import.next + 1,
+        total
+    ));
+    let document = state.id;
+    Task::perform(
+        async move {
+            let result = file_io::load_image(&path);
+            (path, result)
+        },
+        move |(path, result)| Message::FolderImageLoaded {
+            document,
+            path,
+            result,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::update::{apply, dot};
+    use iced::Color;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+
+    fn loaded(state: &EditorState, width: u32, height: u32) -> Message {
+        let pixels = GREEN.repeat((width * height) as usize);
+        Message::FileLoaded {
+            document: state.id,
+            path: "image.png".to_string(),
+            image: Some((width, height, pixels)),
+        }
+    }
+
+    #[test]
+    fn loading_a_larger_image_grows_every_layer_as_one_undo_step() {
+        let mut state = EditorState::new(2, 2);
+        apply(&mut state, dot(1, 1, Color::from_rgb8(255, 0, 0)));
+        let message = loaded(&state, 4, 2);
+        apply(&mut state, [message]);
+
+        assert_eq!((state.canvas_width, state.canvas_height), (4, 2));
+        for layer in &state.layers {
+            assert_eq!((layer.width, layer.height), (4, 2));
+        }
+        assert_eq!(state.layers[0].get_rgba8(1, 1), RED);
+
+        // Undo shrinks the canvas back before the earlier dot is reached
+        apply(&mut state, [Message::Undo]);
+        assert_eq!((state.canvas_width, state.canvas_height), (2, 2));
+        assert_eq!(state.layers.len(), 1);
+        assert_eq!(state.layers[0].get_rgba8(1, 1), RED);
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(state.layers[0].get_rgba8(1, 1), [0; 4]);
+
+        apply(&mut state, [Message::Redo, Message::Redo]);
+        assert_eq!((state.canvas_width, state.canvas_height), (4, 2));
+        assert_eq!(state.layers[0].get_rgba8(1, 1), RED);
+        assert_eq!(state.layers[1].get_rgba8(3, 1), GREEN);
+    }
+
+    #[test]
+    fn loading_a_smaller_image_fits_it_to_the_canvas() {
+        let mut state = EditorState::new(4, 4);
+        apply(&mut state, dot(3, 3, Color::from_rgb8(255, 0, 0)));
+        let message = loaded(&state, 2, 2);
+        apply(&mut state, [message]);
+
+        assert_eq!((state.canvas_width, state.canvas_height), (4, 4));
+        let imported = &state.layers[1];
+        assert_eq!((imported.width, imported.height), (4, 4));
+        assert_eq!(imported.get_rgba8(1, 1), GREEN);
+        assert_eq!(imported.get_rgba8(2, 2), [0; 4]);
+        let composite = file_io::composite_layers(&state);
+        assert_eq!(composite.len(), 4 * 4 * 4);
+        assert_eq!(composite[..4], GREEN);
+        assert_eq!(composite[(3 * 4 + 3) * 4..], RED);
+
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(state.layers.len(), 1);
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(state.layers[0].get_rgba8(3, 3), [0; 4]);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::message::Message;
+use crate::state::{self, EditorState};
+use iced::Task;
+
+/// Undo, redo and restore points.
+pub fn update(state: &mut EditorState, message: Message) -> Task<Message> {
+    match message {
+        Message::Undo => {
+            state.stray_pixels = None;
+            state.finish_continuous_edit();
+            state.cancel_preview();
+            state.floating_paste = None;
+            state.dragging_paste = None;
+            let command = if state.undo_active_layer_only {
+                match state.history.undo_layer(state.active_layer_index) {
+                    Ok(Some(command)) => Some(command),
+                    Ok(None) => {
+                        state.notification = Some("Nothing to undo on this layer".to_string());
+                        None
+                    }
+                    Err(e) => {
+                        state.notification =
+                            Some(format!("{}; undid the newest change instead", e));
+                        state.history.undo()
+                    }
+                }
+            } else {
+                state.history.undo()
+            };
+            if let Some(command) = command {
+                if command
+                    .layer_index()
+                    .is_some_and(|index| index >= state.layers.len())
+                {
+                    state.history.remove_missing_layers(state.layers.len());
+                    state.notification = Some("Cannot undo: layer was deleted".to_string());
+                } else {
+                    apply_undo_command(state, command);
+                }
+                state.forget_undone_colors();
+            }
+        }
+        Message::Redo => {
+            state.stray_pixels = None;
+            state.finish_continuous_edit();
+            state.cancel_preview();
+            state.floating_paste = None;
+            state.dragging_paste = None;
+            if let Some(command) = state.history.redo() {
+                if command
+                    .layer_index()
+                    .is_some_and(|index| index >= state.layers.len())
+                {
+                    state.history.remove_missing_layers(state.layers.len());
+                    state.notification = Some("Cannot redo: layer was deleted".to_string());
+                } else {
+                    apply_redo_command(state, command);
+                }
+            }
+        }
+        Message::UndoActiveLayerOnlyToggled(enabled) => {
+            state.undo_active_layer_only = enabled;
+        }
+        Message::RestorePointNameChanged(name) => {
+            state.restore_point_name = name;
+        }
+        Message::RestorePointCreated => {
+            let name = match state.restore_point_name.trim() {
+                "" => format!("Restore point {}", state.restore_points.len() + 1),
+                name => name.to_string(),
+            };
+            state.restore_point_name.clear();
+            let evicted = state.create_restore_point(name);
+            if state.save_restore_points {
+                state.mark_dirty();
+            }
+            if !evicted.is_empty() {
+                state.notification = Some(format!(
+                    "Dropped the oldest restore point{} to stay within the limit: {}",
+                    if evicted.len() == 1 { "" } else { "s" },
+                    evicted.join(", ")
+                ));
+            }
+        }
+        Message::RestorePointRestored(index) => {
+            state.stray_pixels = None;
+            state.floating_paste = None;
+            state.dragging_paste = None;
+            state.restore_point(index);
+            state.mark_dirty();
+        }
+        Message::RestorePointDeleted(index) if index < state.restore_points.len() => {
+            state.restore_points.remove(index);
+            if state.save_restore_points {
+                state.mark_dirty();
+            }
+        }
+        Message::RestorePointsSavedToggled(enabled) => {
+            state.save_restore_points = enabled;
+            state.mark_dirty();
+        }
+        _ => {}
+    }
+
+    Task::none()
+}
+
+fn apply_undo_command(state: &mut EditorState, command: state::EditCommand) {
+    match command {
+        state::EditCommand::PixelChange {
+            layer_index,
+            x,
+            y,
+            old_color,
+            ..
+        } => {
+            if let Some(layer) = state.layers.get_mut(layer_index) {
+                layer.set_pixel(x, y, old_color);
+            }
+        }
+        state::EditCommand::MultiPixelChange {
+            layer_index,
+            changes,
+        } => {
+            if let Some(layer) = state.layers.get_mut(layer_index) {
+                for (x, y, old_color, _) in changes {
+                    layer.set_pixel(x, y, old_color);
+                }
+            }
+        }
+        state::EditCommand::RegionChange {
+            layer_index,
+            bounds,
+            old,
+            ..
+        } => {
+            if let Some(layer) = state.layers.get_mut(layer_index) {
+                layer.write_region(bounds, &old);
+            }
+        }
+        state::EditCommand::LayerPropertyChanged {
+            layer_index, old, ..
+        } => {
+            state.set_layer_property(layer_index, old);
+        }
+        state::EditCommand::DocumentSnapshot { before, .. } => {
+            before.restore(state);
+        }
+        state::EditCommand::PaletteEntryChanged { index, old, .. } => {
+            state.set_palette_color(index, old);
+        }
+        state::EditCommand::ColorModeChanged { before, .. } => {
+            before.restore(state);
+        }
+        state::EditCommand::LayersReplaced { before, .. } => {
+            before.apply(state);
+        }
+    }
+}
+
+fn apply_redo_command(state: &mut EditorState, command: state::EditCommand) {
+    match command {
+        state::EditCommand::PixelChange {
+            layer_index,
+            x,
+            y,
+            new_color,
+            ..
+        } => {
+            if let Some(layer) = state.layers.get_mut(layer_index) {
+                layer.set_pixel(x, y, new_color);
+            }
+        }
+        state::EditCommand::MultiPixelChange {
+            layer_index,
+            changes,
+        } => {
+            if let Some(layer) = state.layers.get_mut(layer_index) {
+                for (x, y, _, new_color) in changes {
+                    layer.set_pixel(x, y, new_color);
+                }
+            }
+        }
+        state::EditCommand::RegionChange {
+            layer_index,
+            bounds,
+            new,
+            ..
+        } => {
+            if let Some(layer) = state.layers.get_mut(layer_index) {
+                layer.write_region(bounds, &new);
+            }
+        }
+        state::EditCommand::LayerPropertyChanged {
+            layer_index, new, ..
+        } => {
+            state.set_layer_property(layer_index, new);
+        }
+        state::EditCommand::DocumentSnapshot { after, .. } => {
+            after.restore(state);
+        }
+        state::EditCommand::PaletteEntryChanged { index, new, .. } => {
+            state.set_palette_color(index, new);
+        }
+        state::EditCommand::ColorModeChanged { after, .. } => {
+            after.restore(state);
+        }
+        state::EditCommand::LayersReplaced { after, .. } => {
+            after.apply(state);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use crate::message::Message;
+use crate::state::{self, EditorState};
+use crate::utils;
+use iced::Task;
+use iced::widget;
+use std::time::Instant;
+
+/// Adding, removing, reordering and editing layers.
+pub fn update(state: &mut EditorState, message: Message) -> Task<Message> {
+    match message {
+        Message::LayerAdded(name) => {
+            state.record_layers_change(|state| state.add_layer(name));
+            state.mark_dirty();
+        }
+        Message::LayerDeleted(index) => {
+            state.renaming_layer = None;
+            state.delete_layer(index);
+            state.mark_dirty();
+        }
+        Message::LayerMoved { from, to }
+            if from < state.layers.len() && to < state.layers.len() =>
+        {
+            state.renaming_layer = None;
+            state.move_layer(from, to);
+            state.mark_dirty();
+        }
+        Message::LayerDragStarted(index) => {
+            state.layer_drag = Some(state::LayerDrag {
+                from: index,
+                target: None,
+            });
+        }
+        Message::LayerDragHovered(target) => {
+            if let Some(drag) = &mut state.layer_drag {
+                drag.target = target;
+            }
+        }
+        Message::LayerDragEnded => {
+            // Dropped through the regular move so recordings capture it
+            if let Some(state::LayerDrag {
+                from,
+                target: Some(to),
+            }) = state.layer_drag.take()
+                && from != to
+            {
+                return Task::done(Message::LayerMoved { from, to });
+            }
+        }
+        Message::LayerVisibilityToggled(index) => {
+            if let Some(layer) = state.layers.get_mut(index) {
+                layer.visible = !layer.visible;
+                state.mark_dirty();
+            }
+        }
+        Message::HiddenLayerShown(index) if index < state.layers.len() => {
+            if state.solo_index().is_some_and(|solo| solo != index) {
+                state.solo_layer = None;
+            }
+            // A clipped layer shows only where its base does
+            let base = (0..=index)
+                .rev()
+                .find(|&below| !state.layers[below].clipped);
+            for layer in [Some(index), base].into_iter().flatten() {
+                state.layers[layer].visible = true;
+            }
+            if state.layers[index].opacity < state::HIDDEN_LAYER_OPACITY {
+                state.begin_continuous_edit(state::ContinuousEdit::LayerProperty {
+                    layer_index: index,
+                    start: state::LayerProperty::Opacity(state.layers[index].opacity),
+                });
+                state.set_layer_property(index, state::LayerProperty::Opacity(1.0));
+                state.finish_continuous_edit();
+            }
+            state.notification = None;
+            state.hidden_layer_warning = None;
+            state.mark_dirty();
+        }
+        Message::HiddenLayerWarningToggled(enabled) => {
+            state.warn_hidden_layer = enabled;
+            if !enabled {
+                state.hidden_layer_warning = None;
+            }
+        }
+        Message::LayerSoloToggled(index) if index < state.layers.len() => {
+            let id = state.layers[index].id;
+            state.solo_layer = (state.solo_layer != Some(id)).then_some(id);
+        }
+        Message::LayerClippingToggled(index) => {
+            if let Some(layer) = state.layers.get_mut(index) {
+                layer.clipped = !layer.clipped;
+                state.mark_dirty();
+            }
+        }
+        Message::LayerSelected(index) if index < state.layers.len() => {
+            state.active_layer_index = index;
+            state.stray_pixels = None;
+        }
+        Message::LayerOpacityChanged { index, opacity } => {
+            if let Some(layer) = state.layers.get(index) {
+                state.begin_continuous_edit(state::ContinuousEdit::LayerProperty {
+                    layer_index: index,
+                    start: state::LayerProperty::Opacity(layer.opacity),
+                });
+                state.set_layer_property(
+                    index,
+                    state::LayerProperty::Opacity(utils::clamp_f32(opacity, 0.0, 1.0)),
+                );
+            }
+        }
+        Message::ContinuousEditFinished => {
+            state.finish_continuous_edit();
+        }
+        Message::LayerNameClicked(index) => {
+            let now = Instant::now();
+            let double = state.last_layer_click.is_some_and(|(last, time)| {
+                last == index && now.duration_since(time) <= state::DOUBLE_CLICK_INTERVAL
+            });
+            state.last_layer_click = (!double).then_some((index, now));
+            let select = Task::done(Message::LayerSelected(index));
+            if double {
+                return select.chain(Task::done(Message::LayerRenameStarted(index)));
+            }
+            return select;
+        }
+        Message::LayerHovered(index) => {
+            state.hovered_layer = index;
+        }
+        Message::LayerRenameStarted(index) => {
+            if let Some(layer) = state.layers.get(index) {
+                state.renaming_layer = Some((index, layer.name.clone()));
+                return widget::text_input::focus(crate::ui::layer_rename_input());
+            }
+        }
+        Message::LayerRenameEdited(text) => {
+            if let Some((_, name)) = &mut state.renaming_layer {
+                *name = text;
+            }
+        }
+        Message::LayerRenameCommitted => {
+            if let Some((index, name)) = state.renaming_layer.take() {
+                let name = name.trim().to_string();
+                if state
+                    .layers
+                    .get(index)
+                    .is_some_and(|layer| layer.name != name)
+                {
+                    return Task::done(Message::LayerRenamed { index, name });
+                }
+            }
+        }
+        Message::LayerRenameCancelled => {
+            state.renaming_layer = None;
+        }
+        Message::LayerRenamed { index, name } => {
+            if let Some(layer) = state.layers.get_mut(index)
+                && !name.is_empty()
+            {
+                layer.name = name;
+                state.mark_dirty();
+            }
+        }
+        _ => {}
+    }
+
+    Task::none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::update::{apply, dot};
+    use iced::Color;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    fn named_layers(names: &[&str]) -> EditorState {
+        let mut state = EditorState::new(4, 4);
+        state.layers[0].name = names[0].to_string();
+        for name in &names[1..] {
+            state.add_layer(name.to_string());
+        }
+        state
+    }
+
+    fn names(state: &EditorState) -> Vec<&str> {
+        state
+            .layers
+            .iter()
+            .map(|layer| layer.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn moves_reorder_layers_and_keep_the_active_one() {
+        for from in 0..3 {
+            for to in 0..3 {
+                let mut state = named_layers(&["A", "B", "C"]);
+                state.active_layer_index = from;
+                let mut expected = vec!["A", "B", "C"];
+                let moved = expected.remove(from);
+                expected.insert(to, moved);
+
+                apply(&mut state, [Message::LayerMoved { from, to }]);
+
+                assert_eq!(names(&state), expected, "moving {} to {}", from, to);
+                assert_eq!(state.layers[state.active_layer_index].name, moved);
+            }
+        }
+    }
+
+    #[test]
+    fn moves_out_of_range_are_ignored() {
+        let mut state = named_layers(&["A", "B"]);
+        apply(&mut state, [Message::LayerMoved { from: 0, to: 2 }]);
+        assert_eq!(names(&state), ["A", "B"]);
+    }
+
+    #[test]
+    fn undo_and_redo_after_deleting_a_layer() {
+        let mut state = named_layers(&["A", "B", "C"]);
+        apply(&mut state, [Message::LayerSelected(1)]);
+        apply(&mut state, dot(1, 1, Color::from_rgb8(255, 0, 0)));
+        apply(&mut state, [Message::LayerSelected(2)]);
+        apply(&mut state, dot(2, 2, Color::from_rgb8(0, 0, 255)));
+        apply(&mut state, [Message::LayerDeleted(0)]);
+        assert_eq!(names(&state), ["B", "C"]);
+
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(names(&state), ["A", "B", "C"]);
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(state.layers[2].get_rgba8(2, 2), [0; 4]);
+        assert_eq!(state.layers[1].get_rgba8(1, 1), RED);
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(state.layers[1].get_rgba8(1, 1), [0; 4]);
+
+        apply(&mut state, [Message::Redo, Message::Redo, Message::Redo]);
+        assert_eq!(names(&state), ["B", "C"]);
+        assert_eq!(state.layers[0].get_rgba8(1, 1), RED);
+        assert_eq!(state.layers[1].get_rgba8(2, 2), BLUE);
+    }
+
+    #[test]
+    fn added_layers_keep_their_history_across_deletes() {
+        let add = |state: &mut EditorState| {
+            apply(state, [Message::LayerAdded("B copy".to_string())]);
+        };
+        // What a Ctrl+drag with the Move tool does before the drag moves it
+        let duplicate = |state: &mut EditorState| {
+            state.begin_layer_copy();
+            state.finish_continuous_edit();
+        };
+        for add in [add, duplicate] {
+            let mut state = named_layers(&["A", "B"]);
+            add(&mut state);
+            assert_eq!(names(&state), ["A", "B", "B copy"]);
+            apply(&mut state, dot(1, 1, Color::from_rgb8(255, 0, 0)));
+            apply(&mut state, [Message::LayerDeleted(0)]);
+
+            apply(&mut state, [Message::Undo, Message::Undo]);
+            assert_eq!(names(&state), ["A", "B", "B copy"]);
+            assert_eq!(state.layers[2].get_rgba8(1, 1), [0; 4]);
+
+            apply(&mut state, [Message::Redo, Message::Redo]);
+            assert_eq!(names(&state), ["B", "B copy"]);
+            assert_eq!(state.layers[0].get_rgba8(1, 1), [0; 4]);
+            assert_eq!(state.layers[1].get_rgba8(1, 1), RED);
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_after_moving_a_layer() {
+        let mut state = named_layers(&["A", "B", "C"]);
+        apply(&mut state, [Message::LayerSelected(0)]);
+        apply(&mut state, dot(3, 0, Color::from_rgb8(255, 0, 0)));
+        apply(&mut state, [Message::LayerMoved { from: 0, to: 2 }]);
+        assert_eq!(names(&state), ["B", "C", "A"]);
+
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(names(&state), ["A", "B", "C"]);
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(state.layers[0].get_rgba8(3, 0), [0; 4]);
+
+        apply(&mut state, [Message::Redo, Message::Redo]);
+        assert_eq!(names(&state), ["B", "C", "A"]);
+        assert_eq!(state.layers[2].get_rgba8(3, 0), RED);
+        for layer in &state.layers[..2] {
+            assert_eq!(layer.get_rgba8(3, 0), [0; 4]);
+        }
+    }
+
+    #[test]
+    fn solo_follows_its_layer_through_moves_and_undo() {
+        let mut state = named_layers(&["A", "B", "C"]);
+        apply(&mut state, [Message::LayerSoloToggled(0)]);
+        apply(&mut state, [Message::LayerMoved { from: 0, to: 2 }]);
+        assert_eq!(state.solo_index(), Some(2));
+        apply(&mut state, [Message::LayerDeleted(0)]);
+        assert_eq!(names(&state), ["C", "A"]);
+        assert_eq!(state.solo_index(), Some(1));
+
+        apply(&mut state, [Message::Undo, Message::Undo]);
+        assert_eq!(names(&state), ["A", "B", "C"]);
+        assert_eq!(state.solo_index(), Some(0));
+
+        apply(&mut state, [Message::LayerDeleted(0)]);
+        assert_eq!(state.solo_layer, None);
+    }
+
+    #[test]
+    fn dragging_opacity_records_one_undo_step() {
+        let mut state = named_layers(&["A"]);
+        apply(
+            &mut state,
+            (1..=50).map(|tick| Message::LayerOpacityChanged {
+                index: 0,
+                opacity: 1.0 - tick as f32 / 100.0,
+            }),
+        );
+        apply(&mut state, [Message::ContinuousEditFinished]);
+        assert_eq!(state.history.commands.len(), 1);
+        assert_eq!(state.layers[0].opacity, 0.5);
+
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(state.layers[0].opacity, 1.0);
+        apply(&mut state, [Message::Redo]);
+        assert_eq!(state.layers[0].opacity, 0.5);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::message::Message;
+use crate::state::{self, EditorState};
+use crate::tools;
+use iced::Task;
+
+/// Selections, the clipboard and floating pastes.
+pub fn update(
+    state: &mut EditorState,
+    clipboard: &mut Option<state::ClipboardData>,
+    message: Message,
+) -> Task<Message> {
+    match message {
+        Message::SelectionStarted { x, y } => {
+            state.is_selecting = true;
+            state.selection_mask = None;
+            state.selection_base = None;
+            state.selection = Some(iced::Rectangle {
+                x,
+                y,
+                width: 0.0,
+                height: 0.0,
+            });
+        }
+        Message::SelectionUpdated { x, y } if state.is_selecting => {
+            if let Some(sel) = &mut state.selection {
+                sel.width = x - sel.x;
+                sel.height = y - sel.y;
+            } else if state.current_tool == state::Tool::Selection {
+                // Start selection if not already started
+                state.selection = Some(iced::Rectangle {
+                    x,
+                    y,
+                    width: 0.0,
+                    height: 0.0,
+                });
+            }
+        }
+        Message::SelectionEnded => {
+            state.is_selecting = false;
+            if let Some(sel) = &mut state.selection {
+                if sel.width < 0.0 {
+                    sel.x += sel.width;
+                    sel.width = sel.width.abs();
+                }
+                if sel.height < 0.0 {
+                    sel.y += sel.height;
+                    sel.height = sel.height.abs();
+                }
+            }
+            state.finish_combined_selection();
+        }
+        Message::SelectionCloneStarted { x, y } => {
+            state.cancel_preview();
+            if tools::clone_source(state).is_some() {
+                state.clone_drag = Some(((x, y), (0, 0)));
+            }
+        }
+        Message::SelectionCloneMoved { x, y } => {
+            if let Some(((grab_x, grab_y), _)) = state.clone_drag
+                && let Some(source) = state.clone_source.clone()
+            {
+                let offset = (x as i32 - grab_x as i32, y as i32 - grab_y as i32);
+                state.clone_drag = Some(((grab_x, grab_y), offset));
+                match tools::cloned_pixels(state, &source, offset) {
+                    Some(pixels) if offset != (0, 0) => {
+                        state.preview_overlay = Some(state::PreviewBuffer {
+                            layer_index: source.layer_index,
+                            pixels,
+                        })
+                    }
+                    _ => state.preview_overlay = None,
+                }
+            }
+        }
+        // The clone lands as one undo entry; the selection and its source
+        // pixels stay put for the next clone
+        Message::SelectionCloneEnded if state.clone_drag.is_some() => {
+            state.clone_drag = None;
+            state.commit_preview();
+            if let Some(source) = &mut state.clone_source {
+                source.revision = state.history.revision;
+            }
+        }
+        Message::ModifiersChanged(modifiers) => {
+            state.modifiers = modifiers;
+        }
+        Message::SelectionCleared => {
+            state.selection = None;
+            state.selection_mask = None;
+            state.selection_base = None;
+            state.is_selecting = false;
+        }
+        Message::CopySelection => {
+            tools::copy_selection(state, clipboard);
+        }
+        Message::PasteSelection => {
+            if let Some(clipboard) = clipboard.clone() {
+                tools::commit_floating_paste(state);
+                // Float over the selection if there is one, where the pixels
+                // were copied from within it, else centered
+                let (x, y) = match state.selection {
+                    Some(selection) => (
+                        selection.x as i32 + clipboard.offset_x as i32,
+                        selection.y as i32 + clipboard.offset_y as i32,
+                    ),
+                    None => (
+                        (state.canvas_width as i32 - clipboard.width as i32) / 2,
+                        (state.canvas_height as i32 - clipboard.height as i32) / 2,
+                    ),
+                };
+                state.floating_paste = Some(state::FloatingPaste {
+                    pixels: clipboard.pixels,
+                    width: clipboard.width,
+                    height: clipboard.height,
+                    x,
+                    y,
+                });
+            }
+        }
+        Message::ClipboardTransformed(transform) => {
+            let Some(data) = clipboard else {
+                state.notification = Some("Clipboard is empty".into());
+                return Task::none();
+            };
+            let (pixels, width, height) = transform.apply(&data.pixels, data.width, data.height);
+            *data = state::ClipboardData {
+                pixels,
+                width,
+                height,
+                ..*data
+            };
+            // A paste preview already following the cursor turns with it
+            if state.pending_paste.is_some() {
+                state.pending_paste = Some(data.clone());
+            }
+        }
+        Message::PastePreviewStarted => {
+            if let Some(clipboard) = clipboard.clone() {
+                tools::commit_floating_paste(state);
+                state.pending_paste = Some(clipboard);
+            }
+        }
+        Message::PastePreviewPlaced { x, y } => {
+            if let Some((x, y)) = tools::pending_paste_origin(state, x, y)
+                && let Some(clipboard) = state.pending_paste.take()
+            {
+                state.floating_paste = Some(state::FloatingPaste {
+                    pixels: clipboard.pixels,
+                    width: clipboard.width,
+                    height: clipboard.height,
+                    x,
+                    y,
+                });
+                tools::commit_floating_paste(state);
+            }
+        }
+        Message::FloatingPasteNudged { dx, dy } => {
+            if let Some(paste) = &mut state.floating_paste {
+                paste.x += dx;
+                paste.y += dy;
+            }
+        }
+        Message::FloatingPasteDragStarted { x, y } => {
+            if let Some(paste) = &state.floating_paste {
+                state.dragging_paste = Some((x as i32 - paste.x, y as i32 - paste.y));
+            }
+        }
+        Message::FloatingPasteMoved { x, y } => {
+            if let Some((grab_x, grab_y)) = state.dragging_paste
+                && let Some(paste) = &mut state.floating_paste
+            {
+                paste.x = x as i32 - grab_x;
+                paste.y = y as i32 - grab_y;
+            }
+        }
+        Message::FloatingPasteDragEnded => {
+            state.dragging_paste = None;
+        }
+        Message::FloatingPasteCommitted => {
+            tools::commit_floating_paste(state);
+        }
+        Message::EscapePressed => {
+            // Drops a floating paste or one still following the cursor, the
+            // color usage highlight, an open color picker and an unfinished
+            // shape
+            state.floating_paste = None;
+            state.dragging_paste = None;
+            state.pending_paste = None;
+            state.color_usage = None;
+            state.color_picker = None;
+            state.shape_draft = None;
+            state.recent_color_chip = false;
+        }
+        Message::CutSelection => {
+            if let Some((start_x, start_y, end_x, end_y)) = tools::copy_selection(state, clipboard)
+                && let Some(layer) = state.active_layer()
+            {
+                // Clear the selected area as one undo step
+                let mut cleared = layer.clone();
+                for y in start_y..end_y {
+                    for x in start_x..end_x {
+                        if state.is_selected(x, y) {
+                            cleared.set_rgba8(x, y, [0; 4]);
+                        }
+                    }
+                }
+                let layer_index = state.active_layer_index;
+                state.replace_layer_pixels(layer_index, cleared.pixels);
+            }
+        }
+        Message::TrimOnCopyToggled(enabled) => {
+            state.trim_on_copy = enabled;
+        }
+        _ => {}
+    }
+
+    Task::none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::update::{apply, dot};
+    use iced::{Color, Rectangle};
+
+    fn drag(from: (f32, f32), to: (f32, f32)) -> [Message; 3] {
+        [
+            Message::SelectionStarted {
+                x: from.0,
+                y: from.1,
+            },
+            Message::SelectionUpdated { x: to.0, y: to.1 },
+            Message::SelectionEnded,
+        ]
+    }
+
+    #[test]
+    fn selections_dragged_up_and_left_are_normalized() {
+        let mut state = EditorState::new(8, 8);
+        state.current_tool = state::Tool::Selection;
+        apply(&mut state, drag((5.0, 4.0), (1.0, 1.0)));
+        assert_eq!(
+            state.selection,
+            Some(Rectangle {
+                x: 1.0,
+                y: 1.0,
+                width: 4.0,
+                height: 3.0,
+            })
+        );
+        assert!(!state.is_selecting);
+    }
+
+    #[test]
+    fn cuts_undo_in_one_step() {
+        let mut state = EditorState::new(4, 4);
+        apply(&mut state, dot(1, 1, Color::from_rgb8(255, 0, 0)));
+        apply(&mut state, dot(2, 1, Color::from_rgb8(255, 0, 0)));
+        state.current_tool = state::Tool::Selection;
+        apply(&mut state, drag((0.0, 0.0), (4.0, 4.0)));
+        let before = state.layers[0].pixels.clone();
+
+        let mut clipboard = None;
+        let _ = update(&mut state, &mut clipboard, Message::CutSelection);
+        assert!(clipboard.is_some());
+        assert!(state.layers[0].pixels.iter().all(|&byte| byte == 0));
+
+        apply(&mut state, [Message::Undo]);
+        assert_eq!(state.layers[0].pixels, before);
+    }
+}
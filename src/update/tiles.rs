@@ -0,0 +1,60 @@
+use crate::message::Message;
+use crate::state::EditorState;
+use crate::tools;
+use iced::Task;
+
+/// Tile mode: tile size, picking and duplicating tiles, and the seam offset
+/// for making textures tileable.
+pub fn update(state: &mut EditorState, message: Message) -> Task<Message> {
+    match message {
+        Message::TileModeToggled(enabled) => {
+            state.tile_mode = enabled;
+            state.picking_tile = false;
+            state.dragging_tile = None;
+        }
+        Message::TileSizeChanged { width, height } => {
+            state.tile_width = width.clamp(1, 256);
+            state.tile_height = height.clamp(1, 256);
+            state.mark_dirty();
+        }
+        Message::TilePickRequested => {
+            state.picking_tile = true;
+        }
+        Message::TileSelected { x, y } => {
+            state.picking_tile = false;
+            let (start_x, start_y, end_x, end_y) = state.tile_bounds(x, y);
+            state.selection_mask = None;
+            state.selection_base = None;
+            state.is_selecting = false;
+            state.selection = Some(iced::Rectangle {
+                x: start_x as f32,
+                y: start_y as f32,
+                width: (end_x - start_x) as f32,
+                height: (end_y - start_y) as f32,
+            });
+        }
+        Message::TileDragStarted { x, y } => {
+            state.dragging_tile = Some((x, y));
+        }
+        Message::TileDropped(target) => {
+            if let Some(from) = state.dragging_tile.take()
+                && let Some(to) = target
+            {
+                tools::duplicate_tile(state, from, to);
+            }
+        }
+        Message::SeamsExposed => {
+            state.offset_seams(true);
+            state.notification = Some(
+                "Seams moved to the middle; paint over them, then click \"Offset back\"".into(),
+            );
+        }
+        Message::SeamsRestored => {
+            state.offset_seams(false);
+            state.notification = Some("Offset back; the edges now tile".into());
+        }
+        _ => {}
+    }
+
+    Task::none()
+}
@@ -0,0 +1,171 @@
+use crate::message::Message;
+use crate::state::{self, EditorState};
+use crate::utils;
+use iced::Task;
+use std::time::Instant;
+
+/// Zoom, pan, grid, color-vision simulation, 9-slice guides and
+/// notifications.
+pub fn update(state: &mut EditorState, message: Message) -> Task<Message> {
+    match message {
+        Message::NotificationDismissed => {
+            state.notification = None;
+            state.hidden_layer_warning = None;
+        }
+        Message::ZoomChanged(zoom) => {
+            state.zoom_level = utils::clamp_f32(zoom, 1.0, 32.0);
+        }
+        Message::ZoomIn => {
+            state.zoom_level = (state.zoom_level + 1.0).min(32.0);
+        }
+        Message::ZoomOut => {
+            state.zoom_level = (state.zoom_level - 1.0).max(1.0);
+        }
+        Message::ViewFlipped => {
+            state.view_flipped = !state.view_flipped;
+        }
+        Message::ViewRotated => {
+            state.view_rotation = (state.view_rotation + 1) % 4;
+        }
+        Message::ZoomedAt { zoom, pan } => {
+            state.zoom_level = utils::clamp_f32(zoom, 1.0, 32.0);
+            state.pan = pan;
+        }
+        Message::ViewPanned { dx, dy } => {
+            state.pan = (state.pan.0 + dx, state.pan.1 + dy);
+        }
+        Message::ViewReset => {
+            state.view_flipped = false;
+            state.view_rotation = 0;
+            state.pan = (0.0, 0.0);
+        }
+        Message::SafeAreaSelected(name) => {
+            state.safe_area = Some(name);
+        }
+        Message::SafeAreaHidden => {
+            state.safe_area = None;
+        }
+        Message::SafeAreaDraftStarted => {
+            state.safe_area_draft = Some(state::SafeAreaPreset {
+                name: String::new(),
+                aspect: None,
+                margins: [10.0; 4],
+            });
+        }
+        Message::SafeAreaDraftChanged(draft) => {
+            state.safe_area_draft = Some(state::SafeAreaPreset {
+                margins: draft.margins.map(|m| m.clamp(0.0, 45.0)),
+                ..draft
+            });
+        }
+        Message::SafeAreaDraftSaved => {
+            let Some(draft) = state.safe_area_draft.take() else {
+                return Task::none();
+            };
+            let name = draft.name.trim().to_string();
+            if name.is_empty()
+                || state::SafeAreaPreset::builtin()
+                    .iter()
+                    .any(|preset| preset.name == name)
+            {
+                state.notification =
+                    Some("Give the safe area a name not used by a built-in preset".into());
+                state.safe_area_draft = Some(draft);
+                return Task::none();
+            }
+            // Saving under an existing custom name replaces that preset
+            state.custom_safe_areas.retain(|preset| preset.name != name);
+            state.custom_safe_areas.push(state::SafeAreaPreset {
+                name: name.clone(),
+                ..draft
+            });
+            state.safe_area = Some(name);
+        }
+        Message::SafeAreaDraftCancelled => {
+            state.safe_area_draft = None;
+        }
+        Message::SafeAreaDeleted(name) => {
+            state.custom_safe_areas.retain(|preset| preset.name != name);
+            if state.safe_area.as_ref() == Some(&name) {
+                state.safe_area = None;
+            }
+        }
+        Message::KioskModeEntered => {
+            state.kiosk_mode = true;
+            state.notification =
+                Some("Drawing mode: hold the unlock button for 2 seconds to leave".into());
+        }
+        Message::KioskUnlockPressed => {
+            state.kiosk_unlock_pressed = Some(Instant::now());
+        }
+        Message::KioskUnlockReleased => {
+            if let Some(pressed) = state.kiosk_unlock_pressed.take() {
+                if pressed.elapsed() >= state::KIOSK_UNLOCK_HOLD {
+                    state.kiosk_mode = false;
+                    state.notification = None;
+                } else {
+                    state.notification = Some("Keep holding to unlock".into());
+                }
+            }
+        }
+        Message::ColorVisionChanged(mode) => {
+            state.color_vision = mode;
+        }
+        Message::DocumentScanRequested if !state.document_scan_pending => {
+            state.document_scan_pending = true;
+            let revision = state.history.revision;
+            let document = state.id;
+            let buffers: Vec<Vec<u8>> = state
+                .layers
+                .iter()
+                .map(|layer| layer.pixels.clone())
+                .collect();
+            return Task::perform(
+                async move { state::DocumentScan::scan(revision, &buffers) },
+                move |scan| Message::DocumentScanned(document, scan),
+            );
+        }
+        Message::DocumentScanned(_, scan) => {
+            state.document_scan_pending = false;
+            state.document_scan = Some(scan);
+        }
+        Message::OutlineViewToggled(enabled) => {
+            state.outline_view = enabled;
+        }
+        Message::GridToggled => {
+            state.grid_visible = !state.grid_visible;
+        }
+        Message::PanChanged { x, y } => {
+            // Store pan offset for future use
+            // Pan can be used for canvas offset when implementing panning
+            // For now, pan is handled by canvas scrolling, but we store the values
+            let _pan_x = x;
+            let _pan_y = y;
+        }
+        Message::NineSliceToggled(enabled) => {
+            state.nine_slice = enabled
+                .then(|| state::NineSlice::for_canvas(state.canvas_width, state.canvas_height));
+            state.dragging_slice_edge = None;
+            state.mark_dirty();
+        }
+        Message::NineSliceSidecarToggled(enabled) => {
+            state.nine_slice_sidecar = enabled;
+        }
+        Message::SliceGuideDragStarted(edge) => {
+            state.dragging_slice_edge = Some(edge);
+        }
+        Message::SliceGuideMoved { edge, position } => {
+            if let Some(slice) = state.nine_slice {
+                state.nine_slice =
+                    Some(slice.with_guide(edge, position, state.canvas_width, state.canvas_height));
+                state.mark_dirty();
+            }
+        }
+        Message::SliceGuideDragEnded => {
+            state.dragging_slice_edge = None;
+        }
+        _ => {}
+    }
+
+    Task::none()
+}
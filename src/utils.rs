@@ -8,10 +8,318 @@ pub fn rgba8_to_color(rgba: [u8; 4]) -> Color {
     Color::from_rgba8(rgba[0], rgba[1], rgba[2], rgba[3] as f32 / 255.0)
 }
 
+/// Whether every RGBA channel of `a` is within `tolerance` of `b`.
+pub fn colors_match(a: [u8; 4], b: [u8; 4], tolerance: u8) -> bool {
+    a.iter().zip(b).all(|(x, y)| x.abs_diff(y) <= tolerance)
+}
+
+/// Whether writing `new` over `old` would make a fully transparent pixel
+/// visible, which preserve-transparency mode forbids.
+pub fn reveals_transparent(old: Color, new: Color) -> bool {
+    reveals_transparent_rgba8(old.into_rgba8(), new.into_rgba8())
+}
+
+/// `reveals_transparent` for RGBA bytes.
+pub fn reveals_transparent_rgba8(old: [u8; 4], new: [u8; 4]) -> bool {
+    old[3] == 0 && new[3] > 0
+}
+
 pub fn clamp_u32(value: i32, min: u32, max: u32) -> u32 {
     value.max(min as i32).min(max as i32) as u32
 }
 
+/// `value` rounded to the nearest multiple of `step` (halves round up), but
+/// never below one step and never above `max` (the largest multiple that fits
+/// is used instead).
+pub fn snap_to_multiple(value: u32, step: u32, max: u32) -> u32 {
+    let step = step.max(1);
+    let snapped = ((value + step / 2) / step * step).max(step);
+    if snapped > max {
+        (max / step * step).max(step)
+    } else {
+        snapped
+    }
+}
+
 pub fn clamp_f32(value: f32, min: f32, max: f32) -> f32 {
     value.max(min).min(max)
 }
+
+/// Round a position in logical pixels to the nearest device pixel boundary,
+/// given `scale_factor` device pixels per logical pixel.
+pub fn snap_to_device(value: f32, scale_factor: f32) -> f32 {
+    if scale_factor <= 0.0 {
+        return value;
+    }
+    (value * scale_factor).round() / scale_factor
+}
+
+/// A zoom in logical pixels rounded so it spans a whole number of device
+/// pixels, never less than one.
+pub fn snap_zoom(zoom: f32, scale_factor: f32) -> f32 {
+    if scale_factor <= 0.0 {
+        return zoom;
+    }
+    (zoom * scale_factor).round().max(1.0) / scale_factor
+}
+
+/// Convert an sRGB-encoded channel (0.0-1.0) to linear light.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a linear-light channel (0.0-1.0) back to sRGB encoding.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Color-vision deficiency simulation in linear RGB (Machado, Oliveira &
+// Fernandes 2009, severity 1.0)
+pub const PROTANOPIA_MATRIX: [[f32; 3]; 3] = [
+    [0.152286, 1.052583, -0.204868],
+    [0.114503, 0.786281, 0.099216],
+    [-0.003882, -0.048116, 1.051998],
+];
+pub const DEUTERANOPIA_MATRIX: [[f32; 3]; 3] = [
+    [0.367322, 0.860646, -0.227968],
+    [0.280085, 0.672501, 0.047413],
+    [-0.011820, 0.042940, 0.968881],
+];
+pub const TRITANOPIA_MATRIX: [[f32; 3]; 3] = [
+    [1.255528, -0.076749, -0.178779],
+    [-0.078411, 0.930809, 0.147602],
+    [0.004733, 0.691367, 0.303900],
+];
+
+/// Multiply an RGB triple by a 3x3 row-major matrix.
+pub fn apply_color_matrix(rgb: [f32; 3], matrix: &[[f32; 3]; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (out, row) in result.iter_mut().zip(matrix) {
+        *out = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+    }
+    result
+}
+
+/// Run a color through a linear-RGB color-vision simulation matrix, keeping
+/// its alpha.
+pub fn simulate_color_vision(color: Color, matrix: &[[f32; 3]; 3]) -> Color {
+    let linear = [
+        srgb_to_linear(color.r),
+        srgb_to_linear(color.g),
+        srgb_to_linear(color.b),
+    ];
+    let [r, g, b] = apply_color_matrix(linear, matrix);
+    Color::from_rgba(
+        linear_to_srgb(r.clamp(0.0, 1.0)),
+        linear_to_srgb(g.clamp(0.0, 1.0)),
+        linear_to_srgb(b.clamp(0.0, 1.0)),
+        color.a,
+    )
+}
+
+/// Convert 8-bit RGB to hue (degrees, 0-360), saturation and value (0.0-1.0).
+pub fn rgb_to_hsv(rgba: [u8; 4]) -> (f32, f32, f32) {
+    let r = rgba[0] as f32 / 255.0;
+    let g = rgba[1] as f32 / 255.0;
+    let b = rgba[2] as f32 / 255.0;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    (hue, saturation, max)
+}
+
+/// Convert hue (degrees), saturation and value (0.0-1.0) to 8-bit RGB; the
+/// inverse of `rgb_to_hsv`.
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [u8; 3] {
+    let chroma = value * saturation;
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    [
+        ((r + m) * 255.0).round() as u8,
+        ((g + m) * 255.0).round() as u8,
+        ((b + m) * 255.0).round() as u8,
+    ]
+}
+
+/// Parse `RRGGBB` or `RRGGBBAA` hex, with or without a leading `#`.
+pub fn parse_hex_color(text: &str) -> Option<[u8; 4]> {
+    let hex = text.trim().trim_start_matches('#');
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |index: usize| u8::from_str_radix(hex.get(index * 2..index * 2 + 2)?, 16).ok();
+    Some([
+        channel(0)?,
+        channel(1)?,
+        channel(2)?,
+        if hex.len() == 8 { channel(3)? } else { 255 },
+    ])
+}
+
+/// `#RRGGBBAA` for `rgba`.
+pub fn hex_color(rgba: [u8; 4]) -> String {
+    format!(
+        "#{:02X}{:02X}{:02X}{:02X}",
+        rgba[0], rgba[1], rgba[2], rgba[3]
+    )
+}
+
+/// Compare names so runs of digits sort by value: `frame2` before `frame10`.
+/// Letters compare case-insensitively, falling back to the exact text.
+pub fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let mut left = a.chars().peekable();
+    let mut right = b.chars().peekable();
+    loop {
+        match (left.peek().copied(), right.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                        digits.push(c);
+                    }
+                    digits.trim_start_matches('0').to_string()
+                };
+                let (l, r) = (take_number(&mut left), take_number(&mut right));
+                let order = l.len().cmp(&r.len()).then_with(|| l.cmp(&r));
+                if order != std::cmp::Ordering::Equal {
+                    return order;
+                }
+            }
+            (Some(l), Some(r)) => {
+                let order = l.to_ascii_lowercase().cmp(&r.to_ascii_lowercase());
+                if order != std::cmp::Ordering::Equal {
+                    return order;
+                }
+                left.next();
+                right.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIMULATIONS: [[[f32; 3]; 3]; 3] =
+        [PROTANOPIA_MATRIX, DEUTERANOPIA_MATRIX, TRITANOPIA_MATRIX];
+
+    fn simulate(rgb: [u8; 3], matrix: &[[f32; 3]; 3]) -> [u8; 4] {
+        simulate_color_vision(Color::from_rgb8(rgb[0], rgb[1], rgb[2]), matrix).into_rgba8()
+    }
+
+    fn assert_close(actual: [u8; 4], expected: [u8; 4]) {
+        let close = actual
+            .iter()
+            .zip(expected)
+            .all(|(&a, e)| a.abs_diff(e) <= 1);
+        assert!(close, "{:?} is not within 1 of {:?}", actual, expected);
+    }
+
+    #[test]
+    fn simulations_keep_greys() {
+        for matrix in &SIMULATIONS {
+            for row in matrix {
+                assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+            }
+            for grey in [0, 64, 128, 255] {
+                assert_close(simulate([grey; 3], matrix), [grey, grey, grey, 255]);
+            }
+        }
+    }
+
+    #[test]
+    fn simulations_map_primaries_to_reference_colors() {
+        // The published matrices applied to linear-light primaries
+        assert_close(simulate([255, 0, 0], &PROTANOPIA_MATRIX), [109, 95, 0, 255]);
+        assert_close(
+            simulate([0, 255, 0], &DEUTERANOPIA_MATRIX),
+            [239, 214, 58, 255],
+        );
+        assert_close(
+            simulate([0, 0, 255], &TRITANOPIA_MATRIX),
+            [0, 107, 150, 255],
+        );
+    }
+
+    #[test]
+    fn simulation_keeps_alpha() {
+        let color = Color::from_rgba8(10, 200, 30, 0.5);
+        assert_eq!(simulate_color_vision(color, &PROTANOPIA_MATRIX).a, 0.5);
+    }
+
+    #[test]
+    fn sizes_snap_to_the_nearest_tile_multiple() {
+        assert_eq!(snap_to_multiple(60, 16, 2048), 64);
+        assert_eq!(snap_to_multiple(55, 16, 2048), 48);
+        // Halves round up
+        assert_eq!(snap_to_multiple(56, 16, 2048), 64);
+        // Never below one step, and never past the limit
+        assert_eq!(snap_to_multiple(3, 16, 2048), 16);
+        assert_eq!(snap_to_multiple(60, 16, 50), 48);
+        assert_eq!(snap_to_multiple(60, 16, 10), 16);
+        assert_eq!(snap_to_multiple(60, 0, 2048), 60);
+    }
+
+    #[test]
+    fn positions_snap_to_whole_device_pixels() {
+        // At 150% a logical pixel is 1.5 device pixels
+        assert_eq!(snap_to_device(10.3, 1.5), 10.0);
+        assert_eq!(snap_to_device(10.5, 1.5), 16.0 / 1.5);
+        assert_eq!(snap_to_device(10.3, 1.0), 10.0);
+        assert_eq!(snap_to_device(10.3, 0.0), 10.3);
+    }
+
+    #[test]
+    fn zooms_span_whole_device_pixels() {
+        for scale_factor in [1.0, 1.25, 1.5, 2.0] {
+            for zoom in [1.0, 3.0, 7.5, 32.0] {
+                let snapped = snap_zoom(zoom, scale_factor);
+                let device = snapped * scale_factor;
+                assert!(
+                    (device - device.round()).abs() < 1e-4,
+                    "{} at {}",
+                    zoom,
+                    scale_factor
+                );
+                // Off by at most half a device pixel
+                assert!((snapped - zoom).abs() <= 0.5 / scale_factor + 1e-4);
+            }
+        }
+        assert_eq!(snap_zoom(3.0, 1.25), 3.2);
+        // Never below one device pixel
+        assert_eq!(snap_zoom(0.1, 2.0), 0.5);
+    }
+}